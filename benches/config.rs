@@ -29,7 +29,10 @@ class CfgPatches {
             );
             let mut cursor = Cursor::new(input);
 
-            Config::read(&mut cursor, None, &Vec::new()).unwrap();
+            Config::read(&mut cursor, None, &Vec::new(), |path| {
+                std::fs::read_to_string(path).unwrap()
+            })
+            .unwrap();
         })
     });
 }
@@ -26,7 +26,10 @@ class CfgPatches {
 };",
             );
 
-            preprocess(input, None, &Vec::new()).unwrap();
+            preprocess(input, None, &Vec::new(), |path| {
+                std::fs::read_to_string(path).unwrap()
+            })
+            .unwrap();
         })
     });
 }
@@ -0,0 +1,66 @@
+use std::fs::{create_dir, File};
+use std::io::Write;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tempfile::tempdir;
+
+use armake2::sign::{check_signatures, BIPrivateKey, BISignVersion};
+use armake2::PBO;
+
+/// Number of PBOs in the fixture
+const PBOS: usize = 16;
+/// Size of the file in every PBO
+const SIZE: usize = 32 << 20;
+
+fn bench_verify(c: &mut Criterion) {
+    let dir = tempdir().unwrap();
+    let privatekey = BIPrivateKey::generate(1024, String::from("bench")).unwrap();
+    let publickey = privatekey.to_public_key().unwrap();
+
+    let mut pbos: Vec<PathBuf> = Vec::new();
+    for i in 0..PBOS {
+        let source = dir.path().join(format!("addon_{}", i));
+        create_dir(&source).unwrap();
+        File::create(source.join("script.sqf"))
+            .unwrap()
+            .write_all(&vec![b'a' + (i % 26) as u8; SIZE])
+            .unwrap();
+
+        let pbo = PBO::from_directory(source, false, &[], &[]).unwrap();
+        let path = dir.path().join(format!("addon_{}.pbo", i));
+        pbo.write(&mut File::create(&path).unwrap()).unwrap();
+        let pbo = PBO::read(&mut File::open(&path).unwrap()).unwrap();
+        privatekey
+            .sign(&pbo, BISignVersion::V3)
+            .unwrap()
+            .write(
+                &mut File::create(dir.path().join(format!("addon_{}.pbo.bench.bisign", i)))
+                    .unwrap(),
+            )
+            .unwrap();
+        pbos.push(path);
+    }
+
+    let cpus = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let mut group = c.benchmark_group("verify");
+    group.sample_size(10);
+    let mut jobs = vec![1];
+    if cpus > 1 {
+        jobs.push(cpus);
+    }
+    for jobs in jobs.iter() {
+        group.bench_function(format!("{} jobs", jobs), |b| {
+            b.iter(|| {
+                let results = check_signatures(&pbos, std::slice::from_ref(&publickey), *jobs);
+                assert!(results.iter().all(|r| r.is_valid()));
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_verify);
+criterion_main!(benches);
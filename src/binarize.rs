@@ -1,112 +1,585 @@
-//! Functions for calling BI's binarize.exe (on Windows)
+//! Functions for calling BI's binarize.exe (on Windows) or an external binarize command
+//!
+//! See `find_binarizer` for which one is used.
 
 use std::env::{temp_dir, var};
-use std::fs::{create_dir_all, remove_dir_all, File};
-use std::io::{Cursor, Read};
-use std::path::PathBuf;
+use std::fs::{copy, create_dir, remove_dir_all, File};
+use std::io::{Cursor, ErrorKind, Read};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
 
 #[cfg(windows)]
 use winreg::enums::*;
 #[cfg(windows)]
 use winreg::RegKey;
 
-use crate::ArmakeError;
+use crate::preprocess::IncludeReader;
+use crate::warning::Collector;
+use crate::{ArmakeError, Config, Encoding};
 
 use crate::aerror;
 use crate::error::IOPathError;
 
+/// Names binarize.exe has in the Arma 3 Tools, in order of preference
+#[cfg(windows)]
+const BINARIZE_EXE_NAMES: [&str; 2] = ["binarize_x64.exe", "binarize.exe"];
+
+/// Finds BI's binarize.exe, in the folder from the registry key of the Arma 3 Tools or in
+/// `steamapps/common/Arma 3 Tools/Binarize` of any Steam library.
 #[cfg(windows)]
 pub fn find_binarize_exe() -> Result<PathBuf, ArmakeError> {
-    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-    let binarize = hkcu.open_subkey("Software\\Bohemia Interactive\\binarize")?;
-    let value: String = binarize.get_value("path")?;
+    let mut folders = Vec::new();
+    if let Ok(binarize) =
+        RegKey::predef(HKEY_CURRENT_USER).open_subkey("Software\\Bohemia Interactive\\binarize")
+    {
+        if let Ok(path) = binarize.get_value::<String, _>("path") {
+            folders.push(PathBuf::from(path));
+        }
+    }
+    for library in steam_libraries() {
+        folders.push(library.join("steamapps\\common\\Arma 3 Tools\\Binarize"));
+    }
 
-    Ok(PathBuf::from(value).join("binarize_x64.exe"))
+    folders
+        .iter()
+        .flat_map(|folder| BINARIZE_EXE_NAMES.iter().map(move |name| folder.join(name)))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            aerror!(
+                "binarize.exe not found in the registry or the Steam libraries, searched: {}",
+                folders
+                    .iter()
+                    .map(|f| f.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
 }
 
 #[cfg(unix)]
 pub fn find_binarize_exe() -> Result<PathBuf, ArmakeError> {
-    unreachable!();
+    Err(aerror!("binarize.exe is only found on Windows"))
+}
+
+/// Returns the Steam install folder and the library folders from its `libraryfolders.vdf`.
+#[cfg(windows)]
+fn steam_libraries() -> Vec<PathBuf> {
+    let keys = [
+        (
+            HKEY_LOCAL_MACHINE,
+            "SOFTWARE\\WOW6432Node\\Valve\\Steam",
+            "InstallPath",
+        ),
+        (HKEY_LOCAL_MACHINE, "SOFTWARE\\Valve\\Steam", "InstallPath"),
+        (HKEY_CURRENT_USER, "Software\\Valve\\Steam", "SteamPath"),
+    ];
+    let steam = keys.iter().find_map(|(root, key, value)| {
+        RegKey::predef(*root)
+            .open_subkey(key)
+            .and_then(|key| key.get_value::<String, _>(value))
+            .ok()
+    });
+    let steam = match steam {
+        Some(steam) => PathBuf::from(steam),
+        None => return Vec::new(),
+    };
+
+    let mut libraries = vec![steam.clone()];
+    for vdf in [
+        "steamapps\\libraryfolders.vdf",
+        "config\\libraryfolders.vdf",
+    ]
+    .iter()
+    {
+        if let Ok(content) = std::fs::read_to_string(steam.join(vdf)) {
+            for library in parse_library_folders(&content) {
+                if !libraries.contains(&library) {
+                    libraries.push(library);
+                }
+            }
+        }
+    }
+    libraries
+}
+
+/// Returns the library folders listed in a Steam `libraryfolders.vdf`, in the current format
+/// with a `path` in every library and in the old one with numbered paths.
+pub fn parse_library_folders(vdf: &str) -> Vec<PathBuf> {
+    let mut tokens = Vec::new();
+    let mut chars = vdf.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => tokens.push((c.to_string(), false)),
+            '"' => {
+                let mut token = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => token.extend(chars.next()),
+                        _ => token.push(c),
+                    }
+                }
+                tokens.push((token, true));
+            }
+            _ => {}
+        }
+    }
+
+    let mut folders = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        let (token, quoted) = &tokens[i];
+        match (token.as_str(), quoted) {
+            ("{", false) => depth += 1,
+            ("}", false) => depth -= 1,
+            (key, true) => {
+                if let Some((value, true)) = tokens.get(i + 1) {
+                    let numbered = depth == 1 && key.chars().all(|c| c.is_ascii_digit());
+                    if key.eq_ignore_ascii_case("path") || numbered {
+                        folders.push(PathBuf::from(value));
+                    }
+                    i += 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    folders
+}
+
+/// Environment variable with the binarize command, used if none is given in `BinarizeOptions`
+pub const BINARIZE_CMD_VAR: &str = "ARMAKE_BINARIZE_CMD";
+
+/// Environment variable with the path of binarize.exe, used instead of searching for it
+pub const BINARIZE_EXE_VAR: &str = "ARMAKE_BINARIZE";
+
+/// Program files are binarized with, see `find_binarizer`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Binarizer {
+    /// BI's binarize.exe
+    Exe(PathBuf),
+    /// Command with `{source}` and `{target}` placeholders, like a wrapper script calling
+    /// binarize.exe with Wine
+    Command(String),
+}
+
+impl std::fmt::Display for Binarizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Binarizer::Exe(exe) => write!(f, "{}", exe.display()),
+            Binarizer::Command(command) => write!(f, "\"{}\"", command),
+        }
+    }
+}
+
+/// Returns the binarizer to use: the command of `options`, the command in the
+/// `ARMAKE_BINARIZE_CMD` environment variable, the binarize.exe in the `ARMAKE_BINARIZE`
+/// environment variable or, on Windows, the one found by `find_binarize_exe`. `None` if there is
+/// none.
+///
+/// The command is split at whitespace and run without a shell, with `{source}` replaced by the
+/// file to binarize and `{target}` by the path the output has to be written to. The model.cfg of
+/// a model is next to its source.
+pub fn find_binarizer(options: &BinarizeOptions) -> Option<Binarizer> {
+    let command = options
+        .command
+        .clone()
+        .or_else(|| var(BINARIZE_CMD_VAR).ok())
+        .filter(|c| !c.trim().is_empty());
+    if let Some(command) = command {
+        return Some(Binarizer::Command(command));
+    }
+
+    if let Some(exe) = var(BINARIZE_EXE_VAR).ok().filter(|e| !e.is_empty()) {
+        let exe = PathBuf::from(exe);
+        if exe.is_file() {
+            return Some(Binarizer::Exe(exe));
+        }
+        warn!(
+            "{} is set to {}, which doesn't exist",
+            BINARIZE_EXE_VAR,
+            exe.display()
+        );
+        return None;
+    }
+
+    if !cfg!(windows) {
+        return None;
+    }
+    match find_binarize_exe() {
+        Ok(exe) => Some(Binarizer::Exe(exe)),
+        Err(e) => {
+            info!("{}", e);
+            None
+        }
+    }
+}
+
+/// Error for when `find_binarizer` finds nothing
+fn no_binarizer() -> ArmakeError {
+    aerror!(
+        "No binarizer found, binarize.exe is only found on Windows. Set {} or use --binarize-cmd to use another command, or rapify to binarize configs.",
+        BINARIZE_CMD_VAR
+    )
+}
+
+/// Returns true if running under Wine.
+#[cfg(windows)]
+fn is_wine() -> bool {
+    RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey("Software\\Wine")
+        .is_ok()
+}
+
+#[cfg(unix)]
+fn is_wine() -> bool {
+    false
+}
+
+/// Highest default number of jobs under Wine, which misbehaves when many processes start at once
+const WINE_MAX_JOBS: usize = 4;
+
+/// Options for `binarize_all`
+#[derive(Clone, Debug)]
+pub struct BinarizeOptions {
+    /// Maximum number of binarize.exe processes running at the same time
+    pub jobs: usize,
+    /// Don't start any more jobs after the first failure
+    pub fail_fast: bool,
+    /// Binarize command overriding the environment and the registry, see `find_binarizer`
+    pub command: Option<String>,
 }
 
+impl Default for BinarizeOptions {
+    /// Uses one job per CPU, at most `WINE_MAX_JOBS` under Wine.
+    fn default() -> BinarizeOptions {
+        let cpus = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        BinarizeOptions {
+            jobs: if is_wine() {
+                cpus.min(WINE_MAX_JOBS)
+            } else {
+                cpus
+            },
+            fail_fast: false,
+            command: None,
+        }
+    }
+}
+
+/// Creates a new, empty directory in the temp folder. Safe to call from several threads at once.
 fn create_temp_directory(name: &str) -> Result<PathBuf, ArmakeError> {
     let dir = temp_dir();
     let mut i = 0;
 
-    let mut path;
     loop {
-        path = dir.join(format!("armake_{}_{}", name, i));
-        if !path.exists() {
-            break;
+        let path = dir.join(format!("armake_{}_{}", name, i));
+        match create_dir(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => i += 1,
+            Err(e) => return Err(e.into()),
         }
+    }
+}
 
-        i += 1;
+/// Finds the model.cfg for a model, searching the model's folder and its parents up to `root`.
+///
+/// Returns `None` if there is none or if the model is not inside `root`.
+pub fn find_model_cfg(model: &Path, root: &Path) -> Option<PathBuf> {
+    let mut folder = model.parent()?;
+    if !folder.starts_with(root) {
+        return None;
     }
 
-    create_dir_all(&path)?;
+    loop {
+        let candidate = folder.join("model.cfg");
+        if candidate.is_file() {
+            return Some(candidate);
+        }
 
-    Ok(path)
+        if folder == root {
+            return None;
+        }
+        folder = folder.parent()?;
+    }
 }
 
-/// Binarizes the given path with BI's binarize.exe (Only available on Windows).
-pub fn binarize(input: &PathBuf) -> Result<Cursor<Box<[u8]>>, ArmakeError> {
-    if !cfg!(windows) {
-        return Err(aerror!(
-            "binarize.exe is only available on windows. Use rapify to binarize configs."
-        ));
+/// Returns true if the p3d looks animated, i.e. it references RTMs or has named selections.
+///
+/// Named selections are only detected in MLOD p3ds, binarized ones are always reported as not
+/// animated unless they reference an RTM.
+pub fn is_animated(p3d: &[u8]) -> bool {
+    let lower: Vec<u8> = p3d.iter().map(|b| b.to_ascii_lowercase()).collect();
+    if lower.windows(4).any(|w| w == b".rtm") {
+        return true;
+    }
+
+    mlod_has_named_selections(p3d).unwrap_or(false)
+}
+
+/// Walks the LODs of an MLOD p3d and checks them for named selections, which are stored as tags
+/// not starting with `#`.
+fn mlod_has_named_selections(p3d: &[u8]) -> Option<bool> {
+    use byteorder::{LittleEndian, ReadBytesExt};
+
+    use crate::io::ReadExt;
+
+    let mut input = Cursor::new(p3d);
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic).ok()?;
+    if &magic != b"MLOD" {
+        return None;
+    }
+    input.read_u32::<LittleEndian>().ok()?;
+    let lods = input.read_u32::<LittleEndian>().ok()?;
+
+    for _ in 0..lods {
+        input.read_exact(&mut magic).ok()?;
+        if &magic != b"P3DM" {
+            return None;
+        }
+        input.read_u32::<LittleEndian>().ok()?;
+        input.read_u32::<LittleEndian>().ok()?;
+        let points = input.read_u32::<LittleEndian>().ok()?;
+        let normals = input.read_u32::<LittleEndian>().ok()?;
+        let faces = input.read_u32::<LittleEndian>().ok()?;
+        input.read_u32::<LittleEndian>().ok()?;
+
+        let skip = u64::from(points) * 16 + u64::from(normals) * 12;
+        input.set_position(input.position() + skip);
+        for _ in 0..faces {
+            input.set_position(input.position() + 4 + 4 * 16 + 4);
+            input.read_cstring().ok()?;
+            input.read_cstring().ok()?;
+        }
+
+        input.read_exact(&mut magic).ok()?;
+        if &magic != b"TAGG" {
+            return None;
+        }
+        loop {
+            input.read_u8().ok()?;
+            let name = input.read_cstring().ok()?;
+            let size = input.read_u32::<LittleEndian>().ok()?;
+            input.set_position(input.position() + u64::from(size));
+
+            if name == "#EndOfFile#" {
+                break;
+            }
+            if !name.starts_with('#') {
+                return Some(true);
+            }
+        }
+        input.read_f32::<LittleEndian>().ok()?;
     }
 
-    let binarize_exe = find_binarize_exe()?;
-    if !binarize_exe.exists() {
+    Some(false)
+}
+
+/// Output of a binarize job, or its error message
+type JobResult = Result<Cursor<Box<[u8]>>, String>;
+
+/// Binarizes the given files like `binarize` with the binarizer found for `options`, running up
+/// to `options.jobs` processes at once.
+///
+/// The results are in the order of `inputs`, regardless of the order the jobs finish in. Each
+/// failure is logged as a warning and the remaining files are still binarized, unless
+/// `options.fail_fast` is set, in which case no new jobs are started and the first error is
+/// returned. The time taken for every file and in total is printed to stderr.
+pub fn binarize_all(
+    inputs: &[PathBuf],
+    root: &Path,
+    options: &BinarizeOptions,
+) -> Result<Vec<Cursor<Box<[u8]>>>, ArmakeError> {
+    let jobs = options.jobs.max(1).min(inputs.len());
+    let start = Instant::now();
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results: Mutex<Vec<Option<JobResult>>> = Mutex::new(vec![None; inputs.len()]);
+    let binarizer = find_binarizer(options);
+    if let Some(binarizer) = &binarizer {
+        eprintln!("Binarizing with {}", binarizer);
+    }
+
+    let warnings = Collector::current();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                warnings.run(|| loop {
+                    if options.fail_fast && failed.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let input = match inputs.get(index) {
+                        Some(input) => input,
+                        None => break,
+                    };
+                    let name = input.strip_prefix(root).unwrap_or(input).display();
+
+                    let job_start = Instant::now();
+                    let result = match &binarizer {
+                        Some(binarizer) => binarize_with(input, root, binarizer),
+                        None => Err(no_binarizer()),
+                    }
+                    .map_err(|e| e.to_string());
+                    match &result {
+                        Ok(_) => eprintln!(
+                            "[{}] binarized in {:.2}s",
+                            name,
+                            job_start.elapsed().as_secs_f64()
+                        ),
+                        Err(_) => failed.store(true, Ordering::SeqCst),
+                    }
+                    results.lock().unwrap()[index] = Some(result);
+                })
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap();
+    let mut outputs = Vec::with_capacity(inputs.len());
+    let mut failures = 0;
+    for (input, result) in inputs.iter().zip(results) {
+        match result {
+            Some(Ok(output)) => outputs.push(output),
+            Some(Err(e)) if options.fail_fast => {
+                return Err(aerror!("Failed to binarize {}: {}", input.display(), e))
+            }
+            Some(Err(e)) => {
+                warn!("Failed to binarize {}: {}", input.display(), e);
+                failures += 1;
+            }
+            None => {}
+        }
+    }
+
+    eprintln!(
+        "Binarized {} of {} files in {:.2}s using {} jobs",
+        outputs.len(),
+        inputs.len(),
+        start.elapsed().as_secs_f64(),
+        jobs
+    );
+
+    if failures > 0 {
         return Err(aerror!(
-            "BI's binarize.exe found in registry, but doesn't exist."
+            "{} of {} files failed to binarize",
+            failures,
+            inputs.len()
         ));
     }
 
-    let input_dir = PathBuf::from(input.parent().unwrap());
+    Ok(outputs)
+}
+
+/// Binarizes the given path with the binarizer found by `find_binarizer` with the default
+/// options, see `binarize_with`.
+pub fn binarize(input: &Path, root: &Path) -> Result<Cursor<Box<[u8]>>, ArmakeError> {
+    let binarizer = find_binarizer(&BinarizeOptions::default()).ok_or_else(no_binarizer)?;
+    binarize_with(input, root, &binarizer)
+}
+
+/// Binarizes the given path with `binarizer`.
+///
+/// The file is copied into a temporary workspace. For p3ds, the closest model.cfg in the model's
+/// folder or its parents up to `root` is rapified into the workspace. The output of the binarizer
+/// is printed once it exits if it fails, or always with `BIOUTPUT=1`, every line prefixed with the
+/// file name. A binarizer that fails or exits with a non-zero code is an error.
+pub fn binarize_with(
+    input: &Path,
+    root: &Path,
+    binarizer: &Binarizer,
+) -> Result<Cursor<Box<[u8]>>, ArmakeError> {
     let name = input.file_name().unwrap().to_str().unwrap().to_string();
+    let input_dir = create_temp_directory(&format!("{}_source", name))?;
     let tempdir = create_temp_directory(&name)?;
 
+    copy(input, input_dir.join(&name))?;
+    if input.extension().map(|e| e.eq_ignore_ascii_case("p3d")) == Some(true) {
+        match find_model_cfg(input, root) {
+            Some(model_cfg) => {
+                let reader = IncludeReader::new(Encoding::Utf8);
+                let config = reader.finish(Config::read(
+                    &mut File::open(&model_cfg)?,
+                    Some(model_cfg.clone()),
+                    &[],
+                    |path| reader.read(path),
+                ))?;
+                config.write_rapified(&mut File::create(input_dir.join("model.cfg"))?)?;
+            }
+            None => {
+                let mut buffer = Vec::new();
+                File::open(input)?.read_to_end(&mut buffer)?;
+                if is_animated(&buffer) {
+                    warn!(
+                        "{} looks animated, but there is no model.cfg in its folder or above",
+                        input.display()
+                    );
+                }
+            }
+        }
+    }
+
     let piped = var("BIOUTPUT").unwrap_or_else(|_| "0".to_string()) == "1";
 
-    let binarize_output = Command::new(binarize_exe)
-        .args(&[
-            "-norecurse",
-            "-always",
-            "-silent",
-            "-maxProcesses=0",
-            input_dir.to_str().unwrap(),
-            tempdir.to_str().unwrap(),
-            input.file_name().unwrap().to_str().unwrap(),
-        ])
-        .stdout(if piped {
-            Stdio::inherit()
-        } else {
-            Stdio::null()
-        })
-        .stderr(if piped {
-            Stdio::inherit()
-        } else {
-            Stdio::null()
-        })
-        .output()?;
+    let (mut command, program) = match binarizer {
+        Binarizer::Exe(exe) => {
+            let mut command = Command::new(exe);
+            command.args([
+                "-norecurse",
+                "-always",
+                "-silent",
+                "-maxProcesses=0",
+                input_dir.to_str().unwrap(),
+                tempdir.to_str().unwrap(),
+                input.file_name().unwrap().to_str().unwrap(),
+            ]);
+            (command, String::from("binarize.exe"))
+        }
+        Binarizer::Command(template) => {
+            let words = command_words(
+                template,
+                &input_dir.join(&name).to_string_lossy(),
+                &tempdir.join(&name).to_string_lossy(),
+            )?;
+            let mut command = Command::new(&words[0]);
+            command.args(&words[1..]);
+            (command, words[0].clone())
+        }
+    };
 
-    if !binarize_output.status.success() {
-        let msg = match binarize_output.status.code() {
-            Some(code) => format!("binarize.exe terminated with exit code: {}", code),
-            None => "binarize.exe terminated by signal.".to_string(),
-        };
-        let outputhint = if !piped {
-            "\nUse BIOUTPUT=1 to see binarize.exe's output."
-        } else {
-            ""
-        };
+    let binarize_output = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| aerror!("Failed to run {}: {}", program, e))?;
+
+    if piped || !binarize_output.status.success() {
+        let output = String::from_utf8_lossy(&binarize_output.stdout).into_owned()
+            + &String::from_utf8_lossy(&binarize_output.stderr);
+        let lines: Vec<String> = output
+            .lines()
+            .map(|line| format!("[{}] {}\n", name, line))
+            .collect();
+        eprint!("{}", lines.concat());
+    }
 
-        return Err(aerror!("{}{}", msg, outputhint));
+    if !binarize_output.status.success() {
+        return Err(match binarize_output.status.code() {
+            Some(code) => aerror!("{} terminated with exit code: {}", program, code),
+            None => aerror!("{} terminated by signal.", program),
+        });
     }
 
-    let result_path = tempdir.join(input.strip_prefix(&input_dir).unwrap());
+    let result_path = tempdir.join(&name);
     let mut buffer: Vec<u8> = Vec::new();
 
     {
@@ -114,13 +587,20 @@ pub fn binarize(input: &PathBuf) -> Result<Cursor<Box<[u8]>>, ArmakeError> {
             ArmakeError::IOPath(IOPathError {
                 source,
                 path: result_path,
-                message: Some("Failed to open binarize.exe output".to_owned()),
+                message: Some(format!("Failed to open {} output", program)),
             })
         })?;
         file.read_to_end(&mut buffer)
-            .or_else(|_| Err(aerror!("Failed to read binarize.exe output")))?;
+            .map_err(|_| aerror!("Failed to read {} output", program))?;
     }
 
+    remove_dir_all(&input_dir).map_err(|source| {
+        ArmakeError::IOPath(IOPathError {
+            source,
+            path: input_dir,
+            message: Some("Failed to remove temp directory".to_owned()),
+        })
+    })?;
     remove_dir_all(&tempdir).map_err(|source| {
         ArmakeError::IOPath(IOPathError {
             source,
@@ -131,3 +611,18 @@ pub fn binarize(input: &PathBuf) -> Result<Cursor<Box<[u8]>>, ArmakeError> {
 
     Ok(Cursor::new(buffer.into_boxed_slice()))
 }
+
+/// Splits a binarize command at whitespace and fills in the `{source}` and `{target}`
+/// placeholders, which are both required.
+fn command_words(template: &str, source: &str, target: &str) -> Result<Vec<String>, ArmakeError> {
+    if !template.contains("{source}") || !template.contains("{target}") {
+        return Err(aerror!(
+            "The binarize command \"{}\" needs {{source}} and {{target}} placeholders",
+            template
+        ));
+    }
+    Ok(template
+        .split_whitespace()
+        .map(|w| w.replace("{source}", source).replace("{target}", target))
+        .collect())
+}
@@ -3,19 +3,33 @@ use std::io::Write;
 use std::path::PathBuf;
 
 use crate::aerror;
-use crate::{binarize, ArmakeError, Command};
+use crate::{binarize_with, find_binarizer, ArmakeError, BinarizeOptions, Command};
 
 pub struct Binarize {}
 impl Binarize {
-    /// Binarizes the given path using BI's binarize.exe (on Windows) and writes it to the output.
-    fn cmd_binarize(input: PathBuf, output: PathBuf) -> Result<(), ArmakeError> {
-        if !cfg!(windows) {
-            return Err(aerror!(
-                "binarize.exe is only available on windows. Use rapify to binarize configs."
-            ));
-        }
+    /// Binarizes the given path using the binarizer found for `options`, see `find_binarizer`,
+    /// and writes it to the output.
+    ///
+    /// model.cfg files are searched for from the input's folder up to `root`. An existing output
+    /// is only overwritten with `force`.
+    fn cmd_binarize(
+        input: PathBuf,
+        output: PathBuf,
+        root: PathBuf,
+        options: &BinarizeOptions,
+        force: bool,
+    ) -> Result<(), ArmakeError> {
+        let binarizer = find_binarizer(options).ok_or_else(|| {
+            aerror!(
+                "No binarizer found, binarize.exe is only found on Windows. Use --binarize-cmd or {} to use another command, {} for another binarize.exe or rapify to binarize configs.",
+                crate::BINARIZE_CMD_VAR,
+                crate::BINARIZE_EXE_VAR
+            )
+        })?;
 
-        let cursor = binarize(&input)?;
+        crate::check_overwrite(&output, force)?;
+        eprintln!("Binarizing with {}", binarizer);
+        let cursor = binarize_with(&input, &root, &binarizer)?;
         let mut file = File::create(output)?;
         file.write_all(cursor.get_ref())?;
 
@@ -24,9 +38,9 @@ impl Binarize {
 }
 
 impl Command for Binarize {
-    fn register(&self) -> clap::App {
+    fn register(&self) -> clap::App<'_, '_> {
         clap::SubCommand::with_name("binarize")
-            .about("Binarize a file using BI's binarize.exe (Windows only)")
+            .about("Binarize a file using BI's binarize.exe (Windows only) or another command")
             .arg(
                 clap::Arg::with_name("source")
                     .help("Source file")
@@ -37,11 +51,51 @@ impl Command for Binarize {
                     .help("Location to write file")
                     .required(true),
             )
+            .arg(
+                clap::Arg::with_name("root")
+                    .help("Topmost folder to search for model.cfg, defaults to the source's folder")
+                    .long("root")
+                    .validator(crate::commands::validate_dir)
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("binarize-cmd")
+                    .help("Command to binarize with instead of binarize.exe, with {source} and {target} placeholders, defaults to ARMAKE_BINARIZE_CMD")
+                    .long("binarize-cmd")
+                    .value_name("template")
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("warning")
+                    .help("Warning to suppress, all to suppress every warning")
+                    .short("w")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .takes_value(true),
+            )
     }
 
     fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        if let Some(warnings) = args.values_of("warning") {
+            crate::warning::suppress_warnings(&warnings.collect::<Vec<_>>());
+        }
         let input = args.value_of("source").unwrap();
         let output = args.value_of("target").unwrap();
-        Binarize::cmd_binarize(PathBuf::from(input), PathBuf::from(output))
+        let input = PathBuf::from(input);
+        let root = match args.value_of("root") {
+            Some(root) => PathBuf::from(root),
+            None => input.parent().unwrap().to_path_buf(),
+        };
+        let options = BinarizeOptions {
+            command: args.value_of("binarize-cmd").map(String::from),
+            ..BinarizeOptions::default()
+        };
+        Binarize::cmd_binarize(
+            input,
+            PathBuf::from(output),
+            root,
+            &options,
+            args.is_present("force"),
+        )
     }
 }
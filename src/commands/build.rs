@@ -1,38 +1,501 @@
-use std::io::Write;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Cursor, Write};
+#[cfg(feature = "signing")]
+use std::path::Path;
 use std::path::PathBuf;
 
-use crate::{ArmakeError, Command, PBO};
+use crate::commands::deps::{deps_args, write_deps};
+use crate::commands::header_ext::{apply_header_ext, header_ext_args, header_ext_values};
+use crate::commands::preprocess::{apply_define_args, define_args};
+#[cfg(feature = "signing")]
+use crate::io::PendingFile;
+use crate::lint::{lint_cfgpatches, lint_paths, read_known_addons_all, report};
+use crate::pbo::{lzss, EntryOrder, FileAction, FileRule, FileRules, DEFAULT_EXCLUDES};
+use crate::preprocess::PreprocessOptions;
+#[cfg(feature = "signing")]
+use crate::sign::{BIPrivateKey, BISign, BISignVersion};
+use crate::stringtable::{lint_references, Stringtable};
+use crate::{aerror, ArmakeError, BinarizeOptions, Command, Config, PBO};
 
 pub struct Build {}
 impl Build {
-    fn cmd_build<O: Write>(
+    #[allow(clippy::too_many_arguments)]
+    fn cmd_build(
         input: PathBuf,
-        output: &mut O,
         headerext: &[&str],
         excludes: &[&str],
         includefolders: &[PathBuf],
-    ) -> Result<(), ArmakeError> {
-        let mut pbo = PBO::from_directory(input, true, excludes, includefolders)?;
+        preprocess_options: &PreprocessOptions,
+        lint: Option<&[PathBuf]>,
+        check_paths: Option<&[(String, PathBuf)]>,
+        strict: bool,
+        hash_manifest: bool,
+        binarize_options: &BinarizeOptions,
+        rules: &FileRules,
+        order: Option<&EntryOrder>,
+        compress: Option<&[&str]>,
+        timestamp: Option<u32>,
+        dependencies: &mut Vec<PathBuf>,
+    ) -> Result<PBO, ArmakeError> {
+        let mut pbo = PBO::from_directory_with_dependencies(
+            input.clone(),
+            true,
+            excludes,
+            includefolders,
+            preprocess_options,
+            binarize_options,
+            rules,
+            dependencies,
+        )?;
+
+        if strict && pbo.is_empty_addon() {
+            return Err(aerror!("Refusing to build an empty addon"));
+        }
+
+        if let Some(known_addons) = lint {
+            let known = read_known_addons_all(known_addons)?;
+
+            for name in pbo.files.keys() {
+                if !name.to_lowercase().ends_with("config.bin") {
+                    continue;
+                }
+                let data = pbo.entry_data(name)?.unwrap();
+                let config = Config::read_rapified(&mut Cursor::new(&data[..]))?;
+                report(&lint_cfgpatches(&config, known.as_ref()), name, &[]);
+            }
+        }
+
+        apply_header_ext(&mut pbo, headerext, Some(&input), timestamp)?;
+
+        if let Some(order) = order {
+            pbo.apply_order(order);
+        }
+
+        Build::check_stringtables(&pbo)?;
+
+        if let Some(known_prefixes) = check_paths {
+            Build::check_paths(&pbo, known_prefixes)?;
+        }
 
-        for h in headerext {
-            let (key, value) = (h.split('=').nth(0).unwrap(), h.split('=').nth(1).unwrap());
-            pbo.header_extensions
-                .insert(key.to_string(), value.to_string());
+        if hash_manifest {
+            pbo.add_hash_manifest();
         }
 
-        pbo.write(output)?;
+        if let Some(exclude_patterns) = compress {
+            pbo.compress(exclude_patterns)?;
+        }
+
+        if let Some(timestamp) = timestamp {
+            pbo.set_timestamps(timestamp);
+        }
+
+        Ok(pbo)
+    }
+
+    /// Writes the signature of a built PBO to `path`. Like the PBO, the signature only replaces
+    /// an existing file once it is complete.
+    #[cfg(feature = "signing")]
+    pub(crate) fn write_signature(signature: &BISign, path: &Path) -> Result<(), ArmakeError> {
+        let mut file = PendingFile::new(path.to_path_buf());
+        signature.write(&mut file)?;
+        file.commit()?;
+        Ok(())
+    }
+
+    /// Checks the file references in the rapified configs and materials of the PBO against its
+    /// entries and the given prefix folders.
+    fn check_paths(pbo: &PBO, known_prefixes: &[(String, PathBuf)]) -> Result<(), ArmakeError> {
+        let prefix = pbo
+            .header_extension("prefix")
+            .unwrap_or_default()
+            .to_string();
+        let entries: HashSet<String> = pbo.files.keys().map(|k| k.to_lowercase()).collect();
+
+        for name in pbo.files.keys() {
+            let lower = name.to_lowercase();
+            if !(lower.ends_with("config.bin") || lower.ends_with(".rvmat")) {
+                continue;
+            }
+            let data = pbo.entry_data(name)?.unwrap();
+            if !data.starts_with(b"\0raP") {
+                continue;
+            }
+
+            let config = Config::read_rapified(&mut Cursor::new(&data[..]))?;
+            report(
+                &lint_paths(&config, &prefix, &entries, known_prefixes),
+                name,
+                &[],
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Validates any stringtable.xml in the PBO and checks the `$STR` references of the configs
+    /// in the same folder or below against it.
+    fn check_stringtables(pbo: &PBO) -> Result<(), ArmakeError> {
+        for name in pbo.files.keys() {
+            let lower = name.to_lowercase();
+            if !(lower == "stringtable.xml" || lower.ends_with("\\stringtable.xml")) {
+                continue;
+            }
+            let folder = &lower[..lower.len() - "stringtable.xml".len()];
+
+            let data = pbo.entry_data(name)?.unwrap();
+            let content = String::from_utf8_lossy(&data);
+            let stringtable =
+                Stringtable::read(&content).map_err(|e| aerror!("{}: {}", name, e))?;
+            report(&stringtable.lints, name, &[]);
+
+            for config_name in pbo.files.keys() {
+                let config_lower = config_name.to_lowercase();
+                if !config_lower.starts_with(folder) || !config_lower.ends_with("config.bin") {
+                    continue;
+                }
+                let config_data = pbo.entry_data(config_name)?.unwrap();
+                let config = Config::read_rapified(&mut Cursor::new(&config_data[..]))?;
+                report(&lint_references(&config, &stringtable), config_name, &[]);
+            }
+        }
 
         Ok(())
     }
 }
 
+/// Adds the options shared by `build` and `build-mod` to a command.
+pub(crate) fn build_args<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
+    let app = header_ext_args(app)
+        .arg(
+            clap::Arg::with_name("exclude")
+                .help("Exclude the files matching the glob, like *.bak or **/.git/*")
+                .short("x")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("no-default-excludes")
+                .help("Don't exclude version control folders, Thumbs.db, .DS_Store and editor backups by default")
+                .long("no-default-excludes"),
+        )
+        .arg(
+            clap::Arg::with_name("rule")
+                .help("Handle files matching a pattern with an action: skip, copy, rapify or binarize, like *.hpp=rapify. The first matching rule applies, before the defaults")
+                .long("rule")
+                .validator(|v| v.parse::<FileRule>().map(|_| ()).map_err(|e| e.to_string()))
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("no-rapify")
+                .help("Pack files matching the glob as they are instead of rapifying them, like *.rvmat")
+                .long("no-rapify")
+                .value_name("glob")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("include")
+                .help("Include folder")
+                .validator(crate::commands::validate_dir)
+                .short("i")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("lint")
+                .help("Check the built configs for common mistakes")
+                .long("lint"),
+        )
+        .arg(
+            clap::Arg::with_name("known-addons")
+                .help("Config or list of addon names that may be required, implies --lint")
+                .validator(crate::commands::validate_file)
+                .long("known-addons")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("check-paths")
+                .help("Check that files referenced by configs and materials exist")
+                .long("check-paths"),
+        )
+        .arg(
+            clap::Arg::with_name("known-prefix")
+                .help("Folder with the files of another prefix as <prefix>=<folder>, implies --check-paths")
+                .long("known-prefix")
+                .validator(crate::commands::validate_key_value)
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("strict")
+                .help("Fail instead of warning if there are no files to pack")
+                .long("strict"),
+        )
+        .arg(
+            clap::Arg::with_name("hash-manifest")
+                .help("Embed a SHA1 of every entry, see verify-manifest")
+                .long("hash-manifest"),
+        )
+        .arg(
+            clap::Arg::with_name("no-checksum")
+                .help("Write a placeholder checksum instead of hashing the PBO, for development builds only. Such PBOs can't be signed")
+                .long("no-checksum"),
+        )
+        .arg(
+            clap::Arg::with_name("compress")
+                .help("Compress entries with LZSS, except for formats that are compressed already like *.paa and *.ogg")
+                .short("z")
+                .long("compress"),
+        )
+        .arg(
+            clap::Arg::with_name("no-compress")
+                .help("Don't compress files matching the glob, in addition to the defaults")
+                .long("no-compress")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true)
+                .requires("compress"),
+        )
+        .arg(
+            clap::Arg::with_name("timestamp")
+                .help("Unix time stored as the timestamp of every entry instead of the modification times of the files")
+                .long("timestamp")
+                .value_name("unix")
+                .validator(crate::commands::validate_timestamp)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("jobs")
+                .help("Number of models to binarize at the same time, defaults to the number of CPUs")
+                .short("j")
+                .long("jobs")
+                .validator(crate::commands::validate_positive)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("fail-fast")
+                .help("Stop binarizing models after the first failure")
+                .long("fail-fast"),
+        )
+        .arg(
+            clap::Arg::with_name("binarize-cmd")
+                .help("Command to binarize files with instead of binarize.exe, with {source} and {target} placeholders, defaults to ARMAKE_BINARIZE_CMD")
+                .long("binarize-cmd")
+                .value_name("template")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("warning")
+                .help("Warning to suppress, all to suppress every warning")
+                .short("w")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        );
+
+    define_args(app)
+}
+
+/// Returns the patterns given with `-x`, followed by the default excludes unless they are turned
+/// off with `--no-default-excludes`.
+pub(crate) fn exclude_values<'a>(args: &'a clap::ArgMatches) -> Vec<&'a str> {
+    let mut excludes: Vec<&str> = args.values_of("exclude").into_iter().flatten().collect();
+    if !args.is_present("no-default-excludes") {
+        excludes.extend(DEFAULT_EXCLUDES);
+    }
+    excludes
+}
+
+/// Options of a build, parsed from the arguments added by `build_args`
+pub(crate) struct BuildOptions<'a> {
+    headers: Vec<&'a str>,
+    pub(crate) excludes: Vec<&'a str>,
+    includes: Vec<PathBuf>,
+    /// Known addons if the configs are linted
+    lint: Option<Vec<PathBuf>>,
+    /// Known prefixes if paths are checked
+    check_paths: Option<Vec<(String, PathBuf)>>,
+    strict: bool,
+    hash_manifest: bool,
+    preprocess_options: PreprocessOptions,
+    binarize_options: BinarizeOptions,
+    rules: FileRules,
+    checksum: bool,
+    /// Patterns of files not to compress if entries are compressed
+    compress: Option<Vec<&'a str>>,
+    timestamp: Option<u32>,
+}
+
+impl<'a> BuildOptions<'a> {
+    /// Parses the options and suppresses the warnings given with `-w`.
+    pub(crate) fn from_args(args: &'a clap::ArgMatches) -> BuildOptions<'a> {
+        if let Some(warnings) = args.values_of("warning") {
+            crate::warning::suppress_warnings(&warnings.collect::<Vec<_>>());
+        }
+        let known_addons: Vec<PathBuf> = args
+            .values_of("known-addons")
+            .map(|v| v.map(PathBuf::from).collect())
+            .unwrap_or_default();
+        let known_prefixes: Vec<(String, PathBuf)> = args
+            .values_of("known-prefix")
+            .map(|v| {
+                v.map(|p| {
+                    let (prefix, folder) = p.split_at(p.find('=').unwrap());
+                    (prefix.to_string(), PathBuf::from(&folder[1..]))
+                })
+                .collect()
+            })
+            .unwrap_or_default();
+        let mut preprocess_options = PreprocessOptions::default();
+        apply_define_args(args, &mut preprocess_options);
+        let mut binarize_options = BinarizeOptions::default();
+        if let Some(jobs) = args.value_of("jobs") {
+            binarize_options.jobs = jobs.parse().unwrap();
+        }
+        binarize_options.fail_fast = args.is_present("fail-fast");
+        binarize_options.command = args.value_of("binarize-cmd").map(String::from);
+        // --no-rapify comes first so it also overrides the rules given with --rule
+        let rules = FileRules::new(
+            args.values_of("no-rapify")
+                .into_iter()
+                .flatten()
+                .map(|pattern| FileRule::new(pattern, FileAction::Copy))
+                .chain(
+                    args.values_of("rule")
+                        .into_iter()
+                        .flatten()
+                        .map(|r| r.parse().unwrap()),
+                )
+                .collect(),
+        );
+
+        BuildOptions {
+            headers: header_ext_values(args),
+            excludes: exclude_values(args),
+            includes: args
+                .values_of("include")
+                .into_iter()
+                .flatten()
+                .map(PathBuf::from)
+                .collect(),
+            lint: if args.is_present("lint") || !known_addons.is_empty() {
+                Some(known_addons)
+            } else {
+                None
+            },
+            check_paths: if args.is_present("check-paths") || !known_prefixes.is_empty() {
+                Some(known_prefixes)
+            } else {
+                None
+            },
+            strict: args.is_present("strict"),
+            hash_manifest: args.is_present("hash-manifest"),
+            preprocess_options,
+            binarize_options,
+            rules,
+            checksum: !args.is_present("no-checksum"),
+            compress: if args.is_present("compress") {
+                Some(
+                    lzss::DEFAULT_UNCOMPRESSED
+                        .iter()
+                        .cloned()
+                        .chain(args.values_of("no-compress").into_iter().flatten())
+                        .collect(),
+                )
+            } else {
+                None
+            },
+            timestamp: args.value_of("timestamp").map(|t| t.parse().unwrap()),
+        }
+    }
+
+    /// Builds the PBO of the folder `input` with these options, see `Build::cmd_build`.
+    pub(crate) fn build<O: Write>(
+        &self,
+        input: PathBuf,
+        output: &mut O,
+        order: Option<&EntryOrder>,
+        dependencies: &mut Vec<PathBuf>,
+    ) -> Result<(), ArmakeError> {
+        let pbo = self.build_pbo(input, order, dependencies)?;
+        if !self.checksum {
+            pbo.write_without_checksum(output, order.is_some())
+        } else if order.is_some() {
+            pbo.write_ordered(output)
+        } else {
+            pbo.write(output)
+        }
+    }
+
+    /// Builds the PBO like `build` and returns its V3 signature, which is computed while the PBO
+    /// is written so it doesn't have to be read back. PBOs without checksum can't be signed.
+    #[cfg(feature = "signing")]
+    pub(crate) fn build_signed<O: Write>(
+        &self,
+        input: PathBuf,
+        output: &mut O,
+        order: Option<&EntryOrder>,
+        dependencies: &mut Vec<PathBuf>,
+        privatekey: &BIPrivateKey,
+    ) -> Result<BISign, ArmakeError> {
+        if !self.checksum {
+            return Err(aerror!("PBOs built with --no-checksum can't be signed"));
+        }
+        let pbo = self.build_pbo(input, order, dependencies)?;
+        Ok(privatekey
+            .write_signed(&pbo, output, order.is_some(), &[BISignVersion::V3])?
+            .pop()
+            .unwrap())
+    }
+
+    fn build_pbo(
+        &self,
+        input: PathBuf,
+        order: Option<&EntryOrder>,
+        dependencies: &mut Vec<PathBuf>,
+    ) -> Result<PBO, ArmakeError> {
+        Build::cmd_build(
+            input,
+            &self.headers,
+            &self.excludes,
+            &self.includes,
+            &self.preprocess_options,
+            self.lint.as_deref(),
+            self.check_paths.as_deref(),
+            self.strict,
+            self.hash_manifest,
+            &self.binarize_options,
+            &self.rules,
+            order,
+            self.compress.as_deref(),
+            self.timestamp,
+            dependencies,
+        )
+    }
+}
+
 impl Command for Build {
-    fn register(&self) -> clap::App {
-        clap::SubCommand::with_name("build")
+    fn register(&self) -> clap::App<'_, '_> {
+        let app = clap::SubCommand::with_name("build")
             .about("Build a PBO from a folder")
+            .after_help(
+                "Configs are always preprocessed before they are rapified. If the folder contains a \
+                 stringtable.xml, it is validated and the $STR references of the configs are \
+                 checked against it.",
+            )
             .arg(
                 clap::Arg::with_name("source")
                     .help("Source folder")
+                    .validator(crate::commands::validate_dir)
                     .required(true),
             )
             .arg(
@@ -41,55 +504,86 @@ impl Command for Build {
                     .required(true),
             )
             .arg(
-                clap::Arg::with_name("header")
-                    .help("Headers to add into the PBO")
-                    .short("h")
-                    .multiple(true)
-                    .takes_value(true),
-            )
-            .arg(
-                clap::Arg::with_name("exclude")
-                    .help("Excluded files patterns")
-                    .short("x")
-                    .multiple(true)
-                    .takes_value(true),
-            )
-            .arg(
-                clap::Arg::with_name("include")
-                    .help("Include folder")
-                    .short("i")
-                    .multiple(true)
+                clap::Arg::with_name("order-file")
+                    .help("Order file written by unpack --order, to write the entries in their original order with their original headers")
+                    .long("order-file")
+                    .validator(crate::commands::validate_file)
                     .takes_value(true),
-            )
+            );
+
+        let app = deps_args(build_args(app));
+
+        #[cfg(feature = "signing")]
+        let app = app.arg(
+            clap::Arg::with_name("key")
+                .help("Private key to sign the built PBO with, the signature is written to <target>.<authority>.bisign")
+                .short("k")
+                .long("key")
+                .value_name("privatekey")
+                .validator(crate::commands::validate_file)
+                .conflicts_with("no-checksum")
+                .takes_value(true),
+        );
+
+        app
     }
 
     fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        let options = BuildOptions::from_args(args);
         let input = args.value_of("source").unwrap();
-        let mut output = crate::get_output(args.value_of("target"))?;
-        let headers: Vec<_> = if let Some(values) = args.values_of("header") {
-            values.collect()
-        } else {
-            Vec::new()
+        crate::check_target_outside(input, args.value_of("target"), &options.excludes)?;
+        // the PBO is signed while it is written
+        #[cfg(feature = "signing")]
+        let signer = match args.value_of("key") {
+            Some(path) => {
+                let target = match args.value_of("target") {
+                    Some(target) => target,
+                    None => return Err(aerror!("Signing needs a target file, not stdout")),
+                };
+                let privatekey = BIPrivateKey::open(Path::new(path))?;
+                let signature_path =
+                    PathBuf::from(format!("{}.{}.bisign", target, privatekey.name));
+                crate::check_overwrite(&signature_path, args.is_present("force"))?;
+                Some((privatekey, signature_path))
+            }
+            None => None,
         };
-        let excludes: Vec<_> = if let Some(values) = args.values_of("exclude") {
-            values.collect()
-        } else {
-            Vec::new()
+        let mut output = crate::get_output(args.value_of("target"), args.is_present("force"))?;
+        let order = args
+            .value_of("order-file")
+            .map(|path| EntryOrder::read(&mut File::open(path)?))
+            .transpose()?;
+
+        let input = PathBuf::from(input);
+        let mut dependencies = Vec::new();
+        #[cfg(feature = "signing")]
+        let signature = match &signer {
+            Some((privatekey, path)) => Some((
+                options.build_signed(
+                    input,
+                    &mut output,
+                    order.as_ref(),
+                    &mut dependencies,
+                    privatekey,
+                )?,
+                path,
+            )),
+            None => {
+                options.build(input, &mut output, order.as_ref(), &mut dependencies)?;
+                None
+            }
         };
-        let includes: Vec<_> = if let Some(values) = args.values_of("includes") {
-            values.collect()
-        } else {
-            Vec::new()
+        #[cfg(not(feature = "signing"))]
+        options.build(input, &mut output, order.as_ref(), &mut dependencies)?;
+        output.finish()?;
+
+        #[cfg(feature = "signing")]
+        {
+            if let Some((signature, path)) = &signature {
+                Build::write_signature(signature, path)?;
+            }
         }
-        .into_iter()
-        .map(PathBuf::from)
-        .collect();
-        Build::cmd_build(
-            PathBuf::from(input),
-            &mut output,
-            &headers,
-            &excludes,
-            &includes,
-        )
+
+        write_deps(args, args.value_of("target"), &dependencies)
     }
 }
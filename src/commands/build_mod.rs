@@ -0,0 +1,234 @@
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+#[cfg(feature = "signing")]
+use crate::commands::build::Build;
+use crate::commands::build::{build_args, BuildOptions};
+use crate::io::PendingFile;
+#[cfg(feature = "signing")]
+use crate::sign::BIPrivateKey;
+use crate::warning::Collector;
+use crate::{aerror, ArmakeError, Command};
+
+pub struct BuildMod {}
+impl BuildMod {
+    /// Returns the addon folders of the mod in the folder `source`, the direct subfolders of its
+    /// `addons` folder in name order. Hidden folders, like `.git`, are skipped.
+    fn addon_folders(source: &Path) -> Result<Vec<PathBuf>, ArmakeError> {
+        let addons = source.join("addons");
+        if !addons.is_dir() {
+            return Err(aerror!("{} has no addons folder", source.display()));
+        }
+
+        let mut folders = Vec::new();
+        for entry in addons.read_dir()? {
+            let path = entry?.path();
+            let hidden = path.file_name().unwrap().to_string_lossy().starts_with('.');
+            if path.is_dir() && !hidden {
+                folders.push(path);
+            }
+        }
+        folders.sort();
+        if folders.is_empty() {
+            return Err(aerror!("{} contains no addon folders", addons.display()));
+        }
+        Ok(folders)
+    }
+
+    /// Builds every addon of the mod in the folder `source` with `options` into
+    /// `<target>/addons/<name>.pbo`, running up to `threads` builds at once, and signs each PBO
+    /// with the private key at `key` if there is one.
+    ///
+    /// A failed build doesn't stop the others, the failures are listed at the end. Existing PBOs
+    /// and signatures are only overwritten with `force`.
+    #[cfg_attr(not(feature = "signing"), allow(unused_variables))]
+    fn cmd_build_mod(
+        source: &Path,
+        target: &Path,
+        options: &BuildOptions,
+        threads: usize,
+        key: Option<&Path>,
+        force: bool,
+    ) -> Result<(), ArmakeError> {
+        let folders = BuildMod::addon_folders(source)?;
+        #[cfg(feature = "signing")]
+        let privatekey = key.map(BIPrivateKey::open).transpose()?;
+
+        let addons = target.join("addons");
+        create_dir_all(&addons)?;
+        let mut targets = Vec::with_capacity(folders.len());
+        for folder in folders.iter() {
+            let name = folder.file_name().unwrap().to_string_lossy();
+            let pbo = addons.join(format!("{}.pbo", name));
+            crate::check_overwrite(&pbo, force)?;
+            #[cfg(feature = "signing")]
+            {
+                if let Some(privatekey) = &privatekey {
+                    let signature = addons.join(format!("{}.pbo.{}.bisign", name, privatekey.name));
+                    crate::check_overwrite(&signature, force)?;
+                }
+            }
+            targets.push(pbo);
+        }
+
+        let threads = threads.max(1).min(folders.len());
+        let start = Instant::now();
+        let next = AtomicUsize::new(0);
+        let failures: Mutex<Vec<(usize, String)>> = Mutex::new(Vec::new());
+
+        let warnings = Collector::current();
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    warnings.run(|| loop {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let (folder, pbo) = match (folders.get(index), targets.get(index)) {
+                            (Some(folder), Some(pbo)) => (folder, pbo),
+                            _ => break,
+                        };
+                        let name = folder.file_name().unwrap().to_string_lossy();
+
+                        let build_start = Instant::now();
+                        let result = (|| -> Result<(), ArmakeError> {
+                            crate::check_target_outside(
+                                &folder.to_string_lossy(),
+                                Some(&pbo.to_string_lossy()),
+                                &options.excludes,
+                            )?;
+                            let mut file = PendingFile::new(pbo.clone());
+                            #[cfg(feature = "signing")]
+                            {
+                                if let Some(privatekey) = &privatekey {
+                                    let signature = options.build_signed(
+                                        folder.clone(),
+                                        &mut file,
+                                        None,
+                                        &mut Vec::new(),
+                                        privatekey,
+                                    )?;
+                                    file.commit()?;
+                                    let path = PathBuf::from(format!(
+                                        "{}.{}.bisign",
+                                        pbo.display(),
+                                        privatekey.name
+                                    ));
+                                    return Build::write_signature(&signature, &path);
+                                }
+                            }
+                            options.build(folder.clone(), &mut file, None, &mut Vec::new())?;
+                            file.commit()?;
+                            Ok(())
+                        })();
+
+                        match result {
+                            Ok(()) => eprintln!(
+                                "[{}] built in {:.2}s",
+                                name,
+                                build_start.elapsed().as_secs_f64()
+                            ),
+                            Err(e) => {
+                                warn!("Failed to build {}: {}", name, e);
+                                failures.lock().unwrap().push((index, e.to_string()));
+                            }
+                        }
+                    })
+                });
+            }
+        });
+
+        let mut failures = failures.into_inner().unwrap();
+        failures.sort();
+        eprintln!(
+            "Built {} of {} addons in {:.2}s using {} threads",
+            folders.len() - failures.len(),
+            folders.len(),
+            start.elapsed().as_secs_f64(),
+            threads
+        );
+
+        if !failures.is_empty() {
+            let mut message = format!(
+                "{} of {} addons failed to build:",
+                failures.len(),
+                folders.len()
+            );
+            for (index, error) in failures {
+                let name = folders[index].file_name().unwrap().to_string_lossy();
+                message.push_str(&format!("\n    {}: {}", name, error));
+            }
+            return Err(aerror!("{}", message));
+        }
+
+        Ok(())
+    }
+}
+
+impl Command for BuildMod {
+    fn register(&self) -> clap::App<'_, '_> {
+        let app = clap::SubCommand::with_name("build-mod")
+            .about("Build every addon of a mod into a PBO")
+            .after_help(
+                "Every folder in <modfolder>/addons is built like with build, using the same \
+                 options, into <targetfolder>/addons/<name>.pbo.",
+            )
+            .arg(
+                clap::Arg::with_name("source")
+                    .help("Mod folder, containing an addons folder")
+                    .value_name("modfolder")
+                    .validator(crate::commands::validate_dir)
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("target")
+                    .help("Folder to write the PBOs to, in an addons folder")
+                    .value_name("targetfolder")
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("threads")
+                    .help("Number of addons to build at the same time, defaults to the number of CPUs")
+                    .short("t")
+                    .long("threads")
+                    .validator(crate::commands::validate_positive)
+                    .takes_value(true),
+            );
+
+        let app = build_args(app);
+
+        #[cfg(feature = "signing")]
+        let app = app.arg(
+            clap::Arg::with_name("key")
+                .help("Private key to sign every built PBO with, the signatures are written next to them")
+                .short("k")
+                .long("key")
+                .value_name("privatekey")
+                .validator(crate::commands::validate_file)
+                .conflicts_with("no-checksum")
+                .takes_value(true),
+        );
+
+        app
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        let options = BuildOptions::from_args(args);
+        let threads = match args.value_of("threads") {
+            Some(threads) => threads.parse().unwrap(),
+            None => thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+        };
+        BuildMod::cmd_build_mod(
+            Path::new(args.value_of("source").unwrap()),
+            Path::new(args.value_of("target").unwrap()),
+            &options,
+            threads,
+            args.value_of("key").map(Path::new),
+            args.is_present("force"),
+        )
+    }
+}
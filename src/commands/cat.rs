@@ -1,31 +1,80 @@
-use std::io::{Read, Write};
+use std::io::{stdout, Cursor, IsTerminal, Read, Seek, Write};
+use std::path::Path;
 
-use crate::{ArmakeError, Command, PBO};
+use crate::{aerror, ArmakeError, Command, Config, Encoding, PBOIndex};
 
 pub struct Cat {}
 impl Cat {
-    fn cmd_cat<I: Read, O: Write>(
+    /// Returns the stored name of the entry `name` of the PBO read from `source`, looked up
+    /// like `PBO::retrieve`. Fails with up to five similar entry names if there is none.
+    fn find_entry<'a>(
+        index: &'a PBOIndex,
+        source: &str,
+        name: &str,
+    ) -> Result<&'a str, ArmakeError> {
+        if let Some(name) = index.retrieve(name) {
+            return Ok(name);
+        }
+        let similar = index.similar_entries(name, 5);
+        if similar.is_empty() {
+            return Err(aerror!("{} has no entry named {}", source, name));
+        }
+        Err(aerror!(
+            "{} has no entry named {}, similar entries: {}",
+            source,
+            name,
+            similar.join(", ")
+        ))
+    }
+
+    /// Writes the entry with the stored name `name` of the PBO to output. With `derap`,
+    /// rapified entries are derapified first, other entries and entries that fail to derapify
+    /// are written as they are with a notice on stderr.
+    ///
+    /// Compressed entries are decompressed. Entries with a packing method armake2 doesn't know
+    /// are only written with `raw`, which writes the stored data as it is.
+    ///
+    /// Only the headers and the entry are kept in memory, see `PBOIndex`.
+    fn cmd_cat<I: Read + Seek, O: Write>(
+        index: &PBOIndex,
         input: &mut I,
         output: &mut O,
         name: &str,
+        derap: bool,
+        raw: bool,
     ) -> Result<(), ArmakeError> {
-        let pbo = PBO::read(input)?;
-
-        match pbo.files.get(name) {
-            Some(cursor) => {
-                output.write_all(cursor.get_ref())?;
+        if let Some(header) = index.header(name).filter(|h| h.has_unknown_method()) {
+            if !raw {
+                return Err(aerror!(
+                    "{} has the unknown packing method 0x{:08x}, use --raw to write the stored \
+                     data as it is",
+                    name,
+                    header.packing_method
+                ));
             }
-            None => {
-                eprintln!("not found"); // @todo
+        }
+        if raw {
+            return Ok(output.write_all(&index.read_raw(input, name)?.unwrap())?);
+        }
+        let data = index.read_file(input, name)?.unwrap();
+        let data = &data[..];
+        if derap && !data.starts_with(b"\0raP") {
+            eprintln!("{} is not rapified, writing the raw data", name);
+        } else if derap {
+            let mut derapified = Vec::new();
+            match Config::derapify(&mut Cursor::new(data), &mut derapified, Encoding::default()) {
+                Ok(()) => return Ok(output.write_all(&derapified)?),
+                Err(e) => eprintln!("Writing the raw data, {} failed to derapify: {}", name, e),
             }
         }
+        output.write_all(data)?;
 
         Ok(())
     }
 }
 
 impl Command for Cat {
-    fn register(&self) -> clap::App {
+    fn register(&self) -> clap::App<'_, '_> {
         clap::SubCommand::with_name("cat")
             .about("Read the named file from the target PBO")
             .arg(
@@ -38,13 +87,88 @@ impl Command for Cat {
                     .help("File to read from PBO")
                     .required(true),
             )
-            .arg(clap::Arg::with_name("target").help("Location to write file"))
+            .arg(
+                clap::Arg::with_name("target")
+                    .help("Location to write file, - for stdout")
+                    .long_help(
+                        "Location to write file, - for stdout. If omitted, the file is written to \
+                         stdout if it is not a terminal and to a file named after the entry in the \
+                         current directory otherwise.",
+                    ),
+            )
+            .arg(
+                clap::Arg::with_name("derap")
+                    .help("Derapify the file if it is rapified, like config.bin or rvmats")
+                    .long("derap"),
+            )
+            .arg(
+                clap::Arg::with_name("raw")
+                    .help("Write the stored data as it is without decompressing it, required for unknown packing methods")
+                    .long("raw")
+                    .conflicts_with("derap"),
+            )
+            .arg(
+                clap::Arg::with_name("strict")
+                    .help("Fail instead of warning if the checksum of the PBO doesn't match or is missing")
+                    .long("strict"),
+            )
+            .arg(
+                clap::Arg::with_name("no-verify")
+                    .help("Don't hash the PBO to verify its checksum, only check that there is one")
+                    .long("no-verify"),
+            )
+            .arg(
+                clap::Arg::with_name("keep-garbage")
+                    .help("Also read entries that look like garbage, like names with control characters or data past the end of the PBO")
+                    .long("keep-garbage"),
+            )
     }
 
     fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
         let mut input = crate::get_input(args.value_of("source"))?;
-        let mut output = crate::get_output(args.value_of("target"))?;
-        let filename = args.value_of("filename").unwrap();
-        Cat::cmd_cat(&mut input, &mut output, filename)
+        let source = args.value_of("source").unwrap();
+
+        // a bad checksum is a warning, or an error with --strict. With --keep-garbage, entries
+        // that look like garbage can be read, see PBO::read_keeping_garbage
+        let index = if args.is_present("keep-garbage") {
+            PBOIndex::read_keeping_garbage(&mut input)?
+        } else {
+            PBOIndex::read(&mut input)?
+        };
+        crate::commands::check_index_checksum(
+            &index,
+            &mut input,
+            source,
+            args.is_present("strict"),
+            !args.is_present("no-verify"),
+        )?;
+        let name = Cat::find_entry(&index, source, args.value_of("filename").unwrap())?;
+
+        let target = match args.value_of("target") {
+            Some("-") => None,
+            Some(target) => Some(target.to_string()),
+            None if stdout().is_terminal() => {
+                let basename = name.rsplit(['\\', '/']).next().unwrap().to_string();
+                if Path::new(&basename).exists() && !args.is_present("force") {
+                    return Err(aerror!(
+                        "{} already exists, use --force to overwrite it or - to write to stdout",
+                        basename
+                    ));
+                }
+                Some(basename)
+            }
+            None => None,
+        };
+
+        let mut output = crate::get_output(target.as_deref(), args.is_present("force"))?;
+        Cat::cmd_cat(
+            &index,
+            &mut input,
+            &mut output,
+            name,
+            args.is_present("derap"),
+            args.is_present("raw"),
+        )?;
+        Ok(output.finish()?)
     }
 }
@@ -0,0 +1,273 @@
+use std::fs::File;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::config::{split_path, ConfigArray, ConfigArrayElement, ConfigEntry};
+use crate::lint::read_config_file;
+use crate::{aerror, ArmakeError, Command, SourceMap, PBO};
+
+/// Returns the type of an entry as shown by `config get`.
+fn entry_type(entry: &ConfigEntry) -> &'static str {
+    match entry {
+        ConfigEntry::StringEntry(_) => "string",
+        ConfigEntry::FloatEntry(_) => "float",
+        ConfigEntry::IntEntry(_) => "int",
+        ConfigEntry::ArrayEntry(_) => "array",
+        ConfigEntry::ClassEntry(c) if c.is_deletion => "delete",
+        ConfigEntry::ClassEntry(c) if c.is_external => "extern class",
+        ConfigEntry::ClassEntry(_) => "class",
+    }
+}
+
+/// Formats a value on a single line, arrays in config syntax. Classes have no value.
+fn format_value(entry: &ConfigEntry) -> Option<String> {
+    match entry {
+        ConfigEntry::StringEntry(s) => Some(s.clone()),
+        ConfigEntry::FloatEntry(f) => Some(format!("{:?}", f)),
+        ConfigEntry::IntEntry(i) => Some(i.to_string()),
+        ConfigEntry::ArrayEntry(a) => {
+            let mut buffer = Vec::new();
+            a.write(&mut buffer).ok()?;
+            Some(String::from_utf8_lossy(&buffer).into_owned())
+        }
+        ConfigEntry::ClassEntry(c) if c.parent.is_empty() => None,
+        ConfigEntry::ClassEntry(c) => Some(c.parent.clone()),
+    }
+}
+
+fn array_json(array: &ConfigArray) -> serde_json::Value {
+    array
+        .elements
+        .iter()
+        .map(|e| match e {
+            ConfigArrayElement::StringElement(s) => serde_json::Value::from(s.as_str()),
+            ConfigArrayElement::FloatElement(f) => serde_json::Value::from(*f),
+            ConfigArrayElement::IntElement(i) => serde_json::Value::from(*i),
+            ConfigArrayElement::ArrayElement(a) => array_json(a),
+        })
+        .collect()
+}
+
+fn value_json(entry: &ConfigEntry) -> Option<serde_json::Value> {
+    match entry {
+        ConfigEntry::StringEntry(s) => Some(s.as_str().into()),
+        ConfigEntry::FloatEntry(f) => Some((*f).into()),
+        ConfigEntry::IntEntry(i) => Some((*i).into()),
+        ConfigEntry::ArrayEntry(a) => Some(array_json(a)),
+        ConfigEntry::ClassEntry(c) if c.parent.is_empty() => None,
+        ConfigEntry::ClassEntry(c) => Some(c.parent.as_str().into()),
+    }
+}
+
+/// Reads the config at `source`, which is a config file, rapified or not, or a PBO whose
+/// `config.bin` is read.
+fn read_source(source: &Path, includefolders: &[PathBuf]) -> Result<crate::Config, ArmakeError> {
+    let is_pbo = source
+        .extension()
+        .map(|e| e.eq_ignore_ascii_case("pbo"))
+        .unwrap_or(false);
+    if !is_pbo {
+        return Ok(read_config_file(source, includefolders)?.0);
+    }
+
+    let pbo = PBO::read(&mut File::open(source)?)?;
+    let name = pbo
+        .retrieve("config.bin")
+        .ok_or_else(|| aerror!("{} contains no config.bin", source.display()))?;
+    let data = pbo.entry_data(name)?.unwrap();
+    crate::Config::read_rapified(&mut Cursor::new(&data[..]))
+        .map_err(|e| aerror!("{} in {}: {}", name, source.display(), e))
+}
+
+pub struct Config {}
+impl Config {
+    /// Prints the entry at `path` in the config at `source`, see `read_source`.
+    ///
+    /// If the last segment of the path contains `*`, the matching entries of the class are
+    /// listed with their types instead, and with their values if `values` is set. Classes are
+    /// listed with their parent as the value.
+    fn cmd_get(
+        source: &Path,
+        path: &str,
+        includefolders: &[PathBuf],
+        json: bool,
+        values: bool,
+    ) -> Result<(), ArmakeError> {
+        let config = read_source(source, includefolders)?;
+        let segments = split_path(path);
+        let (last, classes) = segments
+            .split_last()
+            .ok_or_else(|| aerror!("Config path is empty"))?;
+
+        if let Some(segment) = classes.iter().find(|s| s.contains('*')) {
+            return Err(aerror!(
+                "Wildcards are only allowed in the last segment of the path, not in \"{}\"",
+                segment
+            ));
+        }
+
+        if !last.contains('*') {
+            let entry = config.get(path)?;
+            return match entry {
+                ConfigEntry::ClassEntry(_) => Err(aerror!(
+                    "{} is a class, use \"{}/*\" to list its entries",
+                    segments.join(" >> "),
+                    segments.join("/")
+                )),
+                ConfigEntry::ArrayEntry(a) if json => {
+                    println!("{}", array_json(a));
+                    Ok(())
+                }
+                ConfigEntry::ArrayEntry(a) => {
+                    for element in a.elements.iter() {
+                        match element {
+                            ConfigArrayElement::StringElement(s) => println!("{}", s),
+                            ConfigArrayElement::FloatElement(f) => println!("{:?}", f),
+                            ConfigArrayElement::IntElement(i) => println!("{}", i),
+                            ConfigArrayElement::ArrayElement(a) => {
+                                a.write(&mut std::io::stdout())?;
+                                println!();
+                            }
+                        }
+                    }
+                    Ok(())
+                }
+                _ if json => {
+                    println!("{}", value_json(entry).unwrap());
+                    Ok(())
+                }
+                _ => {
+                    println!("{}", format_value(entry).unwrap());
+                    Ok(())
+                }
+            };
+        }
+
+        let matches = config.class(classes)?.matching(last);
+        if matches.is_empty() {
+            return Err(aerror!("No entries match {}", segments.join(" >> ")));
+        }
+
+        if json {
+            let list: Vec<serde_json::Value> = matches
+                .iter()
+                .map(|(name, entry)| {
+                    let mut object = serde_json::Map::new();
+                    object.insert("name".to_string(), (*name).into());
+                    object.insert("type".to_string(), entry_type(entry).into());
+                    if values {
+                        if let Some(value) = value_json(entry) {
+                            object.insert("value".to_string(), value);
+                        }
+                    }
+                    serde_json::Value::Object(object)
+                })
+                .collect();
+            println!("{}", serde_json::Value::Array(list));
+            return Ok(());
+        }
+
+        for (name, entry) in matches {
+            match format_value(entry) {
+                Some(value) if values => println!("{}\t{}\t{}", name, entry_type(entry), value),
+                _ => println!("{}\t{}", name, entry_type(entry)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Prints the location of the entry at `path` from a source map as `file:line`.
+    fn cmd_locate(source_map: &Path, path: &str) -> Result<(), ArmakeError> {
+        let map = SourceMap::read(&mut File::open(source_map)?)?;
+        let location = map
+            .locate(path)
+            .ok_or_else(|| aerror!("{} not found in the source map", path))?;
+        println!(
+            "{}:{}",
+            location.file.as_deref().unwrap_or("?"),
+            location.line
+        );
+        Ok(())
+    }
+}
+
+impl Command for Config {
+    fn register(&self) -> clap::App<'_, '_> {
+        clap::SubCommand::with_name("config")
+            .about("Query configs")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("get")
+                    .about("Print an entry of a config, or list the entries of a class matching a wildcard")
+                    .arg(
+                        clap::Arg::with_name("source")
+                            .help("Config file, rapified or not, or a PBO to read the config.bin of")
+                            .validator(crate::commands::validate_file)
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("path")
+                            .help("Path of the entry, like CfgPatches/my_addon/units or CfgVehicles/*")
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("include")
+                            .help("Include folder")
+                            .validator(crate::commands::validate_dir)
+                            .short("i")
+                            .multiple(true)
+                            .number_of_values(1)
+                            .takes_value(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("json")
+                            .help("Print the result as JSON")
+                            .long("json"),
+                    )
+                    .arg(
+                        clap::Arg::with_name("values")
+                            .help("Include the values of listed entries")
+                            .long("values"),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("locate")
+                    .about("Print the file and line an entry was defined on, from a source map written by rapify")
+                    .arg(
+                        clap::Arg::with_name("sourcemap")
+                            .help("Source map")
+                            .validator(crate::commands::validate_file)
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("path")
+                            .help("Path of the entry, like CfgPatches/my_addon/units")
+                            .required(true),
+                    ),
+            )
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        match args.subcommand() {
+            ("get", Some(args)) => {
+                let includes: Vec<PathBuf> = args
+                    .values_of("include")
+                    .map(|v| v.map(PathBuf::from).collect())
+                    .unwrap_or_default();
+                Config::cmd_get(
+                    Path::new(args.value_of("source").unwrap()),
+                    args.value_of("path").unwrap(),
+                    &includes,
+                    args.is_present("json"),
+                    args.is_present("values"),
+                )
+            }
+            ("locate", Some(args)) => Config::cmd_locate(
+                Path::new(args.value_of("sourcemap").unwrap()),
+                args.value_of("path").unwrap(),
+            ),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{aerror, ArmakeError};
+
+/// Adds the arguments for writing the files an output was made from to a command.
+pub(crate) fn deps_args<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
+    app.arg(
+        clap::Arg::with_name("deps")
+            .help("Write every file the output was made from, including all resolved includes, to this file")
+            .long("deps")
+            .alias("dep-file")
+            .takes_value(true),
+    )
+    .arg(
+        clap::Arg::with_name("deps-format")
+            .help("Format of the dependency file, a Makefile rule for the target (make, the default) or one path per line")
+            .long("deps-format")
+            .possible_values(&["make", "lines"])
+            .requires("deps")
+            .takes_value(true),
+    )
+    .arg(
+        clap::Arg::with_name("dep-base")
+            .help("Folder the paths in the dependency file are relative to, defaults to absolute paths")
+            .long("dep-base")
+            .validator(crate::commands::validate_dir)
+            .requires("deps")
+            .takes_value(true),
+    )
+}
+
+/// Fails if a Makefile rule is requested but there is no target file to write it for.
+pub(crate) fn check_deps_args(
+    args: &clap::ArgMatches,
+    target: Option<&str>,
+) -> Result<(), ArmakeError> {
+    if args.is_present("deps") && target.is_none() && args.value_of("deps-format") != Some("lines")
+    {
+        return Err(aerror!(
+            "A Makefile rule needs a target file, use --deps-format lines when writing to stdout"
+        ));
+    }
+    Ok(())
+}
+
+/// Writes the dependency file requested with the arguments added by `deps_args`, if any.
+/// `target` is the file the output was written to, which is required for Makefile rules.
+pub(crate) fn write_deps(
+    args: &clap::ArgMatches,
+    target: Option<&str>,
+    dependencies: &[PathBuf],
+) -> Result<(), ArmakeError> {
+    let path = match args.value_of("deps") {
+        Some(path) => Path::new(path),
+        None => return Ok(()),
+    };
+    let base = args
+        .value_of("dep-base")
+        .map(|b| Path::new(b).canonicalize())
+        .transpose()?;
+    let format = |path: &Path| -> String {
+        let absolute = absolute_path(path);
+        let path = match &base {
+            Some(base) => relative_path(&absolute, base),
+            None => absolute,
+        };
+        path.to_string_lossy().into_owned()
+    };
+
+    let mut seen = HashSet::new();
+    let dependencies = dependencies
+        .iter()
+        .map(|d| format(d))
+        .filter(|d| seen.insert(d.clone()));
+
+    let content = if args.value_of("deps-format") == Some("lines") {
+        dependencies.map(|d| d + "\n").collect()
+    } else {
+        let target = target.ok_or_else(|| aerror!("A Makefile rule needs a target file"))?;
+        let mut rule = format!("{}:", escape_make(&format(Path::new(target))));
+        for dependency in dependencies {
+            rule.push_str(" \\\n  ");
+            rule.push_str(&escape_make(&dependency));
+        }
+        rule.push('\n');
+        rule
+    };
+
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+/// Returns the absolute form of a path, resolving symlinks if it exists.
+fn absolute_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| match path.parent() {
+        // the target doesn't exist yet if the build failed
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            absolute_path(parent).join(path.file_name().unwrap())
+        }
+        _ => std::env::current_dir().unwrap().join(path),
+    })
+}
+
+/// Returns `path` relative to `base`, both absolute.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path
+        .iter()
+        .zip(base.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    for component in &path[common..] {
+        relative.push(component);
+    }
+    relative
+}
+
+/// Escapes a path for use in a Makefile rule.
+fn escape_make(path: &str) -> String {
+    path.replace('$', "$$")
+        .replace(' ', "\\ ")
+        .replace('#', "\\#")
+}
@@ -1,41 +1,207 @@
-use std::io::{Read, Seek, Write};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 
-use crate::{ArmakeError, Command, Config};
+use crate::config::split_path;
+use crate::io::Input;
+use crate::{aerror, ArmakeError, Command, Config, Encoding, PBO};
+
+/// Start of every PBO: the empty name and the `Vers` packing method of the header extensions
+const PBO_MAGIC: &[u8] = b"\0sreV";
 
 pub struct Derapify {}
 impl Derapify {
-    /// Reads input, derapifies it and writes to output.
+    /// Returns true if the input starts like a PBO, leaving it at the start.
+    fn is_pbo<I: Read + Seek>(input: &mut I) -> Result<bool, ArmakeError> {
+        let mut magic = Vec::new();
+        input.take(PBO_MAGIC.len() as u64).read_to_end(&mut magic)?;
+        input.seek(SeekFrom::Start(0))?;
+        Ok(magic == PBO_MAGIC)
+    }
+
+    /// Reads the PBO `input` and returns the content of the entry `entry`, by default
+    /// `config.bin` or `config.cpp` if there is only that. Names are matched like in
+    /// `PBO::retrieve`. Fails if the entry is not rapified.
+    fn pbo_entry<I: Read>(
+        input: &mut I,
+        source: &str,
+        entry: Option<&str>,
+    ) -> Result<Input, ArmakeError> {
+        let pbo = PBO::read(input)?;
+        let name = match entry {
+            Some(entry) => pbo
+                .retrieve(entry)
+                .ok_or_else(|| aerror!("{} contains no entry {}", source, entry))?,
+            None => pbo
+                .retrieve("config.bin")
+                .or_else(|| pbo.retrieve("config.cpp"))
+                .ok_or_else(|| {
+                    aerror!(
+                        "{} contains no config.bin, pick an entry with --entry",
+                        source
+                    )
+                })?,
+        };
+        let data = pbo.entry_data(name)?.unwrap();
+        if !data.starts_with(b"\0raP") {
+            return Err(aerror!(
+                "{} in {} is not rapified, use cat to read it",
+                name,
+                source
+            ));
+        }
+        Ok(Input::Cursor(Cursor::new(
+            data.into_owned().into_boxed_slice(),
+        )))
+    }
+
+    /// Reads input, derapifies it and writes to output in the given encoding.
+    ///
+    /// If `annotate` is set, classes and properties are annotated with their offsets in the input.
+    /// If `stats` is set, the number of entries of each type and the deepest class path are
+    /// printed to stderr afterwards. If `class` is given, only the class at that path is written,
+    /// inside the classes enclosing it. With `json`, the config is written as JSON, see
+    /// `Config::write_json`.
+    #[allow(clippy::too_many_arguments)]
     fn cmd_derapify<I: Read + Seek, O: Write>(
         input: &mut I,
         output: &mut O,
+        encoding: Encoding,
+        annotate: bool,
+        stats: bool,
+        class: Option<&str>,
+        json: bool,
     ) -> Result<(), ArmakeError> {
-        let config = Config::read_rapified(input)?;
+        if json && annotate {
+            return Err(aerror!("JSON output can't be annotated"));
+        }
 
-        config.write(output)?;
+        if class.is_some() || json {
+            let mut config = Config::read_rapified(input)?;
+            if let Some(class) = class {
+                config.retain_class(&split_path(class))?;
+            }
+            if json {
+                config.write_json(output)?;
+            } else {
+                config.write_encoded(output, encoding)?;
+            }
+        } else if annotate {
+            Config::derapify_annotated(input, output, encoding)?;
+        } else {
+            Config::derapify(input, output, encoding)?;
+        }
+
+        if stats {
+            input.seek(SeekFrom::Start(0))?;
+            let stats = Config::read_rapified(input)?.stats();
+            eprintln!("classes:          {}", stats.classes);
+            eprintln!("external classes: {}", stats.external_classes);
+            eprintln!("deletions:        {}", stats.deletions);
+            eprintln!("strings:          {}", stats.strings);
+            eprintln!("floats:           {}", stats.floats);
+            eprintln!("ints:             {}", stats.ints);
+            eprintln!("arrays:           {}", stats.arrays);
+            eprintln!("expansions:       {}", stats.expansions);
+            eprintln!(
+                "deepest:          {} ({})",
+                stats.deepest.join(" >> "),
+                stats.deepest.len()
+            );
+        }
 
         Ok(())
     }
 }
 
 impl Command for Derapify {
-    fn register(&self) -> clap::App {
+    fn register(&self) -> clap::App<'_, '_> {
         clap::SubCommand::with_name("derapify")
             .about("Derapify a config")
+            .after_help(
+                "If the source is a PBO, its config.bin is derapified, or the entry picked with \
+                 --entry. Offsets written with --annotate are relative to the entry.",
+            )
             .arg(
                 clap::Arg::with_name("source")
-                    .help("Source file")
-                    .required(true),
+                    .help("Source file, a rapified config or a PBO, read from stdin if omitted"),
             )
+            .arg(clap::Arg::with_name("target").help("Location to write file, stdout if omitted"))
             .arg(
-                clap::Arg::with_name("target")
-                    .help("Location to write file")
-                    .required(true),
+                clap::Arg::with_name("output-encoding")
+                    .help("Encoding of the output, strings that can't be represented are an error")
+                    .long("output-encoding")
+                    .takes_value(true)
+                    .possible_values(&["utf8", "cp1252"])
+                    .default_value("utf8"),
+            )
+            .arg(
+                clap::Arg::with_name("annotate")
+                    .help("Annotate classes and properties with their offsets in the source")
+                    .long("annotate"),
+            )
+            .arg(
+                clap::Arg::with_name("format")
+                    .help("Format of the output, json for a JSON document instead of a config, always in UTF-8")
+                    .long("format")
+                    .takes_value(true)
+                    .possible_values(&["config", "json"]),
+            )
+            .arg(
+                clap::Arg::with_name("class")
+                    .help("Only write the class at this path, like CfgVehicles/B_Soldier_F or CfgVehicles >> B_Soldier_F")
+                    .long("class")
+                    .conflicts_with("annotate")
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("stats")
+                    .help(
+                        "Print the number of entries of each type and the deepest class to stderr",
+                    )
+                    .long("stats"),
+            )
+            .arg(
+                clap::Arg::with_name("entry")
+                    .help("Entry to derapify if the source is a PBO, like data\\mat.rvmat, defaults to config.bin")
+                    .long("entry")
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("in-place")
+                    .help("Allow the target to be the source file, which is replaced after a successful run")
+                    .long("in-place"),
             )
     }
 
     fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
         let mut input = crate::get_input(args.value_of("source"))?;
-        let mut output = crate::get_output(args.value_of("target"))?;
-        Derapify::cmd_derapify(&mut input, &mut output)
+        if Derapify::is_pbo(&mut input)? {
+            if args.is_present("in-place") {
+                return Err(aerror!("A PBO can't be derapified in place"));
+            }
+            input = Derapify::pbo_entry(
+                &mut input,
+                args.value_of("source").unwrap_or("stdin"),
+                args.value_of("entry"),
+            )?;
+        } else if args.is_present("entry") {
+            return Err(aerror!("--entry needs a PBO as source"));
+        }
+        let mut output = crate::get_output_for(
+            args.value_of("source"),
+            args.value_of("target"),
+            args.is_present("in-place"),
+            args.is_present("force"),
+        )?;
+        Derapify::cmd_derapify(
+            &mut input,
+            &mut output,
+            args.value_of("output-encoding").unwrap().parse()?,
+            args.is_present("annotate"),
+            args.is_present("stats"),
+            args.value_of("class"),
+            args.value_of("format") == Some("json"),
+        )?;
+        output.finish()?;
+        Ok(())
     }
 }
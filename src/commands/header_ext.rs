@@ -0,0 +1,125 @@
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::pbo::{clamp_timestamp, read_prefix_files};
+use crate::warning::{self, Warning};
+use crate::{aerror, ArmakeError, PBO};
+
+/// Adds the argument for setting header extensions to a command. `-h` is still accepted as its
+/// old name.
+pub(crate) fn header_ext_args<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
+    app.arg(
+        clap::Arg::with_name("header-ext")
+            .help("Header extension to add as key=value, overriding the prefix file. {date}, {timestamp} and {dir} in the value are replaced with the build date, the Unix time and the name of the source folder")
+            .short("e")
+            .long("header-ext")
+            .value_name("key=value")
+            .validator(crate::commands::validate_key_value)
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+    )
+    .arg(
+        clap::Arg::with_name("header")
+            .short("h")
+            .hidden(true)
+            .validator(crate::commands::validate_key_value)
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+    )
+}
+
+/// Returns the values of the arguments added by `header_ext_args`.
+pub(crate) fn header_ext_values<'a>(args: &'a clap::ArgMatches) -> Vec<&'a str> {
+    args.values_of("header")
+        .into_iter()
+        .flatten()
+        .chain(args.values_of("header-ext").into_iter().flatten())
+        .collect()
+}
+
+/// Sets the header extensions given as `key=value` on the PBO built from the folder `source`,
+/// after substituting the placeholders of the value, see `expand_value`. Overriding a value of
+/// the prefix file of `source` is warned about.
+///
+/// `timestamp` is the time of the build if set, otherwise the current time clamped to
+/// `SOURCE_DATE_EPOCH`.
+pub(crate) fn apply_header_ext(
+    pbo: &mut PBO,
+    values: &[&str],
+    source: Option<&Path>,
+    timestamp: Option<u32>,
+) -> Result<(), ArmakeError> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    let timestamp = timestamp.unwrap_or_else(|| {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .ok();
+        clamp_timestamp(now)
+    });
+    let from_file = source.map(read_prefix_files).transpose()?.flatten();
+
+    for value in values {
+        let (key, value) = value.split_at(value.find('=').unwrap());
+        let key = key.trim();
+        let value = expand_value(value[1..].trim(), source, timestamp)?;
+
+        if let Some((path, extensions)) = &from_file {
+            if let Some((_, old)) = extensions
+                .iter()
+                .find(|(k, v)| k.eq_ignore_ascii_case(key) && *v != value)
+            {
+                warning::raise(
+                    Warning::new(
+                        "overridden-header-extension",
+                        format!("{}={} is overridden with {}={}", key, old, key, value),
+                    )
+                    .in_file(path.display().to_string()),
+                );
+            }
+        }
+        pbo.set_header_extension(key, &value);
+    }
+    Ok(())
+}
+
+/// Replaces `{date}` with the UTC date of `timestamp`, `{timestamp}` with `timestamp` and `{dir}`
+/// with the name of the folder `source` in a header extension value.
+fn expand_value(value: &str, source: Option<&Path>, timestamp: u32) -> Result<String, ArmakeError> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = start
+            + rest[start..].find('}').ok_or_else(|| {
+                aerror!("Unclosed {{ in the header extension value \"{}\"", value)
+            })?;
+        match &rest[start + 1..end] {
+            "date" => expanded.push_str(
+                &time::OffsetDateTime::from_unix_timestamp(i64::from(timestamp)).format("%Y-%m-%d"),
+            ),
+            "timestamp" => expanded.push_str(&timestamp.to_string()),
+            "dir" => {
+                let name = source
+                    .and_then(|s| s.canonicalize().ok())
+                    .and_then(|s| s.file_name().map(|n| n.to_string_lossy().into_owned()))
+                    .ok_or_else(|| aerror!("{{dir}} needs a source folder"))?;
+                expanded.push_str(&name);
+            }
+            name => {
+                return Err(aerror!(
+                    "Unknown placeholder {{{}}} in the header extension value \"{}\", expected {{date}}, {{timestamp}} or {{dir}}",
+                    name,
+                    value
+                ))
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
@@ -1,11 +1,94 @@
-use std::io::Read;
+use std::io::{Read, Seek};
 
-use crate::{ArmakeError, Command, PBO};
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+
+use crate::commands::{format_size, format_timestamp};
+use crate::pbo::{matches_glob, ChecksumStatus, PBOHeader};
+use crate::{aerror, ArmakeError, Command, PBOIndex};
+
+/// PBO as printed by `inspect --json`
+#[derive(Serialize)]
+struct InspectJson<'a> {
+    /// Header extensions in stored order
+    #[serde(serialize_with = "ordered_map")]
+    header_extensions: Vec<(&'a str, &'a str)>,
+    files: Vec<FileJson<'a>>,
+    garbage: Vec<GarbageJson>,
+    /// Stored checksum as hex
+    checksum: Option<String>,
+    checksum_status: &'static str,
+    checksum_valid: bool,
+}
+
+#[derive(Serialize)]
+struct FileJson<'a> {
+    name: &'a str,
+    packing_method: u32,
+    original_size: u32,
+    data_size: u32,
+    timestamp: u32,
+}
+
+#[derive(Serialize)]
+struct GarbageJson {
+    name: String,
+    data_size: u32,
+    reason: String,
+}
+
+fn ordered_map<S: Serializer>(pairs: &[(&str, &str)], serializer: S) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(pairs.len()))?;
+    for (key, value) in pairs {
+        map.serialize_entry(key, value)?;
+    }
+    map.end()
+}
+
+/// Formats a packing method for the Method column: 0 for uncompressed entries, the FourCC like
+/// `Cprs` if it is printable and hex otherwise.
+fn format_method(method: u32) -> String {
+    let fourcc = method.to_be_bytes();
+    if method == 0 {
+        String::from("0")
+    } else if fourcc.iter().all(|b| b.is_ascii_graphic()) {
+        String::from_utf8_lossy(&fourcc).to_string()
+    } else {
+        format!("0x{:08x}", method)
+    }
+}
 
 pub struct Inspect {}
 impl Inspect {
-    fn cmd_inspect<I: Read>(input: &mut I) -> Result<(), ArmakeError> {
-        let pbo = PBO::read(input)?;
+    /// Prints the header extensions and files of a PBO, as JSON with `json`. If `filters` are
+    /// given, only files matching any of them (case-insensitively) are listed. The data of the
+    /// entries is only read for the checksum, see `PBOIndex`.
+    fn cmd_inspect<I: Read + Seek>(
+        input: &mut I,
+        filters: &[&str],
+        json: bool,
+    ) -> Result<(), ArmakeError> {
+        let pbo = PBOIndex::read(input)?;
+        let filters: Vec<String> = filters.iter().map(|f| f.to_lowercase()).collect();
+        let headers: Vec<_> = pbo
+            .headers()
+            .filter(|h| {
+                filters.is_empty()
+                    || filters
+                        .iter()
+                        .any(|f| matches_glob(&h.filename.to_lowercase(), f))
+            })
+            .collect();
+        for header in headers.iter().filter(|h| h.has_unknown_method()) {
+            warn!(
+                "{} has the unknown packing method 0x{:08x}, its data can only be copied as it is",
+                header.filename, header.packing_method
+            );
+        }
+
+        if json {
+            return Inspect::print_json(input, &pbo, &headers);
+        }
 
         if !pbo.header_extensions.is_empty() {
             println!("Header extensions:");
@@ -15,41 +98,141 @@ impl Inspect {
             println!();
         }
 
-        println!("# Files: {}\n", pbo.files.len());
+        println!("Checksum: {}\n", pbo.checksum_status(input)?);
+
+        if !pbo.garbage.is_empty() {
+            println!("Garbage entries, left out of the files below:");
+            for entry in pbo.garbage.iter() {
+                println!(
+                    "- {:?} ({} bytes): {}",
+                    entry.header.filename, entry.header.data_size, entry.reason
+                );
+            }
+            println!();
+        }
+
+        if filters.is_empty() {
+            println!("# Files: {}\n", pbo.entries.len());
+        } else {
+            let shown: u64 = headers.iter().map(|h| u64::from(h.data_size)).sum();
+            let total: u64 = pbo.headers().map(|h| u64::from(h.data_size)).sum();
+            println!(
+                "# Files: showing {} of {} entries, {} of {}\n",
+                headers.len(),
+                pbo.entries.len(),
+                format_size(shown),
+                format_size(total)
+            );
+        }
 
         println!(
-            "Path                                                  Method  Original    Packed"
+            "Path                                                   Method  Original    Packed  Timestamp (UTC)"
         );
         println!(
-            "                                                                  Size      Size"
+            "                                                                   Size      Size"
         );
         println!(
-            "================================================================================"
+            "======================================================================================================"
         );
-        for header in pbo.headers {
+        for header in headers {
             println!(
-                "{:50} {:9} {:9} {:9}",
-                header.filename, header.packing_method, header.original_size, header.data_size
+                "{:50} {:>10} {:9} {:9}  {}",
+                header.filename,
+                format_method(header.packing_method),
+                header.original_size,
+                header.data_size,
+                format_timestamp(header.timestamp)
             );
         }
 
         Ok(())
     }
+
+    fn print_json<I: Read + Seek>(
+        input: &mut I,
+        pbo: &PBOIndex,
+        headers: &[&PBOHeader],
+    ) -> Result<(), ArmakeError> {
+        let (checksum, status) = pbo.checksum(input)?;
+        let report = InspectJson {
+            header_extensions: pbo
+                .header_extensions
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect(),
+            files: headers
+                .iter()
+                .map(|h| FileJson {
+                    name: &h.filename,
+                    packing_method: h.packing_method,
+                    original_size: h.original_size,
+                    data_size: h.data_size,
+                    timestamp: h.timestamp,
+                })
+                .collect(),
+            garbage: pbo
+                .garbage
+                .iter()
+                .map(|g| GarbageJson {
+                    name: g.header.filename.clone(),
+                    data_size: g.header.data_size,
+                    reason: g.reason.to_string(),
+                })
+                .collect(),
+            checksum: checksum.map(|c| c.iter().map(|b| format!("{:02x}", b)).collect()),
+            checksum_status: match status {
+                ChecksumStatus::Valid => "valid",
+                ChecksumStatus::Mismatch => "mismatch",
+                ChecksumStatus::Placeholder => "placeholder",
+                ChecksumStatus::Missing => "missing",
+            },
+            checksum_valid: status == ChecksumStatus::Valid,
+        };
+        serde_json::to_writer_pretty(std::io::stdout(), &report)
+            .map_err(|e| aerror!("Failed to write JSON: {}", e))?;
+        println!();
+        Ok(())
+    }
 }
 
 impl Command for Inspect {
-    fn register(&self) -> clap::App {
+    fn register(&self) -> clap::App<'_, '_> {
         clap::SubCommand::with_name("inspect")
             .about("Inspect a PBO and list contained files")
+            .after_help(
+                "With --json, a single object is printed with the fields header_extensions (object \
+                 in stored order), files (array of objects with name, packing_method, \
+                 original_size, data_size and timestamp as Unix time), garbage (entries left out \
+                 of files, with name, data_size and reason), checksum (stored SHA1 as hex, null if \
+                 missing), checksum_status (valid, mismatch, placeholder or missing) and \
+                 checksum_valid. --filter applies to files.",
+            )
             .arg(
                 clap::Arg::with_name("source")
                     .help("Source file")
                     .required(true),
             )
+            .arg(
+                clap::Arg::with_name("filter")
+                    .help("Only list files matching the glob, can be given multiple times")
+                    .long("filter")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("json")
+                    .help("Print the PBO as JSON, see below for the fields")
+                    .long("json"),
+            )
     }
 
     fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
         let mut input = crate::get_input(args.value_of("source"))?;
-        Inspect::cmd_inspect(&mut input)
+        let filters: Vec<&str> = args
+            .values_of("filter")
+            .map(|v| v.collect())
+            .unwrap_or_default();
+        Inspect::cmd_inspect(&mut input, &filters, args.is_present("json"))
     }
 }
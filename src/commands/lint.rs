@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use crate::lint::{lint_cfgpatches, locate, read_config_file, read_known_addons_all, report};
+use crate::{aerror, ArmakeError, Command};
+
+pub struct Lint {}
+impl Lint {
+    /// Reads the config at `input` (rapified or not) and checks it for common mistakes.
+    ///
+    /// `known_addons` are files listing addons that may appear in `requiredAddons`, see
+    /// `lint::read_known_addons`. Findings in textual configs are reported with their file and
+    /// line. Fails if any unsuppressed lint was found.
+    fn cmd_lint(
+        input: PathBuf,
+        includefolders: &[PathBuf],
+        known_addons: &[PathBuf],
+        suppressed: &[&str],
+    ) -> Result<(), ArmakeError> {
+        let (config, map) = read_config_file(&input, includefolders)?;
+
+        let known = read_known_addons_all(known_addons)?;
+
+        let mut lints = lint_cfgpatches(&config, known.as_ref());
+        if let Some(map) = &map {
+            locate(&mut lints, map);
+        }
+        let count = report(&lints, &input.display().to_string(), suppressed);
+
+        if count > 0 {
+            return Err(aerror!("Found {} problem(s).", count));
+        }
+
+        Ok(())
+    }
+}
+
+impl Command for Lint {
+    fn register(&self) -> clap::App<'_, '_> {
+        clap::SubCommand::with_name("lint")
+            .about("Check a config for common mistakes")
+            .arg(
+                clap::Arg::with_name("source")
+                    .help("Source file")
+                    .validator(crate::commands::validate_file)
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("include")
+                    .help("Include folder")
+                    .validator(crate::commands::validate_dir)
+                    .short("i")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("known-addons")
+                    .help("Config or list of addon names that may be required")
+                    .validator(crate::commands::validate_file)
+                    .long("known-addons")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("warning")
+                    .help("Warning to suppress")
+                    .short("w")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("max-errors")
+                    .help("Number of syntax errors to report before giving up, defaults to 20")
+                    .long("max-errors")
+                    .validator(crate::commands::validate_positive)
+                    .takes_value(true),
+            )
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        if let Some(max) = args.value_of("max-errors") {
+            crate::config::set_max_errors(max.parse().unwrap());
+        }
+        let input = args.value_of("source").unwrap();
+        let includes: Vec<PathBuf> = args
+            .values_of("include")
+            .map(|v| v.map(PathBuf::from).collect())
+            .unwrap_or_default();
+        let known_addons: Vec<PathBuf> = args
+            .values_of("known-addons")
+            .map(|v| v.map(PathBuf::from).collect())
+            .unwrap_or_default();
+        let suppressed: Vec<&str> = args
+            .values_of("warning")
+            .map(|v| v.collect())
+            .unwrap_or_default();
+        Lint::cmd_lint(PathBuf::from(input), &includes, &known_addons, &suppressed)
+    }
+}
@@ -1,8 +1,12 @@
-use crate::ArmakeError;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use crate::pbo::ChecksumStatus;
+use crate::{aerror, ArmakeError, PBOIndex};
 
 pub trait Command {
     // (name, description)
-    fn register(&self) -> clap::App;
+    fn register(&self) -> clap::App<'_, '_>;
 
     fn run(&self, _args: &clap::ArgMatches) -> Result<(), ArmakeError> {
         unimplemented!();
@@ -24,6 +28,9 @@ pub use pack::Pack;
 mod build;
 pub use build::Build;
 
+mod build_mod;
+pub use build_mod::BuildMod;
+
 mod binarize;
 pub use binarize::Binarize;
 
@@ -33,5 +40,218 @@ pub use rapify::Rapify;
 mod derapify;
 pub use derapify::Derapify;
 
+mod deps;
+
+mod header_ext;
+
 mod preprocess;
 pub use preprocess::Preprocess;
+
+mod lint;
+pub use lint::Lint;
+
+mod config;
+pub use config::Config;
+
+mod stringtable;
+pub use stringtable::Stringtable;
+
+mod rename;
+pub use rename::Rename;
+
+mod patch;
+pub use patch::Patch;
+
+#[cfg(feature = "signing")]
+mod report;
+#[cfg(feature = "signing")]
+pub use report::Report;
+
+mod verify_manifest;
+pub use verify_manifest::VerifyManifest;
+
+#[cfg(feature = "signing")]
+pub mod signing;
+
+/// Returns all available commands.
+pub fn commands() -> Vec<Box<dyn Command>> {
+    #[cfg_attr(not(feature = "signing"), allow(unused_mut))]
+    let mut commands: Vec<Box<dyn Command>> = vec![
+        Box::new(Inspect {}),
+        Box::new(Cat {}),
+        Box::new(Binarize {}),
+        Box::new(Rapify {}),
+        Box::new(Preprocess {}),
+        Box::new(Derapify {}),
+        Box::new(Pack {}),
+        Box::new(Unpack {}),
+        Box::new(Build {}),
+        Box::new(BuildMod {}),
+        Box::new(Lint {}),
+        Box::new(Config {}),
+        Box::new(Stringtable {}),
+        Box::new(Rename {}),
+        Box::new(Patch {}),
+        Box::new(VerifyManifest {}),
+    ];
+
+    #[cfg(feature = "signing")]
+    {
+        commands.push(Box::new(signing::Keygen {}));
+        commands.push(Box::new(signing::Sign {}));
+        commands.push(Box::new(signing::Key {}));
+        commands.push(Box::new(signing::Verify {}));
+        commands.push(Box::new(signing::VerifyFolder {}));
+        commands.push(Box::new(Report {}));
+    }
+
+    commands
+}
+
+/// Constructs the armake2 app with the given commands as subcommands.
+pub fn app<'a>(commands: &'a [Box<dyn Command>]) -> clap::App<'a, 'a> {
+    let mut app = clap::App::new("armake2")
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about(env!("CARGO_PKG_DESCRIPTION"))
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .setting(clap::AppSettings::VersionlessSubcommands)
+        .arg(
+            clap::Arg::with_name("verbose")
+                .help(
+                    "Also log informational messages, like the rule applied to each file by build",
+                )
+                .short("v")
+                .long("verbose")
+                .global(true),
+        )
+        .arg(
+            clap::Arg::with_name("force")
+                .help("Overwrite existing output files, or unpack into a folder that isn't empty")
+                .short("f")
+                .long("force")
+                .global(true),
+        );
+
+    for command in commands {
+        app = app.subcommand(command.register());
+    }
+
+    app
+}
+
+/// Validates a `key=value` argument.
+pub(crate) fn validate_key_value(value: String) -> Result<(), String> {
+    match value.find('=') {
+        Some(i) if i > 0 => Ok(()),
+        _ => Err(format!("\"{}\" is not of the form key=value", value)),
+    }
+}
+
+/// Validates that an argument is an existing directory.
+pub(crate) fn validate_dir(value: String) -> Result<(), String> {
+    if Path::new(&value).is_dir() {
+        Ok(())
+    } else {
+        Err(format!("\"{}\" is not a directory", value))
+    }
+}
+
+/// Validates that an argument is an existing file or directory.
+#[cfg(feature = "signing")]
+pub(crate) fn validate_exists(value: String) -> Result<(), String> {
+    if Path::new(&value).exists() {
+        Ok(())
+    } else {
+        Err(format!("\"{}\" doesn't exist", value))
+    }
+}
+
+/// Validates that an argument is an existing file.
+pub(crate) fn validate_file(value: String) -> Result<(), String> {
+    if Path::new(&value).is_file() {
+        Ok(())
+    } else {
+        Err(format!("\"{}\" is not a file", value))
+    }
+}
+
+/// Validates that an argument is a positive number.
+pub(crate) fn validate_positive(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("\"{}\" is not a positive number", value)),
+    }
+}
+
+/// Validates that an argument is a Unix timestamp that fits a PBO header.
+pub(crate) fn validate_timestamp(value: String) -> Result<(), String> {
+    match value.parse::<u32>() {
+        Ok(_) => Ok(()),
+        _ => Err(format!("\"{}\" is not a Unix timestamp", value)),
+    }
+}
+
+/// Warns if the checksum of the PBO read from `source` doesn't match its content or is missing,
+/// fails instead with `strict`.
+pub(crate) fn check_checksum(
+    status: ChecksumStatus,
+    source: &str,
+    strict: bool,
+) -> Result<(), ArmakeError> {
+    let message = match status {
+        ChecksumStatus::Mismatch => format!("The checksum of {} doesn't match its content", source),
+        ChecksumStatus::Missing => {
+            format!("{} is truncated, its checksum is missing", source)
+        }
+        _ => return Ok(()),
+    };
+    if strict {
+        return Err(aerror!(message));
+    }
+    warn!("{}", message);
+    Ok(())
+}
+
+/// Checks the checksum of the PBO read from `source` into `index` like `check_checksum`, hashing
+/// the PBO in chunks. With `verify` false the checksum only has to be present, for large PBOs
+/// that are known to be intact.
+pub(crate) fn check_index_checksum<I: Read + Seek>(
+    index: &PBOIndex,
+    input: &mut I,
+    source: &str,
+    strict: bool,
+    verify: bool,
+) -> Result<(), ArmakeError> {
+    if verify {
+        check_checksum(index.checksum_status(input)?, source, strict)
+    } else if !index.has_checksum(input)? {
+        check_checksum(ChecksumStatus::Missing, source, strict)
+    } else {
+        Ok(())
+    }
+}
+
+/// Formats a Unix timestamp as UTC date and time, `-` for 0, which PBOs use for unknown times.
+pub(crate) fn format_timestamp(timestamp: u32) -> String {
+    if timestamp == 0 {
+        return "-".to_string();
+    }
+    time::OffsetDateTime::from_unix_timestamp(i64::from(timestamp)).format("%Y-%m-%d %H:%M:%S")
+}
+
+/// Formats a size in bytes with a binary unit.
+pub(crate) fn format_size(size: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
@@ -1,69 +1,265 @@
-use std::io::Write;
+use std::fs::read;
+use std::io::{stdin, Read, Write};
 use std::path::PathBuf;
 
-use crate::{ArmakeError, Command, PBO};
+use crate::commands::build::exclude_values;
+use crate::commands::header_ext::{apply_header_ext, header_ext_args, header_ext_values};
+use crate::pbo::lzss;
+use crate::{aerror, ArmakeError, Command, PBO};
+
+/// Parses a file list of `internal\name=path/on/disk` entries separated by `delimiter`. Empty
+/// entries are skipped.
+fn parse_file_list(list: &str, delimiter: char) -> Result<Vec<(String, PathBuf)>, ArmakeError> {
+    list.split(delimiter)
+        .enumerate()
+        .filter(|(_, line)| !line.trim_end_matches('\r').is_empty())
+        .map(|(i, line)| {
+            let line = line.trim_end_matches('\r');
+            match line.find('=') {
+                Some(eq) if eq > 0 && eq < line.len() - 1 => {
+                    Ok((line[..eq].to_string(), PathBuf::from(&line[eq + 1..])))
+                }
+                _ => Err(aerror!(
+                    "Line {} of the file list is not of the form internal\\name=path: {:?}",
+                    i + 1,
+                    line
+                )),
+            }
+        })
+        .collect()
+}
 
 pub struct Pack {}
 impl Pack {
+    /// With `compress`, entries are compressed except for those matching one of its patterns.
+    /// With `timestamp`, every entry gets it as timestamp instead of the modification time of its
+    /// file.
+    #[allow(clippy::too_many_arguments)]
     fn cmd_pack<O: Write>(
         input: PathBuf,
         output: &mut O,
         headerext: &[&str],
         excludes: &[&str],
+        strict: bool,
+        checksum: bool,
+        compress: Option<&[&str]>,
+        timestamp: Option<u32>,
+    ) -> Result<(), ArmakeError> {
+        let mut pbo = PBO::from_directory(input.clone(), false, excludes, &Vec::new())?;
+
+        if strict && pbo.is_empty_addon() {
+            return Err(aerror!("Refusing to build an empty addon"));
+        }
+
+        apply_header_ext(&mut pbo, headerext, Some(&input), timestamp)?;
+
+        if let Some(exclude_patterns) = compress {
+            pbo.compress(exclude_patterns)?;
+        }
+
+        if let Some(timestamp) = timestamp {
+            pbo.set_timestamps(timestamp);
+        }
+
+        if checksum {
+            pbo.write(output)?;
+        } else {
+            pbo.write_without_checksum(output, false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Packs exactly the entries of the file list, see `parse_file_list`, in the order they are
+    /// listed. Only `prefix` and `headerext` are added as header extensions. See `cmd_pack` for
+    /// `compress` and `timestamp`.
+    #[allow(clippy::too_many_arguments)]
+    fn cmd_pack_list<O: Write>(
+        list: &str,
+        delimiter: char,
+        output: &mut O,
+        prefix: Option<&str>,
+        headerext: &[&str],
+        rapify_configs: bool,
+        checksum: bool,
+        compress: Option<&[&str]>,
+        timestamp: Option<u32>,
     ) -> Result<(), ArmakeError> {
-        let mut pbo = PBO::from_directory(input, false, excludes, &Vec::new())?;
+        let entries = parse_file_list(list, delimiter)?;
+        let mut pbo = PBO::from_files(&entries, rapify_configs, &[])?;
+
+        if let Some(prefix) = prefix {
+            pbo.set_header_extension("prefix", prefix);
+        }
+        apply_header_ext(&mut pbo, headerext, None, timestamp)?;
 
-        for h in headerext {
-            let (key, value) = (h.split('=').nth(0).unwrap(), h.split('=').nth(1).unwrap());
-            pbo.header_extensions
-                .insert(key.to_string(), value.to_string());
+        if let Some(exclude_patterns) = compress {
+            pbo.compress(exclude_patterns)?;
         }
 
-        pbo.write(output)?;
+        if let Some(timestamp) = timestamp {
+            pbo.set_timestamps(timestamp);
+        }
+
+        if checksum {
+            pbo.write_ordered(output)?;
+        } else {
+            pbo.write_without_checksum(output, true)?;
+        }
 
         Ok(())
     }
 }
 
 impl Command for Pack {
-    fn register(&self) -> clap::App {
-        clap::SubCommand::with_name("pack")
+    fn register(&self) -> clap::App<'_, '_> {
+        let app = clap::SubCommand::with_name("pack")
             .about("Pack a folder into a PBO without any binarization or rapification")
+            .usage(
+                "armake2 pack [FLAGS] [OPTIONS] <source> [target]\n    \
+                 armake2 pack [FLAGS] [OPTIONS] --files-from <list> [target]",
+            )
             .arg(
                 clap::Arg::with_name("source")
                     .help("Source folder")
-                    .required(true),
+                    .required_unless("files-from"),
             )
-            .arg(clap::Arg::with_name("target").help("Location to write file"))
+            .arg(clap::Arg::with_name("target").help("Location to write file"));
+        header_ext_args(app)
             .arg(
-                clap::Arg::with_name("header")
-                    .help("Headers to add into the PBO")
-                    .short("h")
+                clap::Arg::with_name("exclude")
+                    .help("Exclude the files matching the glob, like *.bak or **/.git/*")
+                    .short("x")
                     .multiple(true)
+                    .number_of_values(1)
                     .takes_value(true),
             )
             .arg(
-                clap::Arg::with_name("exclude")
-                    .help("Excluded files patterns")
-                    .short("x")
+                clap::Arg::with_name("no-default-excludes")
+                    .help("Don't exclude version control folders, Thumbs.db, .DS_Store and editor backups by default")
+                    .long("no-default-excludes"),
+            )
+            .arg(
+                clap::Arg::with_name("strict")
+                    .help("Fail instead of warning if there are no files to pack")
+                    .long("strict"),
+            )
+            .arg(
+                clap::Arg::with_name("files-from")
+                    .help(
+                        "Pack exactly the entries listed in the given file (- for stdin) in that \
+                         order, one internal\\name=path per line",
+                    )
+                    .long("files-from")
+                    .takes_value(true)
+                    .conflicts_with_all(&["exclude", "no-default-excludes", "strict"]),
+            )
+            .arg(
+                clap::Arg::with_name("null")
+                    .help("Entries of the file list are separated by NUL instead of newlines")
+                    .short("0")
+                    .requires("files-from"),
+            )
+            .arg(
+                clap::Arg::with_name("prefix")
+                    .help("Prefix of the PBO packed from a file list")
+                    .long("prefix")
+                    .takes_value(true)
+                    .requires("files-from"),
+            )
+            .arg(
+                clap::Arg::with_name("rapify-configs")
+                    .help("Rapify .cpp and .rvmat files of the file list")
+                    .long("rapify-configs")
+                    .requires("files-from"),
+            )
+            .arg(
+                clap::Arg::with_name("no-checksum")
+                    .help("Write a placeholder checksum instead of hashing the PBO, for development builds only. Such PBOs can't be signed")
+                    .long("no-checksum"),
+            )
+            .arg(
+                clap::Arg::with_name("compress")
+                    .help("Compress entries with LZSS, except for formats that are compressed already like *.paa and *.ogg")
+                    .short("z")
+                    .long("compress"),
+            )
+            .arg(
+                clap::Arg::with_name("no-compress")
+                    .help("Don't compress files matching the glob, in addition to the defaults")
+                    .long("no-compress")
                     .multiple(true)
+                    .number_of_values(1)
+                    .takes_value(true)
+                    .requires("compress"),
+            )
+            .arg(
+                clap::Arg::with_name("timestamp")
+                    .help("Unix time stored as the timestamp of every entry instead of the modification times of the files")
+                    .long("timestamp")
+                    .value_name("unix")
+                    .validator(crate::commands::validate_timestamp)
                     .takes_value(true),
             )
     }
 
     fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
-        let input = args.value_of("source").unwrap();
-        let mut output = crate::get_output(args.value_of("target"))?;
-        let headers: Vec<_> = if let Some(values) = args.values_of("header") {
-            values.collect()
+        let headers = header_ext_values(args);
+        let no_compress: Vec<&str> = lzss::DEFAULT_UNCOMPRESSED
+            .iter()
+            .cloned()
+            .chain(args.values_of("no-compress").into_iter().flatten())
+            .collect();
+        let compress = if args.is_present("compress") {
+            Some(no_compress.as_slice())
         } else {
-            Vec::new()
+            None
         };
-        let excludes: Vec<_> = if let Some(values) = args.values_of("exclude") {
-            values.collect()
-        } else {
-            Vec::new()
-        };
-        Pack::cmd_pack(PathBuf::from(input), &mut output, &headers, &excludes)
+        let timestamp = args.value_of("timestamp").map(|t| t.parse().unwrap());
+
+        if let Some(list_path) = args.value_of("files-from") {
+            // the only positional argument is the target
+            if args.is_present("target") {
+                return Err(aerror!("pack --files-from takes no source folder"));
+            }
+            let list = if list_path == "-" {
+                let mut list = String::new();
+                stdin().read_to_string(&mut list)?;
+                list
+            } else {
+                String::from_utf8(read(list_path)?)
+                    .map_err(|_| aerror!("{} is not valid UTF-8", list_path))?
+            };
+            let mut output = crate::get_output(args.value_of("source"), args.is_present("force"))?;
+            Pack::cmd_pack_list(
+                &list,
+                if args.is_present("null") { '\0' } else { '\n' },
+                &mut output,
+                args.value_of("prefix"),
+                &headers,
+                args.is_present("rapify-configs"),
+                !args.is_present("no-checksum"),
+                compress,
+                timestamp,
+            )?;
+            return Ok(output.finish()?);
+        }
+
+        let input = args.value_of("source").unwrap();
+        crate::commands::validate_dir(input.to_string()).map_err(|e| aerror!(e))?;
+        let excludes = exclude_values(args);
+        crate::check_target_outside(input, args.value_of("target"), &excludes)?;
+        let mut output = crate::get_output(args.value_of("target"), args.is_present("force"))?;
+        Pack::cmd_pack(
+            PathBuf::from(input),
+            &mut output,
+            &headers,
+            &excludes,
+            args.is_present("strict"),
+            !args.is_present("no-checksum"),
+            compress,
+            timestamp,
+        )?;
+        Ok(output.finish()?)
     }
 }
@@ -0,0 +1,98 @@
+use std::fs::{read, write, File};
+use std::path::Path;
+
+use crate::pbo::patch::PBOPatch;
+use crate::{ArmakeError, Command};
+
+pub struct Patch {}
+impl Patch {
+    /// Writes a patch from the PBO `old` to the PBO `new`. An existing output is only overwritten
+    /// with `force`.
+    fn cmd_create(old: &Path, new: &Path, output: &Path, force: bool) -> Result<(), ArmakeError> {
+        crate::check_overwrite(output, force)?;
+        let patch = PBOPatch::create(&read(old)?, &read(new)?)?;
+        patch.write(&mut File::create(output)?)?;
+        Ok(())
+    }
+
+    /// Applies a patch to the PBO `old`, writing the patched PBO to `output`. An existing output
+    /// other than `old` is only overwritten with `force`.
+    fn cmd_apply(old: &Path, patch: &Path, output: &Path, force: bool) -> Result<(), ArmakeError> {
+        if !crate::is_same_file(old, output) {
+            crate::check_overwrite(output, force)?;
+        }
+        let patch = PBOPatch::read(&mut File::open(patch)?)?;
+        // patch in memory first, output may be the input
+        let patched = patch.apply(&read(old)?)?;
+        write(output, patched)?;
+        Ok(())
+    }
+}
+
+impl Command for Patch {
+    fn register(&self) -> clap::App<'_, '_> {
+        clap::SubCommand::with_name("pbo-patch")
+            .about("Create and apply binary patches between PBOs")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("create")
+                    .about("Create a patch from one version of a PBO to another")
+                    .arg(
+                        clap::Arg::with_name("old")
+                            .help("PBO to patch")
+                            .validator(crate::commands::validate_file)
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("new")
+                            .help("PBO the patch produces")
+                            .validator(crate::commands::validate_file)
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("patch")
+                            .help("Location to write the patch")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("apply")
+                    .about("Apply a patch to the PBO it was created from")
+                    .arg(
+                        clap::Arg::with_name("old")
+                            .help("PBO to patch")
+                            .validator(crate::commands::validate_file)
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("patch")
+                            .help("Patch to apply")
+                            .validator(crate::commands::validate_file)
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("target")
+                            .help("Location to write the patched PBO")
+                            .required(true),
+                    ),
+            )
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        match args.subcommand() {
+            ("create", Some(args)) => Patch::cmd_create(
+                Path::new(args.value_of("old").unwrap()),
+                Path::new(args.value_of("new").unwrap()),
+                Path::new(args.value_of("patch").unwrap()),
+                args.is_present("force"),
+            ),
+            ("apply", Some(args)) => Patch::cmd_apply(
+                Path::new(args.value_of("old").unwrap()),
+                Path::new(args.value_of("patch").unwrap()),
+                Path::new(args.value_of("target").unwrap()),
+                args.is_present("force"),
+            ),
+            _ => unreachable!(),
+        }
+    }
+}
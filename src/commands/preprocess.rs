@@ -1,59 +1,296 @@
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{stderr, Read, Write};
 use std::path::PathBuf;
 
-use crate::preprocess::preprocess;
-use crate::{ArmakeError, Command};
+use crate::commands::deps::{check_deps_args, deps_args, write_deps};
+use crate::preprocess::{
+    compare_normalized, normalize, preprocess_with_options, Difference, IncludeReader,
+    PreprocessInfo, PreprocessOptions,
+};
+use crate::{aerror, ArmakeError, Command, Encoding};
+
+/// Number of differences reported by `--compare` unless `--max-differences` is given
+const DEFAULT_MAX_DIFFERENCES: usize = 10;
+
+/// How the preprocessed output is handled
+pub enum OutputMode<'a> {
+    /// Written as it is
+    Raw,
+    /// Written in normalized form, see `preprocess::normalize`
+    Normalized,
+    /// Compared in normalized form with the contents of another file, reporting at most the
+    /// given number of differences instead of writing the output
+    Compare(&'a str, usize),
+}
 
 pub struct Preprocess {}
 impl Preprocess {
+    /// Reads input, preprocesses it and writes to output.
+    ///
+    /// If `macros_out` is given, all macros defined at the end of preprocessing are written to it,
+    /// even if preprocessing failed. Expansions of the macros in `options.trace_macros` are logged
+    /// to stderr. When comparing, the differences are written to output and the command fails if
+    /// there are any. The input and every file it included are added to `dependencies`.
+    #[allow(clippy::too_many_arguments)]
     pub fn cmd_preprocess<I: Read, O: Write>(
         input: &mut I,
         output: &mut O,
         path: Option<PathBuf>,
         includefolders: &[PathBuf],
+        options: &PreprocessOptions,
+        macros_out: Option<&mut dyn Write>,
+        mode: OutputMode,
+        dependencies: &mut Vec<PathBuf>,
     ) -> Result<(), ArmakeError> {
         let mut buffer = String::new();
         input.read_to_string(&mut buffer)?;
 
-        let (result, _) = preprocess(buffer, path, includefolders, |path| {
-            let mut content = String::new();
-            File::open(path)
-                .unwrap()
-                .read_to_string(&mut content)
-                .unwrap();
-            content
-        })?;
+        let mut info = PreprocessInfo::default();
+        let reader = IncludeReader::new(Encoding::Utf8);
+        dependencies.extend(path.clone());
+        let result = reader.finish(preprocess_with_options(
+            buffer,
+            path,
+            includefolders,
+            |path| reader.read(path),
+            options,
+            &mut info,
+        ));
+
+        for trace in &info.macro_traces {
+            eprintln!("{}", trace);
+        }
+
+        if let Some(macros_out) = macros_out {
+            Preprocess::write_macros(&info, macros_out)?;
+        }
 
-        output.write_all(result.as_bytes())?;
+        let result = result?;
+        dependencies.extend(reader.included());
+        match mode {
+            OutputMode::Raw => output.write_all(result.as_bytes())?,
+            OutputMode::Normalized => output.write_all(normalize(&result).as_bytes())?,
+            OutputMode::Compare(other, max) => {
+                let differences = compare_normalized(&result, other, max);
+                for difference in differences.iter() {
+                    Preprocess::write_difference(difference, output)?;
+                }
+                if !differences.is_empty() {
+                    return Err(aerror!(
+                        "Normalized outputs differ{}",
+                        if differences.len() == max {
+                            format!(", showing the first {} differences", max)
+                        } else {
+                            String::new()
+                        }
+                    ));
+                }
+            }
+        }
 
         Ok(())
     }
+
+    /// Writes a difference in the style of a unified diff, with the left side being the output.
+    fn write_difference<O: Write>(
+        difference: &Difference,
+        output: &mut O,
+    ) -> Result<(), ArmakeError> {
+        writeln!(
+            output,
+            "@@ output line {}, other line {} @@",
+            difference.left_line, difference.right_line
+        )?;
+        for line in difference.before.iter() {
+            writeln!(output, " {}", line)?;
+        }
+        for line in difference.left.iter() {
+            writeln!(output, "-{}", line)?;
+        }
+        for line in difference.right.iter() {
+            writeln!(output, "+{}", line)?;
+        }
+        for line in difference.after.iter() {
+            writeln!(output, " {}", line)?;
+        }
+        Ok(())
+    }
+
+    fn write_macros(info: &PreprocessInfo, output: &mut dyn Write) -> Result<(), ArmakeError> {
+        for def in info.macros() {
+            let location = match def.location() {
+                Some((Some(path), line)) => format!("{}:{}", path.display(), line),
+                Some((None, line)) => format!("?:{}", line),
+                None => String::from("<built-in>"),
+            };
+            writeln!(output, "{} // {}", def, location)?;
+        }
+        Ok(())
+    }
+}
+
+/// Validates a `NAME` or `NAME=VALUE` macro definition.
+fn validate_define(value: String) -> Result<(), String> {
+    let name = value.split('=').next().unwrap();
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Ok(())
+    } else {
+        Err(format!(
+            "\"{}\" is not of the form NAME or NAME=VALUE",
+            value
+        ))
+    }
+}
+
+/// Adds the arguments controlling the initial macro definitions to a command.
+pub(crate) fn define_args<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
+    app.arg(
+        clap::Arg::with_name("define")
+            .help("Define a macro as NAME or NAME=VALUE (NAME alone defines it as 1)")
+            .short("D")
+            .validator(validate_define)
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+    )
+    .arg(
+        clap::Arg::with_name("undef")
+            .help("Don't predefine the given macro, like __ARMA3__")
+            .long("undef")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+    )
+    .arg(
+        clap::Arg::with_name("debug-defines")
+            .help("Also predefine the macros of debug builds, like __A3_DEBUG__")
+            .long("debug-defines"),
+    )
+}
+
+/// Applies the arguments added by `define_args` to the options.
+pub(crate) fn apply_define_args(args: &clap::ArgMatches, options: &mut PreprocessOptions) {
+    options.defines = args
+        .values_of("define")
+        .map(|v| {
+            v.map(|d| match d.find('=') {
+                Some(i) => (d[..i].to_string(), d[i + 1..].to_string()),
+                None => (d.to_string(), String::from("1")),
+            })
+            .collect()
+        })
+        .unwrap_or_default();
+    options.undefines = args
+        .values_of("undef")
+        .map(|v| v.map(|s| s.to_string()).collect())
+        .unwrap_or_default();
+    options.debug_defines = args.is_present("debug-defines");
 }
 
 impl Command for Preprocess {
-    fn register(&self) -> clap::App {
-        clap::SubCommand::with_name("preprocess")
+    fn register(&self) -> clap::App<'_, '_> {
+        let app = clap::SubCommand::with_name("preprocess")
             .about("Preprocess a file")
             .arg(
                 clap::Arg::with_name("source")
-                    .help("Source file")
-                    .required(true),
+                    .help("Source file, read from stdin if omitted"),
             )
-            .arg(clap::Arg::with_name("target").help("Location to write file"))
+            .arg(clap::Arg::with_name("target").help("Location to write file, stdout if omitted"))
             .arg(
                 clap::Arg::with_name("include")
                     .help("Include folder")
+                    .validator(crate::commands::validate_dir)
                     .short("i")
                     .multiple(true)
+                    .number_of_values(1)
                     .takes_value(true),
             )
+            .arg(
+                clap::Arg::with_name("keep-comments")
+                    .help("Keep comments in the output")
+                    .long("keep-comments"),
+            )
+            .arg(
+                clap::Arg::with_name("list-macros")
+                    .help("List all macros defined at the end of the input on stderr")
+                    .long("list-macros"),
+            )
+            .arg(
+                clap::Arg::with_name("macros-out")
+                    .help("Write the list of defined macros to the given file instead of stderr")
+                    .long("macros-out")
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("trace-macro")
+                    .help("Log every expansion of the given macro to stderr")
+                    .long("trace-macro")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("warning")
+                    .help("Warning to suppress, all to suppress every warning")
+                    .short("w")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("strict-conditions")
+                    .help("Fail if an #if or #elif condition uses an undefined identifier")
+                    .long("strict-conditions"),
+            )
+            .arg(
+                clap::Arg::with_name("max-include-depth")
+                    .help("Number of files that may be included in each other, defaults to 64")
+                    .long("max-include-depth")
+                    .validator(crate::commands::validate_positive)
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("normalize")
+                    .help("Collapse whitespace and drop blank lines in the output, for comparing it with other preprocessors")
+                    .long("normalize"),
+            )
+            .arg(
+                clap::Arg::with_name("compare")
+                    .help("Compare the normalized output with the normalized contents of this file and print the differences")
+                    .long("compare")
+                    .validator(crate::commands::validate_file)
+                    .conflicts_with("normalize")
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("max-differences")
+                    .help("Number of differences to print with --compare, defaults to 10")
+                    .long("max-differences")
+                    .validator(crate::commands::validate_positive)
+                    .requires("compare")
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("in-place")
+                    .help("Allow the target to be the source file, which is replaced after a successful run")
+                    .long("in-place"),
+            );
+        deps_args(define_args(app))
     }
 
     fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        if let Some(warnings) = args.values_of("warning") {
+            crate::warning::suppress_warnings(&warnings.collect::<Vec<_>>());
+        }
+        check_deps_args(args, args.value_of("target"))?;
         let mut input = crate::get_input(args.value_of("source"))?;
-        let mut output = crate::get_output(args.value_of("target"))?;
-        let includes: Vec<_> = if let Some(values) = args.values_of("includes") {
+        let mut output = crate::get_output_for(
+            args.value_of("source"),
+            args.value_of("target"),
+            args.is_present("in-place"),
+            args.is_present("force"),
+        )?;
+        let includes: Vec<_> = if let Some(values) = args.values_of("include") {
             values.collect()
         } else {
             Vec::new()
@@ -61,11 +298,58 @@ impl Command for Preprocess {
         .into_iter()
         .map(PathBuf::from)
         .collect();
+        let mut options = PreprocessOptions {
+            trace_macros: if let Some(values) = args.values_of("trace-macro") {
+                values.map(|s| s.to_string()).collect()
+            } else {
+                Vec::new()
+            },
+            keep_comments: args.is_present("keep-comments"),
+            strict_conditions: args.is_present("strict-conditions"),
+            ..Default::default()
+        };
+        apply_define_args(args, &mut options);
+        if let Some(depth) = args.value_of("max-include-depth") {
+            options.max_include_depth = depth.parse().unwrap();
+        }
+        let mut macros_out: Option<Box<dyn Write>> = if let Some(path) = args.value_of("macros-out")
+        {
+            Some(Box::new(File::create(path)?))
+        } else if args.is_present("list-macros") {
+            Some(Box::new(stderr()))
+        } else {
+            None
+        };
+        let other = match args.value_of("compare") {
+            Some(path) => {
+                let mut content = String::new();
+                File::open(path)?.read_to_string(&mut content)?;
+                Some(content)
+            }
+            None => None,
+        };
+        let mode = match &other {
+            Some(other) => OutputMode::Compare(
+                other,
+                args.value_of("max-differences")
+                    .map(|m| m.parse().unwrap())
+                    .unwrap_or(DEFAULT_MAX_DIFFERENCES),
+            ),
+            None if args.is_present("normalize") => OutputMode::Normalized,
+            None => OutputMode::Raw,
+        };
+        let mut dependencies = Vec::new();
         Preprocess::cmd_preprocess(
             &mut input,
             &mut output,
-            Some(PathBuf::from(args.value_of("source").unwrap())),
+            args.value_of("source").map(PathBuf::from),
             &includes,
-        )
+            &options,
+            macros_out.as_mut().map(|w| w.as_mut() as &mut dyn Write),
+            mode,
+            &mut dependencies,
+        )?;
+        output.finish()?;
+        write_deps(args, args.value_of("target"), &dependencies)
     }
 }
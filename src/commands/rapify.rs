@@ -1,8 +1,11 @@
 use std::fs::File;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
 
-use crate::{ArmakeError, Command, Config};
+use crate::commands::deps::{check_deps_args, deps_args, write_deps};
+use crate::commands::preprocess::{apply_define_args, define_args};
+use crate::preprocess::{IncludeReader, PreprocessOptions};
+use crate::{aerror, ArmakeError, Command, Config, Encoding, SourceMap};
 
 pub struct Rapify {}
 impl Rapify {
@@ -10,51 +13,147 @@ impl Rapify {
     ///
     /// `path` is the path to the input if it is known and is used for relative includes and error
     /// messages. `includefolders` are the folders searched for absolute includes and should usually at
-    /// least include the current working directory.
+    /// least include the current working directory. If `preprocess` is false, the input is parsed
+    /// as-is without handling any includes or macros, otherwise it is preprocessed with `options`.
+    /// The input and all included files are decoded using `encoding`. If `source_map` is given,
+    /// the source map of the config is written to it, which requires preprocessing. The input and
+    /// every file it included are added to `dependencies`.
+    #[allow(clippy::too_many_arguments)]
     fn cmd_rapify<I: Read, O: Write>(
         input: &mut I,
         output: &mut O,
         path: Option<PathBuf>,
         includefolders: &[PathBuf],
+        preprocess: Option<&PreprocessOptions>,
+        encoding: Encoding,
+        source_map: Option<&Path>,
+        dependencies: &mut Vec<PathBuf>,
     ) -> Result<(), ArmakeError> {
-        let config = Config::read(input, path, includefolders, |path| {
-            let mut content = String::new();
-            File::open(path)
-                .unwrap()
-                .read_to_string(&mut content)
-                .unwrap();
-            content
-        })?;
+        let mut buffer = Vec::new();
+        input.read_to_end(&mut buffer)?;
+        let mut input = Cursor::new(encoding.decode(&buffer)?.into_bytes());
+
+        let reader = IncludeReader::new(encoding);
+        dependencies.extend(path.clone());
+        let fileread = |path: &PathBuf| reader.read(path);
+
+        let result = match (preprocess, source_map) {
+            (Some(options), Some(_)) => {
+                Config::read_with_source_map(&mut input, path, includefolders, fileread, options)
+                    .map(|(config, map)| (config, Some(map)))
+            }
+            (Some(options), None) => {
+                Config::read_with_options(&mut input, path, includefolders, fileread, options)
+                    .map(|config| (config, None))
+            }
+            (None, _) => Config::read_unpreprocessed(&mut input, path).map(|config| (config, None)),
+        };
+        let (config, map): (Config, Option<SourceMap>) = reader.finish(result)?;
+        dependencies.extend(reader.included());
 
         config.write_rapified(output)?;
 
+        if let (Some(map), Some(map_path)) = (map, source_map) {
+            map.write(&mut File::create(map_path)?)?;
+        }
+
         Ok(())
     }
+
+    /// Reads a config in the JSON form of `Config::write_json` from input, rapifies it and
+    /// writes to output.
+    fn cmd_rapify_json<I: Read, O: Write>(
+        input: &mut I,
+        output: &mut O,
+    ) -> Result<(), ArmakeError> {
+        Config::read_json(input)?.write_rapified(output)
+    }
 }
 
 impl Command for Rapify {
-    fn register(&self) -> clap::App {
-        clap::SubCommand::with_name("rapify")
+    fn register(&self) -> clap::App<'_, '_> {
+        let app = clap::SubCommand::with_name("rapify")
             .about("Preprocess and rapify a config file")
             .arg(
                 clap::Arg::with_name("source")
-                    .help("Source file")
-                    .required(true),
+                    .help("Source file, read from stdin if omitted"),
             )
-            .arg(clap::Arg::with_name("target").help("Location to write file"))
+            .arg(clap::Arg::with_name("target").help("Location to write file, stdout if omitted"))
             .arg(
                 clap::Arg::with_name("include")
                     .help("Include folder")
+                    .validator(crate::commands::validate_dir)
                     .short("i")
                     .multiple(true)
+                    .number_of_values(1)
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("format")
+                    .help("Format of the input, json for the JSON written by derapify --format json, which is not preprocessed")
+                    .long("format")
+                    .takes_value(true)
+                    .possible_values(&["config", "json"]),
+            )
+            .arg(
+                clap::Arg::with_name("no-preprocess")
+                    .help("Parse the input as-is without running the preprocessor")
+                    .long("no-preprocess"),
+            )
+            .arg(
+                clap::Arg::with_name("source-map")
+                    .help("Write a source map of the config to this file")
+                    .long("source-map")
+                    .takes_value(true)
+                    .conflicts_with("no-preprocess"),
+            )
+            .arg(
+                clap::Arg::with_name("input-encoding")
+                    .help("Encoding of the input and included files")
+                    .long("input-encoding")
+                    .takes_value(true)
+                    .possible_values(&["utf8", "cp1252"])
+                    .default_value("utf8"),
+            )
+            .arg(
+                clap::Arg::with_name("warning")
+                    .help("Warning to suppress, all to suppress every warning")
+                    .short("w")
+                    .multiple(true)
+                    .number_of_values(1)
                     .takes_value(true),
             )
+            .arg(
+                clap::Arg::with_name("max-errors")
+                    .help("Number of syntax errors to report before giving up, defaults to 20")
+                    .long("max-errors")
+                    .validator(crate::commands::validate_positive)
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("in-place")
+                    .help("Allow the target to be the source file, which is replaced after a successful run")
+                    .long("in-place"),
+            );
+        deps_args(define_args(app))
     }
 
     fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        if let Some(warnings) = args.values_of("warning") {
+            crate::warning::suppress_warnings(&warnings.collect::<Vec<_>>());
+        }
+        if let Some(max) = args.value_of("max-errors") {
+            crate::config::set_max_errors(max.parse().unwrap());
+        }
+        check_deps_args(args, args.value_of("target"))?;
         let mut input = crate::get_input(args.value_of("source"))?;
-        let mut output = crate::get_output(args.value_of("target"))?;
-        let includes: Vec<_> = if let Some(values) = args.values_of("includes") {
+        let mut output = crate::get_output_for(
+            args.value_of("source"),
+            args.value_of("target"),
+            args.is_present("in-place"),
+            args.is_present("force"),
+        )?;
+        let includes: Vec<_> = if let Some(values) = args.values_of("include") {
             values.collect()
         } else {
             Vec::new()
@@ -62,11 +161,33 @@ impl Command for Rapify {
         .into_iter()
         .map(PathBuf::from)
         .collect();
+        let mut options = PreprocessOptions::default();
+        apply_define_args(args, &mut options);
+        let mut dependencies = Vec::new();
+        if args.value_of("format") == Some("json") {
+            if args.is_present("source-map") {
+                return Err(aerror!("JSON input has no source map"));
+            }
+            Rapify::cmd_rapify_json(&mut input, &mut output)?;
+            output.finish()?;
+            dependencies.extend(args.value_of("source").map(PathBuf::from));
+            return write_deps(args, args.value_of("target"), &dependencies);
+        }
         Rapify::cmd_rapify(
             &mut input,
             &mut output,
-            Some(PathBuf::from(args.value_of("source").unwrap())),
+            args.value_of("source").map(PathBuf::from),
             &includes,
-        )
+            if args.is_present("no-preprocess") {
+                None
+            } else {
+                Some(&options)
+            },
+            args.value_of("input-encoding").unwrap().parse()?,
+            args.value_of("source-map").map(Path::new),
+            &mut dependencies,
+        )?;
+        output.finish()?;
+        write_deps(args, args.value_of("target"), &dependencies)
     }
 }
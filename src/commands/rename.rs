@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::{ArmakeError, Command, PBO};
+
+pub struct Rename {}
+impl Rename {
+    /// Renames an entry of the PBO at `input` and writes the result to `output`. An existing
+    /// output other than the input is only overwritten with `force`.
+    fn cmd_rename(
+        input: &Path,
+        output: &Path,
+        old: &str,
+        new: &str,
+        privatekey: Option<&Path>,
+        force: bool,
+    ) -> Result<(), ArmakeError> {
+        if !crate::is_same_file(input, output) {
+            crate::check_overwrite(output, force)?;
+        }
+        let mut pbo = PBO::read(&mut File::open(input)?)?;
+        pbo.rename(old, new)?;
+
+        // write to memory first, output may be the input
+        let cursor = pbo.to_cursor()?;
+        std::fs::write(output, cursor.get_ref())?;
+
+        #[cfg(feature = "signing")]
+        {
+            if let Some(privatekey) = privatekey {
+                return crate::commands::signing::Sign::cmd_sign(
+                    privatekey.to_path_buf(),
+                    output.to_path_buf(),
+                    None,
+                    crate::sign::BISignVersion::V3,
+                    false,
+                    // the old signature doesn't match the renamed PBO anymore
+                    true,
+                );
+            }
+        }
+        #[cfg(not(feature = "signing"))]
+        let _ = privatekey;
+
+        warn!(
+            "Renaming changes the PBO, any existing signatures of {} are invalid now",
+            output.display()
+        );
+
+        Ok(())
+    }
+}
+
+impl Command for Rename {
+    fn register(&self) -> clap::App<'_, '_> {
+        let app = clap::SubCommand::with_name("pbo-rename")
+            .about("Rename an entry inside a PBO")
+            .arg(
+                clap::Arg::with_name("source")
+                    .help("PBO to modify")
+                    .validator(crate::commands::validate_file)
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("oldname")
+                    .help("Current name of the entry")
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("newname")
+                    .help("New name of the entry")
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("output")
+                    .help("Location to write the PBO, defaults to modifying it in place")
+                    .short("o")
+                    .takes_value(true),
+            );
+
+        #[cfg(feature = "signing")]
+        let app = app.arg(
+            clap::Arg::with_name("sign")
+                .help("Private key to sign the renamed PBO with")
+                .long("sign")
+                .validator(crate::commands::validate_file)
+                .takes_value(true),
+        );
+
+        app
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        let input = PathBuf::from(args.value_of("source").unwrap());
+        let output = args
+            .value_of("output")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| input.clone());
+        Rename::cmd_rename(
+            &input,
+            &output,
+            args.value_of("oldname").unwrap(),
+            args.value_of("newname").unwrap(),
+            args.value_of("sign").map(Path::new),
+            args.is_present("force"),
+        )
+    }
+}
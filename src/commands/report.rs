@@ -0,0 +1,255 @@
+use std::fs::{read_dir, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::commands::format_size;
+use crate::io::ReadExt;
+use crate::pbo::{ChecksumStatus, PBOIndex};
+use crate::sign::signature_paths;
+use crate::{aerror, ArmakeError, Command};
+
+/// Summary of a PBO in a mod folder
+#[derive(Serialize)]
+struct PBOReport {
+    /// Path relative to the mod folder
+    path: String,
+    prefix: Option<String>,
+    version: Option<String>,
+    files: usize,
+    /// Sum of the original sizes of all entries
+    size: u64,
+    checksum_valid: bool,
+    /// Authorities of the signatures next to the PBO
+    signatures: Vec<String>,
+    /// Set instead of the other fields if the PBO couldn't be read
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Totals {
+    pbos: usize,
+    files: usize,
+    size: u64,
+    invalid_checksums: usize,
+    unsigned: usize,
+    unreadable: usize,
+}
+
+#[derive(Serialize)]
+struct ModReport {
+    pbos: Vec<PBOReport>,
+    /// Authorities of the keys in `keys/`
+    keys: Vec<String>,
+    totals: Totals,
+}
+
+/// Returns the files in `folder` with the given lowercase extension, sorted.
+fn files_with_extension(folder: &Path, extension: &str) -> Result<Vec<PathBuf>, ArmakeError> {
+    if !folder.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut files: Vec<PathBuf> = read_dir(folder)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.is_file()
+                && p.extension()
+                    .map(|e| e.to_string_lossy().eq_ignore_ascii_case(extension))
+                    .unwrap_or(false)
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Reads the authority name at the start of a `.bikey` or `.bisign`.
+fn authority(path: &Path) -> Result<String, ArmakeError> {
+    Ok(File::open(path)?.read_cstring()?)
+}
+
+impl PBOReport {
+    fn read(path: &Path, relative: String) -> PBOReport {
+        let mut report = PBOReport {
+            path: relative,
+            prefix: None,
+            version: None,
+            files: 0,
+            size: 0,
+            checksum_valid: false,
+            signatures: Vec::new(),
+            error: None,
+        };
+
+        // the PBO is hashed in chunks like in inspect and verify, not read into memory
+        let read = (|| -> Result<(PBOIndex, ChecksumStatus), ArmakeError> {
+            let mut input = BufReader::new(File::open(path)?);
+            let index = PBOIndex::read(&mut input)?;
+            let status = index.checksum_status(&mut input)?;
+            Ok((index, status))
+        })();
+        let (index, status) = match read {
+            Ok(result) => result,
+            Err(e) => {
+                report.error = Some(e.to_string());
+                return report;
+            }
+        };
+
+        report.prefix = index.header_extension("prefix").map(String::from);
+        report.version = index.header_extension("version").map(String::from);
+        report.files = index.entries.len();
+        report.size = index
+            .headers()
+            .map(|h| {
+                u64::from(if h.original_size != 0 {
+                    h.original_size
+                } else {
+                    h.data_size
+                })
+            })
+            .sum();
+        report.checksum_valid = status == ChecksumStatus::Valid;
+
+        for signature in signature_paths(path).unwrap_or_default() {
+            match authority(&signature) {
+                Ok(authority) => report.signatures.push(authority),
+                Err(e) => warn!("Failed to read {}: {}", signature.display(), e),
+            }
+        }
+
+        report
+    }
+}
+
+pub struct Report {}
+impl Report {
+    /// Reports on the PBOs in `addons/` and the keys in `keys/` of a mod folder.
+    fn cmd_report(modfolder: &Path, json: bool) -> Result<(), ArmakeError> {
+        let addons = modfolder.join("addons");
+        if !addons.is_dir() {
+            return Err(aerror!("{} has no addons folder", modfolder.display()));
+        }
+
+        let pbos: Vec<PBOReport> = files_with_extension(&addons, "pbo")?
+            .iter()
+            .map(|path| {
+                let relative = path.strip_prefix(modfolder).unwrap();
+                PBOReport::read(path, relative.to_string_lossy().replace('\\', "/"))
+            })
+            .collect();
+
+        let mut keys = Vec::new();
+        for key in files_with_extension(&modfolder.join("keys"), "bikey")? {
+            match authority(&key) {
+                Ok(authority) => keys.push(authority),
+                Err(e) => warn!("Failed to read {}: {}", key.display(), e),
+            }
+        }
+
+        let readable = || pbos.iter().filter(|p| p.error.is_none());
+        let totals = Totals {
+            pbos: pbos.len(),
+            files: readable().map(|p| p.files).sum(),
+            size: readable().map(|p| p.size).sum(),
+            invalid_checksums: readable().filter(|p| !p.checksum_valid).count(),
+            unsigned: readable().filter(|p| p.signatures.is_empty()).count(),
+            unreadable: pbos.len() - readable().count(),
+        };
+        let report = ModReport { pbos, keys, totals };
+
+        if json {
+            serde_json::to_writer_pretty(std::io::stdout(), &report)
+                .map_err(|e| aerror!("Failed to write report: {}", e))?;
+            println!();
+        } else {
+            Report::print(&report);
+        }
+
+        Ok(())
+    }
+
+    fn print(report: &ModReport) {
+        for pbo in report.pbos.iter() {
+            println!("{}", pbo.path);
+            if let Some(error) = &pbo.error {
+                println!("  error:      {}", error);
+                continue;
+            }
+
+            println!("  prefix:     {}", pbo.prefix.as_deref().unwrap_or("-"));
+            if let Some(version) = &pbo.version {
+                println!("  version:    {}", version);
+            }
+            println!("  files:      {} ({})", pbo.files, format_size(pbo.size));
+            println!(
+                "  checksum:   {}",
+                if pbo.checksum_valid {
+                    "valid"
+                } else {
+                    "INVALID"
+                }
+            );
+            if pbo.signatures.is_empty() {
+                println!("  signatures: none");
+            } else {
+                let signatures: Vec<String> = pbo
+                    .signatures
+                    .iter()
+                    .map(|s| {
+                        if report.keys.contains(s) {
+                            s.clone()
+                        } else {
+                            format!("{} (no key in keys/)", s)
+                        }
+                    })
+                    .collect();
+                println!("  signatures: {}", signatures.join(", "));
+            }
+        }
+
+        println!();
+        if report.keys.is_empty() {
+            println!("Keys: none");
+        } else {
+            println!("Keys: {}", report.keys.join(", "));
+        }
+
+        let totals = &report.totals;
+        println!(
+            "Total: {} PBOs, {} files, {}, {} invalid checksums, {} unsigned, {} unreadable",
+            totals.pbos,
+            totals.files,
+            format_size(totals.size),
+            totals.invalid_checksums,
+            totals.unsigned,
+            totals.unreadable
+        );
+    }
+}
+
+impl Command for Report {
+    fn register(&self) -> clap::App<'_, '_> {
+        clap::SubCommand::with_name("report")
+            .about("Summarize the PBOs, signatures and keys of a mod folder")
+            .arg(
+                clap::Arg::with_name("modfolder")
+                    .help("Mod folder containing addons/ and optionally keys/")
+                    .validator(crate::commands::validate_dir)
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("json")
+                    .help("Print the report as JSON")
+                    .long("json"),
+            )
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        Report::cmd_report(
+            Path::new(args.value_of("modfolder").unwrap()),
+            args.is_present("json"),
+        )
+    }
+}
@@ -0,0 +1,100 @@
+use std::fs::{read, write, File};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::sign::{BIPrivateKey, BIPublicKey};
+use crate::{ArmakeError, Command};
+
+pub struct Key {}
+impl Key {
+    /// Converts a `.biprivatekey` to PKCS #8 PEM or a `.bikey` to SubjectPublicKeyInfo PEM.
+    /// An existing output is only overwritten with `force`.
+    fn cmd_export_pem(input: &Path, output: &Path, force: bool) -> Result<(), ArmakeError> {
+        crate::check_overwrite(output, force)?;
+        let buffer = read(input)?;
+        let pem = match BIPrivateKey::read(&mut Cursor::new(&buffer)) {
+            Ok(private_key) => private_key.to_pem()?,
+            Err(_) => BIPublicKey::read(&mut Cursor::new(&buffer))?.to_pem()?,
+        };
+        write(output, pem)?;
+        Ok(())
+    }
+
+    /// Converts a PEM key to BI keys named after `keyname`. Private keys are written to
+    /// `<keyname>.biprivatekey` and `<keyname>.bikey`, public keys only to `<keyname>.bikey`.
+    /// Existing keys are only overwritten with `force`.
+    fn cmd_import_pem(input: &Path, keyname: PathBuf, force: bool) -> Result<(), ArmakeError> {
+        let pem = read(input)?;
+        let name = keyname.file_name().unwrap().to_str().unwrap().to_string();
+        let public = String::from_utf8_lossy(&pem).contains("PUBLIC KEY-----");
+        if !public {
+            crate::check_overwrite(&keyname.with_extension("biprivatekey"), force)?;
+        }
+        crate::check_overwrite(&keyname.with_extension("bikey"), force)?;
+
+        let public_key = if public {
+            BIPublicKey::from_pem(&pem, name)?
+        } else {
+            let private_key = BIPrivateKey::from_pem(&pem, name)?;
+            private_key.write(&mut File::create(keyname.with_extension("biprivatekey"))?)?;
+            private_key.to_public_key()?
+        };
+        public_key.write(&mut File::create(keyname.with_extension("bikey"))?)?;
+
+        Ok(())
+    }
+}
+
+impl Command for Key {
+    fn register(&self) -> clap::App<'_, '_> {
+        clap::SubCommand::with_name("key")
+            .about("Convert BI keys from and to other formats")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("export-pem")
+                    .about("Convert a .biprivatekey or .bikey to PEM")
+                    .arg(
+                        clap::Arg::with_name("key")
+                            .help("BI key to convert")
+                            .validator(crate::commands::validate_file)
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("target")
+                            .help("PEM file to write")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("import-pem")
+                    .about("Convert a PEM RSA key to BI keys (extensions are added)")
+                    .arg(
+                        clap::Arg::with_name("source")
+                            .help("PEM file with a private or public key")
+                            .validator(crate::commands::validate_file)
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("keyname")
+                            .help("Name of the keypair")
+                            .required(true),
+                    ),
+            )
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        match args.subcommand() {
+            ("export-pem", Some(args)) => Key::cmd_export_pem(
+                Path::new(args.value_of("key").unwrap()),
+                Path::new(args.value_of("target").unwrap()),
+                args.is_present("force"),
+            ),
+            ("import-pem", Some(args)) => Key::cmd_import_pem(
+                Path::new(args.value_of("source").unwrap()),
+                PathBuf::from(args.value_of("keyname").unwrap()),
+                args.is_present("force"),
+            ),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::fs::{create_dir_all, File};
+use std::path::PathBuf;
+
+use crate::sign::BIPrivateKey;
+use crate::{aerror, ArmakeError, Command};
+
+pub struct Keygen {}
+impl Keygen {
+    /// Generates a new key pair of `length` bits, written to `<keyname>.biprivatekey` and
+    /// `<keyname>.bikey`. The authority is the file name of `keyname`, missing parent folders
+    /// are created. Existing keys are only overwritten with `force`.
+    fn cmd_keygen(keyname: PathBuf, length: u32, force: bool) -> Result<(), ArmakeError> {
+        let name = match keyname.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.to_string(),
+            None => return Err(aerror!("\"{}\" is not a valid key name", keyname.display())),
+        };
+        // appended instead of using with_extension, so names like "my.key" keep their dots
+        let with_extension = |extension: &str| {
+            let mut path = keyname.clone().into_os_string();
+            path.push(format!(".{}", extension));
+            PathBuf::from(path)
+        };
+        let private_key_path = with_extension("biprivatekey");
+        let public_key_path = with_extension("bikey");
+        crate::check_overwrite(&private_key_path, force)?;
+        crate::check_overwrite(&public_key_path, force)?;
+
+        if let Some(parent) = keyname.parent() {
+            create_dir_all(parent)?;
+        }
+
+        let private_key = BIPrivateKey::generate(length, name)?;
+        let public_key = private_key.to_public_key()?;
+
+        let mut private_key_file = File::create(private_key_path)?;
+        private_key.write(&mut private_key_file)?;
+
+        let mut public_key_file = File::create(public_key_path)?;
+        public_key.write(&mut public_key_file)?;
+
+        Ok(())
+    }
+}
+
+impl Command for Keygen {
+    fn register(&self) -> clap::App<'_, '_> {
+        clap::SubCommand::with_name("keygen")
+            .about("Generate a keypair with the specified path (extensions are added)")
+            .arg(
+                clap::Arg::with_name("keyname")
+                    .help("Name of the keypair, may include folders like keys/myname")
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("length")
+                    .help("Key length in bits, the game uses 1024")
+                    .long("length")
+                    .takes_value(true)
+                    .value_name("bits")
+                    .possible_values(&["1024", "2048", "4096"])
+                    .default_value("1024"),
+            )
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        Keygen::cmd_keygen(
+            PathBuf::from(args.value_of("keyname").unwrap()),
+            args.value_of("length").unwrap().parse().unwrap(),
+            args.is_present("force"),
+        )
+    }
+}
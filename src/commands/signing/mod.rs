@@ -0,0 +1,23 @@
+use std::path::Path;
+
+mod key;
+pub use key::Key;
+
+mod keygen;
+pub use keygen::Keygen;
+
+mod sign;
+pub use sign::Sign;
+
+mod verify;
+pub use verify::Verify;
+
+mod verify_folder;
+pub use verify_folder::VerifyFolder;
+
+/// Returns true if the path has the given extension, compared case-insensitively.
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().eq_ignore_ascii_case(extension))
+        .unwrap_or(false)
+}
@@ -0,0 +1,220 @@
+use std::fs::{create_dir_all, File};
+use std::path::{Path, PathBuf};
+
+use super::has_extension;
+use crate::pbo::list_files;
+use crate::sign::{BIPrivateKey, BISignVersion};
+use crate::{aerror, ArmakeError, Command, PBO};
+
+pub struct Sign {}
+impl Sign {
+    /// Signs the PBO with the given signature version, writing the signature to `signature_path`
+    /// or `<pbo>.<authority>.bisign`, named after the authority of the private key like servers
+    /// expect it. Prints the authority and the paths of the signatures.
+    ///
+    /// With `all_versions`, `version` is ignored and a V2 signature is written next to the V3
+    /// one, with `.v2` inserted before its extension. Existing signatures are only overwritten
+    /// with `force`. The PBO is only read.
+    pub(crate) fn cmd_sign(
+        privatekey_path: PathBuf,
+        pbo_path: PathBuf,
+        signature_path: Option<PathBuf>,
+        version: BISignVersion,
+        all_versions: bool,
+        force: bool,
+    ) -> Result<(), ArmakeError> {
+        let privatekey = BIPrivateKey::open(&privatekey_path)?;
+        let paths = Sign::sign_pbo(
+            &privatekey,
+            &pbo_path,
+            signature_path,
+            version,
+            all_versions,
+            force,
+        )?;
+        println!("Authority: {}", privatekey.name);
+        for path in paths {
+            println!("{}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Signs every PBO in `folder` and its subfolders like `cmd_sign`, writing the signatures
+    /// next to them, and prints the result for each of them. PBOs that fail to sign don't stop
+    /// the others.
+    ///
+    /// With `copy_key`, the public key is written to `<folder>/keys/<authority>.bikey`.
+    fn cmd_sign_folder(
+        privatekey_path: &Path,
+        folder: &Path,
+        version: BISignVersion,
+        all_versions: bool,
+        copy_key: bool,
+        force: bool,
+    ) -> Result<(), ArmakeError> {
+        let privatekey = BIPrivateKey::open(privatekey_path)?;
+        let mut pbos: Vec<PathBuf> = list_files(&folder.to_path_buf())?
+            .into_iter()
+            .filter(|p| has_extension(p, "pbo"))
+            .collect();
+        pbos.sort();
+        if pbos.is_empty() {
+            return Err(aerror!("{} contains no PBOs", folder.display()));
+        }
+
+        let mut failed = 0;
+        for pbo in &pbos {
+            let name = pbo.strip_prefix(folder).unwrap_or(pbo).display();
+            match Sign::sign_pbo(&privatekey, pbo, None, version, all_versions, force) {
+                Ok(_) if all_versions => println!("{}: signed (V3, V2)", name),
+                Ok(_) => println!("{}: signed ({})", name, version),
+                Err(e) => {
+                    println!("{}: {}", name, e);
+                    failed += 1;
+                }
+            }
+        }
+
+        if copy_key {
+            let keys = folder.join("keys");
+            create_dir_all(&keys)?;
+            let path = keys.join(format!("{}.bikey", privatekey.name));
+            let mut publickey = Vec::new();
+            privatekey.to_public_key()?.write(&mut publickey)?;
+            if std::fs::read(&path).ok().as_ref() != Some(&publickey) {
+                crate::check_overwrite(&path, force)?;
+                std::fs::write(&path, &publickey)?;
+            }
+            println!("{}", path.display());
+        }
+
+        if failed > 0 {
+            return Err(aerror!("{} of {} PBOs failed to sign", failed, pbos.len()));
+        }
+        println!("Signed {} PBOs as {}", pbos.len(), privatekey.name);
+        Ok(())
+    }
+
+    /// Signs one PBO, see `cmd_sign`, and returns the paths of the written signatures.
+    fn sign_pbo(
+        privatekey: &BIPrivateKey,
+        pbo_path: &Path,
+        signature_path: Option<PathBuf>,
+        version: BISignVersion,
+        all_versions: bool,
+        force: bool,
+    ) -> Result<Vec<PathBuf>, ArmakeError> {
+        let pbo = PBO::read(&mut File::open(pbo_path)?)?;
+
+        let signature_path = signature_path.unwrap_or_else(|| {
+            let mut path = pbo_path.to_path_buf().into_os_string();
+            path.push(format!(".{}.bisign", privatekey.name));
+            PathBuf::from(path)
+        });
+
+        if !all_versions {
+            crate::check_overwrite(&signature_path, force)?;
+            let signature = privatekey.sign(&pbo, version)?;
+            signature.write(&mut File::create(&signature_path)?)?;
+            return Ok(vec![signature_path]);
+        }
+
+        let mut v2_path = signature_path.with_extension("").into_os_string();
+        v2_path.push(".v2.");
+        v2_path.push(signature_path.extension().unwrap_or_default());
+        let v2_path = PathBuf::from(v2_path);
+        crate::check_overwrite(&signature_path, force)?;
+        crate::check_overwrite(&v2_path, force)?;
+
+        let signatures = privatekey.sign_versions(&pbo, &[BISignVersion::V3, BISignVersion::V2])?;
+        let paths = vec![signature_path, v2_path];
+        for (signature, path) in signatures.iter().zip(paths.iter()) {
+            signature.write(&mut File::create(path)?)?;
+        }
+
+        Ok(paths)
+    }
+}
+
+impl Command for Sign {
+    fn register(&self) -> clap::App<'_, '_> {
+        clap::SubCommand::with_name("sign")
+            .about("Sign a PBO with the given private key")
+            .arg(
+                clap::Arg::with_name("privatekey")
+                    .help("Private key to sign with")
+                    .validator(crate::commands::validate_file)
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("pbo")
+                    .help("PBO file to sign, or a folder to sign all PBOs in recursively")
+                    .validator(crate::commands::validate_exists)
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("signature")
+                    .help("Signature path, defaults to <pbo>.<authority>.bisign")
+                    .short("s")
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("all-versions")
+                    .help("Also write a V2 signature to <signature>.v2.bisign")
+                    .long("all-versions"),
+            )
+            .arg(
+                clap::Arg::with_name("v2")
+                    .help("Write a V2 signature for older tools instead of a V3 one")
+                    .long("v2")
+                    .conflicts_with_all(&["v3", "all-versions"]),
+            )
+            .arg(
+                clap::Arg::with_name("v3")
+                    .help("Write a V3 signature, the default")
+                    .long("v3")
+                    .conflicts_with("all-versions"),
+            )
+            .arg(
+                clap::Arg::with_name("copy-key")
+                    .help("When signing a folder, also write the public key to <folder>/keys")
+                    .long("copy-key"),
+            )
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        let version = if args.is_present("v2") {
+            BISignVersion::V2
+        } else {
+            BISignVersion::V3
+        };
+        let pbo = Path::new(args.value_of("pbo").unwrap());
+        if pbo.is_dir() {
+            if args.is_present("signature") {
+                return Err(aerror!(
+                    "The signature path can't be given when signing a folder"
+                ));
+            }
+            return Sign::cmd_sign_folder(
+                Path::new(args.value_of("privatekey").unwrap()),
+                pbo,
+                version,
+                args.is_present("all-versions"),
+                args.is_present("copy-key"),
+                args.is_present("force"),
+            );
+        }
+        if args.is_present("copy-key") {
+            return Err(aerror!("--copy-key can only be used when signing a folder"));
+        }
+
+        Sign::cmd_sign(
+            PathBuf::from(args.value_of("privatekey").unwrap()),
+            PathBuf::from(args.value_of("pbo").unwrap()),
+            args.value_of("signature").map(PathBuf::from),
+            version,
+            args.is_present("all-versions"),
+            args.is_present("force"),
+        )
+    }
+}
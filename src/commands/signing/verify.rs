@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::sign::{signature_paths, BIPublicKey, BISign};
+use crate::{aerror, ArmakeError, Command, PBO};
+
+pub struct Verify {}
+impl Verify {
+    /// Finds the signature of the authority of `publickey` next to the PBO, named
+    /// `<pbo>.<authority>.bisign` or after the key file, compared case-insensitively.
+    fn find_signature(
+        publickey: &BIPublicKey,
+        publickey_path: &Path,
+        pbo_path: &Path,
+    ) -> Result<PathBuf, ArmakeError> {
+        let pbo_name = pbo_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        let mut names = vec![publickey.name.clone()];
+        if let Some(stem) = publickey_path.file_stem() {
+            names.push(stem.to_string_lossy().into_owned());
+        }
+        let expected: Vec<String> = names
+            .iter()
+            .map(|name| format!("{}.{}.bisign", pbo_name, name.to_lowercase()))
+            .collect();
+
+        let found = signature_paths(pbo_path)?;
+        for name in &expected {
+            if let Some(path) = found
+                .iter()
+                .find(|p| p.file_name().unwrap().to_string_lossy().to_lowercase() == *name)
+            {
+                return Ok(path.clone());
+            }
+        }
+
+        let mut message = format!(
+            "No signature by \"{}\" found, expected {}.{}.bisign",
+            publickey.name,
+            pbo_path.display(),
+            publickey.name
+        );
+        if !found.is_empty() {
+            let others: Vec<String> = found.iter().map(|p| p.display().to_string()).collect();
+            message.push_str(&format!(", found {}", others.join(", ")));
+        }
+        Err(aerror!("{}", message))
+    }
+
+    /// Verifies the signature at `signature_path` or the one found by `find_signature` with the
+    /// public key and prints its authority and version.
+    fn cmd_verify(
+        publickey_path: PathBuf,
+        pbo_path: PathBuf,
+        signature_path: Option<PathBuf>,
+    ) -> Result<(), ArmakeError> {
+        let publickey = BIPublicKey::read(&mut File::open(&publickey_path)?)?;
+        let pbo = PBO::read(&mut File::open(&pbo_path)?)?;
+
+        let signature_path = match signature_path {
+            Some(path) => path,
+            None => Verify::find_signature(&publickey, &publickey_path, &pbo_path)?,
+        };
+        let signature = BISign::read(&mut File::open(&signature_path)?).map_err(|e| {
+            aerror!(
+                "Failed to read signature {}: {}",
+                signature_path.display(),
+                e
+            )
+        })?;
+
+        println!("Authority: {}", signature.name);
+        if signature.version.can_sign() {
+            println!("Version:   {}", signature.version);
+        } else {
+            println!("Version:   {} (legacy, verify only)", signature.version);
+        }
+
+        publickey.verify(&pbo, &signature)?;
+        println!("Signature is valid");
+
+        Ok(())
+    }
+}
+
+impl Command for Verify {
+    fn register(&self) -> clap::App<'_, '_> {
+        clap::SubCommand::with_name("verify")
+            .about("Verify the signature of a PBO with the given public key")
+            .arg(
+                clap::Arg::with_name("publickey")
+                    .help("Public key to verify with")
+                    .validator(crate::commands::validate_file)
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("pbo")
+                    .help("Signed PBO file")
+                    .validator(crate::commands::validate_file)
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("signature")
+                    .help("Signature path, defaults to <pbo>.<authority>.bisign")
+                    .short("s")
+                    .takes_value(true),
+            )
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        Verify::cmd_verify(
+            PathBuf::from(args.value_of("publickey").unwrap()),
+            PathBuf::from(args.value_of("pbo").unwrap()),
+            args.value_of("signature").map(PathBuf::from),
+        )
+    }
+}
@@ -0,0 +1,121 @@
+use std::fs::{read_dir, File};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::has_extension;
+use crate::pbo::list_files;
+use crate::sign::{check_signatures, BIPublicKey};
+use crate::{aerror, ArmakeError, Command};
+
+pub struct VerifyFolder {}
+impl VerifyFolder {
+    /// Reads the public key at `path`, or all `.bikey` files in it if it is a folder.
+    fn read_keys(path: &Path) -> Result<Vec<BIPublicKey>, ArmakeError> {
+        let mut paths: Vec<PathBuf> = if path.is_dir() {
+            read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && has_extension(p, "bikey"))
+                .collect()
+        } else {
+            vec![path.to_path_buf()]
+        };
+        paths.sort();
+
+        if paths.is_empty() {
+            return Err(aerror!("{} contains no .bikey files", path.display()));
+        }
+        paths
+            .iter()
+            .map(|p| {
+                BIPublicKey::read(&mut File::open(p)?)
+                    .map_err(|e| aerror!("Failed to read {}: {}", p.display(), e))
+            })
+            .collect()
+    }
+
+    /// Checks the signatures of every PBO in `folder` and its subfolders against the keys and
+    /// prints the result for each of them.
+    fn cmd_verify_folder(keys: &Path, folder: &Path, jobs: usize) -> Result<(), ArmakeError> {
+        let keys = VerifyFolder::read_keys(keys)?;
+        let mut pbos: Vec<PathBuf> = list_files(&folder.to_path_buf())?
+            .into_iter()
+            .filter(|p| has_extension(p, "pbo"))
+            .collect();
+        pbos.sort();
+
+        let start = Instant::now();
+        let results = check_signatures(&pbos, &keys, jobs);
+
+        let mut failed = 0;
+        for (pbo, result) in pbos.iter().zip(results.iter()) {
+            println!(
+                "{}: {}",
+                pbo.strip_prefix(folder).unwrap_or(pbo).display(),
+                result
+            );
+            if !result.is_valid() {
+                failed += 1;
+            }
+        }
+        eprintln!(
+            "Checked {} PBOs in {:.2}s using {} jobs",
+            pbos.len(),
+            start.elapsed().as_secs_f64(),
+            jobs.min(pbos.len()).max(1)
+        );
+
+        if failed > 0 {
+            return Err(aerror!(
+                "{} of {} PBOs are not validly signed",
+                failed,
+                pbos.len()
+            ));
+        }
+        println!("All {} PBOs are validly signed", pbos.len());
+        Ok(())
+    }
+}
+
+impl Command for VerifyFolder {
+    fn register(&self) -> clap::App<'_, '_> {
+        clap::SubCommand::with_name("verify-folder")
+            .about("Verify the signatures of all PBOs in a folder against a set of keys")
+            .after_help(
+                "A PBO is valid if any of the <pbo>.<key>.bisign files next to it is a valid \
+                 signature by one of the keys, like servers check them.",
+            )
+            .arg(
+                clap::Arg::with_name("keys")
+                    .help("Public key or folder of .bikey files to verify with")
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("folder")
+                    .help("Folder with the PBOs, searched recursively")
+                    .validator(crate::commands::validate_dir)
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::with_name("jobs")
+                    .help("Number of PBOs to hash at the same time, defaults to the number of CPUs")
+                    .short("j")
+                    .long("jobs")
+                    .validator(crate::commands::validate_positive)
+                    .takes_value(true),
+            )
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        let jobs = match args.value_of("jobs") {
+            Some(jobs) => jobs.parse().unwrap(),
+            None => std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+        };
+        VerifyFolder::cmd_verify_folder(
+            Path::new(args.value_of("keys").unwrap()),
+            Path::new(args.value_of("folder").unwrap()),
+            jobs,
+        )
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs::read_to_string;
+use std::path::PathBuf;
+
+use crate::lint::{locate, read_config_file, report};
+use crate::stringtable::{lint_references, Stringtable as Table};
+use crate::{aerror, ArmakeError, Command};
+
+pub struct Stringtable {}
+impl Stringtable {
+    /// Validates the stringtable at `input` and checks that all `$STR` references in `configs`
+    /// are defined in it. Fails if any unsuppressed lint was found.
+    fn cmd_check(
+        input: PathBuf,
+        configs: &[PathBuf],
+        includefolders: &[PathBuf],
+        suppressed: &[&str],
+    ) -> Result<(), ArmakeError> {
+        let stringtable = Table::read(&read_to_string(&input)?)
+            .map_err(|e| aerror!("{}: {}", input.display(), e))?;
+        let mut count = report(&stringtable.lints, &input.display().to_string(), suppressed);
+
+        for path in configs {
+            let (config, map) = read_config_file(path, includefolders)?;
+            let mut lints = lint_references(&config, &stringtable);
+            if let Some(map) = &map {
+                locate(&mut lints, map);
+            }
+            count += report(&lints, &path.display().to_string(), suppressed);
+        }
+
+        if count > 0 {
+            return Err(aerror!("Found {} problem(s).", count));
+        }
+
+        Ok(())
+    }
+}
+
+impl Command for Stringtable {
+    fn register(&self) -> clap::App<'_, '_> {
+        clap::SubCommand::with_name("stringtable")
+            .about("Work with stringtable.xml files")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("check")
+                    .about("Check a stringtable for mistakes")
+                    .arg(
+                        clap::Arg::with_name("source")
+                            .help("Stringtable file")
+                            .validator(crate::commands::validate_file)
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("config")
+                            .help("Config whose $STR references should be checked")
+                            .validator(crate::commands::validate_file)
+                            .short("c")
+                            .multiple(true)
+                            .number_of_values(1)
+                            .takes_value(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("include")
+                            .help("Include folder")
+                            .validator(crate::commands::validate_dir)
+                            .short("i")
+                            .multiple(true)
+                            .number_of_values(1)
+                            .takes_value(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("warning")
+                            .help("Warning to suppress")
+                            .short("w")
+                            .multiple(true)
+                            .number_of_values(1)
+                            .takes_value(true),
+                    ),
+            )
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        match args.subcommand() {
+            ("check", Some(args)) => {
+                let input = args.value_of("source").unwrap();
+                let configs: Vec<PathBuf> = args
+                    .values_of("config")
+                    .map(|v| v.map(PathBuf::from).collect())
+                    .unwrap_or_default();
+                let includes: Vec<PathBuf> = args
+                    .values_of("include")
+                    .map(|v| v.map(PathBuf::from).collect())
+                    .unwrap_or_default();
+                let suppressed: Vec<&str> = args
+                    .values_of("warning")
+                    .map(|v| v.collect())
+                    .unwrap_or_default();
+                Stringtable::cmd_check(PathBuf::from(input), &configs, &includes, &suppressed)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -1,58 +1,652 @@
-use std::fs::{create_dir_all, File};
-use std::io::{Read, Write};
-use std::path::{PathBuf, MAIN_SEPARATOR};
+use std::collections::{HashMap, HashSet};
+use std::fs::{create_dir_all, read_dir, File};
+use std::io::{copy, Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR_STR};
+use std::time::{Duration, UNIX_EPOCH};
 
-use crate::{ArmakeError, Command, PBO};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+use crate::pbo::{
+    is_valid_entry_name, matches_glob, EntryOrder, Manifest, ManifestEntry, PrefixStyle,
+    MANIFEST_NAME, ORDER_FILE_NAME,
+};
+use crate::{aerror, ArmakeError, Command, Config, Encoding, PBOIndex};
+
+/// Options for all forms of unpacking
+struct UnpackOptions {
+    /// Write the order of the entries to `.pbo-order` in the folder, see `EntryOrder`
+    order: bool,
+    /// Name of the prefix file the header extensions are written to
+    prefix_style: PrefixStyle,
+    /// Fail instead of warning if the checksum of a PBO doesn't match or is missing
+    strict: bool,
+    /// Hash the PBO to verify its checksum, otherwise it only has to be present, see
+    /// `check_index_checksum`
+    verify: bool,
+    /// Unpack into folders that aren't empty and overwrite an existing zip file
+    force: bool,
+    /// Also extract entries that look like garbage, see `PBO::read_keeping_garbage`
+    keep_garbage: bool,
+    /// Lowercase patterns of the entries to extract, all entries if empty
+    includes: Vec<String>,
+    /// Lowercase patterns of the entries not to extract
+    excludes: Vec<String>,
+    /// Extract entries with names like `..\x` or `C:\x` and through symlinks leaving the target
+    insecure: bool,
+    /// Derapify rapified entries, extracting `config.bin` as `config.cpp`
+    derapify: bool,
+}
+
+impl UnpackOptions {
+    /// Reads the headers of the PBO from `source`, checks its checksum and removes the entries
+    /// that are not extracted. Prints how many entries were skipped if there are patterns.
+    fn read<I: Read + Seek>(&self, input: &mut I, source: &str) -> Result<PBOIndex, ArmakeError> {
+        let mut index = if self.keep_garbage {
+            PBOIndex::read_keeping_garbage(input)?
+        } else {
+            PBOIndex::read(input)?
+        };
+        crate::commands::check_index_checksum(&index, input, source, self.strict, self.verify)?;
+        if self.includes.is_empty() && self.excludes.is_empty() {
+            return Ok(index);
+        }
+
+        let total = index.entries.len();
+        index.entries.retain(|entry| {
+            let name = entry.header.filename.to_lowercase();
+            (self.includes.is_empty() || self.includes.iter().any(|p| matches_glob(&name, p)))
+                && !self.excludes.iter().any(|p| matches_glob(&name, p))
+        });
+
+        eprintln!(
+            "{}: extracting {} of {} entries, {} skipped by the patterns",
+            source,
+            index.entries.len(),
+            total,
+            total - index.entries.len()
+        );
+        Ok(index)
+    }
+}
 
 pub struct Unpack {}
 impl Unpack {
-    fn cmd_unpack<I: Read>(input: &mut I, output: PathBuf) -> Result<(), ArmakeError> {
-        let pbo = PBO::read(input)?;
+    /// Unpacks a PBO into a folder, writing a manifest of the extracted files to `manifest` if
+    /// given. Extracted files get the timestamps of their entries as modification times.
+    ///
+    /// Entries are read from the input one at a time, see `PBOIndex`.
+    fn cmd_unpack<I: Read + Seek>(
+        input: &mut I,
+        source: &str,
+        output: PathBuf,
+        manifest: Option<PathBuf>,
+        options: &UnpackOptions,
+    ) -> Result<(), ArmakeError> {
+        let index = options.read(input, source)?;
+        Unpack::unpack(&index, input, &output, manifest, options)
+    }
+
+    /// Unpacks several PBOs into one subfolder of `output` each, named after the prefix of the
+    /// PBO or its file name if it has none. PBOs with the same prefix as a previous one get the
+    /// file name appended to the folder name.
+    ///
+    /// Failures are logged and the remaining PBOs are still unpacked. Fails at the end if any PBO
+    /// could not be unpacked.
+    fn cmd_unpack_batch(
+        sources: &[PathBuf],
+        output: &Path,
+        manifest: bool,
+        options: &UnpackOptions,
+    ) -> Result<(), ArmakeError> {
+        let mut used: HashSet<String> = HashSet::new();
+        let mut failed = 0;
+
+        for source in sources {
+            let result = File::open(source)
+                .map_err(ArmakeError::from)
+                .and_then(|mut file| {
+                    let index = options.read(&mut file, &source.to_string_lossy())?;
+                    let stem = source.file_stem().unwrap().to_string_lossy().to_string();
+                    let folder = match prefix_folder(&index) {
+                        Some(folder) if used.contains(&folder.to_lowercase()) => {
+                            let disambiguated = format!("{}_{}", folder, stem);
+                            warn!(
+                                "{} has the same prefix as a previous PBO, unpacking it to {}",
+                                source.display(),
+                                disambiguated
+                            );
+                            disambiguated
+                        }
+                        Some(folder) => folder,
+                        None => stem,
+                    };
+                    used.insert(folder.to_lowercase());
 
-        create_dir_all(&output)?;
+                    let target = output.join(&folder);
+                    let manifest = if manifest {
+                        Some(target.join(MANIFEST_NAME))
+                    } else {
+                        None
+                    };
+                    Unpack::unpack(&index, &mut file, &target, manifest, options)
+                });
+
+            if let Err(e) = result {
+                warn!("Failed to unpack {}: {}", source.display(), e);
+                failed += 1;
+            }
+        }
 
-        if !pbo.header_extensions.is_empty() {
-            let prefix_path = output.join(PathBuf::from("$PBOPREFIX$"));
+        println!(
+            "Unpacked {} of {} PBOs into {}",
+            sources.len() - failed,
+            sources.len(),
+            output.display()
+        );
+
+        if failed > 0 {
+            return Err(aerror!("{} PBOs failed to unpack", failed));
+        }
+        Ok(())
+    }
+
+    fn unpack<I: Read + Seek>(
+        index: &PBOIndex,
+        input: &mut I,
+        output: &Path,
+        manifest: Option<PathBuf>,
+        options: &UnpackOptions,
+    ) -> Result<(), ArmakeError> {
+        crate::check_folder_empty(output, options.force)?;
+        create_dir_all(output)?;
+        let root = output.canonicalize()?;
+
+        if !index.header_extensions.is_empty() {
+            let prefix_path = output.join(options.prefix_style.file_name());
             let mut prefix_file = File::create(prefix_path)?;
 
-            for (key, value) in pbo.header_extensions.iter() {
+            for (key, value) in index.header_extensions.iter() {
                 prefix_file.write_all(format!("{}={}\n", key, value).as_bytes())?;
             }
         }
 
-        for (file_name, cursor) in pbo.files.iter() {
-            // @todo: windows
-            let path = output.join(PathBuf::from(
-                file_name.replace("\\", &MAIN_SEPARATOR.to_string()),
-            ));
+        let paths: HashMap<&str, (PathBuf, bool)> =
+            extraction_paths(index, options.insecure, options.derapify)
+                .into_iter()
+                .map(|(name, path, directory)| (name, (path, directory)))
+                .collect();
+        let mut entries: Vec<ManifestEntry> = Vec::new();
+        for header in index.headers() {
+            let name = header.filename.as_str();
+            let extracted = paths.get(name);
+            if extracted.is_none() && manifest.is_none() {
+                continue;
+            }
+            let data = index.read_file(input, name)?.unwrap();
+            let (extracted, data) = match extracted {
+                Some((path, false)) if options.derapify => {
+                    let (path, data) = derapify_entry(name, path.clone(), data);
+                    (Some((path, false)), data)
+                }
+                extracted => (extracted.cloned(), data),
+            };
+            if manifest.is_some() {
+                let path = match &extracted {
+                    Some((path, _)) => zip_name(path),
+                    None => name.replace("\\", "/"),
+                };
+                entries.push(ManifestEntry::new(name, path, &data, header.timestamp));
+            }
+
+            let path = match extracted {
+                Some((path, _)) if !options.insecure && leaves_root(&root, &output.join(&path)) => {
+                    warn!(
+                        "Skipping {}, it would be extracted outside of the target",
+                        name
+                    );
+                    continue;
+                }
+                Some((path, false)) => output.join(path),
+                Some((path, true)) => {
+                    create_dir_all(output.join(path))?;
+                    continue;
+                }
+                None => continue,
+            };
             create_dir_all(path.parent().unwrap())?;
             let mut file = File::create(path)?;
-            file.write_all(cursor.get_ref())?;
+            file.write_all(&data)?;
+            // 0 means the PBO doesn't know the time, the file keeps the current one
+            if header.timestamp != 0 {
+                file.set_modified(UNIX_EPOCH + Duration::from_secs(u64::from(header.timestamp)))?;
+            }
+        }
+
+        if let Some(manifest) = manifest {
+            Manifest::new(&index.header_extensions, entries).write(&mut File::create(manifest)?)?;
+        }
+
+        if options.order {
+            EntryOrder::from_headers(index.header_extensions.keys(), index.headers())
+                .write(&mut File::create(output.join(ORDER_FILE_NAME))?)?;
         }
 
         Ok(())
     }
+
+    /// Unpacks a PBO into a zip archive, with the prefix file as the first entry if the PBO has
+    /// header extensions. Entries are deflated and keep the timestamps of their PBO headers.
+    fn cmd_unpack_zip<I: Read + Seek, O: Write + Seek>(
+        input: &mut I,
+        source: &str,
+        output: O,
+        options: &UnpackOptions,
+    ) -> Result<(), ArmakeError> {
+        let index = options.read(input, source)?;
+        let mut zip = ZipWriter::new(output);
+        let file_options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        if !index.header_extensions.is_empty() {
+            zip.start_file(options.prefix_style.file_name(), file_options)?;
+            for (key, value) in index.header_extensions.iter() {
+                zip.write_all(format!("{}={}\n", key, value).as_bytes())?;
+            }
+        }
+
+        for (name, path, directory) in extraction_paths(&index, options.insecure, options.derapify)
+        {
+            if directory {
+                zip.add_directory(zip_name(&path), file_options)?;
+                continue;
+            }
+            let data = index.read_file(input, name)?.unwrap();
+            let (path, data) = if options.derapify {
+                derapify_entry(name, path, data)
+            } else {
+                (path, data)
+            };
+            let mut file_options = file_options.large_file(data.len() as u64 >= 0xFFFF_FFFF);
+            if let Some(datetime) = index.header(name).and_then(|h| zip_datetime(h.timestamp)) {
+                file_options = file_options.last_modified_time(datetime);
+            }
+            zip.start_file(zip_name(&path), file_options)?;
+            copy(&mut &data[..], &mut zip)?;
+        }
+
+        zip.finish()?;
+
+        Ok(())
+    }
+}
+
+/// Magic at the start of rapified files
+const RAPIFIED_MAGIC: &[u8] = b"\0raP";
+
+/// Names that can't be used for files on Windows, with or without an extension
+const WINDOWS_RESERVED: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Returns the relative path an entry is extracted to. Entries ending in `\` are directory
+/// markers and get the path of the directory.
+///
+/// Entries whose names would end up outside of the target, like `..\x`, `\x` or `C:\x`, are
+/// skipped with a warning unless `insecure` is set. On Windows, entries with reserved names like
+/// `con.txt` are always skipped.
+fn extraction_path(name: &str, insecure: bool) -> Option<PathBuf> {
+    let relative = name.strip_suffix('\\').unwrap_or(name);
+    if relative.is_empty() {
+        return None;
+    }
+    // a colon makes a drive letter like C: or an alternate data stream on Windows
+    if !insecure && (!is_valid_entry_name(relative) || relative.contains(':')) {
+        warn!("Skipping {}, it is not a relative path", name);
+        return None;
+    }
+    if cfg!(windows) {
+        let reserved = relative.split('\\').any(|component| {
+            let stem = component.split('.').next().unwrap().to_lowercase();
+            WINDOWS_RESERVED.contains(&stem.trim_end())
+                || component.ends_with('.')
+                || component.ends_with(' ')
+                || component.contains(['<', '>', ':', '"', '|', '?', '*'])
+        });
+        if reserved {
+            warn!("Skipping {}, its name can't be used on Windows", name);
+            return None;
+        }
+    }
+    Some(PathBuf::from(relative.replace("\\", MAIN_SEPARATOR_STR)))
+}
+
+/// Returns the relative paths the entries of the PBO are extracted to in stored order, see
+/// `extraction_path`, and whether they are directory markers. With `derapify`, `config.bin` is
+/// extracted as `config.cpp`, see `derapify_entry`.
+///
+/// Entries without a name are extracted as `unnamed` if they have data. Duplicate names get their
+/// suffix when the PBO is read, see `PBO::read`, so only paths that collide through this mapping,
+/// like several `unnamed` entries or a derapified `config.bin` next to a `config.cpp`, get a
+/// suffix like `unnamed.1` here. Both are warned about, so every byte of data ends up in a file.
+fn extraction_paths(
+    index: &PBOIndex,
+    insecure: bool,
+    derapify: bool,
+) -> Vec<(&str, PathBuf, bool)> {
+    let mut used: HashSet<String> = HashSet::new();
+    let mut paths = Vec::new();
+    for entry in index.entries.iter() {
+        let name = &entry.header.filename;
+        let unnamed = name.trim_matches('\\').is_empty();
+        let directory = name.ends_with('\\') && !unnamed;
+        let mut path = if unnamed {
+            if entry.data.start == entry.data.end {
+                continue;
+            }
+            warn!("Extracting an entry without a name as unnamed");
+            PathBuf::from("unnamed")
+        } else {
+            match extraction_path(name, insecure) {
+                Some(path) => path,
+                None => continue,
+            }
+        };
+
+        if derapify && is_config_bin(&path) {
+            path.set_file_name("config.cpp");
+        }
+
+        if !directory {
+            let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+            let mut n = 0;
+            while used.contains(&path.to_string_lossy().to_lowercase()) {
+                n += 1;
+                path.set_file_name(format!("{}.{}", file_name, n));
+            }
+            if n > 0 {
+                warn!(
+                    "{} would overwrite an earlier entry, extracting it as {}",
+                    name,
+                    path.display()
+                );
+            }
+            used.insert(path.to_string_lossy().to_lowercase());
+        }
+        paths.push((name.as_str(), path, directory));
+    }
+    paths
+}
+
+/// Returns true if the file name of `path` is `config.bin`, in any case.
+fn is_config_bin(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.eq_ignore_ascii_case("config.bin"))
+}
+
+/// Returns the path and content to extract a rapified entry with, derapified. Entries that are
+/// not rapified or fail to derapify are extracted as they are, under the original name of a
+/// `config.bin`, the latter with a warning.
+fn derapify_entry(name: &str, path: PathBuf, data: Vec<u8>) -> (PathBuf, Vec<u8>) {
+    let raw_path = |path: PathBuf| match name.rsplit('\\').next() {
+        Some(file_name) if file_name.eq_ignore_ascii_case("config.bin") => {
+            path.with_file_name(file_name)
+        }
+        _ => path,
+    };
+    if !data.starts_with(RAPIFIED_MAGIC) {
+        return (raw_path(path), data);
+    }
+
+    let mut derapified = Vec::new();
+    match Config::derapify(&mut Cursor::new(&data), &mut derapified, Encoding::Utf8) {
+        Ok(()) => (path, derapified),
+        Err(e) => {
+            warn!("Failed to derapify {}, extracting it as it is: {}", name, e);
+            (raw_path(path), data)
+        }
+    }
+}
+
+/// Returns true if `path` would not be inside the folder `root` once it is created, because it or
+/// one of its existing parents is a symlink to somewhere else. `root` is canonical.
+fn leaves_root(root: &Path, path: &Path) -> bool {
+    let existing = path.ancestors().find(|p| p.symlink_metadata().is_ok());
+    match existing.map(|p| p.canonicalize()) {
+        Some(Ok(existing)) => !existing.starts_with(root),
+        // a dangling symlink
+        Some(Err(_)) => true,
+        None => true,
+    }
+}
+
+/// Returns the name of a relative path in a zip archive, separated by `/`.
+fn zip_name(path: &Path) -> String {
+    path.to_string_lossy().replace(MAIN_SEPARATOR_STR, "/")
+}
+
+/// Returns the relative folder for the prefix of a PBO, `None` if it has no usable prefix.
+fn prefix_folder(index: &PBOIndex) -> Option<String> {
+    let components: Vec<&str> = index
+        .header_extension("prefix")?
+        .split(['\\', '/'])
+        .filter(|c| !c.is_empty() && *c != "." && *c != "..")
+        .collect();
+    if components.is_empty() {
+        None
+    } else {
+        Some(components.join(MAIN_SEPARATOR_STR))
+    }
+}
+
+/// Expands sources whose file name contains a `*` to the matching files, compared
+/// case-insensitively and sorted. Other sources are returned as they are.
+fn expand_sources(sources: &[&str]) -> Result<Vec<PathBuf>, ArmakeError> {
+    let mut expanded = Vec::new();
+    for source in sources {
+        let path = PathBuf::from(source);
+        let pattern = match path.file_name() {
+            Some(name) if name.to_string_lossy().contains('*') => {
+                name.to_string_lossy().to_lowercase()
+            }
+            _ => {
+                expanded.push(path);
+                continue;
+            }
+        };
+
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut matches: Vec<PathBuf> = read_dir(parent)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.is_file()
+                    && matches_glob(
+                        &p.file_name().unwrap().to_string_lossy().to_lowercase(),
+                        &pattern,
+                    )
+            })
+            .collect();
+        if matches.is_empty() {
+            warn!("{} doesn't match any files", source);
+        }
+        matches.sort();
+        expanded.extend(matches);
+    }
+    Ok(expanded)
+}
+
+/// Converts a unix timestamp to a zip timestamp, `None` if it is outside the range zip supports
+/// (1980 to 2107).
+fn zip_datetime(timestamp: u32) -> Option<DateTime> {
+    let time = time::OffsetDateTime::from_unix_timestamp(i64::from(timestamp));
+    DateTime::from_date_and_time(
+        time.year() as u16,
+        time.month(),
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second(),
+    )
+    .ok()
 }
 
 impl Command for Unpack {
-    fn register(&self) -> clap::App {
+    fn register(&self) -> clap::App<'_, '_> {
         clap::SubCommand::with_name("unpack")
             .about("Unpack a PBO into a folder")
+            .after_help(
+                "With several PBOs or a pattern like addons/*.pbo, every PBO is unpacked into a \
+                 subfolder of the target named after its prefix, or its file name if it has none.",
+            )
+            // a positional that takes multiple values can't be followed by another positional if
+            // options with values come first, the target is split off the sources instead
+            .usage("armake2 unpack [FLAGS] [OPTIONS] <source>... <target>")
             .arg(
-                clap::Arg::with_name("source")
-                    .help("Source PBO file")
+                clap::Arg::with_name("paths")
+                    .help("Source PBO files followed by the output folder, or zip file with --zip")
+                    .multiple(true)
+                    .min_values(2)
                     .required(true),
             )
             .arg(
-                clap::Arg::with_name("target")
-                    .help("Output folder")
-                    .required(true),
+                clap::Arg::with_name("zip")
+                    .help("Write the contents into a zip file instead of a folder")
+                    .long("zip"),
+            )
+            .arg(
+                clap::Arg::with_name("manifest")
+                    .help("Manifest of the extracted files, defaults to <target>/.armake2-manifest.json")
+                    .long("manifest")
+                    .value_name("path")
+                    .takes_value(true)
+                    .conflicts_with("zip"),
+            )
+            .arg(
+                clap::Arg::with_name("order")
+                    .help("Record the order and headers of the entries in <target>/.pbo-order, see build --order-file")
+                    .long("order")
+                    .conflicts_with("zip"),
+            )
+            .arg(
+                clap::Arg::with_name("no-manifest")
+                    .help("Don't write a manifest of the extracted files")
+                    .long("no-manifest")
+                    .conflicts_with("manifest"),
+            )
+            .arg(
+                clap::Arg::with_name("prefix-style")
+                    .help("Name of the prefix file: $PBOPREFIX$, $PBOPREFIX$.txt, pboprefix.txt or $PREFIX$")
+                    .long("prefix-style")
+                    .takes_value(true)
+                    .possible_values(&["pboprefix", "pboprefix-txt", "txt", "prefix"])
+                    .default_value("pboprefix"),
+            )
+            .arg(
+                clap::Arg::with_name("strict")
+                    .help("Fail instead of warning if the checksum of the PBO doesn't match or is missing")
+                    .long("strict"),
+            )
+            .arg(
+                clap::Arg::with_name("no-verify")
+                    .help("Don't hash the PBO to verify its checksum, only check that there is one")
+                    .long("no-verify"),
+            )
+            .arg(
+                clap::Arg::with_name("keep-garbage")
+                    .help("Also extract entries that look like garbage, like the data left for an entry ending past the end of the PBO. Names that are unsafe to extract are still skipped")
+                    .long("keep-garbage"),
+            )
+            .arg(
+                clap::Arg::with_name("insecure")
+                    .help("Also extract entries whose names leave the target, like ..\\x or C:\\x, and write through symlinks in the target. Only for PBOs you trust")
+                    .long("insecure"),
+            )
+            .arg(
+                clap::Arg::with_name("derapify")
+                    .help("Derapify rapified entries like config.bin and *.rvmat, config.bin is extracted as config.cpp. Entries that fail to derapify are extracted as they are")
+                    .long("derapify")
+                    .conflicts_with("order"),
+            )
+            .arg(
+                clap::Arg::with_name("include")
+                    .help("Only extract the entries matching the pattern, like data\\*.paa")
+                    .short("i")
+                    .long("include")
+                    .value_name("pattern")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .arg(
+                clap::Arg::with_name("exclude")
+                    .help("Don't extract the entries matching the pattern")
+                    .short("x")
+                    .long("exclude")
+                    .value_name("pattern")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1),
             )
     }
 
     fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
-        let mut input = crate::get_input(args.value_of("source"))?;
-        let output = args.value_of("target").unwrap();
-        Unpack::cmd_unpack(&mut input, PathBuf::from(output))
+        let mut sources: Vec<&str> = args.values_of("paths").unwrap().collect();
+        let output = sources.pop().unwrap();
+        let patterns = |name| -> Vec<String> {
+            args.values_of(name)
+                .map(|values| values.map(|p| p.to_lowercase()).collect())
+                .unwrap_or_default()
+        };
+        let options = UnpackOptions {
+            order: args.is_present("order"),
+            prefix_style: args.value_of("prefix-style").unwrap().parse()?,
+            strict: args.is_present("strict"),
+            verify: !args.is_present("no-verify"),
+            force: args.is_present("force"),
+            keep_garbage: args.is_present("keep-garbage"),
+            includes: patterns("include"),
+            excludes: patterns("exclude"),
+            insecure: args.is_present("insecure"),
+            derapify: args.is_present("derapify"),
+        };
+
+        if sources.len() > 1 || sources[0].contains('*') {
+            if args.is_present("zip") {
+                return Err(aerror!("--zip can only be used with a single PBO"));
+            }
+            if args.is_present("manifest") {
+                return Err(aerror!(
+                    "--manifest can only be used with a single PBO, every PBO gets a manifest in \
+                     its folder"
+                ));
+            }
+            return Unpack::cmd_unpack_batch(
+                &expand_sources(&sources)?,
+                Path::new(output),
+                !args.is_present("no-manifest"),
+                &options,
+            );
+        }
+
+        let mut input = crate::get_input(Some(sources[0]))?;
+        if args.is_present("zip") {
+            crate::check_overwrite(Path::new(output), options.force)?;
+            Unpack::cmd_unpack_zip(&mut input, sources[0], File::create(output)?, &options)
+        } else {
+            let output = PathBuf::from(output);
+            let manifest = if args.is_present("no-manifest") {
+                None
+            } else {
+                Some(
+                    args.value_of("manifest")
+                        .map(PathBuf::from)
+                        .unwrap_or_else(|| output.join(MANIFEST_NAME)),
+                )
+            };
+            Unpack::cmd_unpack(&mut input, sources[0], output, manifest, &options)
+        }
     }
 }
@@ -0,0 +1,43 @@
+use std::io::Read;
+
+use crate::{aerror, ArmakeError, Command, PBO};
+
+pub struct VerifyManifest {}
+impl VerifyManifest {
+    /// Checks the entries of a PBO against the hash manifest embedded by `build --hash-manifest`,
+    /// logging every mismatch.
+    fn cmd_verify_manifest<I: Read>(input: &mut I) -> Result<(), ArmakeError> {
+        let pbo = PBO::read(input)?;
+        let mismatches = pbo.verify_hash_manifest()?;
+
+        for mismatch in mismatches.iter() {
+            warn!("{}", mismatch);
+        }
+
+        if !mismatches.is_empty() {
+            return Err(aerror!(
+                "{} entries don't match the hash manifest",
+                mismatches.len()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Command for VerifyManifest {
+    fn register(&self) -> clap::App<'_, '_> {
+        clap::SubCommand::with_name("verify-manifest")
+            .about("Check the entries of a PBO against its embedded hash manifest")
+            .arg(
+                clap::Arg::with_name("source")
+                    .help("PBO file to check")
+                    .required(true),
+            )
+    }
+
+    fn run(&self, args: &clap::ArgMatches) -> Result<(), ArmakeError> {
+        let mut input = crate::get_input(args.value_of("source"))?;
+        VerifyManifest::cmd_verify_manifest(&mut input)
+    }
+}
@@ -0,0 +1,152 @@
+use std::io::{self, Write};
+use std::str::{self, FromStr};
+
+use crate::aerror;
+use crate::ArmakeError;
+
+/// Characters of Windows-1252 in the range 0x80 to 0x9f, bytes not defined by the encoding map to
+/// the control character with the same value.
+const CP1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+/// Text encoding of unrapified configs
+///
+/// Configs are always handled as UTF-8 in memory and in their rapified form, the encoding is only
+/// applied when reading or writing config text.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// Windows-1252
+    Cp1252,
+}
+
+impl FromStr for Encoding {
+    type Err = ArmakeError;
+
+    fn from_str(s: &str) -> Result<Encoding, ArmakeError> {
+        match s.to_lowercase().replace('-', "").as_str() {
+            "utf8" => Ok(Encoding::Utf8),
+            "cp1252" | "windows1252" => Ok(Encoding::Cp1252),
+            _ => Err(aerror!("Unknown encoding: {}", s)),
+        }
+    }
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf8",
+            Encoding::Cp1252 => "cp1252",
+        }
+    }
+
+    /// Decodes text in this encoding.
+    pub fn decode(self, bytes: &[u8]) -> Result<String, ArmakeError> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes.to_vec())
+                .map_err(|e| aerror!("Input is not valid UTF-8: {}", e)),
+            Encoding::Cp1252 => Ok(bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9f => CP1252_HIGH[(b - 0x80) as usize],
+                    _ => b as char,
+                })
+                .collect()),
+        }
+    }
+
+    /// Returns the first character of the string that can't be represented in this encoding.
+    pub fn unrepresentable(self, s: &str) -> Option<char> {
+        match self {
+            Encoding::Utf8 => None,
+            Encoding::Cp1252 => s.chars().find(|&c| Self::cp1252_byte(c).is_none()),
+        }
+    }
+
+    /// Encodes text in this encoding, failing on the first character that can't be represented.
+    pub fn encode(self, s: &str) -> Result<Vec<u8>, ArmakeError> {
+        match self {
+            Encoding::Utf8 => Ok(s.as_bytes().to_vec()),
+            Encoding::Cp1252 => s
+                .chars()
+                .map(|c| {
+                    Self::cp1252_byte(c)
+                        .ok_or_else(|| aerror!("Character {:?} can't be represented in cp1252", c))
+                })
+                .collect(),
+        }
+    }
+
+    fn cp1252_byte(c: char) -> Option<u8> {
+        match c as u32 {
+            0..=0x7f | 0xa0..=0xff => Some(c as u8),
+            _ => CP1252_HIGH
+                .iter()
+                .position(|&h| h == c)
+                .map(|i| 0x80 + i as u8),
+        }
+    }
+}
+
+/// Writer that transcodes the UTF-8 written to it into an encoding
+///
+/// Characters split across writes are buffered until they are complete. `finish` has to be called
+/// after the last write.
+pub(crate) struct EncodingWriter<W: Write> {
+    inner: W,
+    encoding: Encoding,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> EncodingWriter<W> {
+    pub fn new(inner: W, encoding: Encoding) -> EncodingWriter<W> {
+        EncodingWriter {
+            inner,
+            encoding,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Flushes the inner writer, failing if the input ended within a character.
+    pub fn finish(mut self) -> Result<(), ArmakeError> {
+        if !self.pending.is_empty() {
+            return Err(aerror!("Output ended within a UTF-8 character"));
+        }
+        self.inner.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for EncodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.encoding == Encoding::Utf8 {
+            return self.inner.write(buf);
+        }
+
+        self.pending.extend_from_slice(buf);
+        let valid = match str::from_utf8(&self.pending) {
+            Ok(s) => s.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+
+        let text = str::from_utf8(&self.pending[..valid]).unwrap();
+        let encoded = self
+            .encoding
+            .encode(text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        self.inner.write_all(&encoded)?;
+        self.pending.drain(..valid);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
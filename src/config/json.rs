@@ -0,0 +1,206 @@
+//! JSON form of configs, see `Config::write_json`
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Read, Write};
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::config::{Config, ConfigValue};
+use crate::{aerror, ArmakeError};
+
+const PARENT: &str = "_parent";
+const EXTERN: &str = "_extern";
+const DELETE: &str = "_delete";
+const APPEND: &str = "_append";
+
+/// Serializes a `ConfigValue` in the JSON form
+struct Json<'a>(&'a ConfigValue);
+
+impl Serialize for Json<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            ConfigValue::Class { parent, entries } => {
+                let mut map = serializer.serialize_map(None)?;
+                if !parent.is_empty() {
+                    map.serialize_entry(PARENT, parent)?;
+                }
+                for (name, value) in entries {
+                    map.serialize_entry(name, &Json(value))?;
+                }
+                map.end()
+            }
+            ConfigValue::Array(values) => {
+                let mut seq = serializer.serialize_seq(Some(values.len()))?;
+                for value in values {
+                    seq.serialize_element(&Json(value))?;
+                }
+                seq.end()
+            }
+            ConfigValue::Expansion(values) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry(APPEND, &Json(&ConfigValue::Array(values.clone())))?;
+                map.end()
+            }
+            ConfigValue::String(s) => serializer.serialize_str(s),
+            ConfigValue::Int(i) => serializer.serialize_i32(*i),
+            ConfigValue::Float(f) => serializer.serialize_f32(*f),
+            ConfigValue::Extern | ConfigValue::Delete => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                let key = if *self.0 == ConfigValue::Extern {
+                    EXTERN
+                } else {
+                    DELETE
+                };
+                map.serialize_entry(key, &true)?;
+                map.end()
+            }
+        }
+    }
+}
+
+/// Deserializes a `ConfigValue` from the JSON form
+struct JsonValue(ConfigValue);
+
+impl<'de> Deserialize<'de> for JsonValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<JsonValue, D::Error> {
+        deserializer.deserialize_any(JsonVisitor)
+    }
+}
+
+struct JsonVisitor;
+
+impl<'de> Visitor<'de> for JsonVisitor {
+    type Value = JsonValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a class object, string, number or array")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<JsonValue, E> {
+        Ok(JsonValue(ConfigValue::String(value.to_string())))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<JsonValue, E> {
+        i32::try_from(value)
+            .map(|i| JsonValue(ConfigValue::Int(i)))
+            .map_err(|_| E::custom(format!("{} doesn't fit an int, write it as a float", value)))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<JsonValue, E> {
+        i32::try_from(value)
+            .map(|i| JsonValue(ConfigValue::Int(i)))
+            .map_err(|_| E::custom(format!("{} doesn't fit an int, write it as a float", value)))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<JsonValue, E> {
+        Ok(JsonValue(ConfigValue::Float(value as f32)))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<JsonValue, A::Error> {
+        let mut values = Vec::new();
+        while let Some(JsonValue(value)) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(JsonValue(ConfigValue::Array(values)))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsonValue, A::Error> {
+        let mut parent = String::new();
+        let mut special: Option<ConfigValue> = None;
+        let mut entries = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                PARENT => parent = map.next_value()?,
+                EXTERN | DELETE => {
+                    if !map.next_value::<bool>()? {
+                        return Err(de::Error::custom(format!("{} can only be true", key)));
+                    }
+                    special = Some(if key == EXTERN {
+                        ConfigValue::Extern
+                    } else {
+                        ConfigValue::Delete
+                    });
+                }
+                APPEND => match map.next_value()? {
+                    JsonValue(ConfigValue::Array(values)) => {
+                        special = Some(ConfigValue::Expansion(values))
+                    }
+                    _ => return Err(de::Error::custom(format!("{} has to be an array", APPEND))),
+                },
+                _ => {
+                    let JsonValue(value) = map.next_value()?;
+                    entries.push((key, value));
+                }
+            }
+        }
+
+        match special {
+            Some(_) if !parent.is_empty() || !entries.is_empty() => {
+                Err(de::Error::custom(format!(
+                    "{}, {} and {} can't be combined with other keys",
+                    EXTERN, DELETE, APPEND
+                )))
+            }
+            Some(value) => Ok(JsonValue(value)),
+            None => Ok(JsonValue(ConfigValue::Class { parent, entries })),
+        }
+    }
+}
+
+/// Fails if an entry has the name of a reserved key.
+fn check_names(value: &ConfigValue, path: &mut Vec<String>) -> Result<(), ArmakeError> {
+    if let ConfigValue::Class { entries, .. } = value {
+        for (name, value) in entries {
+            path.push(name.clone());
+            if [PARENT, EXTERN, DELETE, APPEND]
+                .iter()
+                .any(|k| k.eq_ignore_ascii_case(name))
+            {
+                return Err(aerror!(
+                    "{} can't be written as JSON, its name is reserved",
+                    path.join(" >> ")
+                ));
+            }
+            check_names(value, path)?;
+            path.pop();
+        }
+    }
+    Ok(())
+}
+
+impl Config {
+    /// Writes the config as pretty-printed JSON.
+    ///
+    /// Classes are objects with their entries as keys in config order, properties are JSON
+    /// strings, numbers and arrays. A few keys are reserved for what JSON can't express otherwise:
+    ///
+    /// ```text
+    /// {
+    ///   "CfgPatches": {"my_addon": {"units": [], "requiredVersion": 2.0}},
+    ///   "Derived": {"_parent": "Base", "value": 1},
+    ///   "Base": {"_extern": true},
+    ///   "Old": {"_delete": true},
+    ///   "list": {"_append": ["x"]}
+    /// }
+    /// ```
+    ///
+    /// Floats are always written with a fraction or exponent, so they read back as floats.
+    /// Entries named like a reserved key can't be written.
+    pub fn write_json<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        let value = self.to_value();
+        check_names(&value, &mut Vec::new())?;
+        serde_json::to_writer_pretty(&mut *output, &Json(&value))
+            .map_err(|e| aerror!("Failed to write JSON: {}", e))?;
+        output.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Reads a config from the JSON form written by `write_json`.
+    pub fn read_json<I: Read>(input: &mut I) -> Result<Config, ArmakeError> {
+        let JsonValue(value) = serde_json::from_reader(input)
+            .map_err(|e| aerror!("Failed to read JSON config: {}", e))?;
+        Config::from_value(value)
+    }
+}
@@ -0,0 +1,122 @@
+use crate::config::{Config, ConfigClass, ConfigEntry};
+use crate::pbo::matches_glob;
+use crate::{aerror, ArmakeError};
+
+/// Splits a config path at `/` or `>>`, ignoring whitespace around the names.
+pub fn split_path(path: &str) -> Vec<&str> {
+    path.split(">>")
+        .flat_map(|part| part.split('/'))
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+impl ConfigClass {
+    /// Returns the entry with the given name, compared case-insensitively like the game does.
+    /// Inherited entries are not considered.
+    pub fn get(&self, name: &str) -> Option<&ConfigEntry> {
+        self.entries
+            .as_ref()?
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, e)| e)
+    }
+
+    /// Returns the entries whose name matches the glob pattern, compared case-insensitively, in
+    /// the order they are defined.
+    pub fn matching(&self, pattern: &str) -> Vec<(&str, &ConfigEntry)> {
+        let pattern = pattern.to_lowercase();
+        self.entries
+            .iter()
+            .flatten()
+            .filter(|(n, _)| matches_glob(&n.to_lowercase(), &pattern))
+            .map(|(n, e)| (n.as_str(), e))
+            .collect()
+    }
+}
+
+/// Number of class names listed when a class is not found
+const MAX_LISTED_CLASSES: usize = 20;
+
+/// Lists the names of the classes in `class` at `path` for errors.
+fn list_classes(class: &ConfigClass, path: &[&str]) -> String {
+    let names: Vec<&str> = class
+        .entries
+        .iter()
+        .flatten()
+        .filter(|(_, e)| matches!(e, ConfigEntry::ClassEntry(c) if !c.is_deletion))
+        .map(|(n, _)| n.as_str())
+        .collect();
+    let location = if path.is_empty() {
+        String::from("at the top level")
+    } else {
+        format!("in {}", path.join(" >> "))
+    };
+    if names.is_empty() {
+        return format!("there are no classes {}", location);
+    }
+
+    let mut list = names[..names.len().min(MAX_LISTED_CLASSES)].join(", ");
+    if names.len() > MAX_LISTED_CLASSES {
+        list.push_str(&format!(" and {} more", names.len() - MAX_LISTED_CLASSES));
+    }
+    format!("classes {}: {}", location, list)
+}
+
+impl Config {
+    /// Returns the class at the given path of class names, see `split_path`. An empty path is
+    /// the root of the config.
+    pub fn class(&self, path: &[&str]) -> Result<&ConfigClass, ArmakeError> {
+        let mut class = &self.root_body;
+        for (i, name) in path.iter().enumerate() {
+            class = match class.get(name) {
+                Some(ConfigEntry::ClassEntry(c)) if c.entries.is_some() => c,
+                Some(ConfigEntry::ClassEntry(_)) => {
+                    return Err(aerror!("{} has no body", path[..=i].join(" >> ")))
+                }
+                Some(_) => return Err(aerror!("{} is not a class", path[..=i].join(" >> "))),
+                None => {
+                    return Err(aerror!(
+                        "{} not found, {}",
+                        path[..=i].join(" >> "),
+                        list_classes(class, &path[..i])
+                    ))
+                }
+            };
+        }
+        Ok(class)
+    }
+
+    /// Removes everything but the class at the given path and the classes enclosing it, see
+    /// `class`. The enclosing classes keep their parents.
+    pub fn retain_class(&mut self, path: &[&str]) -> Result<(), ArmakeError> {
+        self.class(path)?;
+        let mut class = &mut self.root_body;
+        for name in path {
+            let entries = class.entries.as_mut().unwrap();
+            let index = entries
+                .iter()
+                .position(|(n, _)| n.eq_ignore_ascii_case(name))
+                .unwrap();
+            let entry = entries.swap_remove(index);
+            entries.clear();
+            entries.push(entry);
+            class = match &mut entries[0].1 {
+                ConfigEntry::ClassEntry(c) => c,
+                _ => unreachable!(),
+            };
+        }
+        Ok(())
+    }
+
+    /// Returns the entry at the given path, for example `CfgPatches/my_addon/requiredAddons`.
+    pub fn get(&self, path: &str) -> Result<&ConfigEntry, ArmakeError> {
+        let path = split_path(path);
+        let (name, classes) = path
+            .split_last()
+            .ok_or_else(|| aerror!("Config path is empty"))?;
+        self.class(classes)?
+            .get(name)
+            .ok_or_else(|| aerror!("{} not found", path.join(" >> ")))
+    }
+}
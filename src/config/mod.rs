@@ -0,0 +1,1134 @@
+//! Functions for rapifying and derapifying Arma configs
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{sink, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::iter::Sum;
+use std::path::PathBuf;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::io::*;
+use crate::preprocess::*;
+use crate::*;
+
+use crate::error::{ConfigParseError, ConfigSyntaxError};
+use crate::warning::{self, Warning};
+use crate::ArmakeError;
+
+mod encoding;
+pub use encoding::Encoding;
+use encoding::EncodingWriter;
+mod json;
+mod lookup;
+pub use lookup::split_path;
+
+mod recovery;
+pub use recovery::set_max_errors;
+mod source_map;
+use source_map::line_origin;
+pub use source_map::{SourceLocation, SourceMap};
+mod stats;
+pub use stats::ConfigStats;
+
+mod value;
+pub use value::ConfigValue;
+
+pub mod config_grammar {
+    #![allow(missing_docs)]
+    #![allow(clippy::all, ellipsis_inclusive_range_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/config_grammar.rs"));
+}
+
+/// Config
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::Config;
+/// let input = String::from("
+/// #define bar 42
+///
+/// foo = bar;
+/// ");
+///
+/// let config = Config::from_string(input, None, &Vec::new(), |path| {
+///     std::fs::read_to_string(path).unwrap()
+/// })
+/// .expect("Failed to parse config");
+///
+/// assert_eq!("foo = 42;\n", config.to_string().unwrap());
+/// assert_eq!(b"\0raP", &config.to_cursor().unwrap().into_inner()[..4]);
+/// ```
+#[derive(Debug)]
+pub struct Config {
+    pub root_body: ConfigClass,
+}
+
+/// Config class
+#[derive(Debug)]
+pub struct ConfigClass {
+    pub parent: String,
+    pub is_external: bool,
+    pub is_deletion: bool,
+    pub entries: Option<Vec<(String, ConfigEntry)>>,
+}
+
+/// Config entry
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum ConfigEntry {
+    /// String entry
+    StringEntry(String),
+    /// Float entry
+    FloatEntry(f32),
+    /// Int entry
+    IntEntry(i32),
+    /// Array entry
+    ArrayEntry(ConfigArray),
+    /// Class entry
+    ClassEntry(ConfigClass),
+}
+
+/// Config array
+#[derive(Debug)]
+pub struct ConfigArray {
+    pub is_expansion: bool,
+    pub elements: Vec<ConfigArrayElement>,
+}
+
+/// Config array element
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum ConfigArrayElement {
+    /// String element
+    StringElement(String),
+    /// Float element
+    FloatElement(f32),
+    /// Int element
+    IntElement(i32),
+    /// Array element
+    ArrayElement(ConfigArray),
+}
+
+impl ConfigArrayElement {
+    pub fn rapified_length(&self) -> usize {
+        match self {
+            ConfigArrayElement::StringElement(s) => s.len() + 2,
+            ConfigArrayElement::FloatElement(_f) => 5,
+            ConfigArrayElement::IntElement(_i) => 5,
+            ConfigArrayElement::ArrayElement(a) => {
+                1 + compressed_int_len(a.elements.len() as u32)
+                    + usize::sum(a.elements.iter().map(|e| e.rapified_length()))
+            }
+        }
+    }
+}
+
+/// Returns the names of the arrays in `entries` that are both assigned with `=` and appended to
+/// with `+=`. Names are compared ignoring case, like the game does.
+pub(crate) fn mixed_array_appends(entries: &[(String, ConfigEntry)]) -> Vec<&str> {
+    let mut assigned: HashMap<String, &str> = HashMap::new();
+    let mut appended: HashMap<String, &str> = HashMap::new();
+    for (name, entry) in entries {
+        if let ConfigEntry::ArrayEntry(array) = entry {
+            let map = if array.is_expansion {
+                &mut appended
+            } else {
+                &mut assigned
+            };
+            map.entry(name.to_lowercase()).or_insert(name);
+        }
+    }
+    let mut mixed: Vec<&str> = assigned
+        .iter()
+        .filter(|(lower, _)| appended.contains_key(*lower))
+        .map(|(_, name)| *name)
+        .collect();
+    mixed.sort_unstable();
+    mixed
+}
+
+/// Shortens a file from the line origins for messages, making it relative to the working
+/// directory if it is in it.
+fn clean_file(file: Option<String>) -> Option<String> {
+    file.map(|f| {
+        f.trim_start_matches("\\\\?\\")
+            .trim_start_matches(
+                &std::env::current_dir()
+                    .map(|d| d.display().to_string())
+                    .unwrap_or_default(),
+            )
+            .to_string()
+    })
+}
+
+/// Writes the indentation for the given nesting level.
+fn write_indent<O: Write>(output: &mut O, level: i32) -> Result<(), ArmakeError> {
+    write!(output, "{:1$}", "", 4 * level.max(0) as usize)?;
+    Ok(())
+}
+
+/// Writes a quoted string, escaping quotes and line breaks.
+fn write_string<O: Write>(output: &mut O, s: &str) -> Result<(), ArmakeError> {
+    output.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, b) in s.bytes().enumerate() {
+        let escaped: &[u8] = match b {
+            b'"' => b"\"\"",
+            b'\r' => b"\\r",
+            b'\n' => b"\\n",
+            _ => continue,
+        };
+        output.write_all(&s.as_bytes()[start..i])?;
+        output.write_all(escaped)?;
+        start = i + 1;
+    }
+    output.write_all(&s.as_bytes()[start..])?;
+    output.write_all(b"\"")?;
+    Ok(())
+}
+
+impl ConfigArray {
+    pub fn write<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        output.write_all(b"{")?;
+        for (key, value) in self.elements.iter().enumerate() {
+            match value {
+                ConfigArrayElement::ArrayElement(ref a) => {
+                    a.write(output)?;
+                }
+                ConfigArrayElement::StringElement(s) => {
+                    write_string(output, s)?;
+                }
+                ConfigArrayElement::FloatElement(f) => {
+                    write!(output, "{:?}", f)?;
+                }
+                ConfigArrayElement::IntElement(i) => {
+                    write!(output, "{}", i)?;
+                }
+            }
+            if key < self.elements.len() - 1 {
+                output.write_all(b", ")?;
+            }
+        }
+        output.write_all(b"}")?;
+        Ok(())
+    }
+
+    pub fn write_rapified<O: Write>(&self, output: &mut O) -> Result<usize, ArmakeError> {
+        let mut written = output.write_compressed_int(self.elements.len() as u32)?;
+
+        for element in &self.elements {
+            match element {
+                ConfigArrayElement::StringElement(s) => {
+                    output.write_all(&[0])?;
+                    output.write_cstring(s)?;
+                    written += s.len() + 2;
+                }
+                ConfigArrayElement::FloatElement(f) => {
+                    output.write_all(&[1])?;
+                    output.write_f32::<LittleEndian>(*f)?;
+                    written += 5;
+                }
+                ConfigArrayElement::IntElement(i) => {
+                    output.write_all(&[2])?;
+                    output.write_i32::<LittleEndian>(*i)?;
+                    written += 5;
+                }
+                ConfigArrayElement::ArrayElement(a) => {
+                    output.write_all(&[3])?;
+                    written += 1 + a.write_rapified(output)?;
+                }
+            }
+        }
+
+        Ok(written)
+    }
+
+    pub fn read_rapified<I: Read + Seek>(input: &mut I) -> Result<ConfigArray, ArmakeError> {
+        let num_elements: u32 = input.read_compressed_int()?;
+        let mut elements: Vec<ConfigArrayElement> = Vec::with_capacity(num_elements as usize);
+
+        for _i in 0..num_elements {
+            let element_type: u8 = input.read_u8()?;
+
+            if element_type == 0 {
+                elements.push(ConfigArrayElement::StringElement(input.read_cstring()?));
+            } else if element_type == 1 {
+                elements.push(ConfigArrayElement::FloatElement(
+                    input.read_f32::<LittleEndian>()?,
+                ));
+            } else if element_type == 2 {
+                elements.push(ConfigArrayElement::IntElement(
+                    input.read_i32::<LittleEndian>()?,
+                ));
+            } else if element_type == 3 {
+                elements.push(ConfigArrayElement::ArrayElement(
+                    ConfigArray::read_rapified(input)?,
+                ));
+            } else {
+                return Err(aerror!("Unrecognized array element type: {}", element_type));
+            }
+        }
+
+        Ok(ConfigArray {
+            is_expansion: false,
+            elements,
+        })
+    }
+}
+
+impl ConfigEntry {
+    // without the name
+    pub fn rapified_length(&self) -> usize {
+        match self {
+            ConfigEntry::StringEntry(s) => s.len() + 3,
+            ConfigEntry::FloatEntry(_f) => 6,
+            ConfigEntry::IntEntry(_i) => 6,
+            ConfigEntry::ArrayEntry(a) => {
+                let len = 1
+                    + compressed_int_len(a.elements.len() as u32)
+                    + usize::sum(a.elements.iter().map(|e| e.rapified_length()));
+                if a.is_expansion {
+                    len + 4
+                } else {
+                    len
+                }
+            }
+            ConfigEntry::ClassEntry(c) => {
+                if c.is_external || c.is_deletion {
+                    1
+                } else {
+                    5
+                }
+            }
+        }
+    }
+}
+
+/// Writes the start of a class with a body, or the whole class if it is empty.
+fn write_class_start<O: Write>(
+    output: &mut O,
+    name: &str,
+    parent: &str,
+    empty: bool,
+) -> Result<(), ArmakeError> {
+    write!(output, "class {}", name)?;
+    if !parent.is_empty() {
+        write!(output, ": {}", parent)?;
+    }
+    if empty {
+        output.write_all(b" {};\n")?;
+    } else {
+        output.write_all(b" {")?;
+    }
+    Ok(())
+}
+
+impl ConfigEntry {
+    /// Writes the entry with the given name, including its indentation.
+    pub fn write<O: Write>(
+        &self,
+        output: &mut O,
+        name: &str,
+        level: i32,
+    ) -> Result<(), ArmakeError> {
+        write_indent(output, level)?;
+
+        match self {
+            ConfigEntry::ClassEntry(ref c) => {
+                if c.is_deletion {
+                    writeln!(output, "delete {};", name)?;
+                } else if c.is_external {
+                    writeln!(output, "class {};", name)?;
+                } else {
+                    match &c.entries {
+                        Some(entries) if !entries.is_empty() => {
+                            write_class_start(output, name, &c.parent, false)?;
+                            c.write(output, level + 1)?;
+                            write_indent(output, level)?;
+                            output.write_all(b"};\n")?;
+                        }
+                        _ => write_class_start(output, name, &c.parent, true)?,
+                    }
+                }
+            }
+            ConfigEntry::StringEntry(s) => {
+                write!(output, "{} = ", name)?;
+                write_string(output, s)?;
+                output.write_all(b";\n")?;
+            }
+            ConfigEntry::FloatEntry(f) => {
+                writeln!(output, "{} = {:?};", name, f)?;
+            }
+            ConfigEntry::IntEntry(i) => {
+                writeln!(output, "{} = {};", name, i)?;
+            }
+            ConfigEntry::ArrayEntry(ref a) => {
+                if a.is_expansion {
+                    write!(output, "{}[] += ", name)?;
+                } else {
+                    write!(output, "{}[] = ", name)?;
+                }
+                a.write(output)?;
+                output.write_all(b";\n")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ConfigClass {
+    pub fn write<O: Write>(&self, output: &mut O, level: i32) -> Result<(), ArmakeError> {
+        if let Some(entries) = &self.entries {
+            if level > 0 && !entries.is_empty() {
+                output.write_all(b"\n")?;
+            }
+            for (key, value) in entries {
+                value.write(output, key, level)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn rapified_length(&self) -> usize {
+        match &self.entries {
+            Some(entries) => {
+                self.parent.len()
+                    + 1
+                    + compressed_int_len(entries.len() as u32)
+                    + usize::sum(entries.iter().map(|(k, v)| {
+                        k.len()
+                            + 1
+                            + v.rapified_length()
+                            + match v {
+                                ConfigEntry::ClassEntry(c) => c.rapified_length(),
+                                _ => 0,
+                            }
+                    }))
+            }
+            None => 0,
+        }
+    }
+
+    pub fn write_rapified<O: Write>(
+        &self,
+        output: &mut O,
+        offset: usize,
+    ) -> Result<usize, ArmakeError> {
+        let mut written = 0;
+
+        match &self.entries {
+            Some(entries) => {
+                output.write_cstring(&self.parent)?;
+                written += self.parent.len() + 1;
+
+                written += output.write_compressed_int(entries.len() as u32)?;
+
+                let entries_len = usize::sum(
+                    entries
+                        .iter()
+                        .map(|(k, v)| k.len() + 1 + v.rapified_length()),
+                );
+                let mut class_offset = offset + written + entries_len;
+                let mut class_bodies: Vec<Cursor<Box<[u8]>>> = Vec::new();
+                let pre_entries = written;
+
+                for (name, entry) in entries {
+                    let pre_write = written;
+                    match entry {
+                        ConfigEntry::StringEntry(s) => {
+                            output.write_all(&[1, 0])?;
+                            output.write_cstring(name)?;
+                            output.write_cstring(s)?;
+                            written += name.len() + s.len() + 4;
+                        }
+                        ConfigEntry::FloatEntry(f) => {
+                            output.write_all(&[1, 1])?;
+                            output.write_cstring(name)?;
+                            output.write_f32::<LittleEndian>(*f)?;
+                            written += name.len() + 7;
+                        }
+                        ConfigEntry::IntEntry(i) => {
+                            output.write_all(&[1, 2])?;
+                            output.write_cstring(name)?;
+                            output.write_i32::<LittleEndian>(*i)?;
+                            written += name.len() + 7;
+                        }
+                        ConfigEntry::ArrayEntry(a) => {
+                            output.write_all(if a.is_expansion { &[5] } else { &[2] })?;
+                            if a.is_expansion {
+                                output.write_all(&[1, 0, 0, 0])?;
+                                written += 4;
+                            }
+                            output.write_cstring(name)?;
+                            written += name.len() + 2 + a.write_rapified(output)?;
+                        }
+                        ConfigEntry::ClassEntry(c) => {
+                            if c.is_external || c.is_deletion {
+                                output.write_all(if c.is_deletion { &[4] } else { &[3] })?;
+                                output.write_cstring(name)?;
+                                written += name.len() + 2;
+                            } else {
+                                output.write_all(&[0])?;
+                                output.write_cstring(name)?;
+                                output.write_u32::<LittleEndian>(class_offset as u32)?;
+                                written += name.len() + 6;
+
+                                let buffer: Box<[u8]> =
+                                    vec![0; c.rapified_length()].into_boxed_slice();
+                                let mut cursor: Cursor<Box<[u8]>> = Cursor::new(buffer);
+                                class_offset += c.write_rapified(&mut cursor, class_offset)?;
+                                class_bodies.push(cursor);
+                            }
+                        }
+                    }
+                    assert_eq!(
+                        written - pre_write,
+                        entry.rapified_length() + name.len() + 1
+                    );
+                }
+
+                assert_eq!(written - pre_entries, entries_len);
+
+                for cursor in class_bodies {
+                    output.write_all(cursor.get_ref())?;
+                    written += cursor.get_ref().len();
+                }
+            }
+            None => unreachable!(),
+        }
+
+        Ok(written)
+    }
+
+    pub fn read_rapified<I: Read + Seek>(
+        input: &mut I,
+        level: u32,
+    ) -> Result<ConfigClass, ArmakeError> {
+        let mut fp = 0;
+        if level == 0 {
+            input.seek(SeekFrom::Start(16))?;
+        } else {
+            let classbody_fp: u32 = input.read_u32::<LittleEndian>()?;
+
+            fp = input.stream_position()?;
+            input.seek(SeekFrom::Start(classbody_fp.into()))?;
+        }
+
+        let parent = input.read_cstring()?;
+        let num_entries: u32 = input.read_compressed_int()?;
+        let mut entries: Vec<(String, ConfigEntry)> = Vec::with_capacity(num_entries as usize);
+
+        for _i in 0..num_entries {
+            let entry_type: u8 = input.read_u8()?;
+
+            if entry_type == 0 {
+                let name = input.read_cstring()?;
+
+                let class_entry = ConfigClass::read_rapified(input, level + 1)?;
+                entries.push((name, ConfigEntry::ClassEntry(class_entry)));
+            } else {
+                entries.push(ConfigClass::read_rapified_entry(input, entry_type)?);
+            }
+        }
+
+        if level > 0 {
+            input.seek(SeekFrom::Start(fp))?;
+        }
+
+        Ok(ConfigClass {
+            parent,
+            is_external: false,
+            is_deletion: false,
+            entries: Some(entries),
+        })
+    }
+
+    /// Reads an entry of the given type from the input, the type byte already being read. Classes
+    /// with a body (type 0) are read by `read_rapified`.
+    fn read_rapified_entry<I: Read + Seek>(
+        input: &mut I,
+        entry_type: u8,
+    ) -> Result<(String, ConfigEntry), ArmakeError> {
+        if entry_type == 1 {
+            let subtype: u8 = input.read_u8()?;
+            let name = input.read_cstring()?;
+
+            if subtype == 0 {
+                Ok((name, ConfigEntry::StringEntry(input.read_cstring()?)))
+            } else if subtype == 1 {
+                Ok((
+                    name,
+                    ConfigEntry::FloatEntry(input.read_f32::<LittleEndian>()?),
+                ))
+            } else if subtype == 2 {
+                Ok((
+                    name,
+                    ConfigEntry::IntEntry(input.read_i32::<LittleEndian>()?),
+                ))
+            } else {
+                Err(aerror!("Unrecognized variable entry subtype: {}.", subtype))
+            }
+        } else if entry_type == 2 || entry_type == 5 {
+            if entry_type == 5 {
+                input.seek(SeekFrom::Current(4))?;
+            }
+
+            let name = input.read_cstring()?;
+            let mut array = ConfigArray::read_rapified(input)?;
+            array.is_expansion = entry_type == 5;
+
+            Ok((name, ConfigEntry::ArrayEntry(array)))
+        } else if entry_type == 3 || entry_type == 4 {
+            let name = input.read_cstring()?;
+            let class_entry = ConfigClass {
+                parent: String::from(""),
+                is_external: entry_type == 3,
+                is_deletion: entry_type == 4,
+                entries: None,
+            };
+
+            Ok((name, ConfigEntry::ClassEntry(class_entry)))
+        } else {
+            Err(aerror!("Unrecognized class entry type: {}.", entry_type))
+        }
+    }
+}
+
+/// Returns the first character of the entry's strings that can't be represented in the encoding.
+fn unrepresentable(entry: &ConfigEntry, encoding: Encoding) -> Option<char> {
+    fn in_elements(elements: &[ConfigArrayElement], encoding: Encoding) -> Option<char> {
+        elements.iter().find_map(|e| match e {
+            ConfigArrayElement::StringElement(s) => encoding.unrepresentable(s),
+            ConfigArrayElement::ArrayElement(a) => in_elements(&a.elements, encoding),
+            _ => None,
+        })
+    }
+
+    match entry {
+        ConfigEntry::StringEntry(s) => encoding.unrepresentable(s),
+        ConfigEntry::ArrayEntry(a) => in_elements(&a.elements, encoding),
+        _ => None,
+    }
+}
+
+/// Writes the `num_entries` entries of the rapified class body at the current position of the
+/// input, reading one entry at a time.
+///
+/// If `check` is given, nothing is written and the strings are checked to be representable in the
+/// encoding instead. `path` is the class path of the body, used in the resulting error. If
+/// `annotate` is set, every line of an entry ends with a comment containing the offset of the entry
+/// and, for classes, the offset of the body.
+fn derapify_body<I: Read + Seek, O: Write>(
+    input: &mut I,
+    output: &mut O,
+    num_entries: u32,
+    level: i32,
+    path: &str,
+    check: Option<Encoding>,
+    annotate: bool,
+) -> Result<(), ArmakeError> {
+    if level > 0 && num_entries > 0 && check.is_none() {
+        output.write_all(b"\n")?;
+    }
+
+    for _i in 0..num_entries {
+        let offset = input.stream_position()?;
+        let entry_type: u8 = input.read_u8()?;
+
+        if entry_type != 0 {
+            let (name, entry) = ConfigClass::read_rapified_entry(input, entry_type)?;
+            match check {
+                Some(encoding) => {
+                    if let Some(c) = unrepresentable(&entry, encoding) {
+                        return Err(aerror!(
+                            "{}{}: character {:?} can't be represented in {}",
+                            path,
+                            name,
+                            c,
+                            encoding.name()
+                        ));
+                    }
+                }
+                None if annotate => {
+                    let mut line = Vec::new();
+                    entry.write(&mut line, &name, level)?;
+                    output.write_all(&line[..line.len() - 1])?;
+                    writeln!(output, " // @{:#x}", offset)?;
+                }
+                None => entry.write(output, &name, level)?,
+            }
+            continue;
+        }
+
+        let name = input.read_cstring()?;
+        let classbody_fp: u32 = input.read_u32::<LittleEndian>()?;
+        let fp = input.stream_position()?;
+        input.seek(SeekFrom::Start(classbody_fp.into()))?;
+
+        let parent = input.read_cstring()?;
+        let class_entries: u32 = input.read_compressed_int()?;
+        if check.is_none() && annotate {
+            write_indent(output, level)?;
+            write_class_start(output, &name, &parent, false)?;
+            if class_entries == 0 {
+                output.write_all(b"};")?;
+            }
+            write!(output, " // @{:#x} -> {:#x}", offset, classbody_fp)?;
+            if class_entries == 0 {
+                output.write_all(b"\n")?;
+            }
+        } else if check.is_none() {
+            write_indent(output, level)?;
+            write_class_start(output, &name, &parent, class_entries == 0)?;
+        }
+        if class_entries > 0 {
+            let path = format!("{}{} >> ", path, name);
+            derapify_body(
+                input,
+                output,
+                class_entries,
+                level + 1,
+                &path,
+                check,
+                annotate,
+            )?;
+            if check.is_none() {
+                write_indent(output, level)?;
+                output.write_all(b"};\n")?;
+            }
+        }
+
+        input.seek(SeekFrom::Start(fp))?;
+    }
+
+    Ok(())
+}
+
+impl Config {
+    /// Writes the config (unrapified) to the output.
+    ///
+    /// The output is buffered, so it doesn't have to be.
+    pub fn write<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        let mut writer = BufWriter::new(output);
+        self.root_body.write(&mut writer, 0)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the config (unrapified) to the output in the given encoding.
+    ///
+    /// Fails without writing anything if a string contains a character that can't be represented
+    /// in the encoding.
+    pub fn write_encoded<O: Write>(
+        &self,
+        output: &mut O,
+        encoding: Encoding,
+    ) -> Result<(), ArmakeError> {
+        for (path, _, value) in crate::lint::config_strings(self) {
+            if let Some(c) = encoding.unrepresentable(value) {
+                return Err(aerror!(
+                    "{}: character {:?} can't be represented in {}",
+                    path,
+                    c,
+                    encoding.name()
+                ));
+            }
+        }
+
+        let mut writer = EncodingWriter::new(output, encoding);
+        self.write(&mut writer)?;
+        writer.finish()
+    }
+
+    /// Returns the unrapified config as a string.
+    pub fn to_string(&self) -> Result<String, ArmakeError> {
+        let buffer = Vec::new();
+        let mut cursor: Cursor<Vec<u8>> = Cursor::new(buffer);
+        self.write(&mut cursor)?;
+
+        Ok(String::from_utf8(cursor.into_inner()).unwrap())
+    }
+
+    /// Writes the rapified config to the output.
+    pub fn write_rapified<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        let mut writer = BufWriter::new(output);
+
+        writer.write_all(b"\0raP")?;
+        writer.write_all(b"\0\0\0\0\x08\0\0\0")?; // always_0, always_8
+
+        let buffer: Box<[u8]> = vec![0; self.root_body.rapified_length()].into_boxed_slice();
+        let mut cursor: Cursor<Box<[u8]>> = Cursor::new(buffer);
+        self.root_body.write_rapified(&mut cursor, 16)?;
+
+        let enum_offset: u32 = 16 + cursor.get_ref().len() as u32;
+        writer.write_u32::<LittleEndian>(enum_offset)?;
+
+        writer.write_all(cursor.get_ref())?;
+
+        writer.write_all(b"\0\0\0\0")?;
+
+        Ok(())
+    }
+
+    /// Returns the rapified config as a `Cursor`.
+    pub fn to_cursor(&self) -> Result<Cursor<Box<[u8]>>, ArmakeError> {
+        let len = self.root_body.rapified_length() + 20;
+
+        let buffer: Box<[u8]> = vec![0; len].into_boxed_slice();
+        let mut cursor: Cursor<Box<[u8]>> = Cursor::new(buffer);
+        self.write_rapified(&mut cursor)?;
+
+        Ok(cursor)
+    }
+
+    /// Reads the unrapified config from input, preprocessing it.
+    ///
+    /// `path` is the path to the input if it is known and is used for relative includes and error
+    /// messages. `includefolders` are the folders searched for absolute includes and should usually at
+    /// least include the current working directory.
+    pub fn read<I: Read, F>(
+        input: &mut I,
+        path: Option<PathBuf>,
+        includefolders: &[PathBuf],
+        fileread: F,
+    ) -> Result<Config, ArmakeError>
+    where
+        F: Fn(&PathBuf) -> String,
+        F: Copy,
+    {
+        Self::read_with_options(
+            input,
+            path,
+            includefolders,
+            fileread,
+            &PreprocessOptions::default(),
+        )
+    }
+
+    /// Same as `read`, with additional options for the preprocessor.
+    pub fn read_with_options<I: Read, F>(
+        input: &mut I,
+        path: Option<PathBuf>,
+        includefolders: &[PathBuf],
+        fileread: F,
+        options: &PreprocessOptions,
+    ) -> Result<Config, ArmakeError>
+    where
+        F: Fn(&PathBuf) -> String,
+        F: Copy,
+    {
+        let mut buffer = String::new();
+        input.read_to_string(&mut buffer)?;
+
+        let mut info = PreprocessInfo::default();
+        let preprocessed = preprocess_with_options(
+            buffer.clone(),
+            path.clone(),
+            includefolders,
+            fileread,
+            options,
+            &mut info,
+        )?;
+
+        Self::parse(&preprocessed, buffer, path, Some(&info))
+    }
+
+    /// Same as `read_with_options`, also returning the source map of the config.
+    pub fn read_with_source_map<I: Read, F>(
+        input: &mut I,
+        path: Option<PathBuf>,
+        includefolders: &[PathBuf],
+        fileread: F,
+        options: &PreprocessOptions,
+    ) -> Result<(Config, SourceMap), ArmakeError>
+    where
+        F: Fn(&PathBuf) -> String,
+        F: Copy,
+    {
+        let mut buffer = String::new();
+        input.read_to_string(&mut buffer)?;
+
+        let mut info = PreprocessInfo::default();
+        let preprocessed = preprocess_with_options(
+            buffer.clone(),
+            path.clone(),
+            includefolders,
+            fileread,
+            options,
+            &mut info,
+        )?;
+
+        let (config, positions) =
+            Self::parse_with_positions(&preprocessed, buffer, path.clone(), Some(&info))?;
+        let map = SourceMap::build(
+            &config,
+            &positions,
+            &preprocessed,
+            path.as_ref(),
+            Some(&info),
+        )?;
+        Ok((config, map))
+    }
+
+    /// Reads the unrapified config from input without preprocessing it.
+    ///
+    /// The input is handed to the config parser as-is, so any preprocessor directives or macros
+    /// remaining in it result in parse errors. `path` is only used for error messages.
+    pub fn read_unpreprocessed<I: Read>(
+        input: &mut I,
+        path: Option<PathBuf>,
+    ) -> Result<Config, ArmakeError> {
+        let mut buffer = String::new();
+        input.read_to_string(&mut buffer)?;
+
+        Self::parse(&buffer.clone(), buffer, path, None)
+    }
+
+    fn parse(
+        input: &str,
+        original: String,
+        path: Option<PathBuf>,
+        info: Option<&PreprocessInfo>,
+    ) -> Result<Config, ArmakeError> {
+        Self::parse_with_positions(input, original, path, info).map(|(config, _)| config)
+    }
+
+    /// Parses the config, also returning the byte offsets and names of all entries in the order
+    /// they were parsed.
+    fn parse_with_positions(
+        input: &str,
+        original: String,
+        path: Option<PathBuf>,
+        info: Option<&PreprocessInfo>,
+    ) -> Result<(Config, Vec<(usize, String)>), ArmakeError> {
+        let mut warnings: Vec<(usize, String, &'static str)> = Vec::new();
+        let mut positions: Vec<(usize, String)> = Vec::new();
+
+        let result = match config_grammar::config(input, &mut warnings, &mut positions) {
+            Ok(result) => result,
+            Err(source) => {
+                let (errors, truncated) =
+                    Self::recover(input, &original, &source, path.as_ref(), info);
+                return Err(ArmakeError::CONFIG(Box::new(ConfigParseError {
+                    path: Some(
+                        path.clone()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string(),
+                    ),
+                    message: original,
+                    source,
+                    errors,
+                    truncated,
+                })));
+            }
+        };
+
+        for (position, message, name) in warnings {
+            let line = input[..position].chars().filter(|c| c == &'\n').count();
+            let (line, file) = line_origin(line, path.as_ref(), info);
+            warning::raise(Warning::new(name, message).at(clean_file(file), line));
+        }
+
+        Ok((result, positions))
+    }
+
+    /// Collects the syntax errors of input that failed to parse with `first`, see the `recovery`
+    /// module. Returns the errors and whether the error limit was reached.
+    ///
+    /// `original` is the input before preprocessing. It and the included files are only used to
+    /// find the indentation of lines, which the preprocessor removes.
+    fn recover(
+        input: &str,
+        original: &str,
+        first: &config_grammar::ParseError,
+        path: Option<&PathBuf>,
+        info: Option<&PreprocessInfo>,
+    ) -> (Vec<ConfigSyntaxError>, bool) {
+        let locate = |input: &str, offset: usize, message: String| {
+            let line = input[..offset].matches('\n').count();
+            let (line, file) = line_origin(line, path, info);
+            ConfigSyntaxError {
+                file: clean_file(file),
+                line,
+                message,
+            }
+        };
+
+        let included: RefCell<HashMap<PathBuf, Option<String>>> = RefCell::new(HashMap::new());
+        let indentation = |offset: usize| {
+            let line = input[..offset].matches('\n').count();
+            let origin = match info {
+                Some(info) if !info.line_origins.is_empty() => {
+                    &info.line_origins[line.min(info.line_origins.len() - 1)]
+                }
+                _ => return input.lines().nth(line).map(recovery::indentation),
+            };
+            let index = (origin.0 as usize).checked_sub(1)?;
+            match &origin.1 {
+                Some(file) if Some(file) != path => {
+                    let mut included = included.borrow_mut();
+                    let content = included
+                        .entry(file.clone())
+                        .or_insert_with(|| std::fs::read_to_string(file).ok());
+                    content
+                        .as_ref()?
+                        .lines()
+                        .nth(index)
+                        .map(recovery::indentation)
+                }
+                _ => original.lines().nth(index).map(recovery::indentation),
+            }
+        };
+
+        let max = recovery::max_errors();
+        let mut errors = Vec::new();
+        let mut input = input.to_string();
+        while let Some((statement, offset, message)) = recovery::unclosed_array(&input) {
+            errors.push(locate(&input, offset, message));
+            if errors.len() >= max {
+                return (errors, true);
+            }
+            recovery::blank(&mut input, statement);
+        }
+
+        if let Some((offset, message)) = recovery::check_braces(&input, &indentation) {
+            errors.push(locate(&input, offset, message));
+            return (errors, false);
+        }
+
+        let mut error = if errors.is_empty() {
+            first.clone()
+        } else {
+            match config_grammar::config(&input, &mut Vec::new(), &mut Vec::new()) {
+                Ok(_) => return (errors, false),
+                Err(e) => e,
+            }
+        };
+        loop {
+            errors.push(locate(
+                &input,
+                error.offset,
+                recovery::describe(&input, &error),
+            ));
+            if errors.len() >= max {
+                return (errors, true);
+            }
+            if !recovery::skip_statement(&mut input, error.offset) {
+                return (errors, false);
+            }
+            error = match config_grammar::config(&input, &mut Vec::new(), &mut Vec::new()) {
+                Ok(_) => return (errors, false),
+                Err(e) => e,
+            };
+        }
+    }
+
+    /// Preprocesses and parses input string.
+    ///
+    /// `path` is the path to the input if it is known and is used for relative includes and error
+    /// messages. `includefolders` are the folders searched for absolute includes and should usually at
+    /// least include the current working directory.
+    pub fn from_string<F>(
+        input: String,
+        path: Option<PathBuf>,
+        includefolders: &[PathBuf],
+        fileread: F,
+    ) -> Result<Config, ArmakeError>
+    where
+        F: Fn(&PathBuf) -> String,
+        F: Copy,
+    {
+        let mut cursor = Cursor::new(input.into_bytes());
+        Self::read(&mut cursor, path, includefolders, fileread)
+    }
+
+    /// Reads the rapified config from input.
+    pub fn read_rapified<I: Read + Seek>(input: &mut I) -> Result<Config, ArmakeError> {
+        let mut reader = BufReader::new(input);
+
+        let mut buffer = [0; 4];
+        reader.read_exact(&mut buffer)?;
+
+        if &buffer != b"\0raP" {
+            return Err(aerror!("File doesn't seem to be a rapified config."));
+        }
+
+        Ok(Config {
+            root_body: ConfigClass::read_rapified(&mut reader, 0)?,
+        })
+    }
+
+    /// Derapifies the input and writes it to the output in the given encoding.
+    ///
+    /// Unlike `read_rapified` followed by `write_encoded`, only one entry is in memory at a time.
+    /// As with `write_encoded`, nothing is written if a string can't be represented in the
+    /// encoding, which is checked in a separate pass over the input.
+    pub fn derapify<I: Read + Seek, O: Write>(
+        input: &mut I,
+        output: &mut O,
+        encoding: Encoding,
+    ) -> Result<(), ArmakeError> {
+        Self::derapify_with(input, output, encoding, false)
+    }
+
+    /// Derapifies the input like `derapify`, ending the line of every class and property with a
+    /// comment containing its offset in the input, and for classes the offset of their body:
+    ///
+    /// ```text
+    /// class Wheels { // @0x1a3f -> 0x2210
+    /// ```
+    ///
+    /// The annotations are comments, so the output can still be rapified.
+    pub fn derapify_annotated<I: Read + Seek, O: Write>(
+        input: &mut I,
+        output: &mut O,
+        encoding: Encoding,
+    ) -> Result<(), ArmakeError> {
+        Self::derapify_with(input, output, encoding, true)
+    }
+
+    fn derapify_with<I: Read + Seek, O: Write>(
+        input: &mut I,
+        output: &mut O,
+        encoding: Encoding,
+        annotate: bool,
+    ) -> Result<(), ArmakeError> {
+        let mut reader = BufReader::new(input);
+
+        let mut buffer = [0; 4];
+        reader.read_exact(&mut buffer)?;
+        if &buffer != b"\0raP" {
+            return Err(aerror!("File doesn't seem to be a rapified config."));
+        }
+
+        reader.seek(SeekFrom::Start(16))?;
+        reader.read_cstring()?;
+        let num_entries = reader.read_compressed_int()?;
+        let start = reader.stream_position()?;
+
+        if encoding != Encoding::Utf8 {
+            derapify_body(
+                &mut reader,
+                &mut sink(),
+                num_entries,
+                0,
+                "",
+                Some(encoding),
+                false,
+            )?;
+            reader.seek(SeekFrom::Start(start))?;
+        }
+
+        let mut writer = BufWriter::new(EncodingWriter::new(output, encoding));
+        derapify_body(&mut reader, &mut writer, num_entries, 0, "", None, annotate)?;
+        writer
+            .into_inner()
+            .map_err(|e| ArmakeError::from(e.into_error()))?
+            .finish()
+    }
+}
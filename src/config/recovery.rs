@@ -0,0 +1,243 @@
+//! Recovery from syntax errors, so that one run reports as many of them as possible
+//!
+//! The parser itself stops at the first error. To find more, the statement containing the error
+//! is blanked out and the input is parsed again, until it parses or the error limit is reached.
+//! Unbalanced braces would make every later statement look broken, so they are checked first and
+//! reported on their own.
+
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config::config_grammar::ParseError;
+
+/// Default for the maximum number of syntax errors reported per config
+const DEFAULT_MAX_ERRORS: usize = 20;
+
+static MAX_ERRORS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_ERRORS);
+
+/// Sets the maximum number of syntax errors reported per config for the rest of the process.
+pub fn set_max_errors(max: usize) {
+    MAX_ERRORS.store(max.max(1), Ordering::Relaxed);
+}
+
+pub(super) fn max_errors() -> usize {
+    MAX_ERRORS.load(Ordering::Relaxed)
+}
+
+/// Returns the offsets of all `{`, `}` and `;` outside of strings. Strings are taken to end at the
+/// end of their line, which keeps a stray quote from hiding the rest of the input. Values of
+/// entries that are not arrays end at the next `;` or `}` like in the parser, so an unquoted
+/// `{` in them is not counted.
+fn structure(input: &[u8]) -> Vec<(usize, u8)> {
+    let mut result = Vec::new();
+    let mut quote: Option<u8> = None;
+    let mut array = false;
+    let mut value = false;
+    for (i, &c) in input.iter().enumerate() {
+        match (quote, c) {
+            (_, b'\n') => quote = None,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, b'"') | (None, b'\'') => quote = Some(c),
+            (None, b'[') => array = true,
+            (None, b'=') if !array => value = true,
+            (None, b'{') if value => {}
+            (None, b'{') | (None, b'}') | (None, b';') => {
+                result.push((i, c));
+                array = false;
+                value = false;
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+/// Returns the indentation of a line.
+pub(super) fn indentation(line: &str) -> usize {
+    line.chars().take_while(|&c| c == ' ' || c == '\t').count()
+}
+
+/// Returns the offset where the statement before the brace at the offset starts.
+fn statement_start(input: &[u8], brace: usize) -> usize {
+    input[..brace]
+        .iter()
+        .rposition(|&c| c == b'\n' || c == b';' || c == b'{' || c == b'}')
+        .map(|i| i + 1)
+        .unwrap_or(0)
+}
+
+/// Returns the statement before the brace at the offset, like `class Foo: Bar`.
+fn statement_before(input: &[u8], brace: usize) -> String {
+    String::from_utf8_lossy(&input[statement_start(input, brace)..brace])
+        .trim()
+        .to_string()
+}
+
+/// Returns true if the `{` at the offset opens a class body rather than an array, meaning that
+/// it follows a class or parent name, or the `:` of a missing parent.
+fn is_class_body(input: &[u8], opener: usize) -> bool {
+    input[..opener]
+        .iter()
+        .rev()
+        .find(|c| !c.is_ascii_whitespace())
+        .map(|&c| c.is_ascii_alphanumeric() || c == b'_' || c == b':')
+        .unwrap_or(false)
+}
+
+/// Finds the first array that is still open at the `;` ending its statement. Returns the range
+/// of the statement, the offset of the `{` and an error message.
+pub(super) fn unclosed_array(input: &str) -> Option<(Range<usize>, usize, String)> {
+    let input = input.as_bytes();
+    let mut open: Vec<usize> = Vec::new();
+    for (i, c) in structure(input) {
+        match c {
+            b'{' => open.push(i),
+            b'}' => {
+                open.pop();
+            }
+            _ => match open.last() {
+                Some(&opener) if !is_class_body(input, opener) => {
+                    let outermost = open
+                        .iter()
+                        .rev()
+                        .take_while(|&&o| !is_class_body(input, o))
+                        .last()
+                        .unwrap();
+                    let message =
+                        format!("{{ of {} is never closed", statement_before(input, opener));
+                    return Some((statement_start(input, *outermost)..i + 1, opener, message));
+                }
+                _ => {}
+            },
+        }
+    }
+    None
+}
+
+/// Checks that the braces of the input are balanced, returning the offset of the offending brace
+/// and an error message otherwise.
+///
+/// Arrays have to be checked with `unclosed_array` first. Which class is missing its `}` can't be
+/// known for sure, the first pair of braces whose lines are indented
+/// differently in the source is a far better guess than the outermost unclosed brace, so that is
+/// reported if there is one. `indentation` returns the indentation of the source line of an
+/// offset, if it is known.
+pub(super) fn check_braces(
+    input: &str,
+    indentation: &dyn Fn(usize) -> Option<usize>,
+) -> Option<(usize, String)> {
+    let input = input.as_bytes();
+    let mut open: Vec<usize> = Vec::new();
+    let mut suspect: Option<usize> = None;
+
+    for (i, c) in structure(input) {
+        match c {
+            b'{' => open.push(i),
+            b'}' => match open.pop() {
+                Some(opener) => {
+                    if suspect.is_none() {
+                        if let (Some(a), Some(b)) = (indentation(opener), indentation(i)) {
+                            if a != b {
+                                suspect = Some(opener);
+                            }
+                        }
+                    }
+                }
+                None => return Some((i, String::from("Unmatched }"))),
+            },
+            _ => {}
+        }
+    }
+
+    let opener = suspect.unwrap_or(*open.last()?);
+    let message = match statement_before(input, opener).as_str() {
+        "" => String::from("{ is never closed"),
+        statement => format!("{{ of {} is never closed", statement),
+    };
+    Some((opener, message))
+}
+
+/// Describes a parse error without its location.
+pub(super) fn describe(input: &str, error: &ParseError) -> String {
+    let found = match input[error.offset..].chars().next() {
+        Some(c) => format!("{:?}", c),
+        None => String::from("end of input"),
+    };
+    let mut expected: Vec<&str> = error
+        .expected
+        .iter()
+        .map(|e| if *e == "[a-zA-Z0-9_]" { "a name" } else { e })
+        .collect();
+    expected.sort_unstable();
+    format!("Unexpected {}, expected {}", found, expected.join(", "))
+}
+
+/// Blanks out the statement containing `offset`, see `blank`. The statement ends at the next `;`
+/// on the level of the innermost class around it, or at the end of that class. The braces of the
+/// input have to be balanced. Returns false if there was nothing left to blank out.
+pub(super) fn skip_statement(input: &mut String, offset: usize) -> bool {
+    let bytes = input.as_bytes();
+    let structure = structure(bytes);
+
+    let mut body = (0, bytes.len());
+    let mut open: Vec<usize> = Vec::new();
+    for &(i, c) in structure.iter() {
+        match c {
+            b'{' => open.push(i),
+            b'}' => {
+                if let Some(opener) = open.pop() {
+                    if opener < offset
+                        && i >= offset
+                        && opener + 1 > body.0
+                        && is_class_body(bytes, opener)
+                    {
+                        body = (opener + 1, i);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (mut start, mut end) = body;
+    let mut depth = 0;
+    for &(i, c) in structure
+        .iter()
+        .filter(|(i, _)| *i >= body.0 && *i < body.1)
+    {
+        match c {
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            b';' if depth == 0 && i < offset => start = i + 1,
+            b';' if depth == 0 => {
+                end = i + 1;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    if input[start..end].trim().is_empty() {
+        return false;
+    }
+
+    blank(input, start..end);
+    true
+}
+
+/// Replaces the range of the input with spaces, keeping newlines so offsets and lines stay the
+/// same.
+pub(super) fn blank(input: &mut String, range: Range<usize>) {
+    let blanked: String = input[range.clone()]
+        .chars()
+        .map(|c| {
+            if c == '\n' {
+                String::from("\n")
+            } else {
+                " ".repeat(c.len_utf8())
+            }
+        })
+        .collect();
+    input.replace_range(range, &blanked);
+}
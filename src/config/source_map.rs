@@ -0,0 +1,181 @@
+//! Source maps from rapified configs back to the files they were built from
+//!
+//! A source map lists the location of every class and property of a config in the files it was
+//! preprocessed from. Locations are taken after includes are resolved and before macros are
+//! expanded, so an entry from an included file points into that file, and an entry produced by a
+//! macro points at the line the macro was used on.
+//!
+//! # Format (version 1)
+//!
+//! Source maps are JSON:
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "entries": [
+//!     { "path": "CfgPatches", "file": "addons/main/config.cpp", "line": 1 },
+//!     { "path": "CfgPatches/my_addon", "file": "addons/main/config.cpp", "line": 2 },
+//!     { "path": "CfgPatches/my_addon/units", "file": "addons/main/config.cpp", "line": 3 }
+//!   ]
+//! }
+//! ```
+//!
+//! `path` is the class path of the entry with the names separated by `/`, as written in the
+//! source. Entries are listed in the order they are defined, every class before its content.
+//! `file` is the file the entry was found in, as given to the preprocessor, or `null` if that is
+//! unknown. `line` starts at 1. Fields may be added in the same version, readers should ignore
+//! fields they don't know.
+
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{split_path, Config, ConfigClass, ConfigEntry};
+use crate::preprocess::PreprocessInfo;
+use crate::{aerror, ArmakeError};
+
+const VERSION: u32 = 1;
+
+/// Location of a config entry, see the module documentation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub path: String,
+    pub file: Option<String>,
+    pub line: u32,
+}
+
+/// Source map of a config, see the module documentation for the format
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SourceMap {
+    pub version: u32,
+    pub entries: Vec<SourceLocation>,
+}
+
+/// Returns the line (starting at 1) and file that the line with the given index in the
+/// preprocessor output came from.
+pub(super) fn line_origin(
+    line: usize,
+    path: Option<&PathBuf>,
+    info: Option<&PreprocessInfo>,
+) -> (u32, Option<String>) {
+    match info {
+        Some(info) if !info.line_origins.is_empty() => {
+            let origin = &info.line_origins[line.min(info.line_origins.len() - 1)];
+            (
+                origin.0,
+                origin.1.as_ref().map(|p| p.to_string_lossy().to_string()),
+            )
+        }
+        _ => (
+            line as u32 + 1,
+            path.map(|p| p.to_string_lossy().to_string()),
+        ),
+    }
+}
+
+impl SourceMap {
+    /// Builds the source map of a config from the positions of its entries in the parser input.
+    ///
+    /// `positions` are the byte offsets and names of the entries in the order the parser
+    /// finished them, which is every class after its content.
+    pub(super) fn build(
+        config: &Config,
+        positions: &[(usize, String)],
+        input: &str,
+        path: Option<&PathBuf>,
+        info: Option<&PreprocessInfo>,
+    ) -> Result<SourceMap, ArmakeError> {
+        let line_starts: Vec<usize> = input.match_indices('\n').map(|(i, _)| i + 1).collect();
+
+        let mut builder = Builder {
+            positions: positions.iter(),
+            entries: Vec::new(),
+            locate: &|pos| line_origin(line_starts.partition_point(|&s| s <= pos), path, info),
+        };
+        builder.add_class(&config.root_body, "")?;
+
+        Ok(SourceMap {
+            version: VERSION,
+            entries: builder.entries,
+        })
+    }
+
+    /// Returns the location of the entry with the given path, see `split_path`. Names are compared
+    /// case-insensitively.
+    pub fn locate(&self, path: &str) -> Option<&SourceLocation> {
+        let path = split_path(path);
+        self.entries.iter().find(|e| {
+            let names: Vec<&str> = e.path.split('/').collect();
+            names.len() == path.len()
+                && names
+                    .iter()
+                    .zip(path.iter())
+                    .all(|(a, b)| a.eq_ignore_ascii_case(b))
+        })
+    }
+
+    /// Reads a source map.
+    pub fn read<I: Read>(input: &mut I) -> Result<SourceMap, ArmakeError> {
+        let map: SourceMap = serde_json::from_reader(input)
+            .map_err(|e| aerror!("Failed to read source map: {}", e))?;
+        if map.version != VERSION {
+            return Err(aerror!("Unsupported source map version {}", map.version));
+        }
+        Ok(map)
+    }
+
+    /// Writes the source map.
+    pub fn write<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        serde_json::to_writer_pretty(&mut *output, self)
+            .map_err(|e| aerror!("Failed to write source map: {}", e))?;
+        writeln!(output)?;
+        Ok(())
+    }
+}
+
+struct Builder<'a> {
+    positions: std::slice::Iter<'a, (usize, String)>,
+    entries: Vec<SourceLocation>,
+    locate: &'a dyn Fn(usize) -> (u32, Option<String>),
+}
+
+impl<'a> Builder<'a> {
+    fn add_class(&mut self, class: &ConfigClass, path: &str) -> Result<(), ArmakeError> {
+        for (name, entry) in class.entries.iter().flatten() {
+            let path = if path.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", path, name)
+            };
+
+            let index = self.entries.len();
+            self.entries.push(SourceLocation {
+                path: path.clone(),
+                file: None,
+                line: 0,
+            });
+
+            if let ConfigEntry::ClassEntry(c) = entry {
+                self.add_class(c, &path)?;
+            }
+
+            let (pos, parsed) = self
+                .positions
+                .next()
+                .ok_or_else(|| aerror!("Parser didn't report the position of {}", path))?;
+            if parsed != name {
+                return Err(aerror!(
+                    "Parser reported the position of {} instead of {}",
+                    parsed,
+                    path
+                ));
+            }
+
+            let (line, file) = (self.locate)(*pos);
+            self.entries[index].line = line;
+            self.entries[index].file = file;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use crate::config::{Config, ConfigClass, ConfigEntry};
+
+/// Counts of the entry types in a config and its deepest nesting
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigStats {
+    /// Classes with a body
+    pub classes: usize,
+    /// External class declarations (`class name;`)
+    pub external_classes: usize,
+    /// Class deletions (`delete name;`)
+    pub deletions: usize,
+    pub strings: usize,
+    pub floats: usize,
+    pub ints: usize,
+    pub arrays: usize,
+    /// Array expansions (`name[] += {...}`)
+    pub expansions: usize,
+    /// Names of the classes on the path to the most deeply nested class, the first one found if
+    /// there are several
+    pub deepest: Vec<String>,
+}
+
+impl ConfigStats {
+    fn add_class<'a>(&mut self, class: &'a ConfigClass, path: &mut Vec<&'a str>) {
+        if path.len() > self.deepest.len() {
+            self.deepest = path.iter().map(|s| s.to_string()).collect();
+        }
+
+        for (name, entry) in class.entries.iter().flatten() {
+            match entry {
+                ConfigEntry::StringEntry(_) => self.strings += 1,
+                ConfigEntry::FloatEntry(_) => self.floats += 1,
+                ConfigEntry::IntEntry(_) => self.ints += 1,
+                ConfigEntry::ArrayEntry(a) if a.is_expansion => self.expansions += 1,
+                ConfigEntry::ArrayEntry(_) => self.arrays += 1,
+                ConfigEntry::ClassEntry(c) if c.is_deletion => self.deletions += 1,
+                ConfigEntry::ClassEntry(c) if c.is_external => self.external_classes += 1,
+                ConfigEntry::ClassEntry(c) => {
+                    self.classes += 1;
+                    path.push(name);
+                    self.add_class(c, path);
+                    path.pop();
+                }
+            }
+        }
+    }
+}
+
+impl Config {
+    /// Returns the number of entries of each type in the config and its deepest class path.
+    pub fn stats(&self) -> ConfigStats {
+        let mut stats = ConfigStats::default();
+        stats.add_class(&self.root_body, &mut Vec::new());
+        stats
+    }
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aerror;
+use crate::config::{Config, ConfigArray, ConfigArrayElement, ConfigClass, ConfigEntry};
+use crate::ArmakeError;
+
+/// Typed, serde-compatible representation of a config
+///
+/// Class entries are kept as an ordered list of `(name, value)` pairs since the order of entries
+/// is significant in Arma configs.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::{Config, ConfigValue};
+/// let config = Config::from_string(String::from("foo = 42;"), None, &Vec::new(), |path| {
+///     std::fs::read_to_string(path).unwrap()
+/// })
+/// .unwrap();
+///
+/// match config.to_value() {
+///     ConfigValue::Class { entries, .. } => {
+///         assert_eq!(vec![(String::from("foo"), ConfigValue::Int(42))], entries)
+///     }
+///     _ => unreachable!(),
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ConfigValue {
+    /// Class with its parent (empty if none) and entries
+    Class {
+        parent: String,
+        entries: Vec<(String, ConfigValue)>,
+    },
+    /// Array (`name[] = {...}`)
+    Array(Vec<ConfigValue>),
+    /// Array expansion (`name[] += {...}`)
+    Expansion(Vec<ConfigValue>),
+    /// String value
+    String(String),
+    /// Int value
+    Int(i32),
+    /// Float value
+    Float(f32),
+    /// Class deletion (`delete name;`)
+    Delete,
+    /// External class declaration (`class name;`)
+    Extern,
+}
+
+impl ConfigArrayElement {
+    fn to_value(&self) -> ConfigValue {
+        match self {
+            ConfigArrayElement::StringElement(s) => ConfigValue::String(s.clone()),
+            ConfigArrayElement::FloatElement(f) => ConfigValue::Float(*f),
+            ConfigArrayElement::IntElement(i) => ConfigValue::Int(*i),
+            ConfigArrayElement::ArrayElement(a) => ConfigValue::Array(a.to_values()),
+        }
+    }
+
+    fn from_value(value: ConfigValue) -> Result<ConfigArrayElement, ArmakeError> {
+        Ok(match value {
+            ConfigValue::String(s) => ConfigArrayElement::StringElement(s),
+            ConfigValue::Float(f) => ConfigArrayElement::FloatElement(f),
+            ConfigValue::Int(i) => ConfigArrayElement::IntElement(i),
+            ConfigValue::Array(a) => ConfigArrayElement::ArrayElement(ConfigArray {
+                is_expansion: false,
+                elements: ConfigArray::elements_from_values(a)?,
+            }),
+            _ => {
+                return Err(aerror!(
+                    "Arrays can only contain strings, numbers and arrays."
+                ))
+            }
+        })
+    }
+}
+
+impl ConfigArray {
+    fn to_values(&self) -> Vec<ConfigValue> {
+        self.elements.iter().map(|e| e.to_value()).collect()
+    }
+
+    fn elements_from_values(
+        values: Vec<ConfigValue>,
+    ) -> Result<Vec<ConfigArrayElement>, ArmakeError> {
+        values
+            .into_iter()
+            .map(ConfigArrayElement::from_value)
+            .collect()
+    }
+}
+
+impl ConfigClass {
+    fn to_value(&self) -> ConfigValue {
+        if self.is_deletion {
+            return ConfigValue::Delete;
+        }
+        if self.is_external {
+            return ConfigValue::Extern;
+        }
+
+        ConfigValue::Class {
+            parent: self.parent.clone(),
+            entries: self
+                .entries
+                .iter()
+                .flatten()
+                .map(|(name, entry)| (name.clone(), entry.to_value()))
+                .collect(),
+        }
+    }
+}
+
+impl ConfigEntry {
+    fn to_value(&self) -> ConfigValue {
+        match self {
+            ConfigEntry::StringEntry(s) => ConfigValue::String(s.clone()),
+            ConfigEntry::FloatEntry(f) => ConfigValue::Float(*f),
+            ConfigEntry::IntEntry(i) => ConfigValue::Int(*i),
+            ConfigEntry::ArrayEntry(a) if a.is_expansion => ConfigValue::Expansion(a.to_values()),
+            ConfigEntry::ArrayEntry(a) => ConfigValue::Array(a.to_values()),
+            ConfigEntry::ClassEntry(c) => c.to_value(),
+        }
+    }
+
+    fn from_value(value: ConfigValue) -> Result<ConfigEntry, ArmakeError> {
+        Ok(match value {
+            ConfigValue::Class { parent, entries } => ConfigEntry::ClassEntry(ConfigClass {
+                parent,
+                is_external: false,
+                is_deletion: false,
+                entries: Some(
+                    entries
+                        .into_iter()
+                        .map(|(name, value)| Ok((name, ConfigEntry::from_value(value)?)))
+                        .collect::<Result<Vec<_>, ArmakeError>>()?,
+                ),
+            }),
+            ConfigValue::Array(a) => ConfigEntry::ArrayEntry(ConfigArray {
+                is_expansion: false,
+                elements: ConfigArray::elements_from_values(a)?,
+            }),
+            ConfigValue::Expansion(a) => ConfigEntry::ArrayEntry(ConfigArray {
+                is_expansion: true,
+                elements: ConfigArray::elements_from_values(a)?,
+            }),
+            ConfigValue::String(s) => ConfigEntry::StringEntry(s),
+            ConfigValue::Int(i) => ConfigEntry::IntEntry(i),
+            ConfigValue::Float(f) => ConfigEntry::FloatEntry(f),
+            ConfigValue::Delete | ConfigValue::Extern => ConfigEntry::ClassEntry(ConfigClass {
+                parent: String::new(),
+                is_external: value == ConfigValue::Extern,
+                is_deletion: value == ConfigValue::Delete,
+                entries: None,
+            }),
+        })
+    }
+}
+
+impl Config {
+    /// Returns the config as a serde-compatible `ConfigValue`, with the root body as a class.
+    pub fn to_value(&self) -> ConfigValue {
+        self.root_body.to_value()
+    }
+
+    /// Constructs a config from a `ConfigValue`, which has to be a class.
+    pub fn from_value(value: ConfigValue) -> Result<Config, ArmakeError> {
+        match ConfigEntry::from_value(value)? {
+            ConfigEntry::ClassEntry(root_body) if root_body.entries.is_some() => {
+                Ok(Config { root_body })
+            }
+            _ => Err(aerror!("The root of a config has to be a class.")),
+        }
+    }
+}
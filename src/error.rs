@@ -32,17 +32,58 @@ pub struct PreprocessError {
     pub source: Box<ArmakeError>,
 }
 
+/// Syntax error at a location in the files a config was preprocessed from
+#[derive(Debug)]
+pub struct ConfigSyntaxError {
+    pub file: Option<String>,
+    pub line: u32,
+    pub message: String,
+}
+
+impl std::fmt::Display for ConfigSyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}:{}] {}",
+            self.file.as_deref().unwrap_or("?"),
+            self.line,
+            self.message
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct ConfigParseError {
     pub path: Option<String>,
     pub message: String,
+    /// First error returned by the parser
     pub source: crate::config::config_grammar::ParseError,
+    /// All errors found, in the order they were found
+    pub errors: Vec<ConfigSyntaxError>,
+    /// True if parsing stopped at the error limit, so there may be more errors
+    pub truncated: bool,
+}
+
+impl std::fmt::Display for ConfigParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.errors.len() {
+            1 => write!(f, "Config: Syntax error")?,
+            n => write!(f, "Config: {} syntax errors", n)?,
+        }
+        for error in self.errors.iter() {
+            write!(f, "\n  {}", error)?;
+        }
+        if self.truncated {
+            write!(f, "\n  Stopped at the error limit, there may be more")?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
 pub enum ArmakeError {
     GENERIC(String),
-    CONFIG(ConfigParseError),
+    CONFIG(Box<ConfigParseError>),
     PARSE(PreprocessParseError),
     PREPROCESS(PreprocessError),
     IO(std::io::Error),
@@ -63,7 +104,7 @@ impl std::fmt::Display for ArmakeError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match *self {
             ArmakeError::GENERIC(ref s) => write!(f, "{}", s),
-            ArmakeError::CONFIG(ref e) => write!(f, "Config: {}", e.message),
+            ArmakeError::CONFIG(ref e) => write!(f, "{}", e),
             ArmakeError::PARSE(ref e) => write!(f, "Preprocessor Parse: {}", e.message),
             ArmakeError::PREPROCESS(ref e) => write!(f, "Preprocessor: {}", e.message),
             ArmakeError::IO(ref e) => write!(f, "IO error: {}", e),
@@ -90,3 +131,19 @@ impl From<std::io::Error> for ArmakeError {
         ArmakeError::IO(err)
     }
 }
+
+impl From<zip::result::ZipError> for ArmakeError {
+    fn from(err: zip::result::ZipError) -> ArmakeError {
+        match err {
+            zip::result::ZipError::Io(e) => ArmakeError::IO(e),
+            e => ArmakeError::GENERIC(format!("Zip error: {}", e)),
+        }
+    }
+}
+
+#[cfg(feature = "signing")]
+impl From<openssl::error::ErrorStack> for ArmakeError {
+    fn from(err: openssl::error::ErrorStack) -> ArmakeError {
+        ArmakeError::GENERIC(format!("OpenSSL error: {}", err))
+    }
+}
@@ -1,18 +1,124 @@
-use std::fs::File;
+use std::fs::{remove_file, rename, File};
 use std::io;
 use std::io::{Cursor, Read, Seek, Stdout, Write};
+use std::path::PathBuf;
 
 #[cfg(feature = "signing")]
 use openssl::bn::BigNum;
+use sha1::{Digest, Sha1};
 
 pub enum Input {
     File(File),
     Cursor(Cursor<Box<[u8]>>),
 }
 
+/// File written under a temporary name next to its path, which only replaces the file at the
+/// path in `commit`
+///
+/// The temporary file is created on the first write and removed if the `PendingFile` is dropped
+/// without being committed, so a failed or interrupted write leaves the previous file untouched.
+pub struct PendingFile {
+    path: PathBuf,
+    temp: PathBuf,
+    file: Option<File>,
+}
+
+impl PendingFile {
+    pub fn new(path: PathBuf) -> PendingFile {
+        let mut temp = path.clone().into_os_string();
+        temp.push(".armake2-tmp");
+        PendingFile {
+            path,
+            temp: PathBuf::from(temp),
+            file: None,
+        }
+    }
+
+    fn file(&mut self) -> io::Result<&mut File> {
+        if self.file.is_none() {
+            self.file = Some(File::create(&self.temp)?);
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    /// Replaces the file at the path with what was written, creating it if nothing was.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.file()?.flush()?;
+        // closed first, Windows can't rename open files
+        drop(self.file.take());
+        rename(&self.temp, &self.path).inspect_err(|_| {
+            let _ = remove_file(&self.temp);
+        })
+    }
+}
+
+impl Write for PendingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for PendingFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = remove_file(&self.temp);
+        }
+    }
+}
+
 pub enum Output {
-    File(File),
+    /// File that is only replaced in `finish`, see `PendingFile`
+    File(PendingFile),
     Standard(Stdout),
+    /// Output kept in memory until `finish` writes it to the path, for replacing the input
+    Staged(Vec<u8>, PathBuf),
+}
+
+impl Output {
+    /// Flushes the output. Files are written to a temporary file next to their path, which only
+    /// replaces the file at the path here, after a successful run. Without `finish`, the previous
+    /// file is left as it was.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Output::File(f) => f.commit(),
+            Output::Standard(mut s) => s.flush(),
+            Output::Staged(data, path) => {
+                let mut file = PendingFile::new(path);
+                file.write_all(&data)?;
+                file.commit()
+            }
+        }
+    }
+}
+
+/// Reader that hashes everything read through it, for computing the checksum while streaming
+pub(crate) struct HashingReader<'a, I: Read> {
+    pub inner: &'a mut I,
+    pub hash: Sha1,
+}
+
+impl<'a, I: Read> HashingReader<'a, I> {
+    pub fn new(inner: &'a mut I) -> HashingReader<'a, I> {
+        HashingReader {
+            inner,
+            hash: Sha1::new(),
+        }
+    }
+}
+
+impl<'a, I: Read> Read for HashingReader<'a, I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hash.update(&buf[..read]);
+        Ok(read)
+    }
 }
 
 impl Read for Input {
@@ -38,6 +144,7 @@ impl Write for Output {
         match *self {
             Output::File(ref mut f) => f.write(buf),
             Output::Standard(ref mut s) => s.write(buf),
+            Output::Staged(ref mut v, _) => v.write(buf),
         }
     }
 
@@ -45,6 +152,7 @@ impl Write for Output {
         match *self {
             Output::File(ref mut f) => f.flush(),
             Output::Standard(ref mut s) => s.flush(),
+            Output::Staged(_, _) => Ok(()),
         }
     }
 }
@@ -52,37 +160,51 @@ impl Write for Output {
 pub trait ReadExt: Read {
     fn read_cstring(&mut self) -> io::Result<String>;
     fn read_compressed_int(&mut self) -> io::Result<u32>;
+    #[cfg(feature = "signing")]
+    fn read_bignum(&mut self, size: usize) -> io::Result<BigNum>;
 }
 
 impl<T: Read> ReadExt for T {
     fn read_cstring(&mut self) -> io::Result<String> {
         let mut bytes: Vec<u8> = Vec::new();
-        for byte in self.bytes() {
-            let b = byte?;
-            if b == 0 {
+        let mut byte = [0; 1];
+        while self.read(&mut byte)? == 1 {
+            if byte[0] == 0 {
                 break;
             } else {
-                bytes.push(b);
+                bytes.push(byte[0]);
             }
         }
 
-        Ok(String::from_utf8(bytes).unwrap())
+        String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
     }
 
     fn read_compressed_int(&mut self) -> io::Result<u32> {
         let mut result: u32 = 0;
+        let mut byte = [0; 1];
+        let mut i = 0;
 
-        for (i, byte) in self.bytes().enumerate() {
-            let b: u32 = byte?.into();
+        while self.read(&mut byte)? == 1 {
+            let b: u32 = byte[0].into();
             result |= (b & 0x7f) << (i * 7);
 
             if b < 0x80 {
                 break;
             }
+            i += 1;
         }
 
         Ok(result)
     }
+
+    #[cfg(feature = "signing")]
+    fn read_bignum(&mut self, size: usize) -> io::Result<BigNum> {
+        let mut buffer = vec![0; size];
+        self.read_exact(&mut buffer)?;
+        buffer.reverse();
+
+        BigNum::from_slice(&buffer).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
 }
 
 pub trait WriteExt: Write {
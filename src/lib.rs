@@ -1,43 +1,206 @@
+//! Arma 3 modding tools, used by the `armake2` binary and usable as a library
+//!
+//! The main entry points are:
+//!
+//! - `pbo::PBO` for reading, building and writing PBOs, and `pbo::PBOIndex` for reading single
+//!   entries of large PBOs
+//! - `Config` for parsing, rapifying and derapifying configs
+//! - `preprocess::preprocess` for running the preprocessor on its own
+//! - `sign` for BI keys and signatures, with the `signing` feature
+//!
+//! Failures are returned as `ArmakeError`, which prints as a message for the user.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::fs::File;
+//!
+//! use armake2::pbo::PBO;
+//!
+//! # fn main() -> Result<(), armake2::ArmakeError> {
+//! let pbo = PBO::read(&mut File::open("addon.pbo")?)?;
+//! for header in pbo.headers.iter() {
+//!     println!("{} ({} bytes)", header.filename, header.original_size);
+//! }
+//! if let Some(config) = pbo.entry_data("config.bin")? {
+//!     armake2::Config::derapify(
+//!         &mut std::io::Cursor::new(config),
+//!         &mut std::io::stdout(),
+//!         armake2::Encoding::default(),
+//!     )?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
 #[macro_use]
 extern crate log;
 
 pub mod error;
 pub use crate::error::ArmakeError;
 
+pub mod warning;
+
 mod binarize;
-pub use binarize::{binarize, find_binarize_exe};
+pub use binarize::{
+    binarize, binarize_all, binarize_with, find_binarize_exe, find_binarizer, find_model_cfg,
+    is_animated, parse_library_folders, BinarizeOptions, Binarizer, BINARIZE_CMD_VAR,
+    BINARIZE_EXE_VAR,
+};
 
 mod config;
-pub use config::Config;
+pub use config::{Config, ConfigStats, ConfigValue, Encoding, SourceLocation, SourceMap};
 
 pub mod commands;
 pub use commands::Command;
 
 pub mod pbo;
-pub use pbo::{PBOHeader, PBO};
+pub use pbo::{PBOHeader, PBOIndex, PBO};
 
 pub mod preprocess;
 
+#[cfg(feature = "signing")]
+pub mod sign;
+
 pub mod io;
-use crate::io::{Input, Output};
+
+pub mod lint;
+
+pub mod stringtable;
+use crate::io::{Input, Output, PendingFile};
 
 use std::fs::File;
 use std::io::{stdin, stdout, Cursor, Read};
+use std::path::{Path, PathBuf};
 
 fn get_input(source: Option<&str>) -> Result<Input, ArmakeError> {
     if let Some(ref path) = source {
         Ok(Input::File(File::open(path)?))
     } else {
         let mut buffer: Vec<u8> = Vec::new();
-        stdin().read_to_end(&mut buffer).unwrap();
+        stdin().read_to_end(&mut buffer)?;
         Ok(Input::Cursor(Cursor::new(buffer.into_boxed_slice())))
     }
 }
 
-fn get_output(target: Option<&str>) -> Result<Output, ArmakeError> {
+/// Opens the output file `target`, stdout if it is `None`. An existing file is only overwritten
+/// with `force`, and only replaced in `Output::finish`.
+fn get_output(target: Option<&str>, force: bool) -> Result<Output, ArmakeError> {
     if let Some(ref path) = target {
-        Ok(Output::File(File::create(path)?))
+        check_overwrite(Path::new(path), force)?;
+        let folder = match Path::new(path).parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        if !folder.is_dir() {
+            return Err(aerror!(
+                "Can't write {}, the folder {} doesn't exist",
+                path,
+                folder.display()
+            ));
+        }
+        Ok(Output::File(PendingFile::new(PathBuf::from(path))))
     } else {
         Ok(Output::Standard(stdout()))
     }
 }
+
+/// Refuses to overwrite the existing file `path` without `force`.
+fn check_overwrite(path: &Path, force: bool) -> Result<(), ArmakeError> {
+    if !force && path.exists() {
+        return Err(aerror!(
+            "{} already exists, use -f to overwrite it",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Refuses to write into the folder `path` without `force` if it exists and isn't empty.
+fn check_folder_empty(path: &Path, force: bool) -> Result<(), ArmakeError> {
+    if !force && path.is_dir() && path.read_dir()?.next().is_some() {
+        return Err(aerror!(
+            "{} is not empty, use -f to write into it anyway",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Returns true if both paths exist and are the same file after resolving symlinks and relative
+/// components.
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Opens the output like `get_output`, refusing to truncate the input file `source` before it
+/// has been read. With `in_place`, output to the input file is staged in memory instead and only
+/// replaces it in `Output::finish`, after a successful run. Other existing files are only
+/// overwritten with `force`.
+fn get_output_for(
+    source: Option<&str>,
+    target: Option<&str>,
+    in_place: bool,
+    force: bool,
+) -> Result<Output, ArmakeError> {
+    if let (Some(source), Some(target)) = (source, target) {
+        if is_same_file(Path::new(source), Path::new(target)) {
+            if in_place {
+                return Ok(Output::Staged(Vec::new(), PathBuf::from(target)));
+            }
+            return Err(aerror!(
+                "The target {} is the source file, refusing to overwrite it. Use --in-place to \
+                 replace the source after a successful run",
+                target
+            ));
+        }
+    }
+    get_output(target, force)
+}
+
+/// Refuses a target inside the folder `source` that is packed, unless it is excluded by one of
+/// the patterns or the `.pboignore` file of the folder. The PBO would otherwise contain a
+/// truncated copy of itself.
+fn check_target_outside(
+    source: &str,
+    target: Option<&str>,
+    exclude_patterns: &[&str],
+) -> Result<(), ArmakeError> {
+    let target = match target {
+        Some(target) => Path::new(target),
+        None => return Ok(()),
+    };
+    let parent = match target.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+    let (directory, parent, file_name) = match (
+        Path::new(source).canonicalize(),
+        parent.canonicalize(),
+        target.file_name(),
+    ) {
+        (Ok(directory), Ok(parent), Some(file_name)) => (directory, parent, file_name),
+        _ => return Ok(()),
+    };
+
+    if let Ok(relative) = parent.join(file_name).strip_prefix(&directory) {
+        let name = relative.to_string_lossy().replace("/", "\\");
+        let ignored = pbo::read_ignore_file(&directory)?;
+        let ignored: Vec<&str> = ignored.iter().map(|p| p.as_str()).collect();
+        if pbo::excluded_by(&name, exclude_patterns).is_none()
+            && pbo::excluded_by(&name, &ignored).is_none()
+        {
+            return Err(aerror!(
+                "The target {} is inside the source folder {} and would be packed into itself. \
+                 Write it elsewhere or exclude it with -x {}",
+                target.display(),
+                source,
+                name
+            ));
+        }
+    }
+    Ok(())
+}
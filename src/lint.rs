@@ -0,0 +1,399 @@
+//! Checks for common mistakes in configs
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use crate::config::{ConfigArrayElement, ConfigClass, ConfigEntry};
+use crate::preprocess::{IncludeReader, PreprocessOptions};
+use crate::warning::{self, Warning};
+use crate::{ArmakeError, Config, Encoding, SourceMap};
+
+/// Lowest `requiredVersion` that is considered plausible
+const MIN_REQUIRED_VERSION: f32 = 0.1;
+/// Highest `requiredVersion` that is considered plausible
+const MAX_REQUIRED_VERSION: f32 = 5.0;
+
+/// Extensions of files that configs and materials commonly reference
+const PATH_EXTENSIONS: &[&str] = &[
+    "paa", "pac", "p3d", "rvmat", "jpg", "png", "tga", "ogg", "wss", "wav", "sqf", "sqs", "fsm",
+    "rtm", "bikb", "lip", "emat", "bisurf",
+];
+
+/// Finding of a lint check
+#[derive(Debug, PartialEq)]
+pub struct Lint {
+    /// Name of the warning, used for suppression
+    pub name: &'static str,
+    /// Location of the finding within the file, a class path like
+    /// `CfgPatches >> my_addon >> requiredAddons` for configs
+    pub path: String,
+    pub message: String,
+    /// File the finding is in, if known, see `locate`
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+impl Lint {
+    pub fn new<P: Into<String>, M: Into<String>>(name: &'static str, path: P, message: M) -> Lint {
+        Lint {
+            name,
+            path: path.into(),
+            message: message.into(),
+            file: None,
+            line: None,
+        }
+    }
+}
+
+/// Sets the file and line of lints found in a config with the given source map, looked up by
+/// their class path. Lints with paths that are not in the map are left as they are.
+pub fn locate(lints: &mut [Lint], map: &SourceMap) {
+    for lint in lints {
+        if let Some(location) = map.locate(&lint.path) {
+            lint.file = location.file.clone();
+            lint.line = Some(location.line);
+        }
+    }
+}
+
+fn is_class_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns the names of all addons defined in the config's `CfgPatches`.
+pub fn defined_addons(config: &Config) -> Vec<String> {
+    match config.root_body.get("CfgPatches") {
+        Some(ConfigEntry::ClassEntry(patches)) => patches
+            .entries
+            .iter()
+            .flatten()
+            .filter(|(_, e)| match e {
+                ConfigEntry::ClassEntry(c) => !c.is_external && !c.is_deletion,
+                _ => false,
+            })
+            .map(|(n, _)| n.clone())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns all strings in the config, in arrays or not, as `(class path, entry name, value)`.
+pub fn config_strings(config: &Config) -> Vec<(String, &str, &str)> {
+    let mut strings = Vec::new();
+    collect_class(&config.root_body, "", &mut strings);
+    strings
+}
+
+fn collect_class<'a>(
+    class: &'a ConfigClass,
+    path: &str,
+    strings: &mut Vec<(String, &'a str, &'a str)>,
+) {
+    for (name, entry) in class.entries.iter().flatten() {
+        let path = if path.is_empty() {
+            name.clone()
+        } else {
+            format!("{} >> {}", path, name)
+        };
+        match entry {
+            ConfigEntry::StringEntry(s) => strings.push((path, name, s)),
+            ConfigEntry::ArrayEntry(a) => collect_elements(&a.elements, &path, name, strings),
+            ConfigEntry::ClassEntry(c) => collect_class(c, &path, strings),
+            _ => {}
+        }
+    }
+}
+
+fn collect_elements<'a>(
+    elements: &'a [ConfigArrayElement],
+    path: &str,
+    name: &'a str,
+    strings: &mut Vec<(String, &'a str, &'a str)>,
+) {
+    for element in elements {
+        match element {
+            ConfigArrayElement::StringElement(s) => strings.push((path.to_string(), name, s)),
+            ConfigArrayElement::ArrayElement(a) => {
+                collect_elements(&a.elements, path, name, strings)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Reads a config from a file, rapified or not. Textual configs come with their source map, so
+/// that lints can be located in the files they were found in, see `locate`.
+pub fn read_config_file(
+    path: &Path,
+    includefolders: &[PathBuf],
+) -> Result<(Config, Option<SourceMap>), ArmakeError> {
+    let mut buffer = Vec::new();
+    File::open(path)?.read_to_end(&mut buffer)?;
+
+    if buffer.starts_with(b"\0raP") {
+        Ok((Config::read_rapified(&mut Cursor::new(buffer))?, None))
+    } else {
+        let reader = IncludeReader::new(Encoding::Utf8);
+        let (config, map) = reader.finish(Config::read_with_source_map(
+            &mut Cursor::new(buffer),
+            Some(path.to_path_buf()),
+            includefolders,
+            |path| reader.read(path),
+            &PreprocessOptions::default(),
+        ))?;
+        Ok((config, Some(map)))
+    }
+}
+
+/// Reads a list of known addons from a file.
+///
+/// Configs (`.cpp`, `.hpp`, `.bin`) contribute the addons defined in their `CfgPatches`, any other
+/// file is read as a list of addon names, one per line.
+pub fn read_known_addons(path: &Path) -> Result<HashSet<String>, ArmakeError> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    if ["cpp", "hpp", "bin"].contains(&extension.as_str()) {
+        let (config, _) = read_config_file(path, &[])?;
+        return Ok(defined_addons(&config).into_iter().collect());
+    }
+
+    let mut content = String::new();
+    File::open(path)?.read_to_string(&mut content)?;
+    Ok(content
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with("//"))
+        .map(|l| l.to_string())
+        .collect())
+}
+
+/// Reads the known addons from all given files, see `read_known_addons`. Returns `None` if no
+/// files are given, meaning that any addon is accepted.
+pub fn read_known_addons_all(paths: &[PathBuf]) -> Result<Option<HashSet<String>>, ArmakeError> {
+    if paths.is_empty() {
+        return Ok(None);
+    }
+
+    let mut known = HashSet::new();
+    for path in paths {
+        known.extend(read_known_addons(path)?);
+    }
+    Ok(Some(known))
+}
+
+/// Checks `requiredVersion` and `requiredAddons` of every addon in `CfgPatches`.
+///
+/// If `known_addons` is given, every required addon has to be in it (compared
+/// case-insensitively) or defined by the config itself.
+pub fn lint_cfgpatches(config: &Config, known_addons: Option<&HashSet<String>>) -> Vec<Lint> {
+    let mut lints = Vec::new();
+
+    let patches = match config.root_body.get("CfgPatches") {
+        Some(ConfigEntry::ClassEntry(c)) => c,
+        _ => return lints,
+    };
+
+    let own_addons: HashSet<String> = defined_addons(config)
+        .iter()
+        .map(|a| a.to_lowercase())
+        .collect();
+
+    for (name, entry) in patches.entries.iter().flatten() {
+        let addon = match entry {
+            ConfigEntry::ClassEntry(c) if c.entries.is_some() => c,
+            _ => continue,
+        };
+        let path = format!("CfgPatches >> {}", name);
+
+        match addon.get("requiredVersion") {
+            Some(ConfigEntry::FloatEntry(f)) => lint_required_version(*f, &path, &mut lints),
+            Some(ConfigEntry::IntEntry(i)) => lint_required_version(*i as f32, &path, &mut lints),
+            Some(_) => lints.push(Lint::new(
+                "required-version-invalid",
+                format!("{} >> requiredVersion", path),
+                "requiredVersion is not a number",
+            )),
+            None => {}
+        }
+
+        let required = match addon.get("requiredAddons") {
+            Some(ConfigEntry::ArrayEntry(a)) => &a.elements,
+            Some(_) => {
+                lints.push(Lint::new(
+                    "required-addons-invalid",
+                    format!("{} >> requiredAddons", path),
+                    "requiredAddons is not an array",
+                ));
+                continue;
+            }
+            None => continue,
+        };
+        let path = format!("{} >> requiredAddons", path);
+
+        let mut seen: HashSet<String> = HashSet::new();
+        for element in required {
+            let required_addon = match element {
+                ConfigArrayElement::StringElement(s) => s,
+                _ => {
+                    lints.push(Lint::new(
+                        "required-addons-invalid",
+                        path.clone(),
+                        "requiredAddons contains a non-string element",
+                    ));
+                    continue;
+                }
+            };
+
+            if required_addon.trim().is_empty() {
+                lints.push(Lint::new(
+                    "required-addons-empty",
+                    path.clone(),
+                    "requiredAddons contains an empty string",
+                ));
+                continue;
+            }
+
+            if !is_class_name(required_addon) {
+                lints.push(Lint::new(
+                    "required-addons-invalid",
+                    path.clone(),
+                    format!("\"{}\" is not a valid addon name", required_addon),
+                ));
+            }
+
+            if !seen.insert(required_addon.to_lowercase()) {
+                lints.push(Lint::new(
+                    "required-addons-duplicate",
+                    path.clone(),
+                    format!("\"{}\" is required more than once", required_addon),
+                ));
+            }
+
+            if let Some(known) = known_addons {
+                if !own_addons.contains(&required_addon.to_lowercase())
+                    && !known.iter().any(|k| k.eq_ignore_ascii_case(required_addon))
+                {
+                    lints.push(Lint::new(
+                        "required-addons-unknown",
+                        path.clone(),
+                        format!("\"{}\" is not a known addon", required_addon),
+                    ));
+                }
+            }
+        }
+    }
+
+    lints
+}
+
+fn lint_required_version(version: f32, path: &str, lints: &mut Vec<Lint>) {
+    if !(MIN_REQUIRED_VERSION..=MAX_REQUIRED_VERSION).contains(&version) {
+        lints.push(Lint::new(
+            "required-version-implausible",
+            format!("{} >> requiredVersion", path),
+            format!("requiredVersion {} is implausible", version),
+        ));
+    }
+}
+
+/// Logs the given lints as warnings, skipping those whose name is in `suppressed` or that were
+/// suppressed globally, and returns the number of lints logged. `location` is the file the lints
+/// were found in, located lints are logged with their own file and line.
+pub fn report(lints: &[Lint], location: &str, suppressed: &[&str]) -> usize {
+    let mut count = 0;
+    for lint in lints {
+        if suppressed.contains(&lint.name) || suppressed.contains(&"all") {
+            continue;
+        }
+        let message = format!("{}: {}", lint.path, lint.message);
+        let warning = match lint.line {
+            Some(line) => Warning::new(lint.name, message).at(
+                Some(lint.file.clone().unwrap_or_else(|| location.to_string())),
+                line,
+            ),
+            None => Warning::new(lint.name, message).in_file(location),
+        };
+        if warning::raise(warning) {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Returns the normalized form (lowercase, without leading backslash) of a string from the entry
+/// `entry` if it looks like a file reference.
+///
+/// Procedural textures (`#(...)`) are not file references. `model` entries may omit the `.p3d`
+/// extension.
+pub fn normalize_path_reference(value: &str, entry: &str) -> Option<String> {
+    let value = value.trim();
+    if value.starts_with('#') || !value.contains('\\') {
+        return None;
+    }
+
+    let mut path = value
+        .trim_start_matches('\\')
+        .replace('/', "\\")
+        .to_lowercase();
+    let filename = path.rsplit('\\').next().unwrap();
+    match filename.rfind('.').map(|i| &filename[i + 1..]) {
+        Some(extension) if PATH_EXTENSIONS.contains(&extension) => {}
+        _ if entry.eq_ignore_ascii_case("model") => path.push_str(".p3d"),
+        _ => return None,
+    }
+
+    Some(path)
+}
+
+/// Checks the file references in a config against the entries of the addon with the given prefix
+/// and against folders of other prefixes.
+///
+/// `entries` are the lowercase names of the addon's files, `known_prefixes` maps prefixes to the
+/// folders containing their files. References to other prefixes are not checked.
+pub fn lint_paths(
+    config: &Config,
+    prefix: &str,
+    entries: &HashSet<String>,
+    known_prefixes: &[(String, PathBuf)],
+) -> Vec<Lint> {
+    let prefix = format!("{}\\", prefix.trim_matches('\\').to_lowercase());
+    let known_prefixes: Vec<(String, &PathBuf)> = known_prefixes
+        .iter()
+        .map(|(p, dir)| (format!("{}\\", p.trim_matches('\\').to_lowercase()), dir))
+        .collect();
+
+    let mut lints = Vec::new();
+    for (path, entry, value) in config_strings(config) {
+        let reference = match normalize_path_reference(value, entry) {
+            Some(reference) => reference,
+            None => continue,
+        };
+
+        let exists = if let Some(relative) = reference.strip_prefix(&prefix) {
+            entries.contains(relative)
+        } else if let Some((known, dir)) = known_prefixes
+            .iter()
+            .find(|(known, _)| reference.starts_with(known.as_str()))
+        {
+            dir.join(reference[known.len()..].replace('\\', "/"))
+                .is_file()
+        } else {
+            continue;
+        };
+
+        if !exists {
+            lints.push(Lint::new(
+                "missing-path",
+                path,
+                format!("\"{}\" does not exist", value),
+            ));
+        }
+    }
+
+    lints
+}
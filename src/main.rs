@@ -1,56 +1,67 @@
-use std::collections::HashMap;
-
-use clap;
+use std::io::IsTerminal;
 
 use armake2::error::PrintableError;
-use armake2::Command;
+
+/// Prints log messages to stderr
+struct Logger;
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            let level = record.level().to_string().to_lowercase();
+            let color = match record.level() {
+                log::Level::Error => Some("31"),
+                log::Level::Warn => Some("33"),
+                _ => None,
+            };
+            match color {
+                Some(color) if use_color() => {
+                    eprintln!("\x1b[1;{}m{}\x1b[0m: {}", color, level, record.args())
+                }
+                _ => eprintln!("{}: {}", level, record.args()),
+            }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: Logger = Logger;
+
+/// Returns true if stderr is a terminal and NO_COLOR isn't set.
+fn use_color() -> bool {
+    std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
 
 fn main() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Warn);
+
     let mut version = env!("CARGO_PKG_VERSION").to_string();
     if cfg!(debug_assertions) {
         version.push_str("-debug");
     }
 
-    let mut app = clap::App::new("armake2")
-        .version(version.as_ref())
-        .author(env!("CARGO_PKG_AUTHORS"))
-        .about(env!("CARGO_PKG_DESCRIPTION"));
-
-    let mut commands: Vec<Box<dyn Command>> = Vec::new();
-    let mut hash_commands: HashMap<String, &Box<dyn Command>> = HashMap::new();
-
-    commands.push(Box::new(armake2::commands::Inspect {}));
-    commands.push(Box::new(armake2::commands::Cat {}));
-    commands.push(Box::new(armake2::commands::Binarize {}));
-    commands.push(Box::new(armake2::commands::Rapify {}));
-    commands.push(Box::new(armake2::commands::Derapify {}));
-    commands.push(Box::new(armake2::commands::Pack {}));
-    commands.push(Box::new(armake2::commands::Unpack {}));
-    commands.push(Box::new(armake2::commands::Build {}));
-
-    #[cfg(feature = "signing")]
-    {
-        commands.push(Box::new(armake2::commands::signing::Keygen {}));
-        commands.push(Box::new(armake2::commands::signing::Sign {}));
-        commands.push(Box::new(armake2::commands::signing::Verify {}));
-    }
-
-    for command in commands.iter() {
-        let sub = command.register();
-        hash_commands.insert(sub.get_name().to_owned(), command);
-        app = app.subcommand(sub);
-    }
+    let commands = armake2::commands::commands();
+    let app = armake2::commands::app(&commands).version(version.as_ref());
 
     let matches = app.get_matches();
+    if matches.is_present("verbose") {
+        log::set_max_level(log::LevelFilter::Info);
+    }
 
-    match matches.subcommand_name() {
-        Some(v) => match hash_commands.get(v) {
-            Some(c) => {
-                let sub_matches = matches.subcommand_matches(v).unwrap();
-                c.run(sub_matches).unwrap_or_print();
-            }
-            None => println!("Unknown Command"),
-        },
-        None => println!("No command"),
+    let (name, sub_matches) = matches.subcommand();
+    let command = commands
+        .iter()
+        .find(|c| c.register().get_name() == name)
+        .unwrap();
+    let result = command.run(sub_matches.unwrap());
+    if let Some(summary) = armake2::warning::summary() {
+        eprintln!("{}", summary);
     }
+    result.unwrap_or_print();
 }
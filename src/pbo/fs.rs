@@ -3,32 +3,132 @@ use std::path::PathBuf;
 
 use crate::ArmakeError;
 
-/// Checks a string against a glob pattern
-pub fn matches_glob(s: &str, pattern: &str) -> bool {
-    if let Some(index) = pattern.find('*') {
-        if s[..index] != pattern[..index] {
-            return false;
-        }
+/// Part of a parsed glob pattern, see `matches_glob`
+#[derive(Debug)]
+enum GlobToken {
+    Char(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    AnyString,
+    /// `**` followed by a separator, matching nothing or everything up to a separator
+    AnyFolders,
+    /// `[abc]`, `[a-z]` or `[!abc]`, as inclusive ranges
+    Class(Vec<(char, char)>, bool),
+}
 
-        for i in (index + 1)..(s.len() - 1) {
-            if matches_glob(&s[i..].to_string(), &pattern[(index + 1)..].to_string()) {
-                return true;
+/// Parses a lowercase glob pattern with `/` as separator already replaced.
+fn parse_glob(pattern: &[char]) -> Vec<GlobToken> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            '?' => tokens.push(GlobToken::AnyChar),
+            '*' if pattern.get(i + 1) == Some(&'*') && pattern.get(i + 2) == Some(&'\\') => {
+                tokens.push(GlobToken::AnyFolders);
+                i += 2;
             }
+            '*' => {
+                while pattern.get(i + 1) == Some(&'*') {
+                    i += 1;
+                }
+                tokens.push(GlobToken::AnyString);
+            }
+            '[' => {
+                let negated = matches!(pattern.get(i + 1), Some('!') | Some('^'));
+                let start = if negated { i + 2 } else { i + 1 };
+                // a ] right after the opening bracket is part of the class
+                let end = (start + 1..pattern.len()).find(|&j| pattern[j] == ']');
+                match end {
+                    Some(end) => {
+                        let mut ranges = Vec::new();
+                        let mut j = start;
+                        while j < end {
+                            if j + 2 < end && pattern[j + 1] == '-' {
+                                ranges.push((pattern[j], pattern[j + 2]));
+                                j += 3;
+                            } else {
+                                ranges.push((pattern[j], pattern[j]));
+                                j += 1;
+                            }
+                        }
+                        tokens.push(GlobToken::Class(ranges, negated));
+                        i = end;
+                    }
+                    None => tokens.push(GlobToken::Char('[')),
+                }
+            }
+            c => tokens.push(GlobToken::Char(c)),
         }
-        false
-    } else {
-        s == pattern
+        i += 1;
     }
+    tokens
 }
 
-/// Checks a filename against a blacklist
-pub fn file_allowed(name: &str, exclude_patterns: &[&str]) -> bool {
-    for pattern in exclude_patterns {
-        if matches_glob(&name, &pattern) {
-            return false;
+/// Checks a string against a glob pattern, ignoring case.
+///
+/// `*` matches any characters, including separators, `?` any single character and `[abc]`,
+/// `[a-z]` or `[!abc]` one character of a class. `**` followed by a separator matches any number
+/// of folders, including none, so `**\\.git\\*` also matches `.git\\config`. `/` and `\\` are
+/// the same separator.
+pub fn matches_glob(s: &str, pattern: &str) -> bool {
+    let s: Vec<char> = s.to_lowercase().replace('/', "\\").chars().collect();
+    let pattern: Vec<char> = pattern.to_lowercase().replace('/', "\\").chars().collect();
+    let tokens = parse_glob(&pattern);
+
+    // matched[i] is true if the tokens so far match the first i characters
+    let mut matched = vec![false; s.len() + 1];
+    matched[0] = true;
+    for token in tokens.iter() {
+        let mut next = vec![false; s.len() + 1];
+        for i in 0..=s.len() {
+            if !matched[i] {
+                continue;
+            }
+            match token {
+                GlobToken::AnyString => {
+                    next[i..].iter_mut().for_each(|m| *m = true);
+                    break;
+                }
+                GlobToken::AnyFolders => {
+                    next[i] = true;
+                    for j in i..s.len() {
+                        if s[j] == '\\' {
+                            next[j + 1] = true;
+                        }
+                    }
+                }
+                GlobToken::AnyChar => {
+                    if i < s.len() {
+                        next[i + 1] = true;
+                    }
+                }
+                GlobToken::Char(c) => {
+                    if s.get(i) == Some(c) {
+                        next[i + 1] = true;
+                    }
+                }
+                GlobToken::Class(ranges, negated) => {
+                    if let Some(c) = s.get(i) {
+                        let inside = ranges.iter().any(|(a, b)| a <= c && c <= b);
+                        if inside != *negated {
+                            next[i + 1] = true;
+                        }
+                    }
+                }
+            }
         }
+        matched = next;
     }
-    true
+    matched[s.len()]
+}
+
+/// Checks a filename against a blacklist, returning the first pattern that excludes it
+pub fn excluded_by<'a>(name: &str, exclude_patterns: &[&'a str]) -> Option<&'a str> {
+    exclude_patterns
+        .iter()
+        .find(|pattern| matches_glob(name, pattern))
+        .cloned()
 }
 
 /// Return all files in a directory recursively
@@ -0,0 +1,133 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use sha1::{Digest, Sha1};
+
+use crate::{aerror, ArmakeError, PBO};
+
+/// Header extension holding the hash manifest
+pub const HASH_EXTENSION: &str = "hashes";
+/// Entry holding the hash manifest if it is too long for a header extension
+pub const HASH_ENTRY: &str = "$HASHES$";
+/// Longest hash manifest that is stored as a header extension
+const MAX_EXTENSION_LENGTH: usize = 1024;
+
+/// Mismatch between the entries of a PBO and its hash manifest
+#[derive(Debug, PartialEq)]
+pub enum HashMismatch {
+    /// Entry whose content doesn't match its hash
+    Modified(String),
+    /// Entry in the manifest that is not in the PBO
+    Missing(String),
+    /// Entry in the PBO that is not in the manifest
+    Added(String),
+}
+
+impl std::fmt::Display for HashMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HashMismatch::Modified(name) => write!(f, "{} doesn't match its hash", name),
+            HashMismatch::Missing(name) => write!(f, "{} is missing", name),
+            HashMismatch::Added(name) => write!(f, "{} is not in the hash manifest", name),
+        }
+    }
+}
+
+fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl PBO {
+    /// Returns the hash manifest of the PBO as `name:sha1;` pairs, ordered like `write` orders
+    /// the entries. Compressed entries are hashed decompressed, or as they are stored if they
+    /// fail to decompress.
+    fn hash_manifest(&self) -> String {
+        let mut files: Vec<(&String, &Cursor<Box<[u8]>>)> = self
+            .files
+            .iter()
+            .filter(|(name, _)| name.as_str() != HASH_ENTRY)
+            .collect();
+        files.sort_by_key(|(name, _)| name.to_lowercase());
+
+        files
+            .iter()
+            .map(|(name, cursor)| {
+                let data = match self.entry_data(name) {
+                    Ok(Some(data)) => data,
+                    _ => Cow::Borrowed(&cursor.get_ref()[..]),
+                };
+                format!("{}:{};", name, sha1_hex(&data))
+            })
+            .collect()
+    }
+
+    /// Stores a SHA1 of every entry in the PBO, replacing any existing hash manifest.
+    ///
+    /// The manifest is stored in the `hashes` header extension, or in a `$HASHES$` entry if it
+    /// is longer than 1024 bytes.
+    pub fn add_hash_manifest(&mut self) {
+        self.files.remove(HASH_ENTRY);
+        self.header_extensions.remove(HASH_EXTENSION);
+
+        let manifest = self.hash_manifest();
+        if manifest.len() > MAX_EXTENSION_LENGTH {
+            self.files.insert(
+                HASH_ENTRY.to_string(),
+                Cursor::new(manifest.into_bytes().into_boxed_slice()),
+            );
+        } else {
+            self.header_extensions
+                .insert(HASH_EXTENSION.to_string(), manifest);
+        }
+    }
+
+    /// Compares the entries of the PBO against its hash manifest. Compressed entries are compared
+    /// by their decompressed content, like they were hashed. Fails if the PBO has no hash
+    /// manifest or it is malformed.
+    pub fn verify_hash_manifest(&self) -> Result<Vec<HashMismatch>, ArmakeError> {
+        let manifest = match (
+            self.header_extensions.get(HASH_EXTENSION),
+            self.files.get(HASH_ENTRY),
+        ) {
+            (Some(manifest), _) => manifest.clone(),
+            (None, Some(cursor)) => String::from_utf8(cursor.get_ref().to_vec())
+                .map_err(|_| aerror!("Hash manifest is not valid UTF-8"))?,
+            (None, None) => return Err(aerror!("PBO has no hash manifest")),
+        };
+
+        let mut expected: HashMap<&str, &str> = HashMap::new();
+        for pair in manifest.split(';').filter(|p| !p.is_empty()) {
+            let (name, hash) = pair
+                .rsplit_once(':')
+                .ok_or_else(|| aerror!("Malformed hash manifest entry \"{}\"", pair))?;
+            expected.insert(name, hash);
+        }
+
+        let mut mismatches = Vec::new();
+        for name in self.files.keys() {
+            if name == HASH_ENTRY {
+                continue;
+            }
+            let data = self.entry_data(name)?.unwrap();
+            match expected.remove(name.as_str()) {
+                Some(hash) if hash.eq_ignore_ascii_case(&sha1_hex(&data)) => {}
+                Some(_) => mismatches.push(HashMismatch::Modified(name.clone())),
+                None => mismatches.push(HashMismatch::Added(name.clone())),
+            }
+        }
+
+        let mut missing: Vec<&str> = expected.keys().cloned().collect();
+        missing.sort_unstable();
+        mismatches.extend(
+            missing
+                .into_iter()
+                .map(|name| HashMismatch::Missing(name.to_string())),
+        );
+
+        Ok(mismatches)
+    }
+}
@@ -5,7 +5,7 @@ use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use crate::io::{ReadExt, WriteExt};
 use crate::ArmakeError;
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PBOHeader {
     pub filename: String,
     pub packing_method: u32,
@@ -15,9 +15,10 @@ pub struct PBOHeader {
     pub data_size: u32,
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PackingMethod {
     Uncompressed,
+    /// LZSS-compressed, see `lzss`
     Packed,
     ProductEntry,
     Unknown,
@@ -48,9 +49,24 @@ impl PBOHeader {
     pub fn method(&self) -> PackingMethod {
         match self.packing_method {
             0x0000_0000 => PackingMethod::Uncompressed,
-            0x0430_7273 => PackingMethod::Packed,
+            0x4370_7273 | 0x0000_0100 => PackingMethod::Packed,
             0x5665_7273 => PackingMethod::ProductEntry,
             _ => PackingMethod::Unknown,
         }
     }
+
+    /// Returns true if armake2 doesn't know the packing method of the entry, like encryption or
+    /// vendor-specific markers. The data of such entries is copied as it is but never transformed.
+    pub fn has_unknown_method(&self) -> bool {
+        self.method() == PackingMethod::Unknown
+    }
+
+    /// Returns true if the header terminates the header list of a PBO, which is a header with an
+    /// empty name and no data that doesn't start header extensions. Headers with an empty name
+    /// and data are entries, see `PBO::read`.
+    pub(crate) fn ends_header_list(&self) -> bool {
+        self.method() != PackingMethod::ProductEntry
+            && self.filename.is_empty()
+            && self.data_size == 0
+    }
 }
@@ -0,0 +1,85 @@
+//! Files excluded from a PBO by default and with a `.pboignore` file
+//!
+//! A `.pboignore` file in the top-level folder of an addon lists patterns like a `.gitignore`:
+//! one glob per line, `#` starts a comment and empty lines are skipped. A pattern without a `/`
+//! matches files and folders of that name at any depth, `/` at the start anchors it to the
+//! top-level folder and `/` at the end only matches folders. Negated patterns are not supported.
+//! The ignore file itself is never packed.
+
+use std::fs::read_to_string;
+use std::path::Path;
+
+use crate::error::IOPathError;
+use crate::{aerror, ArmakeError};
+
+/// Name of the ignore file in the top-level folder of an addon
+pub const IGNORE_FILE_NAME: &str = ".pboignore";
+
+/// Patterns of version control folders, OS metadata and editor backups that are excluded unless
+/// turned off with `--no-default-excludes`
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    "**/.git/*",
+    "**/.svn/*",
+    "**/Thumbs.db",
+    "**/.DS_Store",
+    "*~",
+    "*.bak",
+    "*.swp",
+];
+
+/// Parses the content of an ignore file into glob patterns for `matches_glob`. `origin` is the
+/// name of the file in errors.
+pub fn parse_ignore(content: &str, origin: &str) -> Result<Vec<String>, ArmakeError> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let mut patterns = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('!') {
+            return Err(aerror!(
+                "{}:{}: negated patterns like {} are not supported",
+                origin,
+                i + 1,
+                line
+            ));
+        }
+
+        let line = line.replace('\\', "/");
+        let (pattern, folder) = match line.strip_suffix('/') {
+            Some(pattern) => (pattern, true),
+            None => (line.as_str(), false),
+        };
+        let pattern = match pattern.strip_prefix('/') {
+            Some(pattern) => pattern.to_string(),
+            None if pattern.contains('/') => pattern.to_string(),
+            None => format!("**/{}", pattern),
+        };
+        if pattern.is_empty() {
+            continue;
+        }
+
+        if !folder {
+            patterns.push(pattern.clone());
+        }
+        patterns.push(format!("{}/*", pattern));
+    }
+    Ok(patterns)
+}
+
+/// Reads the patterns of the ignore file in `directory`, none if there is no ignore file.
+pub fn read_ignore_file(directory: &Path) -> Result<Vec<String>, ArmakeError> {
+    let path = directory.join(IGNORE_FILE_NAME);
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let content = read_to_string(&path).map_err(|source| {
+        ArmakeError::IOPath(IOPathError {
+            source,
+            path: path.clone(),
+            message: Some(String::from("Failed to read the ignore file")),
+        })
+    })?;
+    parse_ignore(&content, &path.to_string_lossy())
+}
@@ -0,0 +1,195 @@
+//! Headers of a PBO read without its data, for reading single entries of large PBOs
+//!
+//! `PBO::read` keeps the whole PBO in memory. `PBOIndex` only reads the header list and records
+//! where the data of every entry is, so entries can be read one at a time by seeking to them.
+
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+use linked_hash_map::LinkedHashMap;
+use sha1::{Digest, Sha1};
+
+use crate::pbo::{
+    find_header_extension, layout_entries, lzss, read_checksum, read_header_list, retrieve,
+    similar_entries, ChecksumStatus, GarbageEntry, PBOHeader, PackingMethod,
+};
+use crate::{aerror, ArmakeError};
+
+/// Size of the chunks the data is hashed in by `PBOIndex::checksum_status`
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Entry of a PBO with the position of its data in the input
+#[derive(Clone, Debug)]
+pub struct IndexEntry {
+    pub header: PBOHeader,
+    /// Range of the stored data in the input, shorter than the header says for garbage entries
+    /// that end past the end of the input
+    pub data: Range<u64>,
+}
+
+/// Headers of a PBO with the positions of their data, see the module documentation
+///
+/// Entries are filtered and named like in `PBO::read`, so an index and a PBO read from the same
+/// input have the same entries.
+#[derive(Clone, Debug)]
+pub struct PBOIndex {
+    /// Header extensions in stored order, see `PBO::header_extensions`
+    pub header_extensions: LinkedHashMap<String, String>,
+    /// Entries in stored order
+    pub entries: Vec<IndexEntry>,
+    /// Entries that were filtered out, see `PBO::read`
+    pub garbage: Vec<GarbageEntry>,
+    /// Position of the PBO in the input
+    start: u64,
+    /// Position of the end of the data in the input, where the checksum follows
+    data_end: u64,
+}
+
+impl PBOIndex {
+    /// Reads the header list of a PBO starting at the current position of the input and seeks
+    /// over the data to find out which entries have all of theirs, see `PBO::read`.
+    pub fn read<I: Read + Seek>(input: &mut I) -> Result<PBOIndex, ArmakeError> {
+        PBOIndex::read_entries(input, false)
+    }
+
+    /// Reads the header list like `read`, keeping the entries that look like garbage, see
+    /// `PBO::read_keeping_garbage`.
+    pub fn read_keeping_garbage<I: Read + Seek>(input: &mut I) -> Result<PBOIndex, ArmakeError> {
+        PBOIndex::read_entries(input, true)
+    }
+
+    /// Returns the value of the header extension `key`, see `PBO::header_extension`.
+    pub fn header_extension(&self, key: &str) -> Option<&str> {
+        find_header_extension(&self.header_extensions, key)
+    }
+
+    fn read_entries<I: Read + Seek>(
+        input: &mut I,
+        keep_garbage: bool,
+    ) -> Result<PBOIndex, ArmakeError> {
+        let start = input.stream_position()?;
+        let mut garbage: Vec<GarbageEntry> = Vec::new();
+        let (list, data_start) = {
+            let mut reader = BufReader::new(&mut *input);
+            let list = read_header_list(&mut reader, &mut garbage)?;
+            (list, reader.stream_position()?)
+        };
+        let available = input.seek(SeekFrom::End(0))?.saturating_sub(data_start);
+        let (entries, data_end) = layout_entries(
+            list.headers,
+            data_start,
+            available,
+            keep_garbage,
+            &mut garbage,
+        );
+
+        Ok(PBOIndex {
+            header_extensions: list.header_extensions,
+            entries,
+            garbage,
+            start,
+            data_end,
+        })
+    }
+
+    /// Returns the headers of the entries in stored order.
+    pub fn headers(&self) -> impl Iterator<Item = &PBOHeader> {
+        self.entries.iter().map(|e| &e.header)
+    }
+
+    /// Returns the header of the entry `name`, `None` if there is no such entry.
+    pub fn header(&self, name: &str) -> Option<&PBOHeader> {
+        self.headers().find(|h| h.filename == name)
+    }
+
+    /// Returns the stored name of the entry `name`, looked up like `PBO::retrieve`.
+    pub fn retrieve(&self, name: &str) -> Option<&str> {
+        retrieve(
+            self.entries.iter().map(|e| e.header.filename.as_str()),
+            name,
+        )
+    }
+
+    /// Returns up to `max` entry names close to `name`, see `PBO::similar_entries`.
+    pub fn similar_entries(&self, name: &str, max: usize) -> Vec<&str> {
+        similar_entries(self.headers().map(|h| h.filename.as_str()), name, max)
+    }
+
+    /// Reads the stored data of the entry `name` from the input the index was read from, `None`
+    /// if there is no such entry.
+    pub fn read_raw<I: Read + Seek>(
+        &self,
+        input: &mut I,
+        name: &str,
+    ) -> Result<Option<Vec<u8>>, ArmakeError> {
+        let entry = match self.entries.iter().find(|e| e.header.filename == name) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        input.seek(SeekFrom::Start(entry.data.start))?;
+        let mut data = vec![0; (entry.data.end - entry.data.start) as usize];
+        input.read_exact(&mut data)?;
+        Ok(Some(data))
+    }
+
+    /// Reads the content of the entry `name` from the input the index was read from, `None` if
+    /// there is no such entry. Compressed entries are decompressed like in `PBO::entry_data`.
+    pub fn read_file<I: Read + Seek>(
+        &self,
+        input: &mut I,
+        name: &str,
+    ) -> Result<Option<Vec<u8>>, ArmakeError> {
+        let data = match self.read_raw(input, name)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        match self.header(name) {
+            Some(header) if header.method() == PackingMethod::Packed => {
+                lzss::decompress(&data, header.original_size as usize)
+                    .map(Some)
+                    .map_err(|e| aerror!("Failed to decompress {}: {}", name, e))
+            }
+            _ => Ok(Some(data)),
+        }
+    }
+
+    /// Hashes the PBO in the input the index was read from in chunks and compares it with the
+    /// stored checksum, see `PBO::checksum_status`.
+    pub fn checksum_status<I: Read + Seek>(
+        &self,
+        input: &mut I,
+    ) -> Result<ChecksumStatus, ArmakeError> {
+        Ok(self.checksum(input)?.1)
+    }
+
+    /// Returns true if the PBO in the input the index was read from ends with a checksum, without
+    /// hashing it like `checksum_status`.
+    pub fn has_checksum<I: Read + Seek>(&self, input: &mut I) -> Result<bool, ArmakeError> {
+        input.seek(SeekFrom::Start(self.data_end))?;
+        let mut trailer = Vec::new();
+        input.take(21).read_to_end(&mut trailer)?;
+        Ok(trailer.len() == 21)
+    }
+
+    /// Returns the stored checksum, `None` if it is missing, with its status like
+    /// `checksum_status`.
+    pub fn checksum<I: Read + Seek>(
+        &self,
+        input: &mut I,
+    ) -> Result<(Option<Vec<u8>>, ChecksumStatus), ArmakeError> {
+        input.seek(SeekFrom::Start(self.start))?;
+        let mut hash = Sha1::new();
+        let mut buffer = vec![0; CHUNK_SIZE];
+        let mut remaining = self.data_end - self.start;
+        while remaining > 0 {
+            let chunk = &mut buffer[..remaining.min(CHUNK_SIZE as u64) as usize];
+            input.read_exact(chunk)?;
+            hash.update(&chunk[..]);
+            remaining -= chunk.len() as u64;
+        }
+
+        let mut trailer = Vec::new();
+        input.take(21).read_to_end(&mut trailer)?;
+        Ok(read_checksum(&hash.finalize(), &trailer))
+    }
+}
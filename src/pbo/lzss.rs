@@ -0,0 +1,179 @@
+//! LZSS compression of PBO entries
+//!
+//! Compressed data is a sequence of blocks, each starting with a flag byte followed by eight
+//! packets, one per bit starting at the lowest. A set bit is a literal byte, a clear bit a two byte
+//! back reference `b1 b2` copying `(b2 & 0x0F) + 3` bytes that start
+//! `b1 | (b2 & 0xF0) << 4` bytes before the end of the output. Positions before the start of the
+//! output read as spaces. The data ends with the sum of all decompressed bytes as a little-endian
+//! `u32`.
+
+use crate::{aerror, ArmakeError};
+
+/// Patterns of files that are not compressed by default, because their formats are compressed
+/// already
+pub const DEFAULT_UNCOMPRESSED: &[&str] = &["*.paa", "*.pac", "*.ogg", "*.wss", "*.jpg", "*.png"];
+
+/// Longest distance of a back reference
+const WINDOW: usize = 0xFFF;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 0xF + MIN_MATCH;
+/// Number of earlier positions with the same hash that are tried for a match
+const MAX_CHAIN: usize = 256;
+
+/// Compresses `input`, see the module documentation. Matches are searched greedily.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output: Vec<u8> = Vec::with_capacity(input.len() + input.len() / 8 + 5);
+    let mut matcher = Matcher {
+        input,
+        head: vec![usize::MAX; 1 << 16],
+        previous: vec![usize::MAX; input.len()],
+    };
+
+    let mut position = 0;
+    while position < input.len() {
+        let flags = output.len();
+        output.push(0);
+        for bit in 0..8 {
+            if position >= input.len() {
+                break;
+            }
+
+            let (length, distance) = matcher.longest_match(position);
+            if length >= MIN_MATCH {
+                output.push((distance & 0xFF) as u8);
+                output.push(((distance >> 4) & 0xF0 | (length - MIN_MATCH)) as u8);
+                for i in position..position + length {
+                    matcher.insert(i);
+                }
+                position += length;
+            } else {
+                output[flags] |= 1 << bit;
+                output.push(input[position]);
+                matcher.insert(position);
+                position += 1;
+            }
+        }
+    }
+
+    output.extend_from_slice(&checksum(input).to_le_bytes());
+    output
+}
+
+/// Chains of earlier positions in the input by the hash of the three bytes starting there
+struct Matcher<'a> {
+    input: &'a [u8],
+    /// Most recent position of every hash
+    head: Vec<usize>,
+    /// Previous position with the same hash for every position
+    previous: Vec<usize>,
+}
+
+impl<'a> Matcher<'a> {
+    fn hash(&self, i: usize) -> usize {
+        let input = self.input;
+        (usize::from(input[i]) << 8 ^ usize::from(input[i + 1]) << 4 ^ usize::from(input[i + 2]))
+            & 0xFFFF
+    }
+
+    fn insert(&mut self, i: usize) {
+        if i + MIN_MATCH <= self.input.len() {
+            let h = self.hash(i);
+            self.previous[i] = self.head[h];
+            self.head[h] = i;
+        }
+    }
+
+    /// Returns the length and distance of the longest earlier match for the bytes at
+    /// `position`.
+    fn longest_match(&self, position: usize) -> (usize, usize) {
+        let input = self.input;
+        if position + MIN_MATCH > input.len() {
+            return (0, 0);
+        }
+        let max = MAX_MATCH.min(input.len() - position);
+        let mut best = (0, 0);
+        let mut candidate = self.head[self.hash(position)];
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || position - candidate > WINDOW {
+                break;
+            }
+            let length = (0..max)
+                .take_while(|&i| input[candidate + i] == input[position + i])
+                .count();
+            if length > best.0 {
+                best = (length, position - candidate);
+                if length == max {
+                    break;
+                }
+            }
+            candidate = self.previous[candidate];
+        }
+        best
+    }
+}
+
+/// Decompresses `input` to `size` bytes and validates the trailing checksum.
+pub fn decompress(input: &[u8], size: usize) -> Result<Vec<u8>, ArmakeError> {
+    let mut output: Vec<u8> = Vec::with_capacity(size);
+    let mut bytes = input.iter();
+    let mut next = || {
+        bytes.next().cloned().ok_or_else(|| {
+            aerror!(
+                "Compressed data ends before {} bytes were decompressed",
+                size
+            )
+        })
+    };
+
+    while output.len() < size {
+        let flags = next()?;
+        for bit in 0..8 {
+            if output.len() >= size {
+                break;
+            }
+            if flags & (1 << bit) != 0 {
+                output.push(next()?);
+                continue;
+            }
+
+            let (b1, b2) = (next()?, next()?);
+            let distance = usize::from(b1) | usize::from(b2 & 0xF0) << 4;
+            let length = usize::from(b2 & 0x0F) + 3;
+            if distance == 0 {
+                return Err(aerror!(
+                    "Invalid back reference at offset {} of the decompressed data",
+                    output.len()
+                ));
+            }
+            for _ in 0..length.min(size - output.len()) {
+                let byte = match output.len().checked_sub(distance) {
+                    Some(position) => output[position],
+                    None => b' ',
+                };
+                output.push(byte);
+            }
+        }
+    }
+
+    let consumed = input.len() - bytes.as_slice().len();
+    let stored = match input.get(consumed..consumed + 4) {
+        Some(stored) => u32::from_le_bytes([stored[0], stored[1], stored[2], stored[3]]),
+        None => return Err(aerror!("Compressed data ends before its checksum")),
+    };
+    let checksum = checksum(&output);
+    if stored != checksum {
+        return Err(aerror!(
+            "Checksum mismatch, stored 0x{:08x} but the data sums to 0x{:08x}",
+            stored,
+            checksum
+        ));
+    }
+
+    Ok(output)
+}
+
+/// Returns the checksum of decompressed data, the wrapping sum of its bytes.
+fn checksum(data: &[u8]) -> u32 {
+    data.iter()
+        .fold(0u32, |sum, &b| sum.wrapping_add(u32::from(b)))
+}
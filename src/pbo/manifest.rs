@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use linked_hash_map::LinkedHashMap;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::{aerror, ArmakeError, PBO};
+
+/// Default file name of the manifest written by `unpack`, relative to the target folder
+pub const MANIFEST_NAME: &str = ".armake2-manifest.json";
+
+/// Record of the files extracted from a PBO
+///
+/// Entries are sorted by their internal name and extensions by key, so the same PBO always
+/// results in the same manifest.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of armake2 that wrote the manifest
+    pub version: String,
+    pub prefix: Option<String>,
+    /// Header extensions other than the prefix
+    pub extensions: BTreeMap<String, String>,
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// Extracted file of a PBO
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Name of the entry in the PBO
+    pub name: String,
+    /// Path the entry was extracted to, relative to the target folder and separated by `/`
+    pub path: String,
+    pub size: u64,
+    /// Lowercase hex SHA1 of the content
+    pub sha1: String,
+    /// Timestamp from the entry header
+    pub timestamp: u32,
+}
+
+impl ManifestEntry {
+    /// Describes the entry `name` with the given content, extracted to `path`.
+    pub fn new(name: &str, path: String, data: &[u8], timestamp: u32) -> ManifestEntry {
+        ManifestEntry {
+            name: name.to_string(),
+            path,
+            size: data.len() as u64,
+            sha1: Sha1::digest(data)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            timestamp,
+        }
+    }
+}
+
+impl Manifest {
+    /// Creates the manifest of a PBO, `path` returns the path an entry name is extracted to.
+    /// Compressed entries are described by their decompressed content.
+    pub fn from_pbo<F: Fn(&str) -> String>(pbo: &PBO, path: F) -> Result<Manifest, ArmakeError> {
+        let mut entries: Vec<ManifestEntry> = Vec::new();
+        for header in pbo.headers.iter() {
+            let data = match pbo.entry_data(&header.filename)? {
+                Some(data) => data,
+                None => continue,
+            };
+            entries.push(ManifestEntry::new(
+                &header.filename,
+                path(&header.filename),
+                &data,
+                header.timestamp,
+            ));
+        }
+        Ok(Manifest::new(&pbo.header_extensions, entries))
+    }
+
+    /// Creates the manifest of a PBO with the given header extensions from its entries, for
+    /// entries that are read one at a time.
+    pub fn new(
+        header_extensions: &LinkedHashMap<String, String>,
+        mut entries: Vec<ManifestEntry>,
+    ) -> Manifest {
+        let mut extensions: BTreeMap<String, String> = header_extensions
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        let prefix = match extensions.remove("prefix") {
+            Some(prefix) => Some(prefix),
+            None => extensions
+                .keys()
+                .find(|k| k.eq_ignore_ascii_case("prefix"))
+                .cloned()
+                .and_then(|k| extensions.remove(&k)),
+        };
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Manifest {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            prefix,
+            extensions,
+            entries,
+        }
+    }
+
+    /// Writes the manifest as pretty-printed JSON.
+    pub fn write<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        serde_json::to_writer_pretty(&mut *output, self)
+            .map_err(|e| aerror!("Failed to write manifest: {}", e))?;
+        output.write_all(b"\n")?;
+        Ok(())
+    }
+}
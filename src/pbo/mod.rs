@@ -1,199 +1,1148 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::ffi::OsStr;
 use std::fs::File;
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 use linked_hash_map::LinkedHashMap;
 use regex::Regex;
 use sha1::{Digest, Sha1};
 
-use crate::io::{ReadExt, WriteExt};
-use crate::{binarize, ArmakeError, Config};
+use crate::error::IOPathError;
+use crate::io::{HashingReader, ReadExt, WriteExt};
+use crate::preprocess::{IncludeReader, PreprocessOptions};
+use crate::warning::{self, Warning};
+use crate::{aerror, binarize, ArmakeError, BinarizeOptions, Config, Encoding};
 
 mod fs;
+pub use fs::matches_glob;
+pub(crate) use fs::{excluded_by, list_files};
 
 mod header;
 pub use header::{PBOHeader, PackingMethod};
 
+pub mod lzss;
+
+mod ignore;
+pub use ignore::{parse_ignore, read_ignore_file, DEFAULT_EXCLUDES, IGNORE_FILE_NAME};
+
+mod index;
+pub use index::{IndexEntry, PBOIndex};
+
+mod hashes;
+pub use hashes::{HashMismatch, HASH_ENTRY, HASH_EXTENSION};
+
+mod manifest;
+pub use manifest::{Manifest, ManifestEntry, MANIFEST_NAME};
+
+mod order;
+pub use order::{EntryOrder, OrderEntry, ORDER_FILE_NAME};
+
+mod prefix;
+pub use prefix::{is_prefix_file, parse_prefix, prefix_files, read_prefix_files, PrefixStyle};
+
+pub mod patch;
+
+mod rules;
+pub use rules::{FileAction, FileRule, FileRules};
+
+/// Returns the timestamp to store in the header of an entry with the given modification time.
+///
+/// If `SOURCE_DATE_EPOCH` is set, timestamps are clamped to it and entries without a modification
+/// time use it directly, otherwise they use 0.
+pub fn clamp_timestamp(mtime: Option<u32>) -> u32 {
+    let epoch = std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|value| {
+        let epoch = value.trim().parse::<u32>().ok();
+        if epoch.is_none() {
+            warn!("Ignoring invalid SOURCE_DATE_EPOCH \"{}\"", value);
+        }
+        epoch
+    });
+
+    match (mtime, epoch) {
+        (Some(mtime), Some(epoch)) => mtime.min(epoch),
+        (Some(mtime), None) => mtime,
+        (None, epoch) => epoch.unwrap_or(0),
+    }
+}
+
+/// Returns the form of an entry name used for lookups, lowercase with backslashes as separators.
+fn normalize_entry_name(name: &str) -> String {
+    name.replace('/', "\\").to_lowercase()
+}
+
+/// Returns the number of single character insertions, deletions and substitutions that turn `a`
+/// into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == *cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Looks up `name` among the entry names, see `PBO::retrieve`.
+fn retrieve<'a, N: Iterator<Item = &'a str> + Clone>(names: N, name: &str) -> Option<&'a str> {
+    if let Some(stored) = names.clone().find(|k| *k == name) {
+        return Some(stored);
+    }
+
+    let normalized = normalize_entry_name(name);
+    let matches: Vec<&str> = names
+        .filter(|k| normalize_entry_name(k) == normalized)
+        .collect();
+    if matches.len() > 1 {
+        warn!(
+            "\"{}\" matches several entries: {}, using {}",
+            name,
+            matches.join(", "),
+            matches[0]
+        );
+    }
+    matches.first().cloned()
+}
+
+/// Returns the entry names close to `name`, see `PBO::similar_entries`.
+fn similar_entries<'a, N: Iterator<Item = &'a str>>(
+    names: N,
+    name: &str,
+    max: usize,
+) -> Vec<&'a str> {
+    let normalized = normalize_entry_name(name);
+    let file_name = normalized.rsplit('\\').next().unwrap();
+    let mut similar: Vec<(usize, &str)> = names
+        .filter_map(|k| {
+            let key = normalize_entry_name(k);
+            let distance = edit_distance(&normalized, &key);
+            if distance <= normalized.chars().count() / 3
+                || key.rsplit('\\').next().unwrap() == file_name
+            {
+                Some((distance, k))
+            } else {
+                None
+            }
+        })
+        .collect();
+    similar.sort_by_key(|(distance, _)| *distance);
+    similar.into_iter().take(max).map(|(_, k)| k).collect()
+}
+
+/// Returns the modification time of a file as a Unix timestamp, `None` if it is unknown or doesn't
+/// fit the header field.
+fn modification_time(path: &Path) -> Option<u32> {
+    let modified = path.metadata().and_then(|m| m.modified()).ok()?;
+    let seconds = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    u32::try_from(seconds).ok()
+}
+
+/// Returns the header of an uncompressed entry.
+fn entry_header(name: &str, size: usize, timestamp: u32) -> PBOHeader {
+    PBOHeader {
+        filename: name.to_string(),
+        packing_method: 0,
+        original_size: size as u32,
+        reserved: 0,
+        timestamp,
+        data_size: size as u32,
+    }
+}
+
+/// Returns the size of the file `path` for the entry `name`, failing if it's too large for an
+/// entry.
+fn source_size(name: &str, path: &Path) -> Result<u32, ArmakeError> {
+    let size = path.metadata()?.len();
+    u32::try_from(size).map_err(|_| {
+        aerror!(
+            "{} is too large for {}, entries can't exceed 4 GiB",
+            path.display(),
+            name
+        )
+    })
+}
+
+/// Returns the value of the header extension `key`, see `PBO::header_extension`.
+pub(crate) fn find_header_extension<'a>(
+    extensions: &'a LinkedHashMap<String, String>,
+    key: &str,
+) -> Option<&'a str> {
+    extensions
+        .get(key)
+        .or_else(|| {
+            extensions
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value)
+        })
+        .map(|value| value.as_str())
+}
+
+/// Checksum written instead of the SHA1 by `PBO::write_without_checksum`
+pub const PLACEHOLDER_CHECKSUM: [u8; 20] = [0; 20];
+
+/// Result of comparing the stored checksum of a PBO with its content, see `PBO::read`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChecksumStatus {
+    Valid,
+    /// The stored checksum is not the SHA1 of the content
+    Mismatch,
+    /// The PBO was built with `--no-checksum`, see `PBO::write_without_checksum`
+    Placeholder,
+    /// The PBO ends before its checksum
+    Missing,
+}
+
+impl ChecksumStatus {
+    /// Returns true if the checksum doesn't match the content or is missing.
+    pub fn is_bad(self) -> bool {
+        self == ChecksumStatus::Mismatch || self == ChecksumStatus::Missing
+    }
+}
+
+impl std::fmt::Display for ChecksumStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            ChecksumStatus::Valid => "valid",
+            ChecksumStatus::Mismatch => "doesn't match the content",
+            ChecksumStatus::Placeholder => "placeholder checksum, built with --no-checksum",
+            ChecksumStatus::Missing => "missing, the PBO is truncated",
+        })
+    }
+}
+
+/// Returns true for files like `$PBOPREFIX$` or `$NOBIN$` that only carry metadata
+fn is_metadata(name: &str) -> bool {
+    name.len() > 1 && name.starts_with('$') && name.ends_with('$')
+}
+
+/// Receives the entries of a PBO while it is written, see `PBO::write_observed`
+pub(crate) trait WriteObserver {
+    /// Called with the header of every entry in stored order, before its data.
+    fn entry(&mut self, header: &PBOHeader);
+    /// Called with the stored data of the current entry, possibly in several chunks.
+    fn data(&mut self, chunk: &[u8]);
+}
+
+impl WriteObserver for () {
+    fn entry(&mut self, _header: &PBOHeader) {}
+    fn data(&mut self, _chunk: &[u8]) {}
+}
+
+/// Returns the prefix of an addon without a prefix file, the name of its folder. Paths like `.`
+/// that don't end in a name use the name of the folder they resolve to.
+fn default_prefix(directory: &Path) -> Result<String, ArmakeError> {
+    let name = match directory.file_name() {
+        Some(name) => Some(name.to_owned()),
+        None => directory.canonicalize()?.file_name().map(|n| n.to_owned()),
+    };
+    name.and_then(|n| n.to_str().map(String::from))
+        .ok_or_else(|| {
+            aerror!(
+                "cannot derive a prefix from {:?}; pass one explicitly",
+                directory
+            )
+        })
+}
+
+/// Returns true for the marker files that turn off binarization of a folder, see
+/// `PBO::from_directory_with_dependencies`
+fn is_nobin_marker(name: &str) -> bool {
+    name.eq_ignore_ascii_case("$NOBIN$") || name.eq_ignore_ascii_case("$NOBIN-NOTEST$")
+}
+
+/// Returns true if `name` can be used as an entry name: a relative path with backslashes as
+/// separators and no empty, `.` or `..` components.
+pub(crate) fn is_valid_entry_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains('/')
+        && !name.chars().any(|c| c.is_control())
+        && !name
+            .split('\\')
+            .any(|c| c.is_empty() || c == "." || c == "..")
+}
+
+/// Why `PBO::read` left out an entry
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GarbageReason {
+    /// The name contains control characters
+    ControlCharacters,
+    /// The data of the entry would end after the end of the input
+    DataPastEnd,
+    /// A header extension block after the first header
+    MisplacedExtensions,
+}
+
+impl std::fmt::Display for GarbageReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            GarbageReason::ControlCharacters => "the name contains control characters",
+            GarbageReason::DataPastEnd => "the data would end after the end of the PBO",
+            GarbageReason::MisplacedExtensions => "header extensions after the first header",
+        })
+    }
+}
+
+/// Header entry of a PBO that doesn't describe a real file, see `PBO::read`
+#[derive(Clone, Debug)]
+pub struct GarbageEntry {
+    pub header: PBOHeader,
+    pub reason: GarbageReason,
+}
+
+/// Headers and header extensions at the start of a PBO, see `read_header_list`
+struct HeaderList {
+    header_extensions: LinkedHashMap<String, String>,
+    headers: Vec<PBOHeader>,
+}
+
+/// Reads the headers and header extensions of a PBO up to the end of the header list. Header
+/// extensions after the first header are recorded in `garbage`.
+fn read_header_list<I: Read>(
+    input: &mut I,
+    garbage: &mut Vec<GarbageEntry>,
+) -> Result<HeaderList, ArmakeError> {
+    let mut list = HeaderList {
+        header_extensions: LinkedHashMap::new(),
+        headers: Vec::new(),
+    };
+    let mut first = true;
+
+    loop {
+        let header = PBOHeader::read(input)?;
+
+        if header.method() == PackingMethod::ProductEntry {
+            loop {
+                let s = input.read_cstring()?;
+                if s.is_empty() {
+                    break;
+                }
+
+                let value = input.read_cstring()?;
+                if first {
+                    list.header_extensions.insert(s, value);
+                }
+            }
+            if !first {
+                garbage.push(GarbageEntry {
+                    header,
+                    reason: GarbageReason::MisplacedExtensions,
+                });
+            }
+        } else if header.ends_header_list() {
+            break;
+        } else {
+            list.headers.push(header);
+        }
+
+        first = false;
+    }
+
+    Ok(list)
+}
+
+/// Assigns the data following the header list, which starts at `start` and of which `available`
+/// bytes are there, to the entries in header order, see `PBO::read` for the rules. Returns the
+/// kept entries with the position of their data and the end of the data.
+fn layout_entries(
+    headers: Vec<PBOHeader>,
+    start: u64,
+    available: u64,
+    keep_garbage: bool,
+    garbage: &mut Vec<GarbageEntry>,
+) -> (Vec<IndexEntry>, u64) {
+    let mut entries: Vec<IndexEntry> = Vec::new();
+    let mut names: HashSet<String> = HashSet::new();
+    let mut offset = 0;
+    for mut header in headers {
+        let size = u64::from(header.data_size);
+        let reason = if available - offset < size {
+            Some(GarbageReason::DataPastEnd)
+        } else if header.filename.chars().any(|c| c.is_control()) {
+            Some(GarbageReason::ControlCharacters)
+        } else {
+            None
+        };
+        let end = (offset + size).min(available);
+        if let Some(reason) = reason {
+            warn!(
+                "Skipping the entry {:?} ({} bytes): {}",
+                header.filename, header.data_size, reason
+            );
+            garbage.push(GarbageEntry {
+                header: header.clone(),
+                reason,
+            });
+            if !keep_garbage {
+                if reason != GarbageReason::DataPastEnd {
+                    offset = end;
+                }
+                continue;
+            }
+        }
+        if names.contains(&header.filename.to_lowercase()) {
+            let name = (1..)
+                .map(|n| format!("{}.{}", header.filename, n))
+                .find(|name| !names.contains(&name.to_lowercase()))
+                .unwrap();
+            warn!(
+                "The entry {:?} is in the PBO more than once, reading the copy as {:?}",
+                header.filename, name
+            );
+            header.filename = name;
+        }
+        names.insert(header.filename.to_lowercase());
+        entries.push(IndexEntry {
+            header,
+            data: start + offset..start + end,
+        });
+        offset = end;
+    }
+    (entries, start + offset)
+}
+
+/// Returns the checksum stored in the trailer following the data and how it compares to the
+/// `computed` one. The trailer is a zero byte followed by the checksum.
+fn read_checksum(computed: &[u8], trailer: &[u8]) -> (Option<Vec<u8>>, ChecksumStatus) {
+    if trailer.len() < 21 {
+        return (None, ChecksumStatus::Missing);
+    }
+    let checksum = trailer[1..21].to_vec();
+    let status = if checksum == PLACEHOLDER_CHECKSUM {
+        ChecksumStatus::Placeholder
+    } else if checksum == computed {
+        ChecksumStatus::Valid
+    } else {
+        ChecksumStatus::Mismatch
+    };
+    (Some(checksum), status)
+}
+
 #[derive(Clone)]
 pub struct PBO {
     pub files: LinkedHashMap<String, Cursor<Box<[u8]>>>,
-    pub header_extensions: HashMap<String, String>,
-    pub extension_order: Vec<String>,
+    /// Header extensions in the order they are written, with the keys in their original case,
+    /// see `header_extension`
+    pub header_extensions: LinkedHashMap<String, String>,
     pub headers: Vec<PBOHeader>,
     /// only defined when reading existing PBOs, for created PBOs this is calculated during writing
     /// and included in the output
     pub checksum: Option<Vec<u8>>,
+    /// Whether the checksum matched the content when the PBO was read, `None` for created PBOs
+    pub checksum_status: Option<ChecksumStatus>,
+    /// Entries that were filtered out when reading the PBO, see `read`
+    pub garbage: Vec<GarbageEntry>,
+    /// Files on disk the data of entries is copied from when the PBO is written, so that large
+    /// files are never read into memory. Their cursors in `files` stay empty, `entry_data` reads
+    /// the file. Remove an entry from here to replace its data in `files`.
+    pub sources: HashMap<String, PathBuf>,
 }
 
 impl PBO {
     /// Reads an existing PBO from input.
+    ///
+    /// The headers of the entries are kept in `headers`, including packing methods armake2
+    /// doesn't know, which `write` keeps as long as the data of the entry doesn't change.
+    ///
+    /// The content is hashed while it is read and compared with the stored checksum, see
+    /// `checksum_status`. A PBO that ends before its checksum is read without one.
+    ///
+    /// Headers that can't describe a real file, like the ones obfuscators insert, are left out
+    /// with a warning and recorded in `garbage`: names with control characters, data ending after
+    /// the end of the input and header extensions after the first header. The data of a left out
+    /// entry is skipped if it is there. See `read_keeping_garbage` to keep them.
+    ///
+    /// Data is assigned to the entries strictly in header order. An entry whose name is taken
+    /// already, ignoring case, which obfuscators use to hide data, is read with a suffix like
+    /// `file.sqf.1`, so no two entries extract to the same file. The
+    /// header list ends with an empty name without data, empty names with data are entries.
+    ///
+    /// The whole PBO is kept in memory, `read_headers` reads single entries of large PBOs.
     pub fn read<I: Read>(input: &mut I) -> Result<PBO, ArmakeError> {
-        let mut headers: Vec<PBOHeader> = Vec::new();
-        let mut first = true;
-        let mut header_extensions: HashMap<String, String> = HashMap::new();
-        let mut extension_order: Vec<String> = Vec::new();
-
-        loop {
-            let header = PBOHeader::read(input)?;
-            // todo: garbage filter
-
-            if header.method() == PackingMethod::ProductEntry {
-                if !first {
-                    unreachable!();
-                }
+        PBO::read_entries(input, false)
+    }
 
-                loop {
-                    let s = input.read_cstring()?;
-                    if s.is_empty() {
-                        break;
-                    }
+    /// Reads an existing PBO like `read`, keeping the entries with control characters in their
+    /// names and the available data of entries ending after the end of the input. They are still
+    /// recorded in `garbage`.
+    pub fn read_keeping_garbage<I: Read>(input: &mut I) -> Result<PBO, ArmakeError> {
+        PBO::read_entries(input, true)
+    }
 
-                    header_extensions.insert(s.clone(), input.read_cstring()?);
-                    extension_order.push(s);
-                }
-            } else if header.filename == "" {
-                break;
-            } else {
-                headers.push(header);
-            }
+    fn read_entries<I: Read>(input: &mut I, keep_garbage: bool) -> Result<PBO, ArmakeError> {
+        let mut input = HashingReader::new(input);
+        let mut garbage: Vec<GarbageEntry> = Vec::new();
+        let list = read_header_list(&mut input, &mut garbage)?;
 
-            first = false;
-        }
+        // the sizes in the headers are only trusted once it's known that the data is there
+        let mut rest = Vec::new();
+        input.inner.read_to_end(&mut rest)?;
+        let (entries, end) = layout_entries(
+            list.headers,
+            0,
+            rest.len() as u64,
+            keep_garbage,
+            &mut garbage,
+        );
 
         let mut files: LinkedHashMap<String, Cursor<Box<[u8]>>> = LinkedHashMap::new();
-        for header in &headers {
-            let mut buffer: Box<[u8]> = vec![0; header.data_size as usize].into_boxed_slice();
-            input.read_exact(&mut buffer)?;
-            files.insert(header.filename.clone(), Cursor::new(buffer));
+        let mut headers: Vec<PBOHeader> = Vec::new();
+        for entry in entries {
+            let data = &rest[entry.data.start as usize..entry.data.end as usize];
+            files.insert(
+                entry.header.filename.clone(),
+                Cursor::new(data.to_vec().into_boxed_slice()),
+            );
+            headers.push(entry.header);
         }
 
-        input.bytes().next();
-        let mut checksum = vec![0; 20];
-        input.read_exact(&mut checksum)?;
+        let end = end as usize;
+        input.hash.update(&rest[..end]);
+        let computed = std::mem::take(&mut input.hash).finalize();
+        let (checksum, status) = read_checksum(&computed, &rest[end..rest.len().min(end + 21)]);
 
         Ok(PBO {
             files,
-            header_extensions,
-            extension_order,
+            header_extensions: list.header_extensions,
             headers,
-            checksum: Some(checksum),
+            checksum,
+            checksum_status: Some(status),
+            garbage,
+            sources: HashMap::new(),
         })
     }
 
+    /// Reads the headers of an existing PBO without its data, see `PBOIndex`.
+    pub fn read_headers<I: Read + Seek>(input: &mut I) -> Result<PBOIndex, ArmakeError> {
+        PBOIndex::read(input)
+    }
+
     /// Constructs a PBO from a directory with optional binarization.
     ///
-    /// `exclude_patterns` contains glob patterns to exclude from the PBO, `includefolders` contain
+    /// When binarizing, configs are always preprocessed before they are rapified.
+    ///
+    /// `exclude_patterns` contains glob patterns to exclude from the PBO, along with those of the
+    /// `.pboignore` file of the directory, see `read_ignore_file`. `includefolders` contain
     /// paths to search for absolute includes and should generally include the current working
-    /// directory.
+    /// directory. The header extensions are read from the prefix file of the directory, see
+    /// `PrefixStyle` for the recognized names.
+    ///
+    /// Every entry gets a header with the modification time of its source file as timestamp,
+    /// clamped to `SOURCE_DATE_EPOCH`, see `clamp_timestamp`.
+    ///
+    /// Files that are packed as they are only get their size read here, their data is copied
+    /// when the PBO is written, see `sources`. Rapified and binarized files are kept in memory.
+    ///
+    /// Logs a warning with the number of found and excluded files if the directory contains no
+    /// files besides metadata like `$PBOPREFIX$`, see `is_empty_addon`.
     pub fn from_directory(
         directory: PathBuf,
-        mut binarize: bool,
+        binarize: bool,
         exclude_patterns: &[&str],
         includefolders: &[PathBuf],
+    ) -> Result<PBO, ArmakeError> {
+        PBO::from_directory_with_dependencies(
+            directory,
+            binarize,
+            exclude_patterns,
+            includefolders,
+            &PreprocessOptions::default(),
+            &BinarizeOptions::default(),
+            &FileRules::default(),
+            &mut Vec::new(),
+        )
+    }
+
+    /// Constructs a PBO from a directory like `from_directory`, adding every file that was read
+    /// to `dependencies`: the packed files and the files included by configs.
+    ///
+    /// What happens to each file is decided by the first matching rule of `rules`, followed by
+    /// the default rules, see `FileRules`. Rapified files are preprocessed with
+    /// `preprocess_options`. Models are binarized with `binarize_all` using `binarize_options`,
+    /// if `find_binarizer` finds no binarizer they are packed as they are with a warning.
+    ///
+    /// A `$NOBIN$` or `$NOBIN-NOTEST$` marker in the top-level folder, in any case, turns off
+    /// binarization: everything is packed as it is, including `config.cpp`, and rules that rapify
+    /// or binarize are ignored. The marker itself is not packed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_directory_with_dependencies(
+        directory: PathBuf,
+        binarize: bool,
+        exclude_patterns: &[&str],
+        includefolders: &[PathBuf],
+        preprocess_options: &PreprocessOptions,
+        binarize_options: &BinarizeOptions,
+        rules: &FileRules,
+        dependencies: &mut Vec<PathBuf>,
     ) -> Result<PBO, ArmakeError> {
         let file_list = fs::list_files(&directory)?;
+        let p3do_regex = Regex::new(".p3do$").unwrap();
         let mut files: LinkedHashMap<String, Cursor<Box<[u8]>>> = LinkedHashMap::new();
-        let mut header_extensions: HashMap<String, String> = HashMap::new();
+        let mut header_extensions: LinkedHashMap<String, String> = LinkedHashMap::new();
+
+        let nobin = file_list.iter().any(|path| {
+            path.parent() == Some(directory.as_path())
+                && path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(is_nobin_marker)
+        });
+        let rules = if nobin {
+            info!(
+                "{} has a $NOBIN$ marker, packing it as it is",
+                directory.display()
+            );
+            FileRules::new(
+                rules
+                    .rules
+                    .iter()
+                    .filter(|r| r.action == FileAction::Skip || r.action == FileAction::Copy)
+                    .cloned()
+                    .collect(),
+            )
+            .with_defaults(false)
+        } else {
+            rules.with_defaults(binarize)
+        };
 
-        if directory.join("$NOBIN$").exists() || directory.join("$NOBIN-NOTEST$").exists() {
-            binarize = false;
+        // extensions keep the order of $PBOPREFIX$, except for the prefix which comes first
+        let extensions = prefix::read_prefix_files(&directory)?
+            .map(|(_, extensions)| extensions)
+            .unwrap_or_default();
+        let prefix = match extensions.iter().find(|(key, _)| key == "prefix") {
+            Some((_, value)) => value.clone(),
+            None => default_prefix(&directory)?,
+        };
+        header_extensions.insert("prefix".to_string(), prefix);
+        header_extensions.extend(extensions.into_iter().filter(|(key, _)| key != "prefix"));
+
+        let ignored = ignore::read_ignore_file(&directory)?;
+        if directory.join(IGNORE_FILE_NAME).is_file() {
+            dependencies.push(directory.join(IGNORE_FILE_NAME));
         }
+        let exclude_patterns: Vec<&str> = exclude_patterns
+            .iter()
+            .cloned()
+            .chain(ignored.iter().map(|p| p.as_str()))
+            .collect();
+
+        let mut excluded: LinkedHashMap<&str, usize> = LinkedHashMap::new();
+        let mut models: Vec<(String, PathBuf)> = Vec::new();
+        let binarizer = binarize::find_binarizer(binarize_options);
+        let mut unbinarized = 0;
+        let mut timestamps: HashMap<String, u32> = HashMap::new();
+        let mut sources: HashMap<String, PathBuf> = HashMap::new();
+        let total = file_list.len();
 
         for path in file_list {
             let mut relative = path.strip_prefix(&directory).unwrap().to_path_buf();
-            if binarize && relative.file_name() == Some(OsStr::new("config.cpp")) {
+            let source_name = match relative.to_str() {
+                Some(name) => name.replace("/", "\\"),
+                None => {
+                    return Err(aerror!(
+                        "{} can't be packed, its name is not valid unicode",
+                        path.display()
+                    ))
+                }
+            };
+            let rule = rules.rule(&source_name).unwrap();
+            info!("{}: rule {}", source_name, rule);
+
+            if rule.action == FileAction::Rapify
+                && relative.file_name() == Some(OsStr::new("config.cpp"))
+            {
                 relative = relative.with_file_name("config.bin");
             }
 
-            let mut name: String = relative.to_str().unwrap().replace("/", "\\");
-            let is_binarizable = Regex::new(".(rtm|p3d)$").unwrap().is_match(&name);
+            let mut name: String = relative.to_string_lossy().replace("/", "\\");
 
-            if !fs::file_allowed(&name, &exclude_patterns) {
+            if name == MANIFEST_NAME || name == ORDER_FILE_NAME || name == IGNORE_FILE_NAME {
                 continue;
             }
 
-            let mut file = File::open(&path)?;
+            // read above, even if they match an exclude pattern
+            if prefix::is_prefix_file(&name) || is_nobin_marker(&name) {
+                dependencies.push(path.clone());
+                continue;
+            }
 
-            if name == "$PBOPREFIX$" {
-                let mut content = String::new();
-                file.read_to_string(&mut content)?;
-                for l in content.lines() {
-                    if l.is_empty() {
-                        break;
-                    }
+            if let Some(pattern) = fs::excluded_by(&name, &exclude_patterns) {
+                *excluded.entry(pattern).or_insert(0) += 1;
+                continue;
+            }
 
-                    let eq: Vec<String> = l.split('=').map(|s| s.to_string()).collect();
-                    if eq.len() == 1 {
-                        header_extensions.insert("prefix".to_string(), l.to_string());
-                    } else {
-                        header_extensions.insert(eq[0].clone(), eq[1].clone());
-                    }
-                }
-            } else if binarize
-                && vec!["cpp", "rvmat"].contains(
-                    &path
-                        .extension()
-                        .unwrap_or_else(|| OsStr::new(""))
-                        .to_str()
-                        .unwrap(),
-                )
-            {
-                let config = Config::read(&mut file, Some(path.clone()), includefolders, |path| {
-                    let mut content = String::new();
-                    File::open(path)
-                        .unwrap()
-                        .read_to_string(&mut content)
-                        .unwrap();
-                    content
-                })?;
-                let cursor = config.to_cursor()?;
+            if rule.action == FileAction::Skip {
+                *excluded.entry(&rule.pattern).or_insert(0) += 1;
+                continue;
+            }
 
-                files.insert(name, cursor);
-            } else if cfg!(windows) && binarize && is_binarizable {
-                let cursor = binarize::binarize(&path)?;
+            let mut file = File::open(&path)?;
+            dependencies.push(path.clone());
+            let timestamp = clamp_timestamp(modification_time(&path));
+
+            if rule.action == FileAction::Rapify {
+                let reader = IncludeReader::new(Encoding::Utf8);
+                let config = reader.finish(Config::read_with_options(
+                    &mut file,
+                    Some(path.clone()),
+                    includefolders,
+                    |path| reader.read(path),
+                    preprocess_options,
+                ))?;
+                dependencies.extend(reader.included());
+                let cursor = config.to_cursor()?;
 
+                timestamps.insert(name.clone(), timestamp);
                 files.insert(name, cursor);
+            } else if binarizer.is_some() && rule.action == FileAction::Binarize {
+                // binarized below, the entry keeps its place in the file order
+                files.insert(name.clone(), Cursor::new(Box::new([])));
+                timestamps.insert(name.clone(), timestamp);
+                models.push((name, path));
             } else {
-                // if is_binarizable && !cfg!(windows) {
-                //     warning!("On non-Windows systems binarize.exe cannot be used; file will be copied as-is.", Some("non-windows-binarization"), (Some(&relative.to_str().unwrap()), None));
-                // }
+                if rule.action == FileAction::Binarize {
+                    unbinarized += 1;
+                }
 
-                let mut buffer: Vec<u8> = Vec::new();
-                file.read_to_end(&mut buffer)?;
+                name = p3do_regex.replace_all(&name, ".p3d").to_string();
 
-                name = Regex::new(".p3do$")
-                    .unwrap()
-                    .replace_all(&name, ".p3d")
-                    .to_string();
+                timestamps.insert(name.clone(), timestamp);
+                files.insert(name.clone(), Cursor::new(Box::new([])));
+                sources.insert(name, path);
+            }
+        }
 
-                files.insert(name, Cursor::new(buffer.into_boxed_slice()));
+        if unbinarized > 0 {
+            warning::raise(
+                Warning::new(
+                    "no-binarizer",
+                    format!(
+                        "No binarizer found, skipping binarization, packing MLOD as-is: {} file(s). Set {} or use --binarize-cmd to binarize them.",
+                        unbinarized,
+                        binarize::BINARIZE_CMD_VAR
+                    ),
+                )
+                .in_file(directory.display().to_string()),
+            );
+        }
+
+        if !models.is_empty() {
+            let paths: Vec<PathBuf> = models.iter().map(|(_, path)| path.clone()).collect();
+            let outputs = binarize::binarize_all(&paths, &directory, binarize_options)?;
+            for ((name, _), cursor) in models.into_iter().zip(outputs) {
+                *files.get_mut(&name).unwrap() = cursor;
             }
         }
 
-        if header_extensions.get("prefix").is_none() {
-            let prefix: String = directory.file_name().unwrap().to_str().unwrap().to_string();
-            header_extensions.insert("prefix".to_string(), prefix);
+        if files.keys().all(|name| is_metadata(name)) {
+            let mut message = format!(
+                "{} contains no addon files: found {} file(s), {} excluded",
+                directory.display(),
+                total,
+                excluded.values().sum::<usize>()
+            );
+            for (pattern, count) in excluded.iter() {
+                message.push_str(&format!("\n    {} by \"{}\"", count, pattern));
+            }
+            if !files.is_empty() {
+                message.push_str(&format!(
+                    "\n    only metadata files left: {}",
+                    files.keys().cloned().collect::<Vec<_>>().join(", ")
+                ));
+            }
+            warn!("{}", message);
+        } else if !excluded.is_empty() {
+            info!(
+                "{} file(s) excluded: {}",
+                excluded.values().sum::<usize>(),
+                excluded
+                    .iter()
+                    .map(|(pattern, count)| format!("{} by \"{}\"", count, pattern))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
         }
 
-        Ok(PBO {
+        let mut pbo = PBO {
             files,
-            extension_order: {
-                let mut order = Vec::new();
-                for he in &header_extensions {
-                    order.push(he.0.clone());
-                }
-                order
-            },
             header_extensions,
             headers: Vec::new(),
             checksum: None,
+            checksum_status: None,
+            garbage: Vec::new(),
+            sources,
+        };
+        for name in pbo.files.keys() {
+            let size = pbo.data_size(name)?;
+            pbo.headers
+                .push(entry_header(name, size as usize, timestamps[name]));
+        }
+
+        Ok(pbo)
+    }
+
+    /// Constructs a PBO with exactly the given entries, as pairs of entry name and the file to
+    /// read it from, in the given order. See `write_ordered` to keep that order in the output.
+    ///
+    /// If `rapify_configs` is set, files with the extension `cpp` or `rvmat` are preprocessed and
+    /// rapified, keeping the given entry name. Nothing else is treated specially, the PBO has no
+    /// header extensions. Timestamps are taken from the files like in `from_directory`, files that
+    /// are not rapified are copied when the PBO is written like there.
+    pub fn from_files(
+        entries: &[(String, PathBuf)],
+        rapify_configs: bool,
+        includefolders: &[PathBuf],
+    ) -> Result<PBO, ArmakeError> {
+        let mut files: LinkedHashMap<String, Cursor<Box<[u8]>>> = LinkedHashMap::new();
+        let mut headers: Vec<PBOHeader> = Vec::new();
+        let mut sources: HashMap<String, PathBuf> = HashMap::new();
+
+        for (name, path) in entries {
+            if !is_valid_entry_name(name) {
+                return Err(aerror!("\"{}\" is not a valid entry name", name));
+            }
+            if files.keys().any(|k| k.eq_ignore_ascii_case(name)) {
+                return Err(aerror!("Entry \"{}\" is listed more than once", name));
+            }
+
+            let extension = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let mut file = File::open(path).map_err(|source| {
+                ArmakeError::IOPath(IOPathError {
+                    source,
+                    path: path.clone(),
+                    message: Some(format!("Failed to open the file for {}", name)),
+                })
+            })?;
+
+            let (cursor, size) = if rapify_configs && (extension == "cpp" || extension == "rvmat") {
+                let reader = IncludeReader::new(Encoding::Utf8);
+                let config = reader.finish(Config::read(
+                    &mut file,
+                    Some(path.clone()),
+                    includefolders,
+                    |path| reader.read(path),
+                ))?;
+                let cursor = config.to_cursor()?;
+                let size = cursor.get_ref().len();
+                (cursor, size)
+            } else {
+                sources.insert(name.clone(), path.clone());
+                (
+                    Cursor::new(Box::new([]) as Box<[u8]>),
+                    source_size(name, path)? as usize,
+                )
+            };
+
+            headers.push(entry_header(
+                name,
+                size,
+                clamp_timestamp(modification_time(path)),
+            ));
+            files.insert(name.clone(), cursor);
+        }
+
+        Ok(PBO {
+            files,
+            header_extensions: LinkedHashMap::new(),
+            headers,
+            checksum: None,
+            checksum_status: None,
+            garbage: Vec::new(),
+            sources,
         })
     }
 
-    /// Writes PBO to output.
+    /// Returns true if the PBO contains no files other than metadata files like `$NOBIN$`.
+    pub fn is_empty_addon(&self) -> bool {
+        self.files.keys().all(|name| is_metadata(name))
+    }
+
+    /// Returns the value of the header extension `key`, matched exactly if possible and
+    /// otherwise ignoring case, so `Prefix` is found as `prefix`.
+    pub fn header_extension(&self, key: &str) -> Option<&str> {
+        find_header_extension(&self.header_extensions, key)
+    }
+
+    /// Sets the header extension `key` to `value`, returning the old value. An existing extension
+    /// with that key in any case keeps its place and the case of its key. New extensions are
+    /// written after the existing ones, except for the prefix, which comes first.
+    pub fn set_header_extension(&mut self, key: &str, value: &str) -> Option<String> {
+        let existing = self
+            .header_extensions
+            .keys()
+            .find(|k| k.eq_ignore_ascii_case(key))
+            .cloned();
+        if let Some(existing) = existing {
+            return self
+                .header_extensions
+                .get_mut(&existing)
+                .map(|old| std::mem::replace(old, value.to_string()));
+        }
+
+        if key.eq_ignore_ascii_case("prefix") {
+            let rest = std::mem::take(&mut self.header_extensions);
+            self.header_extensions
+                .insert("prefix".to_string(), value.to_string());
+            self.header_extensions.extend(rest);
+        } else {
+            self.header_extensions
+                .insert(key.to_string(), value.to_string());
+        }
+        None
+    }
+
+    /// Renames the entry `old` (matched case-insensitively if there is no exact match) to `new`.
+    ///
+    /// Fails if there is no such entry, if `new` is not a valid entry name or if another entry
+    /// already has that name, ignoring case. Renaming an entry to a different case of its own name
+    /// is allowed. The entry keeps its position, header and content.
+    pub fn rename(&mut self, old: &str, new: &str) -> Result<(), ArmakeError> {
+        let old = match self.files.keys().find(|k| *k == old) {
+            Some(name) => name.clone(),
+            None => match self.files.keys().find(|k| k.eq_ignore_ascii_case(old)) {
+                Some(name) => name.clone(),
+                None => return Err(aerror!("Entry \"{}\" not found", old)),
+            },
+        };
+
+        if !is_valid_entry_name(new) {
+            return Err(aerror!("\"{}\" is not a valid entry name", new));
+        }
+
+        if self
+            .files
+            .keys()
+            .any(|k| *k != old && k.eq_ignore_ascii_case(new))
+        {
+            return Err(aerror!("An entry named \"{}\" already exists", new));
+        }
+
+        let files = std::mem::take(&mut self.files);
+        for (name, cursor) in files {
+            if name == old {
+                self.files.insert(new.to_string(), cursor);
+            } else {
+                self.files.insert(name, cursor);
+            }
+        }
+        if let Some(path) = self.sources.remove(&old) {
+            self.sources.insert(new.to_string(), path);
+        }
+
+        for header in self.headers.iter_mut().filter(|h| h.filename == old) {
+            header.filename = new.to_string();
+        }
+
+        Ok(())
+    }
+
+    /// Writes PBO to output. Entries are sorted by their lowercase name.
+    ///
+    /// Header extensions are written in the order of `header_extensions`. Entries that are in
+    /// `headers` keep their timestamp, and with the same size their packing method, original size
+    /// and reserved field. Other entries get the defaults, with `SOURCE_DATE_EPOCH` as timestamp if
+    /// it is set, see `clamp_timestamp`.
     pub fn write<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        self.write_entries(output, true, true, &mut ())?;
+        Ok(())
+    }
+
+    /// Writes PBO to output like `write`, keeping the entries in the order of `files`.
+    pub fn write_ordered<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        self.write_entries(output, false, true, &mut ())?;
+        Ok(())
+    }
+
+    /// Writes PBO to output like `write`, or like `write_ordered` if `ordered` is set, passing
+    /// the entries to `observer` as they are written, and returns the checksum. Hashing a PBO
+    /// that was not read from a file this way keeps at most one entry in memory, see `sign`.
+    #[cfg(feature = "signing")]
+    pub(crate) fn write_observed<O: Write>(
+        &self,
+        output: &mut O,
+        ordered: bool,
+        observer: &mut dyn WriteObserver,
+    ) -> Result<Vec<u8>, ArmakeError> {
+        self.write_entries(output, !ordered, true, observer)
+    }
+
+    /// Writes PBO to output like `write`, or like `write_ordered` if `ordered` is set, with a
+    /// placeholder checksum of 20 zero bytes instead of the SHA1 of the content.
+    ///
+    /// This saves hashing the whole PBO and is only meant for development builds, such PBOs can't
+    /// be signed. See `has_placeholder_checksum`.
+    pub fn write_without_checksum<O: Write>(
+        &self,
+        output: &mut O,
+        ordered: bool,
+    ) -> Result<(), ArmakeError> {
+        self.write_entries(output, !ordered, false, &mut ())?;
+        Ok(())
+    }
+
+    /// Returns the stored name of the entry `name`, matched exactly if possible and otherwise
+    /// ignoring case and accepting `/` as separator.
+    ///
+    /// If several entries only differ in case, the first one is used with a warning.
+    pub fn retrieve(&self, name: &str) -> Option<&str> {
+        retrieve(self.files.keys().map(|k| k.as_str()), name)
+    }
+
+    /// Returns up to `max` entry names close to `name`, closest first, to suggest when there is
+    /// no such entry, in stored order if they are equally close. Entries with the same file name
+    /// or differing in at most a third of the characters are close, compared like in `retrieve`.
+    pub fn similar_entries(&self, name: &str, max: usize) -> Vec<&str> {
+        similar_entries(self.files.keys().map(|k| k.as_str()), name, max)
+    }
+
+    /// Returns the header of the entry `name`, `None` if there is no such entry or it has no
+    /// header yet.
+    pub fn header(&self, name: &str) -> Option<&PBOHeader> {
+        self.headers.iter().find(|h| h.filename == name)
+    }
+
+    /// Returns the content of the entry `name`, `None` if there is no such entry.
+    ///
+    /// Entries that are compressed according to their header are decompressed to their original
+    /// size, other entries are returned as they are stored.
+    pub fn entry_data(&self, name: &str) -> Result<Option<Cow<'_, [u8]>>, ArmakeError> {
+        let data = match self.files.get(name) {
+            Some(_) if self.sources.contains_key(name) => {
+                return Ok(Some(Cow::Owned(self.read_source(name)?)));
+            }
+            Some(cursor) => cursor.get_ref(),
+            None => return Ok(None),
+        };
+        match self.header(name) {
+            Some(header) if header.method() == PackingMethod::Packed => {
+                lzss::decompress(data, header.original_size as usize)
+                    .map(|data| Some(Cow::Owned(data)))
+                    .map_err(|e| aerror!("Failed to decompress {}: {}", name, e))
+            }
+            _ => Ok(Some(Cow::Borrowed(&data[..]))),
+        }
+    }
+
+    /// Compresses the entries of the PBO with LZSS, except for those matching one of the
+    /// patterns (case-insensitively), see `lzss::DEFAULT_UNCOMPRESSED` for the usual ones.
+    ///
+    /// Entries that wouldn't get smaller, entries that are already stored with a packing method
+    /// and the hash manifest entry are left as they are. Entries copied from `sources` are read
+    /// one at a time and only kept in memory if they get smaller. Fails with the name of the
+    /// entry if such a file can't be read.
+    pub fn compress(&mut self, exclude_patterns: &[&str]) -> Result<(), ArmakeError> {
+        let patterns: Vec<String> = exclude_patterns.iter().map(|p| p.to_lowercase()).collect();
+        let names: Vec<String> = self.files.keys().cloned().collect();
+        for name in names {
+            if name == HASH_ENTRY
+                || patterns
+                    .iter()
+                    .any(|p| matches_glob(&name.to_lowercase(), p))
+            {
+                continue;
+            }
+
+            let source = if self.sources.contains_key(&name) {
+                Some(self.read_source(&name)?)
+            } else {
+                None
+            };
+            let headers = &mut self.headers;
+            let cursor = self.files.get_mut(&name).unwrap();
+            let data = source.as_deref().unwrap_or(cursor.get_ref());
+            let recorded = headers.iter().position(|h| h.filename == name);
+            if let Some(header) = recorded.map(|i| &headers[i]) {
+                if header.method() != PackingMethod::Uncompressed
+                    && header.data_size as usize == data.len()
+                {
+                    continue;
+                }
+            }
+
+            let compressed = lzss::compress(data);
+            if compressed.len() >= data.len() {
+                continue;
+            }
+            let header = PBOHeader {
+                filename: name.clone(),
+                packing_method: 0x4370_7273,
+                original_size: data.len() as u32,
+                reserved: 0,
+                timestamp: recorded.map_or_else(|| clamp_timestamp(None), |i| headers[i].timestamp),
+                data_size: compressed.len() as u32,
+            };
+            match recorded {
+                Some(i) => headers[i] = header,
+                None => headers.push(header),
+            }
+            *cursor = Cursor::new(compressed.into_boxed_slice());
+            self.sources.remove(&name);
+        }
+        Ok(())
+    }
+
+    /// Sets the timestamp of every entry to `timestamp`, overriding the modification times of
+    /// the source files and `SOURCE_DATE_EPOCH`.
+    pub fn set_timestamps(&mut self, timestamp: u32) {
+        for name in self.files.keys() {
+            match self.headers.iter_mut().find(|h| &h.filename == name) {
+                Some(header) => header.timestamp = timestamp,
+                None => {
+                    // the size is only a hint for write, which reads it again
+                    let size = self.data_size(name).unwrap_or(0);
+                    self.headers
+                        .push(entry_header(name, size as usize, timestamp))
+                }
+            }
+        }
+    }
+
+    /// Returns the size of the stored data of the entry `name`, read from the file for entries
+    /// in `sources`.
+    fn data_size(&self, name: &str) -> Result<u32, ArmakeError> {
+        match self.sources.get(name) {
+            Some(path) => source_size(name, path),
+            None => Ok(self.files.get(name).map_or(0, |c| c.get_ref().len() as u32)),
+        }
+    }
+
+    /// Reads the whole file of the entry `name` in `sources`.
+    fn read_source(&self, name: &str) -> Result<Vec<u8>, ArmakeError> {
+        let path = &self.sources[name];
+        std::fs::read(path).map_err(|source| {
+            ArmakeError::IOPath(IOPathError {
+                source,
+                path: path.clone(),
+                message: Some(format!("Failed to read the file for {}", name)),
+            })
+        })
+    }
+
+    /// Returns true if the PBO was read with a placeholder checksum, see
+    /// `write_without_checksum`.
+    pub fn has_placeholder_checksum(&self) -> bool {
+        self.checksum.as_deref() == Some(&PLACEHOLDER_CHECKSUM[..])
+    }
+
+    /// Writes the PBO and returns its checksum, see `write_observed` for `observer`.
+    fn write_entries<O: Write>(
+        &self,
+        output: &mut O,
+        sort: bool,
+        checksum: bool,
+        observer: &mut dyn WriteObserver,
+    ) -> Result<Vec<u8>, ArmakeError> {
         let mut headers: Cursor<Vec<u8>> = Cursor::new(Vec::new());
 
         let ext_header = PBOHeader {
@@ -206,36 +1155,47 @@ impl PBO {
         };
         ext_header.write(&mut headers)?;
 
-        if let Some(prefix) = self.header_extensions.get("prefix") {
-            headers.write_all(b"prefix\0")?;
-            headers.write_cstring(prefix)?;
-        }
-
-        for key in &self.extension_order {
-            if key == "prefix" {
-                continue;
-            }
-
+        for (key, value) in &self.header_extensions {
             headers.write_cstring(key)?;
-            headers.write_cstring(self.header_extensions.get(key).unwrap())?;
+            headers.write_cstring(value)?;
         }
-        headers.write_cstring("".to_string())?;
+        headers.write_cstring("")?;
 
-        let mut files_sorted: Vec<(String, &Cursor<Box<[u8]>>)> =
-            self.files.iter().map(|(a, b)| (a.clone(), b)).collect();
-        files_sorted.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()));
+        // entries with a header keep its timestamp, even 0, and the rest of it if the content has
+        // the same size. Entries added without a header get SOURCE_DATE_EPOCH or 0.
+        let recorded: HashMap<&str, &PBOHeader> = self
+            .headers
+            .iter()
+            .map(|h| (h.filename.as_str(), h))
+            .collect();
+        let mut files_sorted = Vec::new();
+        for (name, cursor) in self.files.iter() {
+            files_sorted.push((name.clone(), cursor, self.data_size(name)?));
+        }
+        if sort {
+            files_sorted.sort_by_key(|a| a.0.to_lowercase());
+        }
 
-        for (name, cursor) in &files_sorted {
-            let header = PBOHeader {
+        let mut entry_headers = Vec::with_capacity(files_sorted.len());
+        for (name, _, size) in &files_sorted {
+            let size = *size;
+            let recorded = recorded.get(name.as_str());
+            let mut header = PBOHeader {
                 filename: name.clone(),
                 packing_method: 0,
-                original_size: cursor.get_ref().len() as u32,
+                original_size: size,
                 reserved: 0,
-                timestamp: 0,
-                data_size: cursor.get_ref().len() as u32,
+                timestamp: recorded.map_or_else(|| clamp_timestamp(None), |h| h.timestamp),
+                data_size: size,
             };
+            if let Some(recorded) = recorded.filter(|h| h.data_size == size) {
+                header.packing_method = recorded.packing_method;
+                header.original_size = recorded.original_size;
+                header.reserved = recorded.reserved;
+            }
 
             header.write(&mut headers)?;
+            entry_headers.push(header);
         }
 
         let header = PBOHeader {
@@ -247,19 +1207,61 @@ impl PBO {
         let mut h = Sha1::new();
 
         output.write_all(headers.get_ref())?;
-        h.update(headers.get_ref());
+        if checksum {
+            h.update(headers.get_ref());
+        }
+
+        let mut buffer = vec![0; 64 * 1024];
+        for ((name, cursor, size), header) in files_sorted.iter().zip(&entry_headers) {
+            observer.entry(header);
+            let path = match self.sources.get(name) {
+                Some(path) => path,
+                None => {
+                    output.write_all(cursor.get_ref())?;
+                    if checksum {
+                        h.update(cursor.get_ref());
+                    }
+                    observer.data(cursor.get_ref());
+                    continue;
+                }
+            };
 
-        for (_, cursor) in &files_sorted {
-            output.write_all(cursor.get_ref())?;
-            h.update(cursor.get_ref());
+            // the header is written already, so the file has to keep its size
+            let mut file = File::open(path)?.take(u64::from(*size) + 1);
+            let mut copied: u64 = 0;
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                copied += read as u64;
+                if copied > u64::from(*size) {
+                    break;
+                }
+                output.write_all(&buffer[..read])?;
+                if checksum {
+                    h.update(&buffer[..read]);
+                }
+                observer.data(&buffer[..read]);
+            }
+            if copied != u64::from(*size) {
+                return Err(aerror!(
+                    "{} changed size while the PBO was written",
+                    path.display()
+                ));
+            }
         }
 
         output.write_all(&[0])?;
 
-        let result = h.finalize();
-        output.write_all(&result)?;
+        let checksum = if checksum {
+            h.finalize().to_vec()
+        } else {
+            PLACEHOLDER_CHECKSUM.to_vec()
+        };
+        output.write_all(&checksum)?;
 
-        Ok(())
+        Ok(checksum)
     }
 
     /// Returns the PBO as a `Cursor`.
@@ -0,0 +1,151 @@
+//! Stored order of the entries of a PBO, for rebuilding it byte for byte after unpacking
+//!
+//! A folder can't represent the order of the entries in a PBO or the fields of their headers.
+//! `unpack --order` records them in an order file next to the extracted files, which
+//! `build --order-file` replays. The order file is JSON:
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "extensions": ["prefix", "version"],
+//!   "entries": [
+//!     {
+//!       "name": "config.bin",
+//!       "packing_method": 0,
+//!       "original_size": 2130,
+//!       "reserved": 0,
+//!       "timestamp": 1500000000,
+//!       "data_size": 2130
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `extensions` are the keys of the header extensions in stored order, their values come from
+//! `$PBOPREFIX$`. `entries` are listed in stored order.
+
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{aerror, ArmakeError, PBOHeader, PBO};
+
+/// Default file name of the order file written by `unpack`, relative to the target folder
+pub const ORDER_FILE_NAME: &str = ".pbo-order";
+
+const VERSION: u32 = 1;
+
+/// Stored order of the entries and header extensions of a PBO, see the module documentation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EntryOrder {
+    pub version: u32,
+    /// Keys of the header extensions
+    pub extensions: Vec<String>,
+    pub entries: Vec<OrderEntry>,
+}
+
+/// Header of an entry in an order file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OrderEntry {
+    pub name: String,
+    pub packing_method: u32,
+    pub original_size: u32,
+    pub reserved: u32,
+    pub timestamp: u32,
+    pub data_size: u32,
+}
+
+impl EntryOrder {
+    /// Records the order of the entries and header extensions of a PBO.
+    pub fn from_pbo(pbo: &PBO) -> EntryOrder {
+        EntryOrder::from_headers(pbo.header_extensions.keys(), &pbo.headers)
+    }
+
+    /// Records the order of the given entry headers and header extension keys.
+    pub fn from_headers<'a, K, H>(extension_keys: K, headers: H) -> EntryOrder
+    where
+        K: IntoIterator<Item = &'a String>,
+        H: IntoIterator<Item = &'a PBOHeader>,
+    {
+        EntryOrder {
+            version: VERSION,
+            extensions: extension_keys.into_iter().cloned().collect(),
+            entries: headers
+                .into_iter()
+                .map(|header| OrderEntry {
+                    name: header.filename.clone(),
+                    packing_method: header.packing_method,
+                    original_size: header.original_size,
+                    reserved: header.reserved,
+                    timestamp: header.timestamp,
+                    data_size: header.data_size,
+                })
+                .collect(),
+        }
+    }
+
+    /// Reads an order file.
+    pub fn read<I: Read>(input: &mut I) -> Result<EntryOrder, ArmakeError> {
+        let order: EntryOrder = serde_json::from_reader(input)
+            .map_err(|e| aerror!("Failed to read order file: {}", e))?;
+        if order.version != VERSION {
+            return Err(aerror!("Unsupported order file version {}", order.version));
+        }
+        Ok(order)
+    }
+
+    /// Writes the order file as pretty-printed JSON.
+    pub fn write<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        serde_json::to_writer_pretty(&mut *output, self)
+            .map_err(|e| aerror!("Failed to write order file: {}", e))?;
+        output.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+impl PBO {
+    /// Orders the entries and header extensions of the PBO like `order` and restores the
+    /// recorded entry headers. Use `write_ordered` to keep that order in the output.
+    ///
+    /// Entries and extensions that are not in the order file follow the recorded ones, sorted by
+    /// their lowercase name, and keep their headers. A recorded header is only used if the size of
+    /// the entry is still the same, see `write`.
+    pub fn apply_order(&mut self, order: &EntryOrder) {
+        let mut files = std::mem::take(&mut self.files);
+        let mut previous = std::mem::take(&mut self.headers);
+        let mut headers = Vec::new();
+        for entry in &order.entries {
+            match files.remove(&entry.name) {
+                Some(cursor) => {
+                    self.files.insert(entry.name.clone(), cursor);
+                    headers.push(PBOHeader {
+                        filename: entry.name.clone(),
+                        packing_method: entry.packing_method,
+                        original_size: entry.original_size,
+                        reserved: entry.reserved,
+                        timestamp: entry.timestamp,
+                        data_size: entry.data_size,
+                    });
+                }
+                None => info!("{} is in the order file but not in the PBO", entry.name),
+            }
+        }
+
+        let mut rest: Vec<_> = files.into_iter().collect();
+        rest.sort_by_key(|(name, _)| name.to_lowercase());
+        previous.retain(|h| rest.iter().any(|(name, _)| *name == h.filename));
+        headers.append(&mut previous);
+        self.files.extend(rest);
+        self.headers = headers;
+
+        let mut extensions = std::mem::take(&mut self.header_extensions);
+        for key in order.extensions.iter() {
+            if let Some(value) = extensions.remove(key) {
+                self.header_extensions.insert(key.clone(), value);
+            }
+        }
+        let mut rest: Vec<(String, String)> = extensions.into_iter().collect();
+        rest.sort_by_key(|(key, _)| key.to_lowercase());
+        self.header_extensions.extend(rest);
+    }
+}
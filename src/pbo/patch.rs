@@ -0,0 +1,279 @@
+//! Binary patches between two versions of a PBO
+//!
+//! A patch stores everything needed to rebuild the new PBO byte for byte from the old one:
+//! the stored header block of the new PBO and, for every entry in stored order, either the index
+//! of an old entry with identical data or the new data itself. Entries are never decoded, the
+//! stored bytes are copied as they are.
+//!
+//! # Format (version 1)
+//!
+//! All integers are little-endian `u32`, strings are null-terminated.
+//!
+//! ```text
+//! magic          "ArmaPBOPatch\0"
+//! version        1
+//! old checksum   20 bytes, SHA1 trailer of the PBO the patch applies to
+//! new checksum   20 bytes, SHA1 trailer of the PBO the patch produces
+//! removed        count, then one string per entry of the old PBO missing from the new one
+//! header block   length, then the stored header block of the new PBO, including header
+//!                extensions and the terminating header
+//! entries        count, then for every entry of the new PBO in stored order:
+//!                  0, index of the old entry to copy the data of
+//!                  1, length, data
+//! ```
+//!
+//! The removed entries are informational only, applying a patch doesn't need them.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+use std::ops::Range;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use sha1::{Digest, Sha1};
+
+use crate::io::{ReadExt, WriteExt};
+use crate::pbo::{PBOHeader, PackingMethod, PLACEHOLDER_CHECKSUM};
+use crate::{aerror, ArmakeError};
+
+const MAGIC: &[u8] = b"ArmaPBOPatch\0";
+const VERSION: u32 = 1;
+
+/// Data of a new entry in a patch
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchEntry {
+    /// Data of the old entry with the given index in stored order
+    Copy(u32),
+    /// New data
+    Data(Vec<u8>),
+}
+
+/// Patch that turns one PBO into another, see the module documentation for the format
+#[derive(Clone, Debug, PartialEq)]
+pub struct PBOPatch {
+    pub old_checksum: Vec<u8>,
+    pub new_checksum: Vec<u8>,
+    /// Names of old entries that are not in the new PBO
+    pub removed: Vec<String>,
+    /// Stored header block of the new PBO
+    pub header: Vec<u8>,
+    pub entries: Vec<PatchEntry>,
+}
+
+/// Stored layout of a PBO
+struct Layout {
+    /// Length of the header block
+    header: usize,
+    /// Names and data ranges of the entries in stored order
+    entries: Vec<(String, Range<usize>)>,
+    checksum: Vec<u8>,
+}
+
+impl Layout {
+    /// Reads the layout of a stored PBO and checks that its checksum matches its content.
+    fn read(pbo: &[u8]) -> Result<Layout, ArmakeError> {
+        let mut input = Cursor::new(pbo);
+        let mut headers = Vec::new();
+        loop {
+            let header = PBOHeader::read(&mut input)?;
+            if header.method() == PackingMethod::ProductEntry {
+                while !input.read_cstring()?.is_empty() {
+                    input.read_cstring()?;
+                }
+            } else if header.ends_header_list() {
+                break;
+            } else {
+                headers.push(header);
+            }
+        }
+
+        let header = input.position() as usize;
+        let mut offset = header;
+        let mut entries = Vec::new();
+        for h in headers {
+            let end = offset + h.data_size as usize;
+            entries.push((h.filename, offset..end));
+            offset = end;
+        }
+
+        if pbo.len() != offset + 21 {
+            return Err(aerror!("PBO has no checksum or trailing data"));
+        }
+        let checksum = pbo[offset + 1..].to_vec();
+        if checksum == PLACEHOLDER_CHECKSUM {
+            return Err(aerror!(
+                "PBO has a placeholder checksum, it was built with --no-checksum"
+            ));
+        }
+        if Sha1::digest(&pbo[..offset]).as_slice() != checksum.as_slice() {
+            return Err(aerror!("PBO checksum doesn't match its content"));
+        }
+
+        Ok(Layout {
+            header,
+            entries,
+            checksum,
+        })
+    }
+}
+
+impl PBOPatch {
+    /// Creates the patch from the stored PBO `old` to the stored PBO `new`.
+    pub fn create(old: &[u8], new: &[u8]) -> Result<PBOPatch, ArmakeError> {
+        let old_layout = Layout::read(old)?;
+        let new_layout = Layout::read(new)?;
+
+        let mut old_data: HashMap<&[u8], u32> = HashMap::new();
+        for (i, (_, range)) in old_layout.entries.iter().enumerate().rev() {
+            old_data.insert(&old[range.clone()], i as u32);
+        }
+
+        let entries = new_layout
+            .entries
+            .iter()
+            .map(|(_, range)| {
+                let data = &new[range.clone()];
+                match old_data.get(data) {
+                    Some(index) => PatchEntry::Copy(*index),
+                    None => PatchEntry::Data(data.to_vec()),
+                }
+            })
+            .collect();
+
+        let removed = old_layout
+            .entries
+            .iter()
+            .filter(|(name, _)| !new_layout.entries.iter().any(|(n, _)| n == name))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        Ok(PBOPatch {
+            old_checksum: old_layout.checksum,
+            new_checksum: new_layout.checksum,
+            removed,
+            header: new[..new_layout.header].to_vec(),
+            entries,
+        })
+    }
+
+    /// Applies the patch to the stored PBO `old`, returning the new stored PBO.
+    ///
+    /// Fails if `old` is not the PBO the patch was created from or the result doesn't match the
+    /// expected checksum.
+    pub fn apply(&self, old: &[u8]) -> Result<Vec<u8>, ArmakeError> {
+        let old_layout = Layout::read(old)?;
+        if old_layout.checksum != self.old_checksum {
+            return Err(aerror!(
+                "Patch does not apply to this PBO, its checksum doesn't match"
+            ));
+        }
+
+        let mut output = self.header.clone();
+        for entry in self.entries.iter() {
+            match entry {
+                PatchEntry::Copy(index) => {
+                    let (_, range) = old_layout
+                        .entries
+                        .get(*index as usize)
+                        .ok_or_else(|| aerror!("Patch refers to missing entry {}", index))?;
+                    output.extend_from_slice(&old[range.clone()]);
+                }
+                PatchEntry::Data(data) => output.extend_from_slice(data),
+            }
+        }
+
+        let checksum = Sha1::digest(&output).to_vec();
+        if checksum != self.new_checksum {
+            return Err(aerror!(
+                "Patched PBO doesn't match the expected checksum, the patch is corrupt"
+            ));
+        }
+        output.push(0);
+        output.extend_from_slice(&checksum);
+
+        Ok(output)
+    }
+
+    /// Reads a patch.
+    pub fn read<I: Read>(input: &mut I) -> Result<PBOPatch, ArmakeError> {
+        let mut magic = vec![0; MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(aerror!("Not a PBO patch"));
+        }
+        let version = input.read_u32::<LittleEndian>()?;
+        if version != VERSION {
+            return Err(aerror!("Unsupported PBO patch version {}", version));
+        }
+
+        let mut old_checksum = vec![0; 20];
+        input.read_exact(&mut old_checksum)?;
+        let mut new_checksum = vec![0; 20];
+        input.read_exact(&mut new_checksum)?;
+
+        let removed = (0..input.read_u32::<LittleEndian>()?)
+            .map(|_| input.read_cstring())
+            .collect::<Result<Vec<String>, _>>()?;
+
+        let header = read_data(input)?;
+
+        let mut entries = Vec::new();
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            entries.push(match input.read_u32::<LittleEndian>()? {
+                0 => PatchEntry::Copy(input.read_u32::<LittleEndian>()?),
+                1 => PatchEntry::Data(read_data(input)?),
+                kind => return Err(aerror!("Unknown PBO patch entry kind {}", kind)),
+            });
+        }
+
+        Ok(PBOPatch {
+            old_checksum,
+            new_checksum,
+            removed,
+            header,
+            entries,
+        })
+    }
+
+    /// Writes the patch.
+    pub fn write<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        output.write_all(MAGIC)?;
+        output.write_u32::<LittleEndian>(VERSION)?;
+        output.write_all(&self.old_checksum)?;
+        output.write_all(&self.new_checksum)?;
+
+        output.write_u32::<LittleEndian>(self.removed.len() as u32)?;
+        for name in self.removed.iter() {
+            output.write_cstring(name)?;
+        }
+
+        output.write_u32::<LittleEndian>(self.header.len() as u32)?;
+        output.write_all(&self.header)?;
+
+        output.write_u32::<LittleEndian>(self.entries.len() as u32)?;
+        for entry in self.entries.iter() {
+            match entry {
+                PatchEntry::Copy(index) => {
+                    output.write_u32::<LittleEndian>(0)?;
+                    output.write_u32::<LittleEndian>(*index)?;
+                }
+                PatchEntry::Data(data) => {
+                    output.write_u32::<LittleEndian>(1)?;
+                    output.write_u32::<LittleEndian>(data.len() as u32)?;
+                    output.write_all(data)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn read_data<I: Read>(input: &mut I) -> Result<Vec<u8>, ArmakeError> {
+    let length = input.read_u32::<LittleEndian>()? as usize;
+    let mut data = Vec::new();
+    input.take(length as u64).read_to_end(&mut data)?;
+    if data.len() != length {
+        return Err(aerror!("PBO patch is truncated"));
+    }
+    Ok(data)
+}
@@ -0,0 +1,177 @@
+//! Files in the top-level folder of an addon that hold its header extensions
+//!
+//! Besides `$PBOPREFIX$`, other tools use `$PBOPREFIX$.txt`, `pboprefix.txt` and `$PREFIX$`.
+//! All of them are recognized case-insensitively and never packed. The first line without a `=`
+//! is the prefix, every other line up to the first empty one is a `key=value` extension.
+//! Whitespace around keys and values, `\r` line ends and a UTF-8 byte order mark are ignored.
+//! Keys are compared ignoring case, a `Prefix=` line sets the prefix.
+
+use std::fmt;
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{aerror, ArmakeError};
+
+/// Header extensions as pairs of key and value, in order
+type Extensions = Vec<(String, String)>;
+
+/// Naming convention of the prefix file, in order of precedence
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PrefixStyle {
+    /// `$PBOPREFIX$`
+    PboPrefix,
+    /// `$PBOPREFIX$.txt`
+    PboPrefixTxt,
+    /// `pboprefix.txt`
+    Txt,
+    /// `$PREFIX$`
+    Prefix,
+}
+
+impl PrefixStyle {
+    /// All styles in order of precedence.
+    pub const ALL: [PrefixStyle; 4] = [
+        PrefixStyle::PboPrefix,
+        PrefixStyle::PboPrefixTxt,
+        PrefixStyle::Txt,
+        PrefixStyle::Prefix,
+    ];
+
+    /// Returns the name of the prefix file.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            PrefixStyle::PboPrefix => "$PBOPREFIX$",
+            PrefixStyle::PboPrefixTxt => "$PBOPREFIX$.txt",
+            PrefixStyle::Txt => "pboprefix.txt",
+            PrefixStyle::Prefix => "$PREFIX$",
+        }
+    }
+
+    /// Returns the style of a file name, compared case-insensitively.
+    pub fn from_file_name(name: &str) -> Option<PrefixStyle> {
+        PrefixStyle::ALL
+            .iter()
+            .find(|style| style.file_name().eq_ignore_ascii_case(name))
+            .cloned()
+    }
+}
+
+impl FromStr for PrefixStyle {
+    type Err = ArmakeError;
+
+    fn from_str(s: &str) -> Result<PrefixStyle, ArmakeError> {
+        match s {
+            "pboprefix" => Ok(PrefixStyle::PboPrefix),
+            "pboprefix-txt" => Ok(PrefixStyle::PboPrefixTxt),
+            "txt" => Ok(PrefixStyle::Txt),
+            "prefix" => Ok(PrefixStyle::Prefix),
+            _ => Err(aerror!(
+                "Unknown prefix style \"{}\", expected pboprefix, pboprefix-txt, txt or prefix",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PrefixStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PrefixStyle::PboPrefix => "pboprefix",
+            PrefixStyle::PboPrefixTxt => "pboprefix-txt",
+            PrefixStyle::Txt => "txt",
+            PrefixStyle::Prefix => "prefix",
+        })
+    }
+}
+
+/// Returns true if the entry name is a prefix file in the top-level folder.
+pub fn is_prefix_file(name: &str) -> bool {
+    PrefixStyle::from_file_name(name).is_some()
+}
+
+/// Parses the content of a prefix file into its header extensions in order, see the module
+/// documentation.
+pub fn parse_prefix(content: &str) -> Extensions {
+    let mut extensions: Extensions = Vec::new();
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    for l in content.lines() {
+        // also drops the \r of a \r\n line end
+        let l = l.trim();
+        if l.is_empty() {
+            break;
+        }
+
+        let (key, value) = match l.split_once('=') {
+            Some((key, value)) => (key.trim_end(), value.trim_start()),
+            None => ("prefix", l),
+        };
+        // the game only knows the prefix in lowercase
+        let key = if key.eq_ignore_ascii_case("prefix") {
+            "prefix"
+        } else {
+            key
+        };
+        match extensions
+            .iter_mut()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        {
+            Some(extension) => extension.1 = value.to_string(),
+            None => extensions.push((key.to_string(), value.to_string())),
+        }
+    }
+    extensions
+}
+
+/// Returns the prefix files in a folder with their style, in order of precedence.
+pub fn prefix_files(directory: &Path) -> Result<Vec<(PrefixStyle, PathBuf)>, ArmakeError> {
+    let mut files: Vec<(PrefixStyle, PathBuf)> = Vec::new();
+    for entry in read_dir(directory)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        if let Some(style) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(PrefixStyle::from_file_name)
+        {
+            files.push((style, path));
+        }
+    }
+    files.sort_by_key(|(style, _)| PrefixStyle::ALL.iter().position(|s| s == style));
+    Ok(files)
+}
+
+/// Reads the header extensions of a folder from its prefix files, `None` if it has none.
+///
+/// If there are several prefix files, the one coming first in `PrefixStyle::ALL` is used. They
+/// have to contain the same extensions, otherwise it fails naming both files.
+pub fn read_prefix_files(directory: &Path) -> Result<Option<(PathBuf, Extensions)>, ArmakeError> {
+    let mut result: Option<(PathBuf, Extensions)> = None;
+    for (_, path) in prefix_files(directory)? {
+        let extensions = parse_prefix(&read_to_string(&path)?);
+        match &result {
+            Some((first, used)) => {
+                let mut a = used.clone();
+                let mut b = extensions.clone();
+                a.sort();
+                b.sort();
+                if a != b {
+                    return Err(aerror!(
+                        "{} and {} are both prefix files and have conflicting values",
+                        first.display(),
+                        path.display()
+                    ));
+                }
+                info!(
+                    "{} has the same values as {}, using the latter",
+                    path.display(),
+                    first.display()
+                );
+            }
+            None => result = Some((path, extensions)),
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,145 @@
+//! Rules deciding what happens to each file when a PBO is built from a directory
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::pbo::matches_glob;
+use crate::{aerror, ArmakeError};
+
+/// What happens to a file when building a PBO
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileAction {
+    /// Leave the file out
+    Skip,
+    /// Pack the file as it is
+    Copy,
+    /// Preprocess and rapify the file, `config.cpp` is packed as `config.bin`
+    Rapify,
+    /// Binarize the file with the binarizer found by `find_binarizer`. Without one, the file is
+    /// copied.
+    Binarize,
+}
+
+impl FromStr for FileAction {
+    type Err = ArmakeError;
+
+    fn from_str(s: &str) -> Result<FileAction, ArmakeError> {
+        match s {
+            "skip" => Ok(FileAction::Skip),
+            "copy" => Ok(FileAction::Copy),
+            "rapify" => Ok(FileAction::Rapify),
+            "binarize" => Ok(FileAction::Binarize),
+            "convert-paa" => Err(aerror!(
+                "The convert-paa action is not supported, armake2 can't encode PAA files"
+            )),
+            _ => Err(aerror!(
+                "Unknown action \"{}\", expected skip, copy, rapify or binarize",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FileAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            FileAction::Skip => "skip",
+            FileAction::Copy => "copy",
+            FileAction::Rapify => "rapify",
+            FileAction::Binarize => "binarize",
+        })
+    }
+}
+
+/// Rule applying an action to the files matching a glob pattern, written as `pattern=action`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileRule {
+    /// Pattern matched against the name of the file in the PBO, like `data\*.rvmat`, compared
+    /// case-insensitively
+    pub pattern: String,
+    pub action: FileAction,
+}
+
+impl FileRule {
+    pub fn new(pattern: &str, action: FileAction) -> FileRule {
+        FileRule {
+            pattern: pattern.to_lowercase(),
+            action,
+        }
+    }
+
+    /// Returns true if the rule applies to the file with the given name in the PBO.
+    pub fn matches(&self, name: &str) -> bool {
+        matches_glob(&name.to_lowercase(), &self.pattern)
+    }
+}
+
+impl FromStr for FileRule {
+    type Err = ArmakeError;
+
+    fn from_str(s: &str) -> Result<FileRule, ArmakeError> {
+        match s.rfind('=') {
+            Some(i) if i > 0 => Ok(FileRule::new(&s[..i], s[i + 1..].parse()?)),
+            _ => Err(aerror!("\"{}\" is not of the form pattern=action", s)),
+        }
+    }
+}
+
+impl fmt::Display for FileRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.pattern, self.action)
+    }
+}
+
+/// Rules for the files of a PBO, evaluated in order with the first matching rule applying
+///
+/// The default rules come after the given ones:
+///
+/// ```text
+/// *.cpp=rapify
+/// *.rvmat=rapify
+/// model.cfg=rapify
+/// *\\model.cfg=rapify
+/// *.p3d=binarize
+/// *.rtm=binarize
+/// *=copy
+/// ```
+///
+/// Without binarization only `*=copy` is used.
+#[derive(Clone, Debug, Default)]
+pub struct FileRules {
+    pub rules: Vec<FileRule>,
+}
+
+impl FileRules {
+    pub fn new(rules: Vec<FileRule>) -> FileRules {
+        FileRules { rules }
+    }
+
+    /// Returns the default rules, see the type documentation.
+    pub fn defaults(binarize: bool) -> Vec<FileRule> {
+        let mut defaults = Vec::new();
+        if binarize {
+            defaults.push(FileRule::new("*.cpp", FileAction::Rapify));
+            defaults.push(FileRule::new("*.rvmat", FileAction::Rapify));
+            defaults.push(FileRule::new("model.cfg", FileAction::Rapify));
+            defaults.push(FileRule::new("*\\model.cfg", FileAction::Rapify));
+            defaults.push(FileRule::new("*.p3d", FileAction::Binarize));
+            defaults.push(FileRule::new("*.rtm", FileAction::Binarize));
+        }
+        defaults.push(FileRule::new("*", FileAction::Copy));
+        defaults
+    }
+
+    /// Returns these rules followed by the defaults.
+    pub fn with_defaults(&self, binarize: bool) -> FileRules {
+        let mut rules = self.rules.clone();
+        rules.extend(FileRules::defaults(binarize));
+        FileRules { rules }
+    }
+
+    /// Returns the first rule matching the file with the given name in the PBO.
+    pub fn rule(&self, name: &str) -> Option<&FileRule> {
+        self.rules.iter().find(|rule| rule.matches(name))
+    }
+}
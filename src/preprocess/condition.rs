@@ -0,0 +1,161 @@
+#[derive(Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Operator(&'static str),
+}
+
+const OPERATORS: &[&str] = &[
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*", "/",
+    "%", "!", "~", "(", ")",
+];
+
+/// Binary operators from lowest to highest precedence
+const PRECEDENCE: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", ">", "<=", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let literal = &rest[..len];
+            let number = match literal.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None => literal.parse(),
+            }
+            .map_err(|_| format!("invalid number {}", literal))?;
+            tokens.push(Token::Number(number));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Identifier(rest[..len].to_string()));
+            len
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| format!("unexpected {:?}", c))?;
+            tokens.push(Token::Operator(op));
+            op.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    undefined: &'a mut Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self
+            .peek_operator()
+            .filter(|op| PRECEDENCE[level].contains(op))
+        {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = match op {
+                "||" => (left != 0 || right != 0) as i64,
+                "&&" => (left != 0 && right != 0) as i64,
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" | "%" if right == 0 => return Err(String::from("division by zero")),
+                "/" => left.wrapping_div(right),
+                "%" => left.wrapping_rem(right),
+                _ => unreachable!(),
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        match token {
+            Some(Token::Number(n)) => Ok(*n),
+            Some(Token::Identifier(name)) => {
+                self.undefined.push(name.clone());
+                Ok(0)
+            }
+            Some(Token::Operator("!")) => Ok((self.unary()? == 0) as i64),
+            Some(Token::Operator("~")) => Ok(!self.unary()?),
+            Some(Token::Operator("-")) => Ok(self.unary()?.wrapping_neg()),
+            Some(Token::Operator("+")) => self.unary(),
+            Some(Token::Operator("(")) => {
+                let value = self.binary(0)?;
+                match self.peek_operator() {
+                    Some(")") => {
+                        self.position += 1;
+                        Ok(value)
+                    }
+                    _ => Err(String::from("missing )")),
+                }
+            }
+            Some(Token::Operator(op)) => Err(format!("unexpected {}", op)),
+            None => Err(String::from("unexpected end of condition")),
+        }
+    }
+}
+
+/// Evaluates a macro-expanded condition. Identifiers left after expansion are undefined, they
+/// evaluate to 0 and are added to `undefined`.
+pub fn evaluate(expression: &str, undefined: &mut Vec<String>) -> Result<i64, String> {
+    let tokens = tokenize(expression)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        position: 0,
+        undefined,
+    };
+
+    let value = parser.binary(0)?;
+    match tokens.get(parser.position) {
+        Some(Token::Operator(op)) => Err(format!("unexpected {}", op)),
+        Some(_) => Err(String::from("missing operator")),
+        None => Ok(value),
+    }
+}
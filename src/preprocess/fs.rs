@@ -1,25 +1,82 @@
-use std::fs::{read_dir, File};
-use std::io::Read;
-use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR};
+use std::cell::RefCell;
+use std::fs::read_dir;
+use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR_STR};
 
-use crate::ArmakeError;
+use crate::pbo::read_prefix_files;
+use crate::{ArmakeError, Encoding};
 
 use crate::aerror;
 
-pub fn read_prefix(prefix_path: &Path) -> String {
-    let mut content = String::new();
-    File::open(prefix_path)
-        .unwrap()
-        .read_to_string(&mut content)
-        .unwrap();
+/// Reads the included files for `preprocess`, keeping the first failure instead of panicking
+///
+/// The callback passed to `preprocess` can't fail, so a file that can't be read or decoded is
+/// read as empty and the error is returned by `finish` once preprocessing is done.
+pub struct IncludeReader {
+    encoding: Encoding,
+    error: RefCell<Option<ArmakeError>>,
+    included: RefCell<Vec<PathBuf>>,
+}
+
+impl IncludeReader {
+    pub fn new(encoding: Encoding) -> IncludeReader {
+        IncludeReader {
+            encoding,
+            error: RefCell::new(None),
+            included: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Reads an included file, to be called from the callback passed to `preprocess`.
+    pub fn read(&self, path: &Path) -> String {
+        self.included.borrow_mut().push(path.to_path_buf());
+        let content = std::fs::read(path)
+            .map_err(ArmakeError::from)
+            .and_then(|content| self.encoding.decode(&content));
+        content.unwrap_or_else(|e| {
+            self.error.borrow_mut().get_or_insert_with(|| {
+                aerror!("Failed to read the included file {}: {}", path.display(), e)
+            });
+            String::new()
+        })
+    }
+
+    /// Returns the paths of the files read so far, in the order they were included.
+    pub fn included(&self) -> Vec<PathBuf> {
+        self.included.borrow().clone()
+    }
 
-    content.lines().nth(0).unwrap().to_string()
+    /// Returns the first error of `read` if there was one, the result of preprocessing
+    /// otherwise.
+    pub fn finish<T>(&self, result: Result<T, ArmakeError>) -> Result<T, ArmakeError> {
+        match self.error.borrow_mut().take() {
+            Some(error) => Err(error),
+            None => result,
+        }
+    }
 }
 
-pub fn matches_include_path(path: &PathBuf, include_path: &str) -> bool {
-    let include_pathbuf = PathBuf::from(&include_path.replace("\\", &MAIN_SEPARATOR.to_string()));
+/// Returns the prefix from the prefix files of a folder, see `PrefixStyle`.
+pub fn read_prefix(directory: &Path) -> Option<String> {
+    let (_, extensions) = read_prefix_files(directory).ok()??;
+    extensions
+        .into_iter()
+        .find(|(key, _)| key == "prefix")
+        .map(|(_, value)| value)
+}
+
+pub fn matches_include_path(path: &Path, include_path: &str) -> bool {
+    // like the game, include paths are matched ignoring case
+    let include_pathbuf = PathBuf::from(
+        &include_path
+            .replace("\\", MAIN_SEPARATOR_STR)
+            .to_lowercase(),
+    );
 
-    if path.file_name() != include_pathbuf.file_name() {
+    if path.file_name().map(|n| n.to_string_lossy().to_lowercase())
+        != include_pathbuf
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+    {
         return false;
     }
 
@@ -28,22 +85,25 @@ pub fn matches_include_path(path: &PathBuf, include_path: &str) -> bool {
             continue;
         }
 
-        let prefixpath = parent.join("$PBOPREFIX$");
-        if !prefixpath.is_file() {
-            continue;
-        }
-
-        let mut prefix = read_prefix(&prefixpath);
+        let mut prefix = match read_prefix(parent) {
+            Some(prefix) => prefix,
+            None => continue,
+        };
 
-        prefix = if !prefix.is_empty() && prefix.chars().nth(0).unwrap() != '\\' {
+        prefix = if !prefix.is_empty() && !prefix.starts_with('\\') {
             format!("\\{}", prefix)
         } else {
             prefix
         };
-        let prefix_pathbuf = PathBuf::from(prefix.replace("\\", &MAIN_SEPARATOR.to_string()));
+        let prefix_pathbuf = PathBuf::from(prefix.replace("\\", MAIN_SEPARATOR_STR));
 
         let relative = path.strip_prefix(parent).unwrap();
-        let test_path = prefix_pathbuf.join(relative);
+        let test_path = PathBuf::from(
+            prefix_pathbuf
+                .join(relative)
+                .to_string_lossy()
+                .to_lowercase(),
+        );
 
         if test_path == include_pathbuf {
             return true;
@@ -54,8 +114,19 @@ pub fn matches_include_path(path: &PathBuf, include_path: &str) -> bool {
 }
 
 pub fn search_directory(include_path: &str, directory: PathBuf) -> Option<PathBuf> {
-    for entry in read_dir(&directory).unwrap() {
-        let path = entry.unwrap().path();
+    let entries = match read_dir(&directory) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!(
+                "Skipping {} in the include search: {}",
+                directory.display(),
+                e
+            );
+            return None;
+        }
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
         if path.is_dir() {
             if path.file_name().unwrap() == ".git" {
                 continue;
@@ -69,8 +140,8 @@ pub fn search_directory(include_path: &str, directory: PathBuf) -> Option<PathBu
         }
     }
 
-    let direct_path = (&directory).to_str().unwrap().to_string()
-        + &include_path.replace("\\", &MAIN_SEPARATOR.to_string());
+    let direct_path =
+        directory.to_str().unwrap().to_string() + &include_path.replace("\\", MAIN_SEPARATOR_STR);
     let direct_pathbuf = PathBuf::from(direct_path);
 
     if direct_pathbuf.is_file() {
@@ -80,6 +151,55 @@ pub fn search_directory(include_path: &str, directory: PathBuf) -> Option<PathBu
     None
 }
 
+/// Finds the file `relative` in `directory`, ignoring the case of every component that doesn't
+/// exist as it is written.
+fn find_ignoring_case(directory: &Path, relative: &Path) -> Option<PathBuf> {
+    let mut path = directory.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::ParentDir => {
+                path.pop();
+            }
+            Component::Normal(name) => {
+                let exact = path.join(name);
+                if exact.exists() {
+                    path = exact;
+                    continue;
+                }
+                let lower = name.to_string_lossy().to_lowercase();
+                path = read_dir(&path)
+                    .ok()?
+                    .filter_map(|e| e.ok())
+                    .find(|e| e.file_name().to_string_lossy().to_lowercase() == lower)?
+                    .path();
+            }
+            _ => {}
+        }
+    }
+    Some(path).filter(|p| p.is_file())
+}
+
+/// Returns true if the file `found` for an include path only matches it if case is ignored.
+/// Only the trailing components that name the same files are compared, the rest may come from a
+/// prefix.
+pub fn case_differs(include_path: &str, found: &Path) -> bool {
+    let included = include_path
+        .split(['\\', '/'])
+        .filter(|c| !c.is_empty() && *c != ".")
+        .rev();
+    let found = found
+        .components()
+        .rev()
+        .map(|c| c.as_os_str().to_string_lossy());
+    for (included, found) in included.zip(found) {
+        if included == found {
+            continue;
+        }
+        return included != ".." && included.to_lowercase() == found.to_lowercase();
+    }
+    false
+}
+
 pub fn canonicalize(path: PathBuf) -> PathBuf {
     let mut result = PathBuf::new();
     for component in path.components() {
@@ -95,35 +215,54 @@ pub fn canonicalize(path: PathBuf) -> PathBuf {
     result
 }
 
+/// Finds the file for an include path.
+///
+/// Paths starting with a backslash are searched for in `search_paths`, see `search_directory`.
+/// Other paths are looked for next to the including file `origin` (or in the working directory
+/// without one), then next to the top-level input file `root`, then in each of `search_paths`.
 pub fn find_include_file(
     include_path: &str,
     origin: Option<&PathBuf>,
+    root: Option<&PathBuf>,
     search_paths: &[PathBuf],
 ) -> Result<PathBuf, ArmakeError> {
-    if include_path.chars().nth(0).unwrap() != '\\' {
-        let mut path = PathBuf::from(include_path.replace("\\", &MAIN_SEPARATOR.to_string()));
+    if !include_path.starts_with('\\') {
+        let path = PathBuf::from(include_path.replace("\\", MAIN_SEPARATOR_STR));
 
-        if let Some(origin_path) = origin {
-            let absolute = PathBuf::from(&origin_path).canonicalize()?;
-            let origin_dir = absolute.parent().unwrap();
-            path = origin_dir.join(path);
-        } else {
-            path = std::env::current_dir()?.join(path);
+        let mut directories: Vec<PathBuf> = Vec::new();
+        match origin {
+            Some(origin_path) => {
+                let absolute = PathBuf::from(&origin_path).canonicalize()?;
+                directories.push(absolute.parent().unwrap().to_path_buf());
+            }
+            None => directories.push(std::env::current_dir()?),
         }
+        if let Some(root_dir) = root
+            .and_then(|r| r.canonicalize().ok())
+            .and_then(|r| r.parent().map(|p| p.to_path_buf()))
+        {
+            directories.push(root_dir);
+        }
+        directories.extend(search_paths.iter().filter_map(|p| p.canonicalize().ok()));
 
-        let absolute = canonicalize(path);
-
-        if !absolute.is_file() {
-            match origin {
+        match directories
+            .iter()
+            .map(|directory| canonicalize(directory.join(&path)))
+            .find(|absolute| absolute.is_file())
+            .or_else(|| {
+                directories
+                    .iter()
+                    .find_map(|directory| find_ignoring_case(directory, &path))
+            }) {
+            Some(absolute) => Ok(absolute),
+            None => match origin {
                 Some(origin_path) => Err(aerror!(
                     "File \"{}\" included from \"{}\" not found.",
                     include_path,
                     origin_path.to_str().unwrap().to_string()
                 )),
                 None => Err(aerror!("Included file \"{}\" not found.", include_path)),
-            }
-        } else {
-            Ok(absolute)
+            },
         }
     } else {
         for search_path in search_paths {
@@ -2,16 +2,25 @@
 
 use std::clone::Clone;
 use std::collections::HashMap;
+use std::fmt;
 use std::iter::Sum;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
 
 use crate::error::{PreprocessError, PreprocessParseError};
-use crate::ArmakeError;
+use crate::warning::{self, Warning};
+use crate::{aerror, ArmakeError};
 
+mod condition;
 mod fs;
+pub use fs::IncludeReader;
+mod normalize;
+pub use normalize::{compare_normalized, normalize, normalized_lines, Difference};
 
 pub mod preprocess_grammar {
     #![allow(missing_docs)]
+    #![allow(clippy::all, ellipsis_inclusive_range_patterns)]
     include!(concat!(env!("OUT_DIR"), "/preprocess_grammar.rs"));
 }
 
@@ -22,6 +31,7 @@ pub struct Definition {
     parameters: Option<Vec<String>>,
     value: Vec<Token>,
     local: bool,
+    location: Option<(Option<PathBuf>, u32)>,
 }
 
 /// Preprocessor directive
@@ -37,6 +47,10 @@ pub enum Directive {
     IfDefDirective(String),
     /// `#ifndef` directive containing the name of the macro
     IfNDefDirective(String),
+    /// `#if` directive containing the condition
+    IfDirective(String),
+    /// `#elif` directive containing the condition
+    ElifDirective(String),
     /// `#else` directive
     ElseDirective,
     /// `#endif` directive
@@ -61,8 +75,8 @@ pub enum Token {
     NewlineToken(String, u32),
     /// Potential macro token
     MacroToken(Macro),
-    /// Comment token containing a number of newlines
-    CommentToken(u32),
+    /// Comment token containing the comment text and its number of newlines
+    CommentToken(String, u32),
     /// Token for the concatenation operator (`##`)
     ConcatToken,
 }
@@ -78,15 +92,175 @@ pub enum Line {
 
 /// Struct for additional information about preprocessor output. Contains import stack used for
 /// loop detection and the origins of all the lines in the output.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct PreprocessInfo {
     /// For every line in the output, `line_origins` contains a line number (starting at 1) and a
     /// `PathBuf` to the file where the line was found. The path may be `None` if the line was in the
     /// original input to `preprocess` and `origin` was not given.
     pub line_origins: Vec<(u32, Option<PathBuf>)>,
+    /// Expansions of the macros given in `PreprocessOptions::trace_macros`, in order of expansion
+    pub macro_traces: Vec<String>,
+    definitions: HashMap<String, Definition>,
     import_stack: Vec<PathBuf>,
 }
 
+/// Macros predefined by the game, as name and value
+///
+/// Shared headers commonly use these to tell Arma 3 apart from other games and tools.
+pub const PREDEFINED_MACROS: &[(&str, &str)] = &[("__ARMA__", "1"), ("__ARMA3__", "1")];
+
+/// Macros additionally predefined by debug builds of the game, see
+/// `PreprocessOptions::debug_defines`
+pub const DEBUG_MACROS: &[(&str, &str)] = &[("__A3_DEBUG__", "1")];
+
+/// Macros expanded by the preprocessor itself where they are used, unless they are defined
+///
+/// `__FILE__` is the quoted path of the file being processed, `__LINE__` the number of the line in
+/// it. Used in the body of another macro, they are expanded at the point of use like in the game,
+/// so a logging macro from an include reports the file and line it was called from.
+pub const BUILTIN_MACROS: &[&str] = &["__FILE__", "__LINE__"];
+
+/// Default of `PreprocessOptions::max_include_depth`
+pub const DEFAULT_MAX_INCLUDE_DEPTH: usize = 64;
+
+/// Options for `preprocess_with_options`
+///
+/// The macros defined before the input is read are `PREDEFINED_MACROS` (and `DEBUG_MACROS` if
+/// `debug_defines` is set) without those in `undefines`, followed by `defines`. A `#define` or
+/// `#undef` in the input overrides any of them.
+#[derive(Clone, Debug)]
+pub struct PreprocessOptions {
+    /// Names of macros whose expansions are recorded in `PreprocessInfo::macro_traces`
+    pub trace_macros: Vec<String>,
+    /// Pass comments through to the output instead of removing them. Comments inside directives
+    /// and macro definitions are still removed.
+    pub keep_comments: bool,
+    /// Fail instead of warning when an `#if` or `#elif` condition uses an undefined identifier
+    pub strict_conditions: bool,
+    /// Macros to define, as name and value, overriding predefined macros of the same name
+    pub defines: Vec<(String, String)>,
+    /// Names of predefined macros not to define
+    pub undefines: Vec<String>,
+    /// Also define `DEBUG_MACROS`
+    pub debug_defines: bool,
+    /// Number of files that may be open at the same time, the input and those it includes
+    /// included in each other. Including a file that is already open always fails.
+    pub max_include_depth: usize,
+}
+
+impl Default for PreprocessOptions {
+    fn default() -> PreprocessOptions {
+        PreprocessOptions {
+            trace_macros: Vec::new(),
+            keep_comments: false,
+            strict_conditions: false,
+            defines: Vec::new(),
+            undefines: Vec::new(),
+            debug_defines: false,
+            max_include_depth: DEFAULT_MAX_INCLUDE_DEPTH,
+        }
+    }
+}
+
+impl PreprocessOptions {
+    /// Returns the definitions of the macros defined before the input is read.
+    fn initial_definitions(&self) -> Result<HashMap<String, Definition>, ArmakeError> {
+        let debug: &[(&str, &str)] = if self.debug_defines {
+            DEBUG_MACROS
+        } else {
+            &[]
+        };
+        let predefined = PREDEFINED_MACROS
+            .iter()
+            .chain(debug)
+            .filter(|(name, _)| !self.undefines.iter().any(|u| u == name))
+            .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        let mut definitions = HashMap::new();
+        for (name, value) in predefined.chain(self.defines.iter().cloned()) {
+            let value = preprocess_grammar::tokens(&value)
+                .map_err(|e| aerror!("Failed to parse value of macro {}: {}", name, e))?;
+            definitions.insert(
+                name.clone(),
+                Definition {
+                    name,
+                    parameters: None,
+                    value,
+                    local: false,
+                    location: None,
+                },
+            );
+        }
+        Ok(definitions)
+    }
+}
+
+/// State shared by all macro expansions of a single preprocessor run
+struct ExpansionContext<'a> {
+    options: &'a PreprocessOptions,
+    traces: Vec<String>,
+    /// File and line of the line being expanded
+    location: (Option<PathBuf>, u32),
+}
+
+/// Raises a named warning at a line of a file, see `warning`.
+fn raise_warning<S: Into<String>>(
+    name: &'static str,
+    path: Option<&PathBuf>,
+    line: u32,
+    message: S,
+) {
+    warning::raise(
+        Warning::new(name, message).at(path.map(|p| p.to_string_lossy().to_string()), line),
+    );
+}
+
+/// Formats a file and line for messages, `?` standing in for an unknown file.
+fn format_location(path: Option<&PathBuf>, line: u32) -> String {
+    match path {
+        Some(path) => format!("{}:{}", path.display(), line),
+        None => format!("?:{}", line),
+    }
+}
+
+impl PreprocessInfo {
+    /// Returns all macros defined at the end of preprocessing, sorted by name.
+    pub fn macros(&self) -> Vec<&Definition> {
+        let mut macros: Vec<&Definition> = self.definitions.values().collect();
+        macros.sort_by(|a, b| a.name.cmp(&b.name));
+        macros
+    }
+}
+
+impl Definition {
+    /// Name of the macro
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// File and line (starting at 1) of the `#define` that last defined this macro. The file is
+    /// `None` if the definition was in the original input and no origin was given. Predefined
+    /// macros and those given in `PreprocessOptions::defines` have no location.
+    pub fn location(&self) -> Option<(Option<&PathBuf>, u32)> {
+        self.location.as_ref().map(|(p, l)| (p.as_ref(), *l))
+    }
+}
+
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(params) = &self.parameters {
+            write!(f, "({})", params.join(", "))?;
+        }
+        let (body, _) = Token::concat(&self.value);
+        let body = body.trim();
+        if !body.is_empty() {
+            write!(f, " {}", body)?;
+        }
+        Ok(())
+    }
+}
+
 fn parse_macro(input: &str) -> Macro {
     let without_original: Macro = preprocess_grammar::macro_proper(input).unwrap();
 
@@ -113,7 +287,7 @@ impl Clone for Token {
             Token::RegularToken(s) => Token::RegularToken(s.clone()),
             Token::NewlineToken(s, n) => Token::NewlineToken(s.clone(), *n),
             Token::MacroToken(m) => Token::MacroToken(m.clone()),
-            Token::CommentToken(n) => Token::CommentToken(*n),
+            Token::CommentToken(s, n) => Token::CommentToken(s.clone(), *n),
             Token::ConcatToken => Token::ConcatToken,
         }
     }
@@ -125,9 +299,49 @@ impl Definition {
         arguments: &Option<Vec<String>>,
         def_map: &HashMap<String, Definition>,
         stack: &[Definition],
+        ctx: &mut ExpansionContext,
     ) -> Result<Option<Vec<Token>>, ArmakeError> {
         let params = self.parameters.clone().unwrap_or_default();
-        let args = arguments.clone().unwrap_or_default();
+        let mut args = arguments.clone().unwrap_or_default();
+
+        if self.parameters.is_some() && arguments.is_some() {
+            // `NAME()` passes a single empty argument
+            if params.is_empty() && args.len() == 1 && args[0].trim().is_empty() {
+                args.clear();
+            }
+
+            if args.len() != params.len() {
+                let message = format!(
+                    "Macro {} expects {} argument{} but got {}, defined at {}",
+                    self.name,
+                    params.len(),
+                    if params.len() == 1 { "" } else { "s" },
+                    args.len(),
+                    self.location
+                        .as_ref()
+                        .map(|(path, line)| format_location(path.as_ref(), *line))
+                        .unwrap_or_else(|| String::from("?")),
+                );
+                let (path, line) = &ctx.location;
+                let location = format_location(path.as_ref(), *line);
+
+                if args.len() < params.len() {
+                    return Err(ArmakeError::PREPROCESS(PreprocessError {
+                        path: path.as_ref().map(|p| p.to_string_lossy().to_string()),
+                        message: format!("[{}] {}", location, message),
+                        source: Box::new(aerror!(message)),
+                    }));
+                }
+
+                raise_warning(
+                    "too-many-macro-arguments",
+                    path.as_ref(),
+                    *line,
+                    format!("{}, ignoring the rest", message),
+                );
+                args.truncate(params.len());
+            }
+        }
 
         if params.len() != args.len() {
             return Ok(None);
@@ -149,13 +363,11 @@ impl Definition {
                 local_map.insert(key.clone(), value.clone());
             }
 
-            // @todo: handle these errors properly
             for (param, arg) in params.iter().zip(args.iter()) {
-                let mut tokens =
-                    preprocess_grammar::tokens(&arg).expect("Failed to parse macro argument");
+                let mut tokens = preprocess_grammar::tokens(arg)
+                    .map_err(|e| aerror!("Failed to parse macro argument {}: {}", arg, e))?;
                 let stack: Vec<Definition> = Vec::new();
-                tokens = Macro::resolve_all(&tokens, &def_map, &stack)
-                    .expect("Failed to resolve macro arguments");
+                tokens = Macro::resolve_all(&tokens, def_map, &stack, ctx)?;
 
                 local_map.insert(
                     param.clone(),
@@ -164,24 +376,79 @@ impl Definition {
                         parameters: None,
                         value: tokens,
                         local: true,
+                        location: None,
                     },
                 );
             }
 
-            tokens = Macro::resolve_all(&tokens, &local_map, &stack_new)?;
+            tokens = Macro::resolve_all(&tokens, &local_map, &stack_new, ctx)?;
         } else {
-            tokens = Macro::resolve_all(&tokens, &def_map, &stack_new)?;
+            tokens = Macro::resolve_all(&tokens, def_map, &stack_new, ctx)?;
+        }
+
+        if self.value.iter().any(|t| matches!(t, Token::ConcatToken)) {
+            tokens = Definition::paste(&tokens, def_map, &stack_new, ctx)?;
         }
 
         Ok(Some(tokens))
     }
+
+    /// Joins the tokens around the `##` operators of an expansion, dropping the whitespace next
+    /// to them, and rescans the result, so a joined name can be a macro itself.
+    fn paste(
+        tokens: &[Token],
+        def_map: &HashMap<String, Definition>,
+        stack: &[Definition],
+        ctx: &mut ExpansionContext,
+    ) -> Result<Vec<Token>, ArmakeError> {
+        let mut text = String::new();
+        let mut trim_next = false;
+        for token in tokens {
+            if let Token::ConcatToken = token {
+                text.truncate(text.trim_end_matches([' ', '\t']).len());
+                trim_next = true;
+                continue;
+            }
+
+            let (part, _) = Token::concat(std::slice::from_ref(token));
+            if trim_next {
+                let part = part.trim_start_matches([' ', '\t']);
+                trim_next = part.is_empty();
+                text += part;
+            } else {
+                text += &part;
+            }
+        }
+
+        let tokens = preprocess_grammar::tokens(&text)
+            .map_err(|e| aerror!("Failed to parse macro expansion {}: {}", text, e))?;
+        Macro::resolve_all(&tokens, def_map, stack, ctx)
+    }
 }
 
 impl Macro {
+    /// Returns the expansion of the builtin macro `self` at the location being expanded, see
+    /// `BUILTIN_MACROS`.
+    fn resolve_builtin(&self, ctx: &ExpansionContext) -> Option<Token> {
+        let (path, line) = &ctx.location;
+        match self.name.as_str() {
+            "__FILE__" => {
+                let path = path
+                    .as_ref()
+                    .map(|p| p.to_string_lossy().replace('"', "\"\""))
+                    .unwrap_or_default();
+                Some(Token::RegularToken(format!("\"{}\"", path)))
+            }
+            "__LINE__" => Some(Token::RegularToken(line.to_string())),
+            _ => None,
+        }
+    }
+
     fn resolve_pseudoargs(
         &self,
         def_map: &HashMap<String, Definition>,
         stack: &[Definition],
+        ctx: &mut ExpansionContext,
     ) -> Result<Vec<Token>, ArmakeError> {
         let mut tokens: Vec<Token> = Vec::new();
         tokens.push(Token::RegularToken(self.name.clone()));
@@ -191,10 +458,10 @@ impl Macro {
         }
 
         let (_, without_name) = self.original.split_at(self.name.len());
-        let mut arg_tokens =
-            preprocess_grammar::tokens(&without_name).expect("Failed to parse macro arguments.");
+        let mut arg_tokens = preprocess_grammar::tokens(without_name)
+            .map_err(|e| aerror!("Failed to parse the arguments of {}: {}", self.name, e))?;
 
-        arg_tokens = Macro::resolve_all(&arg_tokens, &def_map, &stack)?;
+        arg_tokens = Macro::resolve_all(&arg_tokens, def_map, stack, ctx)?;
         for t in arg_tokens {
             tokens.push(t);
         }
@@ -202,14 +469,59 @@ impl Macro {
         Ok(tokens)
     }
 
+    /// Resolves a macro without parameters that is followed by parentheses, like a macro
+    /// parameter naming another macro. Its expansion is rescanned together with the parentheses,
+    /// so `#define APPLY(f, x) f(x)` can be used as `APPLY(QUOTE, foo)`.
+    fn resolve_rescanned(
+        &self,
+        def: &Definition,
+        def_map: &HashMap<String, Definition>,
+        stack: &[Definition],
+        ctx: &mut ExpansionContext,
+    ) -> Result<Vec<Token>, ArmakeError> {
+        let value = def.value(&None, def_map, stack, ctx)?.unwrap_or_default();
+        let (mut text, _) = Token::concat(&value);
+        let (_, arguments) = self.original.split_at(self.name.len());
+        text += arguments;
+
+        let tokens = preprocess_grammar::tokens(&text)
+            .map_err(|e| aerror!("Failed to parse macro expansion {}: {}", text, e))?;
+        let mut stack_new: Vec<Definition> = stack.to_vec();
+        stack_new.push(def.clone());
+        Macro::resolve_all(&tokens, def_map, &stack_new, ctx)
+    }
+
     fn resolve(
         &self,
         def_map: &HashMap<String, Definition>,
         stack: &[Definition],
+        ctx: &mut ExpansionContext,
     ) -> Result<Vec<Token>, ArmakeError> {
+        if self.arguments.is_none() && !def_map.contains_key(&self.name) {
+            if let Some(token) = self.resolve_builtin(ctx) {
+                return Ok(vec![token]);
+            }
+        }
+
         match def_map.get(&self.name) {
+            Some(def)
+                if def.parameters.is_none()
+                    && self.arguments.is_some()
+                    && !self.quoted
+                    && !stack.iter().any(|d| d.name == def.name) =>
+            {
+                self.resolve_rescanned(def, def_map, stack, ctx)
+            }
             Some(def) => {
-                let value = def.value(&self.arguments, def_map, stack)?;
+                let value = def.value(&self.arguments, def_map, stack, ctx)?;
+
+                if let Some(tokens) = &value {
+                    if !def.local && ctx.options.trace_macros.contains(&self.name) {
+                        let (result, _) = Token::concat(tokens);
+                        ctx.traces
+                            .push(format!("{} -> {}", self.original.trim(), result.trim()));
+                    }
+                }
 
                 if !def.local && self.quoted {
                     // @todo: complain
@@ -228,10 +540,10 @@ impl Macro {
                         Ok(tokens)
                     }
                 } else {
-                    self.resolve_pseudoargs(def_map, stack)
+                    self.resolve_pseudoargs(def_map, stack, ctx)
                 }
             }
-            None => self.resolve_pseudoargs(def_map, stack),
+            None => self.resolve_pseudoargs(def_map, stack, ctx),
         }
     }
 
@@ -239,13 +551,14 @@ impl Macro {
         tokens: &[Token],
         def_map: &HashMap<String, Definition>,
         stack: &[Definition],
+        ctx: &mut ExpansionContext,
     ) -> Result<Vec<Token>, ArmakeError> {
         let mut result: Vec<Token> = Vec::new();
 
         for token in tokens {
             match token {
                 Token::MacroToken(ref m) => {
-                    let resolved = m.resolve(def_map, stack)?;
+                    let resolved = m.resolve(def_map, stack, ctx)?;
                     for t in resolved {
                         result.push(t);
                     }
@@ -262,22 +575,30 @@ impl Macro {
 
 impl Token {
     fn concat(tokens: &[Token]) -> (String, u32) {
+        Token::join(tokens, false)
+    }
+
+    /// Concatenates the tokens, optionally including the text of comments.
+    fn join(tokens: &[Token], keep_comments: bool) -> (String, u32) {
         let mut output = String::new();
         let mut newlines = 0;
 
         for token in tokens {
             match token {
                 Token::RegularToken(s) => {
-                    output += &s;
+                    output += s;
                 }
                 Token::NewlineToken(s, n) => {
-                    output += &s;
+                    output += s;
                     newlines += n;
                 }
                 Token::MacroToken(m) => {
                     output += &m.original;
                 }
-                Token::CommentToken(n) => {
+                Token::CommentToken(s, n) => {
+                    if keep_comments {
+                        output += s;
+                    }
                     newlines += n;
                 }
                 _ => {}
@@ -288,11 +609,102 @@ impl Token {
     }
 }
 
+/// Formats the chain of files being included for messages, like `a.hpp -> b.hpp`, followed by
+/// `next`. Paths are relative to the folder of the first file if possible.
+fn format_include_chain(stack: &[PathBuf], next: &str) -> String {
+    let base = stack
+        .first()
+        .and_then(|p| p.canonicalize().ok())
+        .and_then(|p| p.parent().map(Path::to_path_buf));
+    let mut chain: Vec<String> = stack
+        .iter()
+        .map(|path| {
+            let absolute = path.canonicalize().unwrap_or_else(|_| path.clone());
+            match base.as_ref().and_then(|b| absolute.strip_prefix(b).ok()) {
+                Some(relative) => relative.display().to_string(),
+                None => path.display().to_string(),
+            }
+        })
+        .collect();
+    chain.push(next.to_string());
+    chain.join(" -> ")
+}
+
+/// Returns a preprocessor error for the given location.
+fn location_error(path: Option<&PathBuf>, line: u32, message: String) -> ArmakeError {
+    ArmakeError::PREPROCESS(PreprocessError {
+        path: path.map(|p| p.to_string_lossy().to_string()),
+        message: format!("[{}] {}", format_location(path, line), message),
+        source: Box::new(aerror!(message)),
+    })
+}
+
+/// Evaluates an `#if` or `#elif` condition at the given location.
+///
+/// `defined(NAME)` and `defined NAME` are replaced before the condition is macro-expanded.
+/// Identifiers remaining after expansion are undefined and evaluate to 0, with a warning or, with
+/// `PreprocessOptions::strict_conditions`, an error.
+fn evaluate_condition(
+    condition: &str,
+    definition_map: &HashMap<String, Definition>,
+    ctx: &mut ExpansionContext,
+    location: (Option<PathBuf>, u32),
+) -> Result<bool, ArmakeError> {
+    let format = format_location(location.0.as_ref(), location.1);
+    let error = |message: String| {
+        ArmakeError::PREPROCESS(PreprocessError {
+            path: location.0.as_ref().map(|p| p.to_string_lossy().to_string()),
+            message: format!("[{}] {}", format, message),
+            source: Box::new(aerror!(message)),
+        })
+    };
+
+    let defined = Regex::new(r"\bdefined\s*(?:\(\s*(\w+)\s*\)|(\w+))").unwrap();
+    let replaced = defined.replace_all(condition, |captures: &regex::Captures| {
+        let name = captures
+            .get(1)
+            .or_else(|| captures.get(2))
+            .unwrap()
+            .as_str();
+        if definition_map.contains_key(name) {
+            " 1 "
+        } else {
+            " 0 "
+        }
+    });
+
+    let tokens = preprocess_grammar::tokens(&replaced)
+        .map_err(|e| error(format!("Failed to parse condition {}: {}", condition, e)))?;
+    ctx.location = location.clone();
+    let resolved = Macro::resolve_all(&tokens, definition_map, &[], ctx)?;
+    let (expanded, _) = Token::concat(&resolved);
+
+    let mut undefined = Vec::new();
+    let value = condition::evaluate(&expanded, &mut undefined)
+        .map_err(|e| error(format!("Invalid condition {}: {}", condition, e)))?;
+
+    for name in undefined {
+        let message = format!("{} is not defined in condition \"{}\"", name, condition);
+        if ctx.options.strict_conditions {
+            return Err(error(message));
+        }
+        raise_warning(
+            "undefined-in-condition",
+            location.0.as_ref(),
+            location.1,
+            format!("{}, treating it as 0", message),
+        );
+    }
+
+    Ok(value != 0)
+}
+
 fn preprocess_rec<F>(
-    input: String,
+    mut input: String,
     origin: Option<PathBuf>,
     definition_map: &mut HashMap<String, Definition>,
     info: &mut PreprocessInfo,
+    ctx: &mut ExpansionContext,
     includefolders: &[PathBuf],
     fileread: F,
 ) -> Result<String, ArmakeError>
@@ -300,12 +712,28 @@ where
     F: Fn(&PathBuf) -> String,
     F: Copy,
 {
+    // a line continuation at the end of the file has no line to continue into, it is dropped
+    let dangling = match input
+        .strip_suffix('\n')
+        .map(|i| i.strip_suffix('\r').unwrap_or(i))
+    {
+        Some(rest) => rest.len(),
+        None => input.len(),
+    };
+    let backslashes = input[..dangling].len() - input[..dangling].trim_end_matches('\\').len();
+    let dangling = if backslashes % 2 == 1 {
+        input.truncate(dangling - 1);
+        Some(input.matches('\n').count() as u32 + 1)
+    } else {
+        None
+    };
+
     let lines = preprocess_grammar::file(&input).map_err(|source| {
         ArmakeError::PARSE(PreprocessParseError {
             path: Some(
                 origin
                     .clone()
-                    .unwrap_or_else(PathBuf::new)
+                    .unwrap_or_default()
                     .to_string_lossy()
                     .to_string(),
             ),
@@ -317,10 +745,30 @@ where
     let mut original_lineno = 1;
     let mut level = 0;
     let mut level_true = 0;
+    // for every open conditional, whether one of its branches was active
+    let mut taken: Vec<bool> = Vec::new();
+    // for every open conditional, its directive and line
+    let mut opened: Vec<(&str, u32)> = Vec::new();
+
+    if let Some(lineno) = dangling {
+        if !matches!(
+            lines.last(),
+            Some(Line::DirectiveLine(Directive::DefineDirective(_), _))
+        ) {
+            raise_warning(
+                "dangling-continuation",
+                origin.as_ref(),
+                lineno,
+                "Line continuation at the end of the file",
+            );
+        }
+    }
 
-    for line in lines {
+    let line_count = lines.len();
+    for (index, line) in lines.into_iter().enumerate() {
         match line {
             Line::DirectiveLine(dir, newlines) => {
+                let directive_lineno = original_lineno;
                 original_lineno += newlines;
 
                 match dir {
@@ -335,39 +783,90 @@ where
                         //    // @todo: complain
                         //}
 
-                        let file_path =
-                            fs::find_include_file(&path, origin.as_ref(), includefolders)?;
+                        let error = |message: String| {
+                            location_error(origin.as_ref(), directive_lineno, message)
+                        };
+                        let file_path = fs::find_include_file(
+                            &path,
+                            origin.as_ref(),
+                            info.import_stack.first(),
+                            includefolders,
+                        )
+                        .map_err(|e| {
+                            if info.import_stack.len() > 1 {
+                                let chain = format_include_chain(&info.import_stack, &path);
+                                error(format!("{}, include chain: {}", e, chain))
+                            } else {
+                                error(e.to_string())
+                            }
+                        })?;
+
+                        if fs::case_differs(&path, &file_path) {
+                            raise_warning(
+                                "case-insensitive-include",
+                                origin.as_ref(),
+                                directive_lineno,
+                                format!(
+                                    "Include \"{}\" only matches {} if case is ignored",
+                                    path,
+                                    file_path.display()
+                                ),
+                            );
+                        }
+                        if info.import_stack.iter().any(|p| {
+                            *p == file_path || p.canonicalize().ok().as_ref() == Some(&file_path)
+                        }) {
+                            let chain = format_include_chain(&info.import_stack, &path);
+                            return Err(error(format!("Circular include: {}", chain)));
+                        }
+                        if info.import_stack.len() >= ctx.options.max_include_depth {
+                            let chain = format_include_chain(&info.import_stack, &path);
+                            return Err(error(format!(
+                                "Includes are nested deeper than {} files: {}",
+                                ctx.options.max_include_depth, chain
+                            )));
+                        }
 
                         info.import_stack.push(file_path.clone());
 
-                        let content = fileread(&file_path);
+                        let content = strip_bom(fileread(&file_path));
                         let result = preprocess_rec(
                             content,
                             Some(file_path),
                             definition_map,
                             info,
+                            ctx,
                             includefolders,
                             fileread,
                         )
-                        .map_err(|e| {
-                            match e {
-                                ArmakeError::PREPROCESS(p) => ArmakeError::PREPROCESS(p),
-                                _ => ArmakeError::PREPROCESS(PreprocessError {
-                                    message: "Failed to process include".to_string(),
-                                    path: Some(path),
-                                    source: Box::new(e),
-                                })
-                            }
+                        .map_err(|e| match e {
+                            ArmakeError::PREPROCESS(p) => ArmakeError::PREPROCESS(p),
+                            _ => ArmakeError::PREPROCESS(PreprocessError {
+                                message: "Failed to process include".to_string(),
+                                path: Some(path),
+                                source: Box::new(e),
+                            }),
                         })?;
 
                         info.import_stack.pop();
 
                         output += &result;
                     }
-                    Directive::DefineDirective(def) => {
+                    Directive::DefineDirective(mut def) => {
+                        if dangling.is_some() && index + 1 == line_count {
+                            return Err(location_error(
+                                origin.as_ref(),
+                                directive_lineno,
+                                format!(
+                                    "Definition of {} continues past the end of the file",
+                                    def.name
+                                ),
+                            ));
+                        }
+
                         original_lineno += u32::sum(def.value.iter().map(|t| match t {
                             Token::NewlineToken(_s, n) => *n,
-                            Token::CommentToken(n) => *n,
+                            Token::CommentToken(_s, n) => *n,
                             _ => 0,
                         }));
 
@@ -375,10 +874,51 @@ where
                             continue;
                         }
 
-                        if definition_map.remove(&def.name).is_some() {
-                            // @todo: warn about redefine
+                        if let Some(previous) = definition_map.remove(&def.name) {
+                            raise_warning(
+                                "redefinition-wo-undef",
+                                origin.as_ref(),
+                                directive_lineno,
+                                format!(
+                                    "Macro {} is redefined without #undef, previously defined at {}",
+                                    def.name,
+                                    previous
+                                        .location
+                                        .as_ref()
+                                        .map(|(path, line)| format_location(path.as_ref(), *line))
+                                        .unwrap_or_else(|| String::from("<built-in>")),
+                                ),
+                            );
+                        }
+                        let value: Vec<&Token> = def
+                            .value
+                            .iter()
+                            .filter(|t| match t {
+                                Token::RegularToken(s) | Token::NewlineToken(s, _) => {
+                                    !s.trim().is_empty()
+                                }
+                                Token::CommentToken(_, _) => false,
+                                _ => true,
+                            })
+                            .collect();
+                        if matches!(value.first(), Some(Token::ConcatToken))
+                            || matches!(value.last(), Some(Token::ConcatToken))
+                        {
+                            raise_warning(
+                                "excessive-concatenation",
+                                origin.as_ref(),
+                                directive_lineno,
+                                format!(
+                                    "## at the start or end of macro {} has nothing to join",
+                                    def.name
+                                ),
+                            );
                         }
 
+                        // comments in definitions are never part of the expansion
+                        def.value
+                            .retain(|t| !matches!(t, Token::CommentToken(_s, _n)));
+                        def.location = Some((origin.clone(), directive_lineno));
                         definition_map.insert(def.name.clone(), def);
                     }
                     Directive::UndefDirective(name) => {
@@ -388,51 +928,104 @@ where
 
                         definition_map.remove(&name);
                     }
-                    Directive::IfDefDirective(name) => {
-                        level_true += if level_true == level && definition_map.contains_key(&name) {
-                            1
-                        } else {
-                            0
+                    Directive::IfDefDirective(_)
+                    | Directive::IfNDefDirective(_)
+                    | Directive::IfDirective(_) => {
+                        let (keyword, active) = match &dir {
+                            Directive::IfDefDirective(name) => {
+                                ("#ifdef", definition_map.contains_key(name))
+                            }
+                            Directive::IfNDefDirective(name) => {
+                                ("#ifndef", !definition_map.contains_key(name))
+                            }
+                            Directive::IfDirective(condition) => (
+                                "#if",
+                                // conditions are only evaluated in active branches
+                                level_true == level
+                                    && evaluate_condition(
+                                        condition,
+                                        definition_map,
+                                        ctx,
+                                        (origin.clone(), directive_lineno),
+                                    )?,
+                            ),
+                            _ => unreachable!(),
                         };
+                        let active = level_true == level && active;
+                        level_true += active as u32;
                         level += 1;
+                        taken.push(active);
+                        opened.push((keyword, directive_lineno));
                     }
-                    Directive::IfNDefDirective(name) => {
-                        level_true += if level_true == level && !definition_map.contains_key(&name)
-                        {
-                            1
-                        } else {
-                            0
+                    Directive::ElifDirective(_)
+                    | Directive::ElseDirective
+                    | Directive::EndIfDirective
+                        if level == 0 =>
+                    {
+                        let keyword = match dir {
+                            Directive::ElifDirective(_) => "#elif",
+                            Directive::ElseDirective => "#else",
+                            _ => "#endif",
                         };
-                        level += 1;
+                        return Err(location_error(
+                            origin.as_ref(),
+                            directive_lineno,
+                            format!("{} without #if, #ifdef or #ifndef", keyword),
+                        ));
+                    }
+                    Directive::ElifDirective(condition) => {
+                        if level_true == level {
+                            level_true -= 1;
+                        } else if level_true + 1 == level
+                            && !taken.last().copied().unwrap_or(true)
+                            && evaluate_condition(
+                                &condition,
+                                definition_map,
+                                ctx,
+                                (origin.clone(), directive_lineno),
+                            )?
+                        {
+                            level_true = level;
+                            *taken.last_mut().unwrap() = true;
+                        }
                     }
                     Directive::ElseDirective => {
-                        if level_true + 1 == level {
+                        if level_true + 1 == level && !taken.last().copied().unwrap_or(true) {
                             level_true = level;
                         } else if level_true == level {
                             level_true -= 1;
                         }
                     }
                     Directive::EndIfDirective => {
-                        assert!(level > 0);
                         level -= 1;
                         if level_true > level {
                             level_true -= 1;
                         }
+                        taken.pop();
+                        opened.pop();
                     }
                 }
             }
             Line::TokenLine(tokens) => {
-                let stack: Vec<Definition> = Vec::new();
-                let resolved =
-                    Macro::resolve_all(&tokens, &definition_map, &stack).map_err(|e| {
-                        ArmakeError::PREPROCESS(PreprocessError {
-                            message: "Failed to process macros".to_string(),
-                            path: None,
-                            source: Box::new(e),
-                        })
-                    })?;
-
-                let (mut result, newlines) = Token::concat(&resolved);
+                // macros are only expanded in active lines
+                let resolved = if level > level_true {
+                    tokens
+                } else {
+                    ctx.location = (origin.clone(), original_lineno);
+                    let stack: Vec<Definition> = Vec::new();
+                    Macro::resolve_all(&tokens, definition_map, &stack, ctx).map_err(
+                        |e| match e {
+                            ArmakeError::PREPROCESS(p) => ArmakeError::PREPROCESS(p),
+                            _ => ArmakeError::PREPROCESS(PreprocessError {
+                                message: "Failed to process macros".to_string(),
+                                path: None,
+                                source: Box::new(e),
+                            }),
+                        },
+                    )?
+                };
+
+                let (mut result, newlines) = Token::join(&resolved, ctx.options.keep_comments);
                 result = result.replace("\r\n", "\n");
                 original_lineno += newlines;
 
@@ -446,20 +1039,38 @@ where
                 output += &result;
                 output += "\n";
 
+                // kept multi-line comments are the only source of newlines in a token line
+                let comment_lines = result.matches('\n').count() as u32;
+                for i in 0..comment_lines {
+                    info.line_origins
+                        .push((original_lineno - comment_lines + i, origin.clone()));
+                }
                 info.line_origins.push((original_lineno, origin.clone()));
                 original_lineno += (before - result.len()) as u32 / 2;
             }
         }
         original_lineno += 1;
+    }
 
-        if level > 0 {
-            // @todo: complain
-        }
+    if let Some((keyword, lineno)) = opened.last() {
+        return Err(location_error(
+            origin.as_ref(),
+            *lineno,
+            format!("{} without #endif", keyword),
+        ));
     }
 
     Ok(output)
 }
 
+/// Removes the UTF-8 byte order mark editors on Windows put at the start of files.
+fn strip_bom(mut text: String) -> String {
+    if text.starts_with('\u{feff}') {
+        text.drain(..'\u{feff}'.len_utf8());
+    }
+    text
+}
+
 /// Reads input string and returns preprocessed string with an info struct containing the origins
 /// of the lines in the output.
 ///
@@ -478,12 +1089,15 @@ where
 /// foo = QUOTE(DOUBLES(abc, xyz));
 /// ");
 ///
-/// let (output, _) = preprocess(input, None, &Vec::new()).expect("Failed to preprocess");
+/// let (output, _) = preprocess(input, None, &Vec::new(), |path| {
+///     std::fs::read_to_string(path).unwrap()
+/// })
+/// .expect("Failed to preprocess");
 ///
 /// assert_eq!("foo = \"abc_xyz\";", output.trim());
 /// ```
 pub fn preprocess<F>(
-    mut input: String,
+    input: String,
     origin: Option<PathBuf>,
     includefolders: &[PathBuf],
     fileread: F,
@@ -492,30 +1106,60 @@ where
     F: Fn(&PathBuf) -> String,
     F: Copy,
 {
-    if input[..3].as_bytes() == [0xef, 0xbb, 0xbf] {
-        input = input[3..].to_string();
-    }
+    let mut info = PreprocessInfo::default();
+    let result = preprocess_with_options(
+        input,
+        origin,
+        includefolders,
+        fileread,
+        &PreprocessOptions::default(),
+        &mut info,
+    )?;
 
-    let mut info = PreprocessInfo {
-        line_origins: Vec::new(),
-        import_stack: Vec::new(),
-    };
+    Ok((result, info))
+}
+
+/// Same as `preprocess`, with additional options.
+///
+/// The info struct is filled in even if preprocessing fails, so the macros defined up to the
+/// point of failure can still be inspected.
+pub fn preprocess_with_options<F>(
+    mut input: String,
+    origin: Option<PathBuf>,
+    includefolders: &[PathBuf],
+    fileread: F,
+    options: &PreprocessOptions,
+    info: &mut PreprocessInfo,
+) -> Result<String, ArmakeError>
+where
+    F: Fn(&PathBuf) -> String,
+    F: Copy,
+{
+    input = strip_bom(input);
 
     if let Some(ref path) = origin {
         info.import_stack.push(path.clone());
     }
 
-    let mut def_map: HashMap<String, Definition> = HashMap::new();
+    let mut def_map = options.initial_definitions()?;
+    let mut ctx = ExpansionContext {
+        options,
+        traces: Vec::new(),
+        location: (None, 0),
+    };
 
-    match preprocess_rec(
+    let result = preprocess_rec(
         input,
         origin,
         &mut def_map,
-        &mut info,
+        info,
+        &mut ctx,
         includefolders,
         fileread,
-    ) {
-        Ok(result) => Ok((result, info)),
-        Err(e) => Err(e),
-    }
+    );
+
+    info.definitions = def_map;
+    info.macro_traces = ctx.traces;
+
+    result
 }
@@ -0,0 +1,119 @@
+//! Whitespace-normalized form of preprocessor output, for comparing it with the output of other
+//! preprocessors
+
+/// Number of lines on each side searched for the end of a difference
+const RESYNC_WINDOW: usize = 200;
+/// Number of equal lines shown before and after a difference
+const CONTEXT_LINES: usize = 2;
+
+/// Difference between two normalized texts
+#[derive(Debug, PartialEq)]
+pub struct Difference {
+    /// Line in the original left text where the difference starts, starting at 1
+    pub left_line: usize,
+    /// Line in the original right text where the difference starts, starting at 1
+    pub right_line: usize,
+    /// Normalized lines before the difference, which are the same on both sides
+    pub before: Vec<String>,
+    /// Normalized lines only in the left text
+    pub left: Vec<String>,
+    /// Normalized lines only in the right text
+    pub right: Vec<String>,
+    /// Normalized lines after the difference, which are the same on both sides
+    pub after: Vec<String>,
+}
+
+/// Returns the normalized lines of the text with their line numbers, starting at 1.
+///
+/// Runs of spaces and tabs are collapsed to one space, trailing whitespace is removed and blank
+/// lines are dropped. `\r\n` and `\r` line endings count as `\n`.
+pub fn normalized_lines(text: &str) -> Vec<(usize, String)> {
+    text.replace("\r\n", "\n")
+        .split(['\n', '\r'])
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let mut normalized = String::with_capacity(line.len());
+            for c in line.trim_end().chars() {
+                if c == ' ' || c == '\t' {
+                    if !normalized.ends_with(' ') {
+                        normalized.push(' ');
+                    }
+                } else {
+                    normalized.push(c);
+                }
+            }
+            if normalized.is_empty() {
+                None
+            } else {
+                Some((i + 1, normalized))
+            }
+        })
+        .collect()
+}
+
+/// Returns the normalized form of the text, see `normalized_lines`. Every line ends with `\n`.
+pub fn normalize(text: &str) -> String {
+    normalized_lines(text)
+        .into_iter()
+        .map(|(_, line)| line + "\n")
+        .collect()
+}
+
+/// Compares the normalized forms of two texts, returning at most `max` differences.
+///
+/// After a mismatch, the closest lines that match again within a window of lines end the
+/// difference. If there are none, the rest of both texts is one difference.
+pub fn compare_normalized(left: &str, right: &str, max: usize) -> Vec<Difference> {
+    let left = normalized_lines(left);
+    let right = normalized_lines(right);
+    let lines = |lines: &[(usize, String)]| -> Vec<String> {
+        lines.iter().map(|(_, l)| l.clone()).collect()
+    };
+
+    let mut differences = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while differences.len() < max && (i < left.len() || j < right.len()) {
+        if i < left.len() && j < right.len() && left[i].1 == right[j].1 {
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        let (x, y) = (1..=2 * RESYNC_WINDOW)
+            .flat_map(|d| (0..=d).map(move |x| (x, d - x)))
+            .filter(|&(x, y)| x <= RESYNC_WINDOW && y <= RESYNC_WINDOW)
+            .find(|&(x, y)| {
+                i + x < left.len() && j + y < right.len() && left[i + x].1 == right[j + y].1
+            })
+            .unwrap_or((left.len() - i, right.len() - j));
+
+        differences.push(Difference {
+            left_line: left
+                .get(i)
+                .or_else(|| left.last())
+                .map(|l| l.0)
+                .unwrap_or(1),
+            right_line: right
+                .get(j)
+                .or_else(|| right.last())
+                .map(|l| l.0)
+                .unwrap_or(1),
+            before: lines(&left[i.saturating_sub(CONTEXT_LINES)..i]),
+            left: lines(&left[i..i + x]),
+            right: lines(&right[j..j + y]),
+            after: {
+                let end = (i + x + CONTEXT_LINES).min(left.len());
+                let common = left[i + x..end]
+                    .iter()
+                    .zip(right[j + y..].iter())
+                    .take_while(|(l, r)| l.1 == r.1)
+                    .count();
+                lines(&left[i + x..i + x + common])
+            },
+        });
+        i += x;
+        j += y;
+    }
+
+    differences
+}
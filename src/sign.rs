@@ -0,0 +1,993 @@
+//! BI keys and signatures (`.biprivatekey`, `.bikey` and `.bisign`)
+//!
+//! # Hashed data
+//!
+//! A signature contains three RSA signatures of SHA1 hashes, all computed over the PBO *as
+//! stored*, never over decoded content:
+//!
+//! 1. The PBO checksum: the SHA1 trailer over the stored header block and the stored data of all
+//!    entries, exactly as the bytes appear in the file.
+//! 2. SHA1 of the PBO checksum, the name hash and the prefix.
+//! 3. SHA1 of the file hash, the name hash and the prefix. Legacy V1 signatures have no file
+//!    hash, their third hash is the SHA1 of only the name hash and the prefix.
+//!
+//! The name hash is the SHA1 of the lowercased names of all entries that have stored data,
+//! sorted. The file hash is the SHA1 of the stored data of all entries whose extension is
+//! selected by the signature version (see `BISignVersion`), in the order they are stored. The
+//! prefix is the `prefix` header extension with a trailing backslash, or nothing if there is
+//! none.
+//!
+//! Because every component uses the stored bytes, an LZSS compressed entry (packing method
+//! `Cprs`, written by `build -z` and `pack -z`) goes into the file hash with its compressed data
+//! as it appears in the file, not with its unpacked content. This keeps the file hash consistent
+//! with the checksum, which can only be computed over the stored stream. `PBO::read` keeps the
+//! stored data in `files` for this and only unpacks it in `entry_data`. Only uncompressed entries
+//! hash the same in both representations.
+//!
+//! This handling of compressed entries follows from the format as described above and is
+//! unverified: there is no PBO with compressed entries signed by DSSignFile in the tests, and
+//! such signatures have not been checked with DSCheckSignatures. Signatures of uncompressed PBOs
+//! are not affected.
+
+use std::fs::{read_dir, File};
+use std::io::{BufReader, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use openssl::bn::{BigNum, BigNumContext, BigNumRef};
+use openssl::pkey::{Id, PKey};
+use openssl::rsa::Rsa;
+use sha1::{Digest, Sha1};
+
+use crate::io::{HashingReader, ReadExt, WriteExt};
+use crate::pbo::{ChecksumStatus, PackingMethod, WriteObserver, PLACEHOLDER_CHECKSUM};
+use crate::warning::Collector;
+use crate::{aerror, ArmakeError, PBOHeader, PBO};
+
+/// Size of the chunks PBOs are read in when they are hashed from a file
+const CHUNK_SIZE: usize = 4 << 20;
+
+/// Version of a BI signature, which determines the files included in the file hash
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BISignVersion {
+    /// Legacy signatures without a file hash, which can only be verified
+    V1,
+    /// Hashes all files except common binary formats
+    V2,
+    /// Hashes only script and config files
+    V3,
+}
+
+/// All signature versions with the number they are stored as
+const VERSIONS: &[(BISignVersion, u32)] = &[
+    (BISignVersion::V1, 1),
+    (BISignVersion::V2, 2),
+    (BISignVersion::V3, 3),
+];
+
+impl BISignVersion {
+    fn from_u32(version: u32) -> Result<BISignVersion, ArmakeError> {
+        VERSIONS
+            .iter()
+            .find(|(_, n)| *n == version)
+            .map(|(v, _)| *v)
+            .ok_or_else(|| aerror!("Unknown signature version {}", version))
+    }
+
+    fn to_u32(self) -> u32 {
+        VERSIONS.iter().find(|(v, _)| *v == self).unwrap().1
+    }
+
+    /// Returns true if signatures of this version can be created, not only verified.
+    pub fn can_sign(self) -> bool {
+        self != BISignVersion::V1
+    }
+
+    /// Returns true if the third hash of this version includes the file hash.
+    fn has_filehash(self) -> bool {
+        self != BISignVersion::V1
+    }
+
+    /// Returns true if a file with the given extension (lowercase) is part of the file hash.
+    fn hashes_extension(self, extension: &str) -> bool {
+        match self {
+            BISignVersion::V1 => false,
+            BISignVersion::V2 => ![
+                "paa", "jpg", "p3d", "tga", "rvmat", "lip", "ogg", "wss", "png", "rtm", "pac",
+                "fxy", "wrp",
+            ]
+            .contains(&extension),
+            BISignVersion::V3 => [
+                "sqf", "inc", "bikb", "ext", "fsm", "sqm", "hpp", "cfg", "sqs", "h",
+            ]
+            .contains(&extension),
+        }
+    }
+
+    /// Data hashed in place of the file hash input if no file is selected.
+    fn nothing(self) -> &'static [u8] {
+        match self {
+            BISignVersion::V1 => b"",
+            BISignVersion::V2 => b"nothing",
+            BISignVersion::V3 => b"gnihton",
+        }
+    }
+}
+
+impl std::fmt::Display for BISignVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "V{}", self.to_u32())
+    }
+}
+
+pub struct BIPrivateKey {
+    pub name: String,
+    pub length: u32,
+    pub exponent: u32,
+    pub n: BigNum,
+    pub p: BigNum,
+    pub q: BigNum,
+    pub dmp1: BigNum,
+    pub dmq1: BigNum,
+    pub iqmp: BigNum,
+    pub d: BigNum,
+}
+
+pub struct BIPublicKey {
+    pub name: String,
+    pub length: u32,
+    pub exponent: u32,
+    pub n: BigNum,
+}
+
+pub struct BISign {
+    pub version: BISignVersion,
+    pub name: String,
+    pub length: u32,
+    pub exponent: u32,
+    pub n: BigNum,
+    pub sig1: BigNum,
+    pub sig2: BigNum,
+    pub sig3: BigNum,
+}
+
+/// Returns the lowercase extension of an entry name.
+fn extension(name: &str) -> String {
+    Path::new(&name.replace("\\", "/"))
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Returns the name hash of the stored PBO.
+fn namehash(pbo: &PBO) -> Vec<u8> {
+    let mut names: Vec<String> = pbo
+        .files
+        .iter()
+        .filter(|(_, cursor)| !cursor.get_ref().is_empty())
+        .map(|(name, _)| name.to_lowercase())
+        .collect();
+    names.sort();
+
+    let mut h = Sha1::new();
+    for name in names {
+        h.update(name.as_bytes());
+    }
+    h.finalize().to_vec()
+}
+
+/// Returns the file hash of the stored PBO for the given signature version.
+fn filehash(pbo: &PBO, version: BISignVersion) -> Vec<u8> {
+    let mut h = Sha1::new();
+    let mut nothing = true;
+
+    for (name, cursor) in pbo.files.iter() {
+        if !version.hashes_extension(&extension(name)) {
+            continue;
+        }
+
+        h.update(cursor.get_ref());
+        nothing = false;
+    }
+
+    if nothing {
+        h.update(version.nothing());
+    }
+    h.finalize().to_vec()
+}
+
+fn prefix(pbo: &PBO) -> Vec<u8> {
+    prefix_bytes(pbo.header_extension("prefix"))
+}
+
+fn prefix_bytes(prefix: Option<&str>) -> Vec<u8> {
+    match prefix {
+        Some(prefix) if prefix.ends_with('\\') => prefix.as_bytes().to_vec(),
+        Some(prefix) => format!("{}\\", prefix).into_bytes(),
+        None => Vec::new(),
+    }
+}
+
+/// Placeholder checksums can't be signed, the signature would vouch for nothing.
+fn check_placeholder(checksum: &[u8]) -> Result<(), ArmakeError> {
+    if checksum == PLACEHOLDER_CHECKSUM {
+        return Err(aerror!(
+            "PBO has a placeholder checksum, rebuild it without --no-checksum to sign or verify it"
+        ));
+    }
+    Ok(())
+}
+
+/// Hashes of a stored PBO that signatures are computed from, see the module documentation
+struct Hashes {
+    checksum: Vec<u8>,
+    namehash: Vec<u8>,
+    prefix: Vec<u8>,
+    /// File hash for each of the requested versions, in order
+    filehashes: Vec<(BISignVersion, Vec<u8>)>,
+}
+
+/// Computes the name hash and the file hashes of a stored PBO from its entries in stored order,
+/// see the module documentation
+struct EntryHasher<'a> {
+    versions: &'a [BISignVersion],
+    /// Lowercase names of the entries with stored data
+    names: Vec<String>,
+    /// File hash for each version, and whether no entry was selected for it yet
+    filehashes: Vec<(Sha1, bool)>,
+    /// Versions whose file hash includes the current entry
+    selected: Vec<usize>,
+}
+
+impl<'a> EntryHasher<'a> {
+    fn new(versions: &'a [BISignVersion]) -> EntryHasher<'a> {
+        EntryHasher {
+            versions,
+            names: Vec::new(),
+            filehashes: versions.iter().map(|_| (Sha1::new(), true)).collect(),
+            selected: Vec::new(),
+        }
+    }
+
+    fn finish(self, checksum: Vec<u8>, prefix: Vec<u8>) -> Hashes {
+        let mut names = self.names;
+        names.sort();
+        let mut h = Sha1::new();
+        for name in names {
+            h.update(name.as_bytes());
+        }
+
+        Hashes {
+            checksum,
+            namehash: h.finalize().to_vec(),
+            prefix,
+            filehashes: self
+                .versions
+                .iter()
+                .zip(self.filehashes)
+                .map(|(version, (mut h, nothing))| {
+                    if nothing {
+                        h.update(version.nothing());
+                    }
+                    (*version, h.finalize().to_vec())
+                })
+                .collect(),
+        }
+    }
+}
+
+impl WriteObserver for EntryHasher<'_> {
+    fn entry(&mut self, header: &PBOHeader) {
+        if header.data_size > 0 {
+            self.names.push(header.filename.to_lowercase());
+        }
+        let extension = extension(&header.filename);
+        self.selected = (0..self.versions.len())
+            .filter(|&i| self.versions[i].hashes_extension(&extension))
+            .collect();
+        for &i in &self.selected {
+            self.filehashes[i].1 = false;
+        }
+    }
+
+    fn data(&mut self, chunk: &[u8]) {
+        for &i in &self.selected {
+            self.filehashes[i].0.update(chunk);
+        }
+    }
+}
+
+impl Hashes {
+    /// Hashes a PBO for the given versions. PBOs that were not read from a file (and thus have no
+    /// checksum) are hashed in the form `PBO::write` stores them, while writing them nowhere.
+    fn from_pbo(pbo: &PBO, versions: &[BISignVersion]) -> Result<Hashes, ArmakeError> {
+        if pbo.checksum_status == Some(ChecksumStatus::Missing) {
+            return Err(aerror!("PBO is truncated, its checksum is missing"));
+        }
+        if pbo.checksum.is_none() {
+            let mut hasher = EntryHasher::new(versions);
+            let checksum = pbo.write_observed(&mut std::io::sink(), false, &mut hasher)?;
+            return Ok(hasher.finish(checksum, prefix(pbo)));
+        }
+
+        let checksum = pbo.checksum.clone().unwrap();
+        check_placeholder(&checksum)?;
+
+        Ok(Hashes {
+            checksum,
+            namehash: namehash(pbo),
+            prefix: prefix(pbo),
+            filehashes: versions
+                .iter()
+                .map(|version| (*version, filehash(pbo, *version)))
+                .collect(),
+        })
+    }
+
+    /// Hashes a stored PBO for the given versions while reading it in chunks of `CHUNK_SIZE`,
+    /// without keeping its content in memory. Fails if the checksum doesn't match the content.
+    fn read<I: Read>(input: &mut I, versions: &[BISignVersion]) -> Result<Hashes, ArmakeError> {
+        let mut input = HashingReader::new(input);
+
+        let mut headers: Vec<PBOHeader> = Vec::new();
+        let mut prefix_extension: Option<String> = None;
+        // found like PBO::header_extension does, the exact key wins over other cases
+        let mut other_case_prefix: Option<String> = None;
+        loop {
+            let header = PBOHeader::read(&mut input)?;
+            if header.method() == PackingMethod::ProductEntry {
+                loop {
+                    let key = input.read_cstring()?;
+                    if key.is_empty() {
+                        break;
+                    }
+                    let value = input.read_cstring()?;
+                    if key == "prefix" {
+                        prefix_extension = Some(value);
+                    } else if key.eq_ignore_ascii_case("prefix") && other_case_prefix.is_none() {
+                        other_case_prefix = Some(value);
+                    }
+                }
+            } else if header.ends_header_list() {
+                break;
+            } else {
+                headers.push(header);
+            }
+        }
+
+        let mut hasher = EntryHasher::new(versions);
+        let mut buffer = vec![0; CHUNK_SIZE];
+        for header in &headers {
+            hasher.entry(header);
+            let mut remaining = header.data_size as usize;
+            while remaining > 0 {
+                let chunk = &mut buffer[..remaining.min(CHUNK_SIZE)];
+                input.read_exact(chunk)?;
+                hasher.data(chunk);
+                remaining -= chunk.len();
+            }
+        }
+
+        let computed = input.hash.finalize();
+        let input = input.inner;
+        input.read_u8()?;
+        let mut checksum = vec![0; 20];
+        input.read_exact(&mut checksum)?;
+        check_placeholder(&checksum)?;
+        if computed.as_slice() != checksum.as_slice() {
+            return Err(aerror!("PBO checksum doesn't match its content"));
+        }
+
+        Ok(hasher.finish(
+            checksum,
+            prefix_bytes(prefix_extension.or(other_case_prefix).as_deref()),
+        ))
+    }
+
+    /// Returns the three hashes of a signature with a key of the given length, padded to the key
+    /// length. The first two hashes are the same for every version, the third is returned for
+    /// each version in order.
+    fn padded(&self, length: u32) -> Result<(BigNum, BigNum, Vec<BigNum>), ArmakeError> {
+        let size = (length / 8) as usize;
+
+        let mut h = Sha1::new();
+        h.update(&self.checksum);
+        h.update(&self.namehash);
+        h.update(&self.prefix);
+        let hash2 = h.finalize();
+
+        let hash3 = self
+            .filehashes
+            .iter()
+            .map(|(version, filehash)| {
+                let mut h = Sha1::new();
+                if version.has_filehash() {
+                    h.update(filehash);
+                }
+                h.update(&self.namehash);
+                h.update(&self.prefix);
+                pad_hash(&h.finalize(), size)
+            })
+            .collect::<Result<Vec<BigNum>, ArmakeError>>()?;
+
+        Ok((
+            pad_hash(&self.checksum, size)?,
+            pad_hash(&hash2, size)?,
+            hash3,
+        ))
+    }
+}
+
+/// Computes the hashes of signatures for the given versions, see `Hashes::padded`.
+fn generate_hashes(
+    pbo: &PBO,
+    versions: &[BISignVersion],
+    length: u32,
+) -> Result<(BigNum, BigNum, Vec<BigNum>), ArmakeError> {
+    Hashes::from_pbo(pbo, versions)?.padded(length)
+}
+
+/// Fails for versions that can only be verified, see `BISignVersion::can_sign`.
+fn check_can_sign(versions: &[BISignVersion]) -> Result<(), ArmakeError> {
+    match versions.iter().find(|v| !v.can_sign()) {
+        Some(version) => Err(aerror!(
+            "Creating {} signatures is not supported, they can only be verified",
+            version
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Pads a SHA1 hash to `size` bytes according to PKCS #1 v1.5.
+fn pad_hash(hash: &[u8], size: usize) -> Result<BigNum, ArmakeError> {
+    let mut vec: Vec<u8> = vec![0, 1];
+    vec.resize(size - 36, 255);
+    vec.extend(b"\x00\x30\x21\x30\x09\x06\x05\x2b");
+    vec.extend(b"\x0e\x03\x02\x1a\x05\x00\x04\x14");
+    vec.extend(hash);
+
+    Ok(BigNum::from_slice(&vec)?)
+}
+
+/// Key lengths in bits that BI keys are created with
+const KEY_LENGTHS: &[u32] = &[1024, 2048, 4096];
+/// Public exponent of BI keys
+const KEY_EXPONENT: u32 = 65537;
+
+/// Returns the RSA key of a PEM key, failing for other key types.
+fn pem_rsa<T>(pkey: PKey<T>) -> Result<Rsa<T>, ArmakeError> {
+    match pkey.id() {
+        Id::RSA => Ok(pkey.rsa()?),
+        Id::DSA => Err(aerror!("BI keys are RSA keys, got a DSA key")),
+        Id::EC => Err(aerror!("BI keys are RSA keys, got an EC key")),
+        _ => Err(aerror!("BI keys are RSA keys, got an unsupported key type")),
+    }
+}
+
+/// Checks that an RSA key has parameters the BI tools create keys with and returns its length
+/// in bits and its exponent.
+fn check_parameters(n: &BigNumRef, e: &BigNumRef) -> Result<(u32, u32), ArmakeError> {
+    let length = n.num_bits() as u32;
+    if !KEY_LENGTHS.contains(&length) {
+        return Err(aerror!(
+            "Key length {} is not supported by BI keys, has to be one of 1024, 2048 or 4096",
+            length
+        ));
+    }
+    if *e != *BigNum::from_u32(KEY_EXPONENT)? {
+        return Err(aerror!(
+            "Public exponent {} is not supported by BI keys, has to be 65537",
+            e
+        ));
+    }
+    Ok((length, KEY_EXPONENT))
+}
+
+fn read_magic<I: Read>(input: &mut I, magic: &[u8]) -> Result<(), ArmakeError> {
+    let mut buffer = vec![0; magic.len()];
+    input.read_exact(&mut buffer)?;
+    if buffer != magic {
+        return Err(aerror!("Invalid key or signature header"));
+    }
+    Ok(())
+}
+
+impl BIPrivateKey {
+    /// Generates a new private key with the given authority name and length in bits.
+    pub fn generate(length: u32, name: String) -> Result<BIPrivateKey, ArmakeError> {
+        let rsa = Rsa::generate(length)?;
+
+        Ok(BIPrivateKey {
+            name,
+            length,
+            exponent: 65537,
+            n: rsa.n().to_owned()?,
+            p: rsa.p().unwrap().to_owned()?,
+            q: rsa.q().unwrap().to_owned()?,
+            dmp1: rsa.dmp1().unwrap().to_owned()?,
+            dmq1: rsa.dmq1().unwrap().to_owned()?,
+            iqmp: rsa.iqmp().unwrap().to_owned()?,
+            d: rsa.d().to_owned()?,
+        })
+    }
+
+    /// Reads a private key from a `.biprivatekey`.
+    pub fn read<I: Read>(input: &mut I) -> Result<BIPrivateKey, ArmakeError> {
+        let name = input.read_cstring()?;
+        input.read_u32::<LittleEndian>()?;
+        read_magic(input, b"\x07\x02\x00\x00\x00\x24\x00\x00RSA2")?;
+
+        let length = input.read_u32::<LittleEndian>()?;
+        let exponent = input.read_u32::<LittleEndian>()?;
+
+        Ok(BIPrivateKey {
+            name,
+            length,
+            exponent,
+            n: input.read_bignum((length / 8) as usize)?,
+            p: input.read_bignum((length / 16) as usize)?,
+            q: input.read_bignum((length / 16) as usize)?,
+            dmp1: input.read_bignum((length / 16) as usize)?,
+            dmq1: input.read_bignum((length / 16) as usize)?,
+            iqmp: input.read_bignum((length / 16) as usize)?,
+            d: input.read_bignum((length / 8) as usize)?,
+        })
+    }
+
+    /// Reads a private key from the file at `path`.
+    pub fn open(path: &Path) -> Result<BIPrivateKey, ArmakeError> {
+        BIPrivateKey::read(&mut File::open(path)?)
+    }
+
+    /// Writes the private key in `.biprivatekey` format.
+    pub fn write<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        output.write_cstring(&self.name)?;
+        output.write_u32::<LittleEndian>(self.length / 16 * 9 + 20)?;
+        output.write_all(b"\x07\x02\x00\x00\x00\x24\x00\x00RSA2")?;
+        output.write_u32::<LittleEndian>(self.length)?;
+        output.write_u32::<LittleEndian>(self.exponent)?;
+        output.write_bignum(&self.n, (self.length / 8) as usize)?;
+        output.write_bignum(&self.p, (self.length / 16) as usize)?;
+        output.write_bignum(&self.q, (self.length / 16) as usize)?;
+        output.write_bignum(&self.dmp1, (self.length / 16) as usize)?;
+        output.write_bignum(&self.dmq1, (self.length / 16) as usize)?;
+        output.write_bignum(&self.iqmp, (self.length / 16) as usize)?;
+        output.write_bignum(&self.d, (self.length / 8) as usize)?;
+        Ok(())
+    }
+
+    /// Reads a private key in PKCS #8 or PKCS #1 PEM format, with the given authority name.
+    ///
+    /// Fails for keys that aren't RSA keys or whose length or exponent BI keys can't have.
+    pub fn from_pem(pem: &[u8], name: String) -> Result<BIPrivateKey, ArmakeError> {
+        let rsa = pem_rsa(PKey::private_key_from_pem(pem)?)?;
+        let (length, exponent) = check_parameters(rsa.n(), rsa.e())?;
+
+        let half = (length / 16) as i32;
+        let component = |bn: Option<&BigNumRef>| -> Result<BigNum, ArmakeError> {
+            match bn {
+                Some(bn) if bn.num_bytes() <= half => Ok(bn.to_owned()?),
+                _ => Err(aerror!(
+                    "Key has no CRT parameters of {} bits, which BI keys require",
+                    length / 2
+                )),
+            }
+        };
+
+        Ok(BIPrivateKey {
+            name,
+            length,
+            exponent,
+            n: rsa.n().to_owned()?,
+            p: component(rsa.p())?,
+            q: component(rsa.q())?,
+            dmp1: component(rsa.dmp1())?,
+            dmq1: component(rsa.dmq1())?,
+            iqmp: component(rsa.iqmp())?,
+            d: rsa.d().to_owned()?,
+        })
+    }
+
+    /// Returns the private key in PKCS #8 PEM format, without the authority name.
+    pub fn to_pem(&self) -> Result<Vec<u8>, ArmakeError> {
+        let rsa = Rsa::from_private_components(
+            self.n.to_owned()?,
+            BigNum::from_u32(self.exponent)?,
+            self.d.to_owned()?,
+            self.p.to_owned()?,
+            self.q.to_owned()?,
+            self.dmp1.to_owned()?,
+            self.dmq1.to_owned()?,
+            self.iqmp.to_owned()?,
+        )?;
+        Ok(PKey::from_rsa(rsa)?.private_key_to_pem_pkcs8()?)
+    }
+
+    /// Returns the public key belonging to this private key.
+    pub fn to_public_key(&self) -> Result<BIPublicKey, ArmakeError> {
+        Ok(BIPublicKey {
+            name: self.name.clone(),
+            length: self.length,
+            exponent: self.exponent,
+            n: self.n.to_owned()?,
+        })
+    }
+
+    /// Signs a PBO with the given signature version.
+    pub fn sign(&self, pbo: &PBO, version: BISignVersion) -> Result<BISign, ArmakeError> {
+        Ok(self.sign_versions(pbo, &[version])?.pop().unwrap())
+    }
+
+    /// Signs a PBO once for each of the given signature versions, hashing it only once.
+    ///
+    /// Fails for versions that can only be verified, see `BISignVersion::can_sign`.
+    pub fn sign_versions(
+        &self,
+        pbo: &PBO,
+        versions: &[BISignVersion],
+    ) -> Result<Vec<BISign>, ArmakeError> {
+        check_can_sign(versions)?;
+        self.sign_hashes(&Hashes::from_pbo(pbo, versions)?, versions)
+    }
+
+    /// Writes the PBO to `output` like `PBO::write`, or like `PBO::write_ordered` if `ordered` is
+    /// set, and signs it like `sign_versions` from the hashes taken while writing it. The written
+    /// PBO is not kept in memory or read back.
+    pub(crate) fn write_signed<O: Write>(
+        &self,
+        pbo: &PBO,
+        output: &mut O,
+        ordered: bool,
+        versions: &[BISignVersion],
+    ) -> Result<Vec<BISign>, ArmakeError> {
+        check_can_sign(versions)?;
+        let mut hasher = EntryHasher::new(versions);
+        let checksum = pbo.write_observed(output, ordered, &mut hasher)?;
+        self.sign_hashes(&hasher.finish(checksum, prefix(pbo)), versions)
+    }
+
+    fn sign_hashes(
+        &self,
+        hashes: &Hashes,
+        versions: &[BISignVersion],
+    ) -> Result<Vec<BISign>, ArmakeError> {
+        let (hash1, hash2, hash3) = hashes.padded(self.length)?;
+
+        let mut ctx = BigNumContext::new()?;
+        let mut sign = |hash: &BigNum| -> Result<BigNum, ArmakeError> {
+            let mut signature = BigNum::new()?;
+            signature.mod_exp(hash, &self.d, &self.n, &mut ctx)?;
+            Ok(signature)
+        };
+
+        let sig1 = sign(&hash1)?;
+        let sig2 = sign(&hash2)?;
+        versions
+            .iter()
+            .zip(hash3.iter())
+            .map(|(version, hash3)| {
+                Ok(BISign {
+                    version: *version,
+                    name: self.name.clone(),
+                    length: self.length,
+                    exponent: self.exponent,
+                    n: self.n.to_owned()?,
+                    sig1: sig1.to_owned()?,
+                    sig2: sig2.to_owned()?,
+                    sig3: sign(hash3)?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl BIPublicKey {
+    /// Reads a public key from a `.bikey`.
+    pub fn read<I: Read>(input: &mut I) -> Result<BIPublicKey, ArmakeError> {
+        let name = input.read_cstring()?;
+        input.read_u32::<LittleEndian>()?;
+        read_magic(input, b"\x06\x02\x00\x00\x00\x24\x00\x00RSA1")?;
+
+        let length = input.read_u32::<LittleEndian>()?;
+        let exponent = input.read_u32::<LittleEndian>()?;
+
+        Ok(BIPublicKey {
+            name,
+            length,
+            exponent,
+            n: input.read_bignum((length / 8) as usize)?,
+        })
+    }
+
+    /// Reads a public key in SubjectPublicKeyInfo PEM format, with the given authority name.
+    ///
+    /// Fails for keys that aren't RSA keys or whose length or exponent BI keys can't have.
+    pub fn from_pem(pem: &[u8], name: String) -> Result<BIPublicKey, ArmakeError> {
+        let rsa = pem_rsa(PKey::public_key_from_pem(pem)?)?;
+        let (length, exponent) = check_parameters(rsa.n(), rsa.e())?;
+
+        Ok(BIPublicKey {
+            name,
+            length,
+            exponent,
+            n: rsa.n().to_owned()?,
+        })
+    }
+
+    /// Returns the public key in SubjectPublicKeyInfo PEM format, without the authority name.
+    pub fn to_pem(&self) -> Result<Vec<u8>, ArmakeError> {
+        let rsa =
+            Rsa::from_public_components(self.n.to_owned()?, BigNum::from_u32(self.exponent)?)?;
+        Ok(PKey::from_rsa(rsa)?.public_key_to_pem()?)
+    }
+
+    /// Writes the public key in `.bikey` format.
+    pub fn write<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        output.write_cstring(&self.name)?;
+        output.write_u32::<LittleEndian>(self.length / 8 + 20)?;
+        output.write_all(b"\x06\x02\x00\x00\x00\x24\x00\x00RSA1")?;
+        output.write_u32::<LittleEndian>(self.length)?;
+        output.write_u32::<LittleEndian>(self.exponent)?;
+        output.write_bignum(&self.n, (self.length / 8) as usize)?;
+        Ok(())
+    }
+
+    /// Verifies a signature of the PBO made with the private key belonging to this key.
+    pub fn verify(&self, pbo: &PBO, signature: &BISign) -> Result<(), ArmakeError> {
+        if self.n != signature.n {
+            return Err(aerror!(
+                "Key \"{}\" doesn't match the signature by \"{}\"",
+                self.name,
+                signature.name
+            ));
+        }
+
+        let (hash1, hash2, mut hash3) = generate_hashes(pbo, &[signature.version], self.length)?;
+        self.verify_hashes(signature, [&hash1, &hash2, &hash3.pop().unwrap()])
+    }
+
+    /// Checks the signature against the padded hashes of a PBO for its version, see
+    /// `Hashes::padded`.
+    fn verify_hashes(&self, signature: &BISign, hashes: [&BigNum; 3]) -> Result<(), ArmakeError> {
+        let exponent = BigNum::from_u32(self.exponent)?;
+        let mut ctx = BigNumContext::new()?;
+        for (i, (hash, sig)) in hashes
+            .iter()
+            .zip([&signature.sig1, &signature.sig2, &signature.sig3].iter())
+            .enumerate()
+        {
+            let mut signed = BigNum::new()?;
+            signed.mod_exp(sig, &exponent, &self.n, &mut ctx)?;
+            if &signed != *hash {
+                return Err(aerror!("Hash {} doesn't match the signature", i + 1));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl BISign {
+    /// Reads a signature from a `.bisign`.
+    pub fn read<I: Read>(input: &mut I) -> Result<BISign, ArmakeError> {
+        let name = input.read_cstring()?;
+        input.read_u32::<LittleEndian>()?;
+        read_magic(input, b"\x06\x02\x00\x00\x00\x24\x00\x00RSA1")?;
+
+        let length = input.read_u32::<LittleEndian>()?;
+        let exponent = input.read_u32::<LittleEndian>()?;
+        let n = input.read_bignum((length / 8) as usize)?;
+
+        input.read_u32::<LittleEndian>()?;
+        let sig1 = input.read_bignum((length / 8) as usize)?;
+        let version = BISignVersion::from_u32(input.read_u32::<LittleEndian>()?)?;
+        input.read_u32::<LittleEndian>()?;
+        let sig2 = input.read_bignum((length / 8) as usize)?;
+        input.read_u32::<LittleEndian>()?;
+        let sig3 = input.read_bignum((length / 8) as usize)?;
+
+        Ok(BISign {
+            version,
+            name,
+            length,
+            exponent,
+            n,
+            sig1,
+            sig2,
+            sig3,
+        })
+    }
+
+    /// Writes the signature in `.bisign` format.
+    pub fn write<O: Write>(&self, output: &mut O) -> Result<(), ArmakeError> {
+        let size = (self.length / 8) as usize;
+
+        output.write_cstring(&self.name)?;
+        output.write_u32::<LittleEndian>(self.length / 8 + 20)?;
+        output.write_all(b"\x06\x02\x00\x00\x00\x24\x00\x00RSA1")?;
+        output.write_u32::<LittleEndian>(self.length)?;
+        output.write_u32::<LittleEndian>(self.exponent)?;
+        output.write_bignum(&self.n, size)?;
+        output.write_u32::<LittleEndian>(self.length / 8)?;
+        output.write_bignum(&self.sig1, size)?;
+        output.write_u32::<LittleEndian>(self.version.to_u32())?;
+        output.write_u32::<LittleEndian>(self.length / 8)?;
+        output.write_bignum(&self.sig2, size)?;
+        output.write_u32::<LittleEndian>(self.length / 8)?;
+        output.write_bignum(&self.sig3, size)?;
+        Ok(())
+    }
+
+    /// Returns the signature as a `Cursor`.
+    pub fn to_cursor(&self) -> Result<Cursor<Vec<u8>>, ArmakeError> {
+        let mut cursor = Cursor::new(Vec::new());
+        self.write(&mut cursor)?;
+        cursor.set_position(0);
+        Ok(cursor)
+    }
+}
+
+/// Outcome of checking the signatures of a PBO against a set of keys, see `check_signatures`
+#[derive(Debug, PartialEq)]
+pub enum SignatureCheck {
+    /// Signed by one of the keys: authority and signature version
+    Valid(String, BISignVersion),
+    /// No signatures next to the PBO
+    Unsigned,
+    /// Only signed by authorities without a key
+    UnknownAuthority(Vec<String>),
+    /// None of the signatures by one of the keys match: authority and reason
+    Invalid(String, String),
+    /// The PBO or one of its signatures couldn't be read
+    Failed(String),
+}
+
+impl SignatureCheck {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, SignatureCheck::Valid(..))
+    }
+}
+
+impl std::fmt::Display for SignatureCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SignatureCheck::Valid(name, version) => write!(f, "signed by {} ({})", name, version),
+            SignatureCheck::Unsigned => f.write_str("not signed"),
+            SignatureCheck::UnknownAuthority(names) => {
+                write!(
+                    f,
+                    "only signed by unknown authorities: {}",
+                    names.join(", ")
+                )
+            }
+            SignatureCheck::Invalid(name, reason) => {
+                write!(f, "invalid signature by {}: {}", name, reason)
+            }
+            SignatureCheck::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Returns the signatures of a PBO: the `.bisign` files next to it named `<pbo>.<key>.bisign`,
+/// compared case-insensitively and sorted.
+pub fn signature_paths(pbo: &Path) -> Result<Vec<PathBuf>, ArmakeError> {
+    let prefix = format!(
+        "{}.",
+        pbo.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase()
+    );
+    let parent = match pbo.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let mut paths: Vec<PathBuf> = read_dir(parent)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| {
+            let name = p.file_name().unwrap().to_string_lossy().to_lowercase();
+            name.starts_with(&prefix) && name.ends_with(".bisign") && p.is_file()
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Checks the signatures of one PBO, see `check_signatures`.
+fn check_pbo(pbo: &Path, keys: &[BIPublicKey]) -> Result<SignatureCheck, ArmakeError> {
+    let mut signatures: Vec<(BISign, &BIPublicKey)> = Vec::new();
+    let mut unknown: Vec<String> = Vec::new();
+    for path in signature_paths(pbo)? {
+        let signature = BISign::read(&mut File::open(&path)?)
+            .map_err(|e| aerror!("Failed to read {}: {}", path.display(), e))?;
+        match keys.iter().find(|key| key.n == signature.n) {
+            Some(key) => signatures.push((signature, key)),
+            None => unknown.push(signature.name),
+        }
+    }
+
+    if signatures.is_empty() {
+        return Ok(if unknown.is_empty() {
+            SignatureCheck::Unsigned
+        } else {
+            SignatureCheck::UnknownAuthority(unknown)
+        });
+    }
+
+    let mut versions: Vec<BISignVersion> = Vec::new();
+    for (signature, _) in &signatures {
+        if !versions.contains(&signature.version) {
+            versions.push(signature.version);
+        }
+    }
+    let hashes = Hashes::read(&mut BufReader::new(File::open(pbo)?), &versions)?;
+
+    let mut invalid = None;
+    for (signature, key) in &signatures {
+        let (hash1, hash2, hash3) = hashes.padded(key.length)?;
+        let index = versions
+            .iter()
+            .position(|v| *v == signature.version)
+            .unwrap();
+        match key.verify_hashes(signature, [&hash1, &hash2, &hash3[index]]) {
+            Ok(()) => {
+                return Ok(SignatureCheck::Valid(
+                    signature.name.clone(),
+                    signature.version,
+                ))
+            }
+            Err(e) => {
+                invalid.get_or_insert_with(|| (signature.name.clone(), e.to_string()));
+            }
+        }
+    }
+    let (name, reason) = invalid.unwrap();
+    Ok(SignatureCheck::Invalid(name, reason))
+}
+
+/// Checks the signatures of the given PBOs against the keys, hashing up to `jobs` PBOs at the
+/// same time. A PBO is valid if any of its signatures (see `signature_paths`) is a valid
+/// signature by one of the keys, like servers check them.
+///
+/// Every PBO is hashed only once for all of its signatures, reading it in chunks so memory stays
+/// bounded regardless of its size. Reading one PBO overlaps with hashing the others. The results
+/// are in the order of `pbos`, regardless of the order the jobs finish in.
+pub fn check_signatures(
+    pbos: &[PathBuf],
+    keys: &[BIPublicKey],
+    jobs: usize,
+) -> Vec<SignatureCheck> {
+    let jobs = jobs.max(1).min(pbos.len());
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<SignatureCheck>>> =
+        Mutex::new((0..pbos.len()).map(|_| None).collect());
+
+    let warnings = Collector::current();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                warnings.run(|| loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let pbo = match pbos.get(index) {
+                        Some(pbo) => pbo,
+                        None => break,
+                    };
+                    let result = check_pbo(pbo, keys)
+                        .unwrap_or_else(|e| SignatureCheck::Failed(e.to_string()));
+                    results.lock().unwrap()[index] = Some(result);
+                })
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.unwrap())
+        .collect()
+}
@@ -0,0 +1,175 @@
+//! Validation of stringtable.xml files
+
+use std::collections::HashSet;
+
+use crate::lint::{config_strings, Lint};
+use crate::{aerror, ArmakeError, Config};
+
+/// Keys defined in a stringtable, along with the problems found while reading it
+#[derive(Debug, Default)]
+pub struct Stringtable {
+    /// Key IDs in order of definition
+    pub keys: Vec<String>,
+    pub lints: Vec<Lint>,
+}
+
+impl Stringtable {
+    /// Parses and validates a stringtable.
+    ///
+    /// Malformed XML is an error, structural problems, duplicate keys and empty or missing
+    /// `Original` values are reported as lints located by line.
+    pub fn read(input: &str) -> Result<Stringtable, ArmakeError> {
+        let doc = roxmltree::Document::parse(input)
+            .map_err(|e| aerror!("Failed to parse stringtable: {}", e))?;
+        let mut stringtable = Stringtable::default();
+        let mut seen = HashSet::new();
+
+        let root = doc.root_element();
+        if root.tag_name().name() != "Project" {
+            stringtable.lint(
+                &doc,
+                root,
+                "stringtable-structure",
+                format!(
+                    "root element is <{}>, expected <Project>",
+                    root.tag_name().name()
+                ),
+            );
+        }
+
+        for package in root.children().filter(|n| n.is_element()) {
+            if package.tag_name().name() != "Package" {
+                stringtable.unexpected(&doc, package, "Package");
+                continue;
+            }
+            stringtable.read_container(&doc, package, &mut seen);
+        }
+
+        Ok(stringtable)
+    }
+
+    /// Returns true if the given key (with or without leading `$`) is defined, ignoring case.
+    pub fn contains(&self, key: &str) -> bool {
+        let key = key.trim_start_matches('$');
+        self.keys.iter().any(|k| k.eq_ignore_ascii_case(key))
+    }
+
+    fn read_container(
+        &mut self,
+        doc: &roxmltree::Document,
+        container: roxmltree::Node,
+        seen: &mut HashSet<String>,
+    ) {
+        for node in container.children().filter(|n| n.is_element()) {
+            match node.tag_name().name() {
+                "Container" => self.read_container(doc, node, seen),
+                "Key" => self.read_key(doc, node, seen),
+                _ => self.unexpected(doc, node, "Container or Key"),
+            }
+        }
+    }
+
+    fn read_key(
+        &mut self,
+        doc: &roxmltree::Document,
+        key: roxmltree::Node,
+        seen: &mut HashSet<String>,
+    ) {
+        let id = match key.attribute("ID") {
+            Some(id) if !id.trim().is_empty() => id,
+            _ => {
+                self.lint(
+                    doc,
+                    key,
+                    "stringtable-structure",
+                    "key without ID".to_string(),
+                );
+                return;
+            }
+        };
+
+        if !seen.insert(id.to_lowercase()) {
+            self.lint(
+                doc,
+                key,
+                "stringtable-duplicate-key",
+                format!("key {} is defined more than once", id),
+            );
+        } else {
+            self.keys.push(id.to_string());
+        }
+
+        match key.children().find(|n| n.has_tag_name("Original")) {
+            Some(original) if original.text().map(|t| t.trim().is_empty()).unwrap_or(true) => self
+                .lint(
+                    doc,
+                    original,
+                    "stringtable-empty-original",
+                    format!("key {} has an empty Original value", id),
+                ),
+            Some(_) => {}
+            None => self.lint(
+                doc,
+                key,
+                "stringtable-empty-original",
+                format!("key {} has no Original value", id),
+            ),
+        }
+    }
+
+    fn unexpected(&mut self, doc: &roxmltree::Document, node: roxmltree::Node, expected: &str) {
+        self.lint(
+            doc,
+            node,
+            "stringtable-structure",
+            format!(
+                "unexpected <{}>, expected {}",
+                node.tag_name().name(),
+                expected
+            ),
+        );
+    }
+
+    fn lint(
+        &mut self,
+        doc: &roxmltree::Document,
+        node: roxmltree::Node,
+        name: &'static str,
+        message: String,
+    ) {
+        self.lints.push(Lint::new(
+            name,
+            format!("line {}", doc.text_pos_at(node.range().start).row),
+            message,
+        ));
+    }
+}
+
+/// Returns all `$STR` references in the config along with the class path they were found in.
+pub fn string_references(config: &Config) -> Vec<(String, String)> {
+    config_strings(config)
+        .into_iter()
+        .filter(|(_, _, value)| {
+            value.len() > 4
+                && value
+                    .get(..4)
+                    .is_some_and(|p| p.eq_ignore_ascii_case("$STR"))
+        })
+        .map(|(path, _, value)| (value[1..].to_string(), path))
+        .collect()
+}
+
+/// Checks that every `$STR` reference in the config is defined in the stringtable.
+pub fn lint_references(config: &Config, stringtable: &Stringtable) -> Vec<Lint> {
+    string_references(config)
+        .into_iter()
+        .filter(|(key, _)| !stringtable.contains(key))
+        .map(|(key, path)| {
+            Lint::new(
+                "stringtable-missing-key",
+                path,
+                format!("{} is not defined in the stringtable", key),
+            )
+        })
+        .collect()
+}
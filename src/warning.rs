@@ -0,0 +1,173 @@
+//! Named warnings raised while preprocessing, rapifying, building and linting
+//!
+//! Every warning has a name that can be suppressed with `suppress_warnings`, like `-w <name>` on
+//! the command line, `all` suppresses every warning. The preprocessor, rapifier and build raise:
+//!
+//! - `redefinition-wo-undef`: a macro is defined again without an `#undef` in between
+//! - `excessive-concatenation`: a `##` at the start or end of a macro has nothing to join
+//! - `case-insensitive-include`: an include only matches a file if case is ignored
+//! - `no-binarizer`: there is no binarizer, so files are packed without binarizing them
+//! - `overridden-header-extension`: `--header-ext` replaces a value of the prefix file
+//! - `too-many-macro-arguments`, `undefined-in-condition`, `dangling-continuation`
+//! - `unquoted-string`, `trailing-comma`, `external-class-parent`,
+//!   `mixed-array-append` in configs
+//!
+//! Lints are raised with their own names, see `lint`. Raised warnings are logged unless they are
+//! collected with `collect`, and counted for `summary`.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Names of warnings that are not printed, `all` suppresses every named warning
+static SUPPRESSED_WARNINGS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+static RAISED: AtomicUsize = AtomicUsize::new(0);
+static SUPPRESSED: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Warnings raised on this thread while `collect` runs, shared with the worker threads it
+    /// starts, see `Collector`
+    static COLLECTED: RefCell<Option<Arc<Mutex<Vec<Warning>>>>> = const { RefCell::new(None) };
+}
+
+/// Warnings collected by the `collect` surrounding a thread, which worker threads add their
+/// warnings to with `run`
+#[derive(Clone)]
+pub(crate) struct Collector(Option<Arc<Mutex<Vec<Warning>>>>);
+
+impl Collector {
+    /// Returns the collector of the current thread, which only collects warnings inside
+    /// `collect`.
+    pub(crate) fn current() -> Collector {
+        Collector(COLLECTED.with(|collected| collected.borrow().clone()))
+    }
+
+    /// Runs `f` on this thread, adding the warnings it raises to this collector.
+    pub(crate) fn run<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        let outer = COLLECTED.with(|collected| collected.replace(self.0.clone()));
+        let result = f();
+        COLLECTED.with(|collected| collected.replace(outer));
+        result
+    }
+}
+
+/// Warning with the location it was raised at
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub name: &'static str,
+    /// File the warning is about, if known
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new<S: Into<String>>(name: &'static str, message: S) -> Warning {
+        Warning {
+            name,
+            file: None,
+            line: None,
+            message: message.into(),
+        }
+    }
+
+    /// Sets the location of the warning, `?` is printed for an unknown file.
+    pub fn at(mut self, file: Option<String>, line: u32) -> Warning {
+        self.file = Some(file.unwrap_or_else(|| String::from("?")));
+        self.line = Some(line);
+        self
+    }
+
+    /// Sets the file of the warning without a line.
+    pub fn in_file<S: Into<String>>(mut self, file: S) -> Warning {
+        self.file = Some(file.into());
+        self
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "[{}:{}] ", file, line)?,
+            (Some(file), None) => write!(f, "[{}] ", file)?,
+            (None, Some(line)) => write!(f, "[?:{}] ", line)?,
+            (None, None) => {}
+        }
+        write!(f, "{} [{}]", self.message, self.name)
+    }
+}
+
+/// Suppresses the named warnings for the rest of the process.
+pub fn suppress_warnings<S: AsRef<str>>(names: &[S]) {
+    SUPPRESSED_WARNINGS
+        .write()
+        .unwrap()
+        .extend(names.iter().map(|n| n.as_ref().to_string()));
+}
+
+/// Returns true if the warning with the given name was suppressed with `suppress_warnings`.
+pub fn warning_suppressed(name: &str) -> bool {
+    SUPPRESSED_WARNINGS
+        .read()
+        .unwrap()
+        .iter()
+        .any(|s| s == name || s == "all")
+}
+
+/// Raises a warning. It is logged, or added to the warnings of the surrounding `collect`, unless
+/// it was suppressed. Returns false if it was suppressed.
+pub fn raise(warning: Warning) -> bool {
+    RAISED.fetch_add(1, Ordering::Relaxed);
+    if warning_suppressed(warning.name) {
+        SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+
+    let warning = COLLECTED.with(|collected| match collected.borrow().as_ref() {
+        Some(collected) => {
+            collected.lock().unwrap().push(warning);
+            None
+        }
+        None => Some(warning),
+    });
+    if let Some(warning) = warning {
+        warn!("{}", warning);
+    }
+    true
+}
+
+/// Runs `f` and returns its result with the warnings it raised, which are not logged.
+/// Suppressed warnings are not collected.
+///
+/// Warnings raised on worker threads, like the jobs of `binarize_all`, are collected as well, in
+/// the order they were raised. Threads `f` starts itself are not covered.
+pub fn collect<T, F: FnOnce() -> T>(f: F) -> (T, Vec<Warning>) {
+    let collector = Collector(Some(Arc::new(Mutex::new(Vec::new()))));
+    let result = collector.run(f);
+    let warnings = std::mem::take(&mut *collector.0.unwrap().lock().unwrap());
+    (result, warnings)
+}
+
+/// Returns the number of warnings raised so far and how many of them were suppressed.
+pub fn counts() -> (usize, usize) {
+    (
+        RAISED.load(Ordering::Relaxed),
+        SUPPRESSED.load(Ordering::Relaxed),
+    )
+}
+
+/// Returns a summary like "3 warnings (1 suppressed)" of the warnings raised so far, `None` if
+/// there were none.
+pub fn summary() -> Option<String> {
+    let (raised, suppressed) = counts();
+    let count = match raised {
+        0 => return None,
+        1 => String::from("1 warning"),
+        n => format!("{} warnings", n),
+    };
+    Some(match suppressed {
+        0 => count,
+        n => format!("{} ({} suppressed)", count, n),
+    })
+}
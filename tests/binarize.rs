@@ -0,0 +1,233 @@
+use std::fs::{create_dir, File};
+
+use tempfile::tempdir;
+
+use armake2::{find_model_cfg, is_animated};
+
+#[test]
+fn binarize_find_model_cfg() {
+    let dir = tempdir().unwrap();
+    let root = dir.path().join("addon");
+    let data = root.join("data");
+    create_dir(&root).unwrap();
+    create_dir(&data).unwrap();
+    File::create(root.join("model.cfg")).unwrap();
+    let model = data.join("car.p3d");
+    File::create(&model).unwrap();
+
+    assert_eq!(Some(root.join("model.cfg")), find_model_cfg(&model, &root));
+    // the search stops at the root
+    assert_eq!(None, find_model_cfg(&model, &data));
+
+    File::create(data.join("model.cfg")).unwrap();
+    assert_eq!(Some(data.join("model.cfg")), find_model_cfg(&model, &root));
+}
+
+fn mlod(tags: &[&str]) -> Vec<u8> {
+    let mut p3d = Vec::new();
+    p3d.extend(b"MLOD");
+    p3d.extend(&257u32.to_le_bytes());
+    p3d.extend(&1u32.to_le_bytes());
+    p3d.extend(b"P3DM");
+    p3d.extend(&0x1cu32.to_le_bytes());
+    p3d.extend(&0x100u32.to_le_bytes());
+    p3d.extend(&[0; 16]);
+    p3d.extend(b"TAGG");
+    for tag in tags.iter().chain(["#EndOfFile#"].iter()) {
+        p3d.push(1);
+        p3d.extend(tag.as_bytes());
+        p3d.push(0);
+        p3d.extend(&0u32.to_le_bytes());
+    }
+    p3d.extend(&1.0f32.to_le_bytes());
+    p3d
+}
+
+#[test]
+fn binarize_is_animated() {
+    assert!(!is_animated(&mlod(&["#SharpEdges#", "#UVSet#"])));
+    assert!(is_animated(&mlod(&["#UVSet#", "wheel_1_1"])));
+    assert!(is_animated(b"ODOL...\\a3\\anims\\Wheel.RTM\0"));
+    assert!(!is_animated(b"ODOL"));
+}
+
+#[cfg(unix)]
+#[test]
+fn binarize_all_failures() {
+    use std::path::PathBuf;
+
+    use armake2::{binarize_all, BinarizeOptions};
+
+    let dir = tempdir().unwrap();
+    let inputs: Vec<PathBuf> = ["a.p3d", "b.p3d", "c.p3d"]
+        .iter()
+        .map(|name| {
+            let path = dir.path().join(name);
+            File::create(&path).unwrap();
+            path
+        })
+        .collect();
+
+    // binarize.exe is not available, so every job fails
+    let options = BinarizeOptions {
+        jobs: 2,
+        fail_fast: false,
+        command: None,
+    };
+    let error = binarize_all(&inputs, dir.path(), &options)
+        .unwrap_err()
+        .to_string();
+    assert_eq!("3 of 3 files failed to binarize", error);
+
+    let options = BinarizeOptions {
+        jobs: 1,
+        fail_fast: true,
+        command: None,
+    };
+    let error = binarize_all(&inputs, dir.path(), &options)
+        .unwrap_err()
+        .to_string();
+    assert!(error.starts_with("Failed to binarize "), "{}", error);
+    assert!(error.contains("a.p3d"), "{}", error);
+}
+
+#[test]
+fn binarize_parse_library_folders() {
+    use std::path::PathBuf;
+
+    use armake2::parse_library_folders;
+
+    let vdf = r#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"C:\\Program Files (x86)\\Steam"
+		"label"		""
+		"apps"
+		{
+			"228980"		"377110426"
+		}
+	}
+	"1"
+	{
+		"path"		"D:\\SteamLibrary"
+		"apps"
+		{
+			"233800"		"19734608722"
+		}
+	}
+}
+"#;
+    assert_eq!(
+        vec![
+            PathBuf::from("C:\\Program Files (x86)\\Steam"),
+            PathBuf::from("D:\\SteamLibrary")
+        ],
+        parse_library_folders(vdf)
+    );
+
+    let old = r#"
+"LibraryFolders"
+{
+	"TimeNextStatsReport"		"1580000000"
+	"ContentStatsID"		"-123456789"
+	"1"		"E:\\Games\\Steam"
+}
+"#;
+    assert_eq!(
+        vec![PathBuf::from("E:\\Games\\Steam")],
+        parse_library_folders(old)
+    );
+    assert!(parse_library_folders("").is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn binarize_exe_override() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+
+    let dir = tempdir().unwrap();
+    std::fs::write(dir.path().join("model.p3d"), "MLOD").unwrap();
+
+    // stands in for binarize.exe, which is called with the source and target folders
+    let exe = dir.path().join("binarize.exe");
+    std::fs::write(&exe, "#!/bin/sh\nprintf ODOL > \"$6/$7\"\n").unwrap();
+    std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let binarize = |exe: &std::path::Path| {
+        Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .current_dir(dir.path())
+            .env_remove("ARMAKE_BINARIZE_CMD")
+            .env("ARMAKE_BINARIZE", exe)
+            .args(["binarize", "-f", "model.p3d", "out.p3d"])
+            .output()
+            .unwrap()
+    };
+
+    let output = binarize(&exe);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("Binarizing with {}", exe.display())),
+        "{}",
+        stderr
+    );
+    assert_eq!(
+        "ODOL",
+        std::fs::read_to_string(dir.path().join("out.p3d")).unwrap()
+    );
+
+    let output = binarize(&dir.path().join("missing.exe"));
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("doesn't exist"), "{}", stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("No binarizer found"), "{}", stdout);
+}
+
+#[cfg(unix)]
+#[test]
+fn binarize_all_collects_warnings() {
+    use std::path::PathBuf;
+
+    use armake2::{binarize_all, BinarizeOptions};
+
+    let dir = tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("model.cfg"),
+        "class CfgModels { class Default { sections[] = {camo,}; }; };",
+    )
+    .unwrap();
+    let inputs: Vec<PathBuf> = ["a.p3d", "b.p3d"]
+        .iter()
+        .map(|name| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, "MLOD").unwrap();
+            path
+        })
+        .collect();
+
+    // the binarizer fails, the model.cfg of every job is read on a worker thread before that
+    let options = BinarizeOptions {
+        jobs: 2,
+        fail_fast: false,
+        command: Some(String::from("false")),
+    };
+    let (result, warnings) =
+        armake2::warning::collect(|| binarize_all(&inputs, dir.path(), &options));
+    assert!(result.is_err());
+    // the jobs run in parallel, so the warnings of both may be interleaved
+    let mut names: Vec<&str> = warnings.iter().map(|w| w.name).collect();
+    names.sort();
+    assert_eq!(
+        vec![
+            "trailing-comma",
+            "trailing-comma",
+            "unquoted-string",
+            "unquoted-string"
+        ],
+        names
+    );
+}
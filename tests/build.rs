@@ -0,0 +1,699 @@
+use std::fs::{create_dir_all, read_to_string, write};
+use std::process::Command;
+
+use tempfile::tempdir;
+
+#[test]
+fn build_dep_file() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("my addon");
+    create_dir_all(&source).unwrap();
+    write(source.join("$PBOPREFIX$"), "x\\test").unwrap();
+    write(
+        source.join("config.cpp"),
+        "#include \"common.hpp\"\nclass CfgPatches {};\n",
+    )
+    .unwrap();
+    write(source.join("common.hpp"), "#define X 1\n").unwrap();
+    write(source.join("script.sqf"), "hint 'hello';").unwrap();
+    write(source.join("notes.txt"), "excluded").unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args([
+            "build",
+            "-x",
+            "*.txt",
+            "--dep-file",
+            "addon.d",
+            "--dep-base",
+            ".",
+        ])
+        .arg(&source)
+        .arg("addon.pbo")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let rule = read_to_string(dir.path().join("addon.d")).unwrap();
+    let (target, dependencies) = rule.split_at(rule.find(':').unwrap());
+    assert_eq!("addon.pbo", target);
+    let mut dependencies: Vec<&str> = dependencies[1..]
+        .split(" \\\n")
+        .map(|d| d.trim())
+        .filter(|d| !d.is_empty())
+        .collect();
+    dependencies.sort_unstable();
+    assert_eq!(
+        vec![
+            "my\\ addon/$$PBOPREFIX$$",
+            "my\\ addon/common.hpp",
+            "my\\ addon/config.cpp",
+            "my\\ addon/script.sqf",
+        ],
+        dependencies
+    );
+
+    // absolute paths without --dep-base
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["build", "-f", "--dep-file", "addon.d"])
+        .arg(&source)
+        .arg("addon.pbo")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let rule = read_to_string(dir.path().join("addon.d")).unwrap();
+    let absolute = dir.path().canonicalize().unwrap();
+    assert!(rule.starts_with(&format!("{}/addon.pbo:", absolute.display())));
+    assert!(rule.contains(&format!("{}/my\\ addon/common.hpp", absolute.display())));
+}
+
+#[test]
+fn build_target_in_source() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(&source).unwrap();
+    write(source.join("$PBOPREFIX$"), "x\\test").unwrap();
+    write(source.join("script.sqf"), "hint 'hello';").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["build", "addon", "./addon/../addon/addon.pbo"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("inside the source folder"));
+    assert!(!source.join("addon.pbo").exists());
+
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["build", "-x", "*.pbo", "addon", "addon/addon.pbo"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(source.join("addon.pbo").exists());
+}
+
+#[test]
+fn build_force() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(&source).unwrap();
+    write(source.join("script.sqf"), "hint 'hello';").unwrap();
+    let target = dir.path().join("addon.pbo");
+    write(&target, "old").unwrap();
+
+    let build = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .args(args)
+            .arg(&source)
+            .arg(&target)
+            .output()
+            .unwrap()
+    };
+    let output = build(&["build"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("use -f to overwrite it"));
+    assert_eq!("old", read_to_string(&target).unwrap());
+
+    assert!(build(&["build", "-f"]).status.success());
+    assert!(build(&["--force", "pack"]).status.success());
+
+    // unpacking needs -f for a folder that isn't empty
+    let folder = dir.path().join("extracted");
+    create_dir_all(&folder).unwrap();
+    let unpack = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .args(args)
+            .arg(&target)
+            .arg(&folder)
+            .output()
+            .unwrap()
+    };
+    assert!(unpack(&["unpack"]).status.success());
+    let output = unpack(&["unpack"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("is not empty"));
+    assert!(unpack(&["unpack", "-f"]).status.success());
+}
+
+#[test]
+fn build_unreadable_include() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(&source).unwrap();
+    write(
+        source.join("config.cpp"),
+        "#include \"strings.hpp\"\nclass CfgPatches {};\n",
+    )
+    .unwrap();
+    // cp1252 umlaut, not valid UTF-8
+    write(source.join("strings.hpp"), b"#define NAME \"M\xfcller\"\n").unwrap();
+
+    for command in ["build", "rapify"].iter() {
+        let mut args = vec![*command];
+        let source = if *command == "build" {
+            source.clone()
+        } else {
+            source.join("config.cpp")
+        };
+        args.push(source.to_str().unwrap());
+        let target = dir.path().join("out");
+        args.push(target.to_str().unwrap());
+        let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .args(&args)
+            .arg("-f")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("Failed to read the included file") && stdout.contains("strings.hpp"),
+            "{}",
+            stdout
+        );
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("panicked"));
+    }
+}
+
+#[test]
+fn build_rapify_nested() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(source.join("sub").join("data")).unwrap();
+    write(source.join("config.cpp"), "class CfgPatches {};\n").unwrap();
+    write(
+        source.join("sub").join("config.cpp"),
+        "class CfgVehicles {};\n",
+    )
+    .unwrap();
+    write(source.join("model.cfg"), "class CfgModels {};\n").unwrap();
+    write(
+        source.join("sub").join("model.cfg"),
+        "class CfgSkeletons {};\n",
+    )
+    .unwrap();
+    write(
+        source.join("sub").join("data").join("metal.rvmat"),
+        "class Stage1 { texture = \"metal_nohq.paa\"; };\n",
+    )
+    .unwrap();
+    let target = dir.path().join("addon.pbo");
+
+    let build = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .arg("build")
+            .arg("-f")
+            .args(args)
+            .arg(&source)
+            .arg(&target)
+            .output()
+            .unwrap()
+    };
+    let entry = |name: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .arg("cat")
+            .arg(&target)
+            .arg(name)
+            .arg("-")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", name);
+        output.stdout
+    };
+
+    assert!(build(&[]).status.success());
+    for name in [
+        "config.bin",
+        "sub\\config.bin",
+        "model.cfg",
+        "sub\\model.cfg",
+        "sub\\data\\metal.rvmat",
+    ]
+    .iter()
+    {
+        assert!(entry(name).starts_with(b"\0raP"), "{}", name);
+    }
+
+    assert!(build(&["--no-rapify", "*.rvmat"]).status.success());
+    assert!(entry("sub\\data\\metal.rvmat").starts_with(b"class Stage1"));
+    assert!(entry("sub\\config.bin").starts_with(b"\0raP"));
+
+    write(
+        source.join("sub").join("data").join("metal.rvmat"),
+        "class Stage1 { texture = \"metal_nohq.paa\" }\n",
+    )
+    .unwrap();
+    let output = build(&[]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("metal.rvmat"), "{}", stdout);
+    assert!(build(&["--no-rapify", "sub\\data\\*"]).status.success());
+}
+
+#[test]
+fn build_nobin() {
+    let dir = tempdir().unwrap();
+    for marker in ["$NOBIN$", "$nobin-notest$"].iter() {
+        let source = dir.path().join("addon");
+        create_dir_all(source.join("data")).unwrap();
+        let config = "class CfgPatches { class test {}; };\n";
+        write(source.join("config.cpp"), config).unwrap();
+        write(source.join("data").join("metal.rvmat"), "unparsable {").unwrap();
+        write(source.join(marker), "").unwrap();
+        let target = dir.path().join("addon.pbo");
+
+        let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .args(["build", "-f", "--rule", "*.sqf=rapify"])
+            .arg(&source)
+            .arg(&target)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{}", marker);
+
+        let pbo = armake2::PBO::read(&mut std::fs::File::open(&target).unwrap()).unwrap();
+        let names: Vec<&String> = pbo.files.keys().collect();
+        assert_eq!(vec!["config.cpp", "data\\metal.rvmat"], names, "{}", marker);
+        assert_eq!(config.as_bytes(), &pbo.files["config.cpp"].get_ref()[..]);
+        std::fs::remove_dir_all(&source).unwrap();
+    }
+}
+
+#[test]
+fn build_exclude_globs() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(source.join(".git").join("objects")).unwrap();
+    create_dir_all(source.join("functions")).unwrap();
+    write(source.join(".git").join("config"), "[core]").unwrap();
+    write(source.join(".git").join("objects").join("ab"), "x").unwrap();
+    write(source.join("functions").join("fn_init.sqf"), "true").unwrap();
+    write(source.join("functions").join("fn_init.sqf.BAK"), "false").unwrap();
+    let target = dir.path().join("addon.pbo");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["build", "-x", "*.bak", "-x", "**/.git/*"])
+        .arg(&source)
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let pbo = armake2::PBO::read(&mut std::fs::File::open(&target).unwrap()).unwrap();
+    let names: Vec<&String> = pbo.files.keys().collect();
+    assert_eq!(vec!["functions\\fn_init.sqf"], names);
+}
+
+#[test]
+fn build_defines() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(&source).unwrap();
+    write(
+        source.join("config.cpp"),
+        "#ifdef DEBUG\ndebug = 1;\n#endif\n#ifndef RELEASE\nrelease = 0;\n#endif\nvalues[] = VALUES;\nname = NAME;\n",
+    )
+    .unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["build", "-D", "DEBUG", "-D", "VALUES={1,2,3}"])
+        .args(["-D", "NAME=\"f(a, b)\""])
+        .arg(&source)
+        .arg("addon.pbo")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["cat", "addon.pbo", "config.bin", "config.bin"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["derapify", "config.bin", "config.cpp"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let config = read_to_string(dir.path().join("config.cpp")).unwrap();
+    assert!(config.contains("debug = 1;"), "{}", config);
+    assert!(config.contains("release = 0;"), "{}", config);
+    assert!(config.contains("values[] = {1, 2, 3};"), "{}", config);
+    assert!(config.contains("name = \"f(a, b)\";"), "{}", config);
+}
+
+#[cfg(unix)]
+#[test]
+fn build_binarize_cmd() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(&source).unwrap();
+    write(source.join("model.p3d"), "MLOD").unwrap();
+    write(source.join("script.sqf"), "hint 'hello';").unwrap();
+
+    let script = dir.path().join("binarize.sh");
+    write(
+        &script,
+        "#!/bin/sh\necho \"binarizing $1\"\n[ -n \"$FAIL\" ] && exit 3\nprintf ODOL > \"$2\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let build = |command: Option<String>, fail: bool| {
+        let mut build = Command::new(env!("CARGO_BIN_EXE_armake2"));
+        build
+            .current_dir(dir.path())
+            .env_remove("ARMAKE_BINARIZE_CMD")
+            .env_remove("ARMAKE_BINARIZE")
+            .args(["build", "-f", "addon", "addon.pbo"]);
+        if let Some(command) = command {
+            build.arg("--binarize-cmd").arg(command);
+        }
+        if fail {
+            build.env("FAIL", "1");
+        }
+        build.output().unwrap()
+    };
+    let unpack = || {
+        let target = dir.path().join("unpacked");
+        let _ = std::fs::remove_dir_all(&target);
+        let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .arg("unpack")
+            .arg(dir.path().join("addon.pbo"))
+            .arg(&target)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        read_to_string(target.join("model.p3d")).unwrap()
+    };
+
+    let command = format!("{} {{source}} {{target}}", script.display());
+    let output = build(Some(command.clone()), false);
+    assert!(output.status.success());
+    assert_eq!("ODOL", unpack());
+
+    // without a binarizer the model is packed as it is
+    let output = build(None, false);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("packing MLOD as-is"), "{}", stderr);
+    assert!(stderr.contains("[no-binarizer]"), "{}", stderr);
+    assert_eq!("MLOD", unpack());
+
+    // the exit code of the command fails the build and its output is shown
+    let output = build(Some(command), true);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stdout.contains("1 of 1 files failed to binarize"),
+        "{}",
+        stdout
+    );
+    assert!(stderr.contains("exit code: 3"), "{}", stderr);
+    assert!(stderr.contains("[model.p3d] binarizing "), "{}", stderr);
+
+    let output = build(Some(format!("{} {{source}}", script.display())), false);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("placeholders"), "{}", stderr);
+}
+
+#[test]
+fn build_mod() {
+    let dir = tempdir().unwrap();
+    let addons = dir.path().join("mod").join("addons");
+    for name in ["main", "weapons", "vehicles"].iter() {
+        create_dir_all(addons.join(name)).unwrap();
+        write(
+            addons.join(name).join("config.cpp"),
+            format!(
+                "#include \"\\x\\common.hpp\"\nclass CfgPatches {{ class {} {{ units[] = {{}}; }}; }};\n",
+                name
+            ),
+        )
+        .unwrap();
+    }
+    create_dir_all(addons.join(".hidden")).unwrap();
+    let include = dir.path().join("include");
+    create_dir_all(include.join("x")).unwrap();
+    write(include.join("x").join("common.hpp"), "#define X 1\n").unwrap();
+
+    let build_mod = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .current_dir(dir.path())
+            .arg("build-mod")
+            .args(args)
+            .args(["-i", "include", "--threads", "2", "mod", "out"])
+            .output()
+            .unwrap()
+    };
+
+    let output = build_mod(&[]);
+    assert!(output.status.success(), "{:?}", output);
+    let mut built: Vec<String> = std::fs::read_dir(dir.path().join("out").join("addons"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    built.sort();
+    assert_eq!(vec!["main.pbo", "vehicles.pbo", "weapons.pbo"], built);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Built 3 of 3 addons"), "{}", stderr);
+
+    // existing PBOs are only overwritten with -f
+    assert!(!build_mod(&[]).status.success());
+
+    write(
+        addons.join("vehicles").join("config.cpp"),
+        "class CfgPatches {",
+    )
+    .unwrap();
+    let output = build_mod(&["-f"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("1 of 3 addons failed to build:"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("vehicles: "), "{}", stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Built 2 of 3 addons"), "{}", stderr);
+}
+
+#[cfg(feature = "signing")]
+#[test]
+fn build_mod_sign() {
+    let dir = tempdir().unwrap();
+    let addons = dir.path().join("mod").join("addons");
+    for name in ["main", "weapons"].iter() {
+        create_dir_all(addons.join(name)).unwrap();
+        write(addons.join(name).join("script.sqf"), "hint 'hello';").unwrap();
+    }
+
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["keygen", "test"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["build-mod", "-k", "test.biprivatekey", "mod", "out"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    for name in ["main", "weapons"].iter() {
+        let pbo = dir
+            .path()
+            .join("out")
+            .join("addons")
+            .join(format!("{}.pbo", name));
+        let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .current_dir(dir.path())
+            .arg("verify")
+            .arg("test.bikey")
+            .arg(&pbo)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+    }
+}
+
+#[test]
+fn build_failure_keeps_target() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(&source).unwrap();
+    write(source.join("config.cpp"), "class CfgPatches {};\n").unwrap();
+
+    let build = || {
+        Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .current_dir(dir.path())
+            .args(["build", "-f", "addon", "addon.pbo"])
+            .output()
+            .unwrap()
+    };
+    assert!(build().status.success());
+    let built = std::fs::read(dir.path().join("addon.pbo")).unwrap();
+
+    write(source.join("config.cpp"), "class CfgPatches {\n").unwrap();
+    assert!(!build().status.success());
+    assert_eq!(built, std::fs::read(dir.path().join("addon.pbo")).unwrap());
+    let files: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(2, files.len(), "{:?}", files);
+
+    // without a previous PBO, nothing is left behind
+    std::fs::remove_file(dir.path().join("addon.pbo")).unwrap();
+    assert!(!build().status.success());
+    assert!(!dir.path().join("addon.pbo").exists());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["pack", "addon", "missing/addon.pbo"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("the folder missing doesn't exist"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn build_header_ext() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("my_addon");
+    create_dir_all(&source).unwrap();
+    write(source.join("script.sqf"), "hint 'hi';").unwrap();
+    write(
+        source.join("$PBOPREFIX$"),
+        "z\\my_mod\\addons\\main\r\nversion=1.0\r\n",
+    )
+    .unwrap();
+
+    let target = dir.path().join("main.pbo");
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .env("SOURCE_DATE_EPOCH", "1500000000")
+        .arg("build")
+        .args([
+            "-e",
+            "version=1.1",
+            "-e",
+            "git = abc123",
+            "-e",
+            "built={date} {dir} {timestamp}",
+        ])
+        .arg(&source)
+        .arg(&target)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("version=1.0 is overridden with version=1.1 [overridden-header-extension]"));
+
+    let pbo = armake2::PBO::read(&mut std::fs::File::open(&target).unwrap()).unwrap();
+    assert_eq!("z\\my_mod\\addons\\main", pbo.header_extensions["prefix"]);
+    assert_eq!("1.1", pbo.header_extensions["version"]);
+    assert_eq!("abc123", pbo.header_extensions["git"]);
+    assert_eq!(
+        "2017-07-14 my_addon 1500000000",
+        pbo.header_extensions["built"]
+    );
+    assert_eq!(
+        vec!["prefix", "version", "git", "built"],
+        pbo.header_extensions.keys().collect::<Vec<_>>()
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["pack", "-f", "-e", "x={unknown}"])
+        .arg(&source)
+        .arg(&target)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Unknown placeholder {unknown}"));
+}
+
+#[test]
+fn build_default_excludes() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(source.join(".git")).unwrap();
+    create_dir_all(source.join("data")).unwrap();
+    write(source.join(".git").join("HEAD"), "ref: refs/heads/main").unwrap();
+    write(source.join("data").join("Thumbs.db"), "x").unwrap();
+    write(source.join("data").join(".DS_Store"), "x").unwrap();
+    write(source.join("script.sqf"), "true").unwrap();
+    write(source.join("script.sqf~"), "false").unwrap();
+    write(source.join(".pboignore"), "*.txt\n").unwrap();
+    write(source.join("notes.txt"), "x").unwrap();
+    let target = dir.path().join("addon.pbo");
+
+    for command in ["build", "pack"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .args(["-v", command, "-f"])
+            .arg(&source)
+            .arg(&target)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("5 file(s) excluded"));
+        let pbo = armake2::PBO::read(&mut std::fs::File::open(&target).unwrap()).unwrap();
+        let names: Vec<&String> = pbo.files.keys().collect();
+        assert_eq!(vec!["script.sqf"], names);
+    }
+
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["pack", "-f", "--no-default-excludes"])
+        .arg(&source)
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let pbo = armake2::PBO::read(&mut std::fs::File::open(&target).unwrap()).unwrap();
+    let mut names: Vec<&String> = pbo.files.keys().collect();
+    names.sort();
+    assert_eq!(
+        vec![
+            ".git\\HEAD",
+            "data\\.DS_Store",
+            "data\\Thumbs.db",
+            "script.sqf",
+            "script.sqf~"
+        ],
+        names
+    );
+}
+
+#[test]
+fn build_current_folder() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("my_addon");
+    create_dir_all(&source).unwrap();
+    write(source.join("script.sqf"), "hint 'hi';").unwrap();
+
+    for command in ["build", "pack"] {
+        let target = dir.path().join(format!("{}.pbo", command));
+        let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .current_dir(&source)
+            .args([command, "."])
+            .arg(&target)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let pbo = armake2::PBO::read(&mut std::fs::File::open(&target).unwrap()).unwrap();
+        assert_eq!(Some("my_addon"), pbo.header_extension("prefix"));
+    }
+}
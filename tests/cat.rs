@@ -0,0 +1,209 @@
+use std::fs::{create_dir, read, File};
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::process::Command;
+
+use tempfile::tempdir;
+
+use armake2::{Config, PBO};
+
+fn write_pbo(dir: &Path) -> std::path::PathBuf {
+    let source = dir.join("addon");
+    create_dir(&source).unwrap();
+    create_dir(source.join("data")).unwrap();
+    File::create(source.join("data").join("file.txt"))
+        .unwrap()
+        .write_all(b"hello world")
+        .unwrap();
+
+    let path = dir.join("addon.pbo");
+    PBO::from_directory(source, false, &[], &[])
+        .unwrap()
+        .write(&mut File::create(&path).unwrap())
+        .unwrap();
+    path
+}
+
+#[test]
+fn cat_output_selection() {
+    let dir = tempdir().unwrap();
+    let pbo = write_pbo(dir.path());
+    let cat = |target: Option<&str>| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_armake2"));
+        command
+            .current_dir(dir.path())
+            .arg("cat")
+            .arg(&pbo)
+            .arg("data\\file.txt");
+        if let Some(target) = target {
+            command.arg(target);
+        }
+        command.output().unwrap()
+    };
+
+    // stdout is a pipe here, so no target streams to it like before
+    let output = cat(None);
+    assert!(output.status.success());
+    assert_eq!(b"hello world", &output.stdout[..]);
+    assert!(!dir.path().join("file.txt").exists());
+
+    let output = cat(Some("-"));
+    assert!(output.status.success());
+    assert_eq!(b"hello world", &output.stdout[..]);
+
+    let output = cat(Some("out.txt"));
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        b"hello world",
+        &read(dir.path().join("out.txt")).unwrap()[..]
+    );
+}
+
+/// Rapified config whose only entry has an unknown type
+const BROKEN: &[u8] = b"\0raP\0\0\0\0\x08\0\0\0\0\0\0\0\0\x01\x09";
+
+#[test]
+fn cat_derap() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    let material = Config::read(
+        &mut Cursor::new("ambient[] = {1, 1, 1, 1};\nclass Stage1 { texture = \"a.paa\"; };\n"),
+        None,
+        &[],
+        |_| String::new(),
+    )
+    .unwrap()
+    .to_cursor()
+    .unwrap();
+    File::create(source.join("mat.rvmat"))
+        .unwrap()
+        .write_all(material.get_ref())
+        .unwrap();
+    File::create(source.join("broken.rvmat"))
+        .unwrap()
+        .write_all(BROKEN)
+        .unwrap();
+    File::create(source.join("notes.txt"))
+        .unwrap()
+        .write_all(b"hello world")
+        .unwrap();
+    let pbo = dir.path().join("addon.pbo");
+    PBO::from_directory(source, false, &[], &[])
+        .unwrap()
+        .write(&mut File::create(&pbo).unwrap())
+        .unwrap();
+
+    let cat = |name: &str| {
+        Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .arg("cat")
+            .arg(&pbo)
+            .arg(name)
+            .arg("--derap")
+            .output()
+            .unwrap()
+    };
+
+    let output = cat("mat.rvmat");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("ambient[] = {1, 1, 1, 1};"), "{}", stdout);
+    assert!(stdout.contains("class Stage1"), "{}", stdout);
+
+    let output = cat("notes.txt");
+    assert!(output.status.success());
+    assert_eq!(b"hello world", &output.stdout[..]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not rapified"));
+
+    let output = cat("broken.rvmat");
+    assert!(output.status.success());
+    assert_eq!(BROKEN, &output.stdout[..]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("failed to derapify"));
+}
+
+#[test]
+fn cat_unknown_packing_method() {
+    let dir = tempdir().unwrap();
+    let path = write_pbo(dir.path());
+    let mut pbo = PBO::read(&mut File::open(&path).unwrap()).unwrap();
+    pbo.headers[0].packing_method = 0x1234_5678;
+    pbo.write(&mut File::create(&path).unwrap()).unwrap();
+
+    // kept through a rewrite
+    let pbo = PBO::read(&mut File::open(&path).unwrap()).unwrap();
+    assert_eq!(0x1234_5678, pbo.headers[0].packing_method);
+    let mut rewritten = Vec::new();
+    pbo.write(&mut rewritten).unwrap();
+    assert_eq!(read(&path).unwrap(), rewritten);
+
+    let cat = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .arg("cat")
+            .args(args)
+            .arg(&path)
+            .args(["data\\file.txt", "-"])
+            .output()
+            .unwrap()
+    };
+    let output = cat(&[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("--raw"));
+    let output = cat(&["--raw"]);
+    assert!(output.status.success());
+    assert_eq!(b"hello world", &output.stdout[..]);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("inspect")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("0x12345678"));
+}
+
+#[test]
+fn cat_lookup() {
+    let dir = tempdir().unwrap();
+    let path = write_pbo(dir.path());
+    let cat = |name: &str| {
+        Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .arg("cat")
+            .arg(&path)
+            .args([name, "-"])
+            .output()
+            .unwrap()
+    };
+
+    let output = cat("Data/FILE.txt");
+    assert!(output.status.success());
+    assert_eq!(b"hello world", &output.stdout[..]);
+
+    let output = cat("data\\fiel.txt");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("similar entries: data\\file.txt"),
+        "{}",
+        stdout
+    );
+
+    let output = cat("other.sqf");
+    assert!(!output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("similar"));
+
+    // an exact match wins over entries that only differ in case
+    let mut pbo = PBO::read(&mut File::open(&path).unwrap()).unwrap();
+    pbo.files.insert(
+        "Data\\File.txt".to_string(),
+        Cursor::new(b"other".to_vec().into_boxed_slice()),
+    );
+    assert_eq!(Some("Data\\File.txt"), pbo.retrieve("Data\\File.txt"));
+    assert_eq!(Some("data\\file.txt"), pbo.retrieve("data/file.txt"));
+    assert_eq!(Some("data\\file.txt"), pbo.retrieve("DATA\\FILE.TXT"));
+    assert_eq!(None, pbo.retrieve("file.txt"));
+    assert_eq!(
+        vec!["data\\file.txt", "Data\\File.txt"],
+        pbo.similar_entries("file.txt", 5)
+    );
+}
@@ -0,0 +1,292 @@
+use std::fs::File;
+
+use tempfile::tempdir;
+
+use armake2::commands::{app, commands};
+
+#[test]
+fn cli_invocations() {
+    let dir = tempdir().unwrap();
+    let folder = dir.path().to_str().unwrap();
+    let file = dir.path().join("config.cpp");
+    File::create(&file).unwrap();
+    let file = file.to_str().unwrap();
+
+    let invocations: Vec<(&str, Vec<&str>, bool)> = vec![
+        ("build", vec!["build", folder, "out.pbo"], true),
+        (
+            "build",
+            vec!["build", "-z", "--no-compress", "*.sqf", folder, "out.pbo"],
+            true,
+        ),
+        (
+            "build",
+            vec!["build", "--no-compress", "*.sqf", folder, "out.pbo"],
+            false,
+        ),
+        (
+            "build",
+            vec!["build", "--no-checksum", folder, "out.pbo"],
+            true,
+        ),
+        (
+            "build",
+            vec!["build", "--timestamp", "1500000000", folder, "out.pbo"],
+            true,
+        ),
+        (
+            "build",
+            vec!["build", "--timestamp", "-1", folder, "out.pbo"],
+            false,
+        ),
+        (
+            "build",
+            vec!["build", "--rule", "*.hpp=rapify", "-v", folder, "out.pbo"],
+            true,
+        ),
+        (
+            "build",
+            vec!["build", "--rule", "*.png=shrink", folder, "out.pbo"],
+            false,
+        ),
+        (
+            "build",
+            vec!["build", "-j", "4", "--fail-fast", folder, "out.pbo"],
+            true,
+        ),
+        (
+            "build",
+            vec!["build", "--jobs", "0", folder, "out.pbo"],
+            false,
+        ),
+        (
+            "build",
+            vec![
+                "build", "-i", folder, "-x", "*.txt", "-x", "*.md", "-h", "a=b", "-h", "c=d",
+                folder, "out.pbo",
+            ],
+            true,
+        ),
+        (
+            "build",
+            vec!["build", folder, "out.pbo", "-x", "*.txt"],
+            true,
+        ),
+        (
+            "build",
+            vec!["build", "-h", "noequals", folder, "out.pbo"],
+            false,
+        ),
+        (
+            "build",
+            vec!["build", "-h", "=value", folder, "out.pbo"],
+            false,
+        ),
+        ("build", vec!["build", "/does/not/exist", "out.pbo"], false),
+        ("build", vec!["build", folder], false),
+        ("pack", vec!["pack", folder], true),
+        (
+            "pack",
+            vec!["pack", "-h", "prefix=x\\y", folder, "out.pbo"],
+            true,
+        ),
+        ("pack", vec!["pack", "--bogus", folder, "out.pbo"], false),
+        (
+            "pack",
+            vec![
+                "pack",
+                "--files-from",
+                "-",
+                "-0",
+                "--prefix",
+                "x",
+                "out.pbo",
+            ],
+            true,
+        ),
+        ("pack", vec!["pack", "--prefix", "x", folder], false),
+        ("inspect", vec!["inspect", "mod.pbo"], true),
+        ("cat", vec!["cat", "mod.pbo", "config.bin"], true),
+        ("cat", vec!["cat", "mod.pbo", "config.bin", "out.bin"], true),
+        ("unpack", vec!["unpack", "mod.pbo", "out"], true),
+        (
+            "unpack",
+            vec!["unpack", "--zip", "mod.pbo", "out.zip"],
+            true,
+        ),
+        (
+            "unpack",
+            vec!["unpack", "--manifest", "m.json", "mod.pbo", "out"],
+            true,
+        ),
+        (
+            "unpack",
+            vec!["unpack", "--no-manifest", "mod.pbo", "out"],
+            true,
+        ),
+        ("unpack", vec!["unpack", "a.pbo", "b.pbo", "out"], true),
+        ("unpack", vec!["unpack", "a.pbo"], false),
+        ("unpack", vec!["unpack", "--order", "mod.pbo", "out"], true),
+        (
+            "unpack",
+            vec!["unpack", "--order", "--zip", "mod.pbo", "out.zip"],
+            false,
+        ),
+        (
+            "unpack",
+            vec![
+                "unpack",
+                "--zip",
+                "--manifest",
+                "m.json",
+                "mod.pbo",
+                "out.zip",
+            ],
+            false,
+        ),
+        (
+            "build",
+            vec!["build", "--hash-manifest", folder, "out.pbo"],
+            true,
+        ),
+        ("verify-manifest", vec!["verify-manifest", "mod.pbo"], true),
+        ("rapify", vec!["rapify", file, "config.bin"], true),
+        ("rapify", vec!["rapify", "-i", folder, file], true),
+        (
+            "rapify",
+            vec!["rapify", "-i", "/does/not/exist", file],
+            false,
+        ),
+        (
+            "derapify",
+            vec!["derapify", "config.bin", "config.cpp"],
+            true,
+        ),
+        (
+            "rapify",
+            vec![
+                "rapify",
+                "-D",
+                "FOO",
+                "-D",
+                "BAR=2",
+                "--undef",
+                "__ARMA3__",
+                "--debug-defines",
+                file,
+            ],
+            true,
+        ),
+        ("rapify", vec!["rapify", "-D", "=2", file], false),
+        (
+            "derapify",
+            vec![
+                "derapify",
+                "--annotate",
+                "--stats",
+                "config.bin",
+                "config.cpp",
+            ],
+            true,
+        ),
+        (
+            "preprocess",
+            vec![
+                "preprocess",
+                file,
+                "--trace-macro",
+                "A",
+                "--trace-macro",
+                "B",
+            ],
+            true,
+        ),
+        ("preprocess", vec!["preprocess", file, "--normalize"], true),
+        (
+            "preprocess",
+            vec![
+                "preprocess",
+                file,
+                "--compare",
+                file,
+                "--max-differences",
+                "3",
+            ],
+            true,
+        ),
+        (
+            "preprocess",
+            vec!["preprocess", file, "--compare", file, "--normalize"],
+            false,
+        ),
+        (
+            "preprocess",
+            vec!["preprocess", file, "--max-differences", "3"],
+            false,
+        ),
+        (
+            "lint",
+            vec!["lint", file, "-w", "required-addons-unknown"],
+            true,
+        ),
+        ("lint", vec!["lint", "/does/not/exist.cpp"], false),
+        ("lint", vec!["lint", file, "--max-errors", "5"], true),
+        ("rapify", vec!["rapify", file, "--max-errors", "0"], false),
+        (
+            "config",
+            vec!["config", "get", file, "CfgVehicles/*", "--json", "--values"],
+            true,
+        ),
+        ("config", vec!["config", "get", file], false),
+        (
+            "config",
+            vec!["config", "locate", file, "CfgPatches/my_addon"],
+            true,
+        ),
+        (
+            "rapify",
+            vec![
+                "rapify",
+                file,
+                "--source-map",
+                "map.json",
+                "--no-preprocess",
+            ],
+            false,
+        ),
+        (
+            "stringtable",
+            vec!["stringtable", "check", file, "-c", file],
+            true,
+        ),
+        ("stringtable", vec!["stringtable", "frobnicate"], false),
+        ("binarize", vec!["binarize", "model.p3d", "out.p3d"], true),
+    ];
+
+    let commands = commands();
+    for (name, args, valid) in invocations {
+        let args: Vec<&str> = std::iter::once("armake2").chain(args).collect();
+        let result = app(&commands).get_matches_from_safe(&args);
+        assert_eq!(valid, result.is_ok(), "{:?}: {:?}", args, result.err());
+        if let Ok(matches) = result {
+            assert_eq!(name, matches.subcommand_name().unwrap());
+        }
+    }
+}
+
+#[test]
+fn cli_subcommand_help() {
+    let commands = commands();
+    for command in commands.iter() {
+        let name = command.register().get_name().to_string();
+        let error = app(&commands)
+            .get_matches_from_safe(vec!["armake2", &name, "--help"])
+            .unwrap_err();
+        assert_eq!(clap::ErrorKind::HelpDisplayed, error.kind);
+        assert!(
+            error.message.contains(&format!("armake2-{}", name)),
+            "{}",
+            error.message
+        );
+    }
+}
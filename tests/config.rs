@@ -1,6 +1,7 @@
-use std::io::{Cursor, Seek, SeekFrom};
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
 
-use armake2::Config;
+use armake2::{Config, ConfigValue, Encoding, SourceMap};
 
 #[test]
 fn config_read() {
@@ -26,7 +27,15 @@ class CfgPatches {
     );
     let mut cursor = Cursor::new(input);
 
-    let mut config = Config::read(&mut cursor, None, &Vec::new()).unwrap();
+    let mut config = Config::read(&mut cursor, None, &Vec::new(), |path| {
+        let mut content = String::new();
+        File::open(path)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    })
+    .unwrap();
 
     let mut rapified = config.to_cursor().unwrap();
     rapified.seek(SeekFrom::Start(0)).unwrap();
@@ -51,3 +60,1094 @@ class CfgPatches {
         output.trim()
     );
 }
+
+#[test]
+fn config_value_roundtrip() {
+    let value = ConfigValue::Class {
+        parent: String::new(),
+        entries: vec![
+            (
+                String::from("CfgPatches"),
+                ConfigValue::Class {
+                    parent: String::new(),
+                    entries: vec![(
+                        String::from("ace_frag"),
+                        ConfigValue::Class {
+                            parent: String::new(),
+                            entries: vec![
+                                (String::from("units"), ConfigValue::Array(Vec::new())),
+                                (String::from("requiredVersion"), ConfigValue::Float(1.56)),
+                                (
+                                    String::from("requiredAddons"),
+                                    ConfigValue::Array(vec![ConfigValue::String(String::from(
+                                        "ace_common",
+                                    ))]),
+                                ),
+                                (
+                                    String::from("versionAr"),
+                                    ConfigValue::Array(vec![
+                                        ConfigValue::Int(3),
+                                        ConfigValue::Array(vec![ConfigValue::Float(0.5)]),
+                                    ]),
+                                ),
+                            ],
+                        },
+                    )],
+                },
+            ),
+            (String::from("CfgVehicles"), ConfigValue::Extern),
+            (
+                String::from("CfgWeapons"),
+                ConfigValue::Class {
+                    parent: String::from("CfgVehicles"),
+                    entries: vec![(
+                        String::from("magazines"),
+                        ConfigValue::Expansion(vec![ConfigValue::String(String::from("foo"))]),
+                    )],
+                },
+            ),
+        ],
+    };
+
+    let config = Config::from_value(value.clone()).unwrap();
+    let mut rapified = config.to_cursor().unwrap();
+    rapified.seek(SeekFrom::Start(0)).unwrap();
+    let derapified = Config::read_rapified(&mut rapified).unwrap();
+
+    assert_eq!(value, derapified.to_value());
+
+    let json = serde_json::to_string(&derapified.to_value()).unwrap();
+    let deserialized: ConfigValue = serde_json::from_str(&json).unwrap();
+    assert_eq!(value, deserialized);
+}
+
+#[test]
+fn config_read_unpreprocessed() {
+    let mut cursor = Cursor::new("foo = \"QUOTE(bar)\";\nbaz[] = {1, 2};");
+    let config = Config::read_unpreprocessed(&mut cursor, None).unwrap();
+    assert_eq!(
+        "foo = \"QUOTE(bar)\";\nbaz[] = {1, 2};",
+        config.to_string().unwrap().trim()
+    );
+
+    let mut cursor = Cursor::new("#define FOO 1\nfoo = FOO;");
+    assert!(Config::read_unpreprocessed(&mut cursor, None).is_err());
+}
+
+#[test]
+fn config_encoding() {
+    let input = b"class Foo {\n    name = \"Caf\xe9 \x80\";\n};\n";
+    let decoded = Encoding::Cp1252.decode(input).unwrap();
+    assert!(decoded.contains("Café €"));
+
+    let config = Config::from_string(decoded, None, &Vec::new(), |_| unreachable!()).unwrap();
+    let mut output = Vec::new();
+    config.write_encoded(&mut output, Encoding::Cp1252).unwrap();
+    assert_eq!(&input[..], &output[..]);
+
+    let mut output = Vec::new();
+    config.write_encoded(&mut output, Encoding::Utf8).unwrap();
+    assert_eq!(
+        "class Foo {\n    name = \"Café €\";\n};\n",
+        String::from_utf8(output).unwrap()
+    );
+
+    let config = Config::from_string(
+        String::from("class Foo { class Bar { list[] = {\"ok\", \"日本\"}; }; };"),
+        None,
+        &Vec::new(),
+        |_| unreachable!(),
+    )
+    .unwrap();
+    let mut output = Vec::new();
+    let error = config
+        .write_encoded(&mut output, Encoding::Cp1252)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("Foo >> Bar >> list"), "{}", error);
+    assert!(output.is_empty());
+}
+
+#[test]
+fn config_trailing_comma() {
+    let config = Config::from_string(
+        String::from("foo[] = {1, {2, {3,}, }, \"four\",};\nbar[] = {};"),
+        None,
+        &Vec::new(),
+        |_| unreachable!(),
+    )
+    .unwrap();
+    assert_eq!(
+        "foo[] = {1, {2, {3}}, \"four\"};\nbar[] = {};\n",
+        config.to_string().unwrap()
+    );
+
+    for input in &["foo[] = {,};", "foo[] = {1,,};", "foo[] = {{,}};"] {
+        assert!(
+            Config::from_string(String::from(*input), None, &Vec::new(), |_| unreachable!())
+                .is_err(),
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn config_trailing_comma_warning() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("config.cpp");
+    std::fs::write(&source, "foo[] = {1, 2,};\n").unwrap();
+
+    let rapify = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .args(["rapify", "-f"])
+            .args(args)
+            .arg(&source)
+            .arg(dir.path().join("config.bin"))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    let stderr = rapify(&[]);
+    assert!(
+        stderr.contains(":1] Array has a trailing comma. [trailing-comma]"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.ends_with("\n1 warning\n"), "{}", stderr);
+    assert_eq!(
+        "1 warning (1 suppressed)\n",
+        rapify(&["-w", "trailing-comma"])
+    );
+    assert_eq!("1 warning (1 suppressed)\n", rapify(&["-w", "all"]));
+}
+
+#[test]
+fn config_strings_verbatim() {
+    let input = String::from(
+        "\
+#define FOO bar
+#define QUOTE(x) #x
+#define ID(x) x
+class Test {
+    condition = \"speed this   >  5 && {FOO} == \"\"a  b\"\"\";
+    statement = \"if (true) then { hint 'FOO'; };   // not a comment\";
+    action = \"[_this, \"\"FOO\"\", QUOTE(x)] call { /* FOO */ };\";
+    expression = ID(\"_x = [1, (2)]; FOO ##FOO\");
+    list[] = {\"FOO  bar\", \"{ }\", \"x##y\", \"#FOO\", \"\\\\FOO\\\\\"};
+};
+",
+    );
+    let expected = vec![
+        "speed this   >  5 && {FOO} == \"a  b\"",
+        "if (true) then { hint 'FOO'; };   // not a comment",
+        "[_this, \"FOO\", QUOTE(x)] call { /* FOO */ };",
+        "_x = [1, (2)]; FOO ##FOO",
+        "FOO  bar",
+        "{ }",
+        "x##y",
+        "#FOO",
+        "\\\\FOO\\\\",
+    ];
+
+    let strings = |config: &Config| -> Vec<String> {
+        armake2::lint::config_strings(config)
+            .into_iter()
+            .map(|(_, _, value)| value.to_string())
+            .collect()
+    };
+
+    let config = Config::from_string(input, None, &Vec::new(), |_| unreachable!()).unwrap();
+    assert_eq!(expected, strings(&config));
+
+    let rapified = config.to_cursor().unwrap().into_inner();
+    let config = Config::read_rapified(&mut Cursor::new(rapified.clone())).unwrap();
+    assert_eq!(expected, strings(&config));
+
+    let derapified = config.to_string().unwrap();
+    let config = Config::from_string(derapified, None, &Vec::new(), |_| unreachable!()).unwrap();
+    assert_eq!(rapified, config.to_cursor().unwrap().into_inner());
+}
+
+#[test]
+fn config_external_classes() {
+    let input = String::from(
+        "\
+class CfgPatches;
+class Extended_PreInit_EventHandlers;
+class Base;
+class Derived: Base;
+class Vehicle: Base {
+    class Turret;
+    class Turret2: Turret {};
+};
+",
+    );
+    let (config, warnings) = armake2::warning::collect(|| {
+        Config::from_string(input, None, &Vec::new(), |_| unreachable!()).unwrap()
+    });
+    assert_eq!(1, warnings.len());
+    assert_eq!("external-class-parent", warnings[0].name);
+    assert_eq!(Some(4), warnings[0].line);
+
+    let rapified = config.to_cursor().unwrap().into_inner();
+    let reread = Config::read_rapified(&mut Cursor::new(rapified)).unwrap();
+    assert_eq!(
+        "\
+class CfgPatches;
+class Extended_PreInit_EventHandlers;
+class Base;
+class Derived;
+class Vehicle: Base {
+    class Turret;
+    class Turret2: Turret {};
+};
+",
+        reread.to_string().unwrap()
+    );
+}
+
+#[test]
+fn config_array_append() {
+    // class CfgPatches { class a { units[] += {"x"}; }; }; as written by the BI tools: entry
+    // type 5 followed by the flags 1
+    let rapified: &[u8] = b"\0raP\0\0\0\0\x08\0\0\0\x3c\0\0\0\
+        \0\x01\0CfgPatches\0\x22\0\0\0\
+        \0\x01\0a\0\x2b\0\0\0\
+        \0\x01\x05\x01\0\0\0units\0\x01\0x\0\
+        \0\0\0\0";
+    let input = "\
+class CfgPatches {
+    class a {
+        units[] += {\"x\"};
+    };
+};
+";
+
+    let config = Config::read_rapified(&mut Cursor::new(rapified)).unwrap();
+    assert_eq!(input, config.to_string().unwrap());
+    let config =
+        Config::from_string(String::from(input), None, &Vec::new(), |_| unreachable!()).unwrap();
+    assert_eq!(rapified, &config.to_cursor().unwrap().into_inner()[..]);
+
+    let (_, warnings) = armake2::warning::collect(|| {
+        Config::from_string(
+            String::from(
+                "class A {\n    list[] = {1};\n    List[] += {2};\n    other[] += {3};\n};\n",
+            ),
+            None,
+            &Vec::new(),
+            |_| unreachable!(),
+        )
+        .unwrap()
+    });
+    assert_eq!(1, warnings.len());
+    assert_eq!("mixed-array-append", warnings[0].name);
+    assert_eq!(Some(1), warnings[0].line);
+    assert!(warnings[0]
+        .message
+        .contains("list[] is both assigned and appended to in class A"));
+}
+
+#[test]
+fn config_number_literals() {
+    let input = "\
+a = 1e-05;
+b = 0xFF0000;
+c = .5;
+d = -0.0001;
+e = 1e10;
+f = -0xFF;
+g = 0xFFFFFFFF;
+h = 3000000000;
+i = 2147483647;
+j = -2147483648;
+k = 1E+3;
+l[] = {1e-05, 0XFF, .5, -2147483649};
+m = 0x123456789;
+";
+    let config =
+        Config::from_string(String::from(input), None, &Vec::new(), |_| unreachable!()).unwrap();
+    let entries = match config.to_value() {
+        ConfigValue::Class { entries, .. } => entries,
+        _ => unreachable!(),
+    };
+    let values: Vec<ConfigValue> = entries.into_iter().map(|(_, v)| v).collect();
+    assert_eq!(
+        vec![
+            ConfigValue::Float(1e-5),
+            ConfigValue::Int(0xFF0000),
+            ConfigValue::Float(0.5),
+            ConfigValue::Float(-0.0001),
+            ConfigValue::Float(1e10),
+            ConfigValue::Int(-255),
+            ConfigValue::Int(-1),
+            ConfigValue::Float(3e9),
+            ConfigValue::Int(i32::MAX),
+            ConfigValue::Int(i32::MIN),
+            ConfigValue::Float(1000.0),
+            ConfigValue::Array(vec![
+                ConfigValue::Float(1e-5),
+                ConfigValue::Int(255),
+                ConfigValue::Float(0.5),
+                ConfigValue::Float(-2147483649.0),
+            ]),
+            // too large for an int, kept as written
+            ConfigValue::String(String::from("0x123456789")),
+        ],
+        values
+    );
+
+    // floats are written in their shortest form that reads back the same
+    let rapified = config.to_cursor().unwrap().into_inner();
+    let derapified = Config::read_rapified(&mut Cursor::new(rapified.clone()))
+        .unwrap()
+        .to_string()
+        .unwrap();
+    assert!(derapified.starts_with("a = 1e-5;\n"), "{}", derapified);
+    let reread = Config::from_string(derapified, None, &Vec::new(), |_| unreachable!()).unwrap();
+    assert_eq!(rapified, reread.to_cursor().unwrap().into_inner());
+}
+
+#[test]
+fn config_delete_roundtrip() {
+    let input = String::from(
+        "\
+class CfgVehicles {
+    class Car;
+    delete Old;
+    class New: Car {
+        delete Wheels;
+        x = 1;
+    };
+};
+delete Top;
+",
+    );
+    let config = Config::from_string(input.clone(), None, &Vec::new(), |_| unreachable!()).unwrap();
+    let rapified = config.to_cursor().unwrap().into_inner();
+
+    let reread = Config::read_rapified(&mut Cursor::new(rapified.clone())).unwrap();
+    assert_eq!(input, reread.to_string().unwrap());
+
+    let mut output = Vec::new();
+    Config::derapify(&mut Cursor::new(rapified), &mut output, Encoding::Utf8).unwrap();
+    assert_eq!(input.into_bytes(), output);
+}
+
+#[test]
+fn config_derapify_streaming() {
+    let input = String::from(
+        "\
+class Empty {};
+class Base;
+class Outer {
+    delete Old;
+    class Inner: Base {
+        name = \"Caf\u{e9} \"\"quoted\"\"\";
+        list[] = {1, 2.5, {\"x\"}};
+        more[] += {\"y\"};
+    };
+    class Other {};
+    value = 3;
+};
+",
+    );
+    let config = Config::from_string(input, None, &Vec::new(), |_| unreachable!()).unwrap();
+    let rapified = config.to_cursor().unwrap().into_inner();
+
+    let reread = Config::read_rapified(&mut Cursor::new(rapified.clone())).unwrap();
+    let mut expected = Vec::new();
+    reread
+        .write_encoded(&mut expected, Encoding::Cp1252)
+        .unwrap();
+
+    let mut output = Vec::new();
+    Config::derapify(
+        &mut Cursor::new(rapified.clone()),
+        &mut output,
+        Encoding::Cp1252,
+    )
+    .unwrap();
+    assert_eq!(expected, output);
+
+    let mut output = Vec::new();
+    Config::derapify(&mut Cursor::new(rapified), &mut output, Encoding::Utf8).unwrap();
+    assert_eq!(reread.to_string().unwrap().into_bytes(), output);
+
+    let config = Config::from_string(
+        String::from("class A { class B { x = \"\u{65e5}\"; }; };"),
+        None,
+        &Vec::new(),
+        |_| unreachable!(),
+    )
+    .unwrap();
+    let mut output = Vec::new();
+    let error = Config::derapify(
+        &mut Cursor::new(config.to_cursor().unwrap().into_inner()),
+        &mut output,
+        Encoding::Cp1252,
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(error.starts_with("A >> B >> x: "), "{}", error);
+    assert!(output.is_empty());
+}
+
+#[test]
+fn config_derapify_annotated() {
+    let input = String::from(
+        "\
+class Outer {
+    class Empty {};
+    class Inner: Empty {
+        value = 3;
+        list[] += {1, \"a\"};
+    };
+    class Base;
+};
+name = \"x\";
+",
+    );
+    let config = Config::from_string(input, None, &Vec::new(), |_| unreachable!()).unwrap();
+    let rapified = config.to_cursor().unwrap().into_inner();
+
+    let mut output = Vec::new();
+    Config::derapify_annotated(
+        &mut Cursor::new(rapified.clone()),
+        &mut output,
+        Encoding::Utf8,
+    )
+    .unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    let offsets: Vec<&str> = output
+        .lines()
+        .filter_map(|l| l.split(" // @").nth(1))
+        .collect();
+    assert_eq!(7, offsets.len(), "{}", output);
+    for offset in offsets {
+        let offset = offset.split(" -> ").next().unwrap();
+        let offset = usize::from_str_radix(offset.trim_start_matches("0x"), 16).unwrap();
+        assert!(offset < rapified.len());
+    }
+
+    let outer = output.lines().next().unwrap();
+    assert!(outer.starts_with("class Outer { // @0x"), "{}", outer);
+    let body = outer.split(" -> 0x").nth(1).unwrap();
+    let body = usize::from_str_radix(body, 16).unwrap();
+    assert_eq!(b"\0", &rapified[body..body + 1]);
+    assert!(output.contains("    class Empty {}; // @0x"), "{}", output);
+
+    let reread = Config::from_string(output, None, &Vec::new(), |_| unreachable!()).unwrap();
+    assert_eq!(rapified, reread.to_cursor().unwrap().into_inner());
+
+    let stats = config.stats();
+    assert_eq!(3, stats.classes);
+    assert_eq!(1, stats.external_classes);
+    assert_eq!(1, stats.ints);
+    assert_eq!(1, stats.strings);
+    assert_eq!(1, stats.expansions);
+    assert_eq!(vec!["Outer", "Empty"], stats.deepest);
+}
+
+fn config_get(config: &std::path::Path, path: &str, flags: &[&str]) -> (bool, String) {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["config", "get"])
+        .arg(config)
+        .arg(path)
+        .args(flags)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn config_get_wildcards() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("config.cpp");
+    std::fs::write(
+        &source,
+        "\
+class CfgVehicles {
+    class Car;
+    class my_car: Car {
+        displayName = \"My Car\";
+        maxSpeed = 120;
+    };
+    class my_truck: my_car {};
+};
+class CfgWeapons {
+    class arifle_MX {
+        displayName = \"MX\";
+        magazines[] = {\"30Rnd_65x39\", \"100Rnd_65x39\"};
+        reloadTime = 0.1;
+    };
+};
+",
+    )
+    .unwrap();
+
+    let (success, stdout) = config_get(&source, "CfgVehicles/*", &[]);
+    assert!(success);
+    assert_eq!(
+        "Car\textern class\nmy_car\tclass\nmy_truck\tclass\n",
+        stdout
+    );
+
+    let (success, stdout) = config_get(&source, "cfgvehicles >> MY_*", &["--values"]);
+    assert!(success);
+    assert_eq!("my_car\tclass\tCar\nmy_truck\tclass\tmy_car\n", stdout);
+
+    let (success, stdout) = config_get(&source, "CfgWeapons/arifle_MX/*", &["--values"]);
+    assert!(success);
+    assert_eq!(
+        "displayName\tstring\tMX\n\
+         magazines\tarray\t{\"30Rnd_65x39\", \"100Rnd_65x39\"}\n\
+         reloadTime\tfloat\t0.1\n",
+        stdout
+    );
+
+    let (success, stdout) = config_get(&source, "CfgWeapons/arifle_MX/*", &["--json", "--values"]);
+    assert!(success);
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(
+        serde_json::json!([
+            {"name": "displayName", "type": "string", "value": "MX"},
+            {"name": "magazines", "type": "array", "value": ["30Rnd_65x39", "100Rnd_65x39"]},
+            {"name": "reloadTime", "type": "float", "value": 0.1f32},
+        ]),
+        json
+    );
+
+    let (success, stdout) = config_get(&source, "CfgWeapons/arifle_MX/magazines", &[]);
+    assert!(success);
+    assert_eq!("30Rnd_65x39\n100Rnd_65x39\n", stdout);
+
+    assert!(!config_get(&source, "CfgWeapons/arifle_MX/foo*", &[]).0);
+    assert!(!config_get(&source, "Cfg*/arifle_MX/*", &[]).0);
+    assert!(!config_get(&source, "CfgWeapons/arifle_MX", &[]).0);
+}
+
+#[test]
+fn config_get_pbo() {
+    let dir = tempfile::tempdir().unwrap();
+    let addon = dir.path().join("addon");
+    std::fs::create_dir(&addon).unwrap();
+    std::fs::write(
+        addon.join("config.cpp"),
+        "class CfgPatches { class my_addon { units[] = {\"a\", \"b\"}; requiredVersion = 1.56; }; };\n",
+    )
+    .unwrap();
+    std::fs::write(addon.join("script.sqf"), "hint \"hi\";\n").unwrap();
+
+    let pbo = dir.path().join("addon.pbo");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("build")
+        .arg(&addon)
+        .arg(&pbo)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let (success, stdout) = config_get(&pbo, "CfgPatches/my_addon/units", &[]);
+    assert!(success);
+    assert_eq!("a\nb\n", stdout);
+
+    let (success, stdout) = config_get(&pbo, "CfgPatches/my_addon/units", &["--json"]);
+    assert!(success);
+    assert_eq!("[\"a\",\"b\"]\n", stdout);
+
+    let (success, stdout) = config_get(&pbo, "CfgPatches/my_addon/requiredVersion", &[]);
+    assert!(success);
+    assert_eq!("1.56\n", stdout);
+
+    assert!(!config_get(&pbo, "CfgPatches/my_addon/missing", &[]).0);
+
+    std::fs::remove_file(addon.join("config.cpp")).unwrap();
+    let empty = dir.path().join("empty.pbo");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("pack")
+        .arg(&addon)
+        .arg(&empty)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(!config_get(&empty, "CfgPatches", &[]).0);
+}
+
+#[test]
+fn config_source_map() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("config.cpp");
+    let include = dir.path().join("version.hpp");
+    std::fs::write(
+        &source,
+        "\
+#define UNITS(x) units[] = {x}
+class CfgPatches {
+    class my_addon {
+#include \"version.hpp\"
+        UNITS(\"my_unit\");
+    };
+};
+",
+    )
+    .unwrap();
+    std::fs::write(&include, "requiredVersion = 1.56;\n").unwrap();
+
+    let map = dir.path().join("config.map.json");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("rapify")
+        .arg(&source)
+        .arg(dir.path().join("config.bin"))
+        .arg("--source-map")
+        .arg(&map)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let map = SourceMap::read(&mut File::open(&map).unwrap()).unwrap();
+    let locations: Vec<(&str, u32)> = map
+        .entries
+        .iter()
+        .map(|e| (e.path.as_str(), e.line))
+        .collect();
+    assert_eq!(
+        vec![
+            ("CfgPatches", 2),
+            ("CfgPatches/my_addon", 3),
+            ("CfgPatches/my_addon/requiredVersion", 1),
+            ("CfgPatches/my_addon/units", 5),
+        ],
+        locations
+    );
+
+    let version = map
+        .locate("cfgpatches >> MY_ADDON >> requiredversion")
+        .unwrap();
+    assert!(version.file.as_ref().unwrap().ends_with("version.hpp"));
+    let units = map.locate("CfgPatches/my_addon/units").unwrap();
+    assert!(units.file.as_ref().unwrap().ends_with("config.cpp"));
+    assert!(map.locate("CfgPatches/other").is_none());
+}
+
+fn syntax_errors(input: &str) -> String {
+    match Config::from_string(input.to_string(), None, &[], |_| String::new()) {
+        Ok(_) => panic!("config parsed"),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn config_error_recovery() {
+    assert_eq!(
+        "Config: 3 syntax errors\n\
+         \x20 [?:3] Unexpected '5', expected {\n\
+         \x20 [?:5] Unexpected '{', expected a name\n\
+         \x20 [?:9] Unexpected '7', expected {",
+        syntax_errors(
+            "\
+class A {
+    x = 1;
+    y[] = 5;
+    z = 3;
+    class B: {
+        a = 1;
+    };
+    w[] = {1, 2};
+    v[] = 7;
+};
+"
+        )
+    );
+
+    // unquoted strings may contain braces, arrays may not end their statement
+    assert_eq!(
+        "Config: Syntax error\n\x20 [?:3] { of y[] = is never closed",
+        syntax_errors("class A {\n    x = {;\n    y[] = {1, 2;\n};\n")
+    );
+
+    // the indentation points at the class that is missing its brace, not the outermost one
+    assert_eq!(
+        "Config: Syntax error\n\x20 [?:2] { of class B is never closed",
+        syntax_errors(
+            "\
+class A {
+    class B {
+        x = 1;
+    class C {
+    };
+};
+"
+        )
+    );
+
+    assert_eq!(
+        "Config: Syntax error\n\x20 [?:3] Unmatched }",
+        syntax_errors("class A {\n};\n};\n")
+    );
+}
+
+#[test]
+fn config_rapify_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = "class CfgPatches {\n    class test {};\n};\n";
+    std::fs::write(dir.path().join("config.cpp"), source).unwrap();
+
+    // ./config.cpp is the same file as config.cpp
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["rapify", "config.cpp", "./config.cpp"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("--in-place"));
+    assert_eq!(
+        source,
+        std::fs::read_to_string(dir.path().join("config.cpp")).unwrap()
+    );
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["rapify", "--in-place", "config.cpp", "./config.cpp"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let rapified = std::fs::read(dir.path().join("config.cpp")).unwrap();
+    assert_eq!(b"\0raP", &rapified[..4]);
+    let config = Config::read_rapified(&mut Cursor::new(rapified)).unwrap();
+    assert!(config.get("CfgPatches/test").is_ok());
+    assert!(!dir.path().join("config.cpp.armake2-tmp").exists());
+}
+
+#[test]
+fn config_rapify_failure_keeps_target() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("config.cpp");
+    let target = dir.path().join("config.bin");
+    std::fs::write(&target, "previous").unwrap();
+    std::fs::write(&source, "class CfgPatches {\n").unwrap();
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["rapify", "-f"])
+        .arg(&source)
+        .arg(&target)
+        .output()
+        .unwrap()
+        .status;
+    assert!(!status.success());
+    assert_eq!("previous", std::fs::read_to_string(&target).unwrap());
+    assert_eq!(2, std::fs::read_dir(dir.path()).unwrap().count());
+
+    std::fs::write(&source, "class CfgPatches {};\n").unwrap();
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["rapify", "-f"])
+        .arg(&source)
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(std::fs::read(&target).unwrap().starts_with(b"\0raP"));
+    assert_eq!(2, std::fs::read_dir(dir.path()).unwrap().count());
+}
+
+#[test]
+fn config_rapify_deps() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("include")).unwrap();
+    std::fs::write(
+        dir.path().join("config.cpp"),
+        "#include \"macros.hpp\"\n#include \"\\common.hpp\"\nclass CfgPatches { class ADDON {}; };\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("macros.hpp"), "#include \"\\common.hpp\"\n").unwrap();
+    std::fs::write(
+        dir.path().join("include").join("common.hpp"),
+        "#define ADDON test\n",
+    )
+    .unwrap();
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args([
+            "rapify",
+            "-i",
+            "include",
+            "--deps",
+            "config.d",
+            "--dep-base",
+            ".",
+        ])
+        .args(["config.cpp", "config.bin"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(
+        "config.bin: \\\n  config.cpp \\\n  macros.hpp \\\n  include/common.hpp\n",
+        std::fs::read_to_string(dir.path().join("config.d")).unwrap()
+    );
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["preprocess", "-i", "include", "--deps", "config.d"])
+        .args(["--deps-format", "lines", "config.cpp"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("class CfgPatches"));
+    let absolute = dir.path().canonicalize().unwrap();
+    assert_eq!(
+        format!(
+            "{0}/config.cpp\n{0}/macros.hpp\n{0}/include/common.hpp\n",
+            absolute.display()
+        ),
+        std::fs::read_to_string(dir.path().join("config.d")).unwrap()
+    );
+
+    // a Makefile rule needs a target
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args([
+            "preprocess",
+            "-i",
+            "include",
+            "--deps",
+            "other.d",
+            "config.cpp",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("--deps-format lines"));
+    assert!(!dir.path().join("other.d").exists());
+}
+
+#[test]
+fn config_derapify_class() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = Config::from_string(
+        String::from(
+            "\
+class CfgVehicles {
+    class Man;
+    class B_Soldier_F: Man {
+        class Inner { x = 1; };
+        y = 2;
+    };
+    class Other: Man {};
+};
+class CfgWeapons {};
+z = 3;
+",
+        ),
+        None,
+        &Vec::new(),
+        |_| unreachable!(),
+    )
+    .unwrap();
+    config
+        .write_rapified(&mut File::create(dir.path().join("config.bin")).unwrap())
+        .unwrap();
+
+    let derapify = |class: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .current_dir(dir.path())
+            .args(["derapify", "-f", "--class", class, "config.bin", "out.hpp"])
+            .output()
+            .unwrap()
+    };
+
+    assert!(derapify("CfgVehicles >> b_soldier_f").status.success());
+    assert_eq!(
+        "\
+class CfgVehicles {
+    class B_Soldier_F: Man {
+        class Inner {
+            x = 1;
+        };
+        y = 2;
+    };
+};
+",
+        std::fs::read_to_string(dir.path().join("out.hpp")).unwrap()
+    );
+
+    let output = derapify("CfgVehicles/B_Soldier_G");
+    assert!(!output.status.success());
+    assert_eq!(
+        "CfgVehicles >> B_Soldier_G not found, classes in CfgVehicles: Man, B_Soldier_F, Other\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let output = derapify("z");
+    assert_eq!(
+        "z is not a class\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn config_json_roundtrip() {
+    let config = Config::from_string(
+        String::from(
+            "\
+class CfgPatches {
+    class a {
+        units[] = {};
+        requiredVersion = 2.0;
+    };
+};
+class Base;
+class Derived: Base {
+    value = 1;
+    list[] += {\"x\", {1, 2.5}};
+    small = 1e-05;
+    large = 3000000000;
+    string = \"Caf\u{e9}\";
+};
+delete Old;
+class Empty {};
+",
+        ),
+        None,
+        &Vec::new(),
+        |_| unreachable!(),
+    )
+    .unwrap();
+    let rapified = config.to_cursor().unwrap().into_inner();
+
+    let mut json = Vec::new();
+    Config::read_rapified(&mut Cursor::new(rapified.clone()))
+        .unwrap()
+        .write_json(&mut json)
+        .unwrap();
+    let text = String::from_utf8(json.clone()).unwrap();
+    assert!(text.contains("\"_parent\": \"Base\""), "{}", text);
+    assert!(text.contains("\"_extern\": true"), "{}", text);
+    assert!(text.contains("\"_delete\": true"), "{}", text);
+    assert!(text.contains("\"_append\": ["), "{}", text);
+    assert!(
+        text.find("\"CfgPatches\"") < text.find("\"Base\""),
+        "{}",
+        text
+    );
+
+    let reread = Config::read_json(&mut Cursor::new(json)).unwrap();
+    assert_eq!(rapified, reread.to_cursor().unwrap().into_inner());
+
+    let error = Config::read_json(&mut Cursor::new("{\"A\": {\"_extern\": true, \"x\": 1}}"))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("can't be combined"), "{}", error);
+
+    let config = Config::from_string(
+        String::from("class A { _parent = 1; };"),
+        None,
+        &Vec::new(),
+        |_| unreachable!(),
+    )
+    .unwrap();
+    let error = config.write_json(&mut Vec::new()).unwrap_err().to_string();
+    assert_eq!(
+        "A >> _parent can't be written as JSON, its name is reserved",
+        error
+    );
+}
+
+#[test]
+fn derapify_pbo() {
+    let dir = tempfile::tempdir().unwrap();
+    let addon = dir.path().join("addon");
+    std::fs::create_dir_all(addon.join("data")).unwrap();
+    std::fs::write(
+        addon.join("config.cpp"),
+        "class CfgPatches { class my_addon { units[] = {}; }; };\n",
+    )
+    .unwrap();
+    std::fs::write(
+        addon.join("data").join("mat.rvmat"),
+        "ambient[] = {1, 1, 1, 1};\n",
+    )
+    .unwrap();
+    std::fs::write(addon.join("script.sqf"), "hint \"hi\";\n").unwrap();
+
+    let pbo = dir.path().join("addon.pbo");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("build")
+        .arg(&addon)
+        .arg(&pbo)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let target = dir.path().join("config.cpp");
+    let derapify = |extra: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .args(["derapify", "-f"])
+            .arg(&pbo)
+            .arg(&target)
+            .args(extra)
+            .output()
+            .unwrap();
+        let written = if output.status.success() {
+            std::fs::read_to_string(&target).unwrap()
+        } else {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+        (output.status.success(), written)
+    };
+
+    let (success, stdout) = derapify(&[]);
+    assert!(success, "{}", stdout);
+    assert!(stdout.contains("class my_addon"), "{}", stdout);
+
+    let (success, stdout) = derapify(&["--entry", "DATA/mat.rvmat"]);
+    assert!(success, "{}", stdout);
+    assert!(stdout.contains("ambient[] = {1, 1, 1, 1};"), "{}", stdout);
+
+    let (success, stdout) = derapify(&["--entry", "script.sqf"]);
+    assert!(!success);
+    assert!(stdout.contains("is not rapified"), "{}", stdout);
+
+    let (success, stdout) = derapify(&["--entry", "missing.rvmat"]);
+    assert!(!success);
+    assert!(
+        stdout.contains("contains no entry missing.rvmat"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn config_commands_stdin() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let run = |args: &[&str], input: &[u8]| -> Vec<u8> {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        output.stdout
+    };
+
+    let source = b"#define VALUE 42\nclass A { value = VALUE; };\n";
+    let preprocessed = String::from_utf8(run(&["preprocess"], source)).unwrap();
+    assert!(preprocessed.contains("value = 42;"));
+
+    let rapified = run(&["rapify"], source);
+    assert_eq!(b"\0raP", &rapified[..4]);
+
+    let derapified = String::from_utf8(run(&["derapify"], &rapified)).unwrap();
+    assert_eq!("class A {\n    value = 42;\n};\n", derapified);
+}
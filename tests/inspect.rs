@@ -0,0 +1,306 @@
+use std::fs::{create_dir_all, File};
+use std::io::Write;
+use std::process::Command;
+
+use sha1::{Digest, Sha1};
+use tempfile::tempdir;
+
+use armake2::pbo::{ChecksumStatus, GarbageReason};
+use armake2::{PBOHeader, PBO};
+
+#[test]
+fn inspect_filter() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    for (name, content) in [
+        ("functions/fn_a.sqf", "a"),
+        ("functions/fn_b.sqf", "bb"),
+        ("other/x.hpp", "ccc"),
+        ("other/y.paa", "dddd"),
+    ]
+    .iter()
+    {
+        let path = source.join(name);
+        create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(path)
+            .unwrap()
+            .write_all(content.as_bytes())
+            .unwrap();
+    }
+
+    let pbo = dir.path().join("addon.pbo");
+    PBO::from_directory(source, false, &[], &[])
+        .unwrap()
+        .write(&mut File::create(&pbo).unwrap())
+        .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("inspect")
+        .arg(&pbo)
+        .arg("--filter")
+        .arg("FUNCTIONS\\*")
+        .arg("--filter")
+        .arg("*.hpp")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("prefix=addon"), "{}", stdout);
+    assert!(
+        stdout.contains("showing 3 of 4 entries, 6 B of 10 B"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("functions\\fn_a.sqf"));
+    assert!(stdout.contains("other\\x.hpp"));
+    assert!(!stdout.contains("y.paa"));
+}
+
+#[test]
+fn inspect_json() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(source.join("data")).unwrap();
+    std::fs::write(source.join("$PBOPREFIX$"), "x\\addon").unwrap();
+    std::fs::write(source.join("script.sqf"), "hint 'hello';").unwrap();
+    std::fs::write(source.join("data").join("x.paa"), "texture").unwrap();
+    let pbo = dir.path().join("addon.pbo");
+    let mut data = Vec::new();
+    PBO::from_directory(source, false, &[], &[])
+        .unwrap()
+        .write(&mut data)
+        .unwrap();
+    std::fs::write(&pbo, &data).unwrap();
+
+    let inspect = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .arg("inspect")
+            .args(args)
+            .arg(&pbo)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+
+    let json = inspect(&["--json"]);
+    assert_eq!(
+        serde_json::json!({"prefix": "x\\addon"}),
+        json["header_extensions"]
+    );
+    let files = json["files"].as_array().unwrap();
+    assert_eq!(2, files.len());
+    assert_eq!("data\\x.paa", files[0]["name"]);
+    assert_eq!(0, files[0]["packing_method"]);
+    assert_eq!(7, files[0]["original_size"]);
+    assert_eq!(7, files[0]["data_size"]);
+    assert!(files[0]["timestamp"].as_u64().unwrap() > 0);
+    assert_eq!(serde_json::json!([]), json["garbage"]);
+    let checksum: String = data[data.len() - 20..]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert_eq!(checksum, json["checksum"]);
+    assert_eq!("valid", json["checksum_status"]);
+    assert_eq!(true, json["checksum_valid"]);
+
+    let json = inspect(&["--json", "--filter", "*.sqf"]);
+    let files = json["files"].as_array().unwrap();
+    assert_eq!(1, files.len());
+    assert_eq!("script.sqf", files[0]["name"]);
+
+    let offset = data.len() - 25;
+    data[offset] ^= 1;
+    std::fs::write(&pbo, &data).unwrap();
+    let json = inspect(&["--json"]);
+    assert_eq!("mismatch", json["checksum_status"]);
+    assert_eq!(false, json["checksum_valid"]);
+}
+
+#[test]
+fn inspect_checksum() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(&source).unwrap();
+    File::create(source.join("script.sqf"))
+        .unwrap()
+        .write_all(b"hint 'hello';")
+        .unwrap();
+    let mut data = Vec::new();
+    PBO::from_directory(source, false, &[], &[])
+        .unwrap()
+        .write(&mut data)
+        .unwrap();
+
+    let run = |data: &[u8], args: &[&str]| {
+        let pbo = dir.path().join("addon.pbo");
+        std::fs::write(&pbo, data).unwrap();
+        Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .args(args)
+            .arg(&pbo)
+            .args(if args[0] == "inspect" {
+                vec![]
+            } else {
+                vec!["script.sqf", "-"]
+            })
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&data, &["inspect"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Checksum: valid"));
+
+    let mut modified = data.clone();
+    let offset = modified.len() - 25;
+    modified[offset] ^= 1;
+    let output = run(&modified, &["inspect"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Checksum: doesn't match"));
+    let output = run(&modified, &["cat"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("doesn't match its content"));
+    // --no-verify only checks that there is a checksum
+    let output = run(&modified, &["cat", "--no-verify"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty(), "{:?}", output);
+    let output = run(&modified, &["cat", "--strict"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("doesn't match its content"));
+
+    let truncated = &data[..data.len() - 10];
+    let output = run(truncated, &["inspect"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Checksum: missing"));
+    let output = run(truncated, &["cat", "--no-verify"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("truncated"));
+    let target = dir.path().join("extracted");
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "--strict"])
+        .arg(dir.path().join("addon.pbo"))
+        .arg(&target)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("truncated"));
+}
+
+#[test]
+fn inspect_garbage() {
+    let header = |name: &str, method: u32, size: u32| PBOHeader {
+        filename: name.to_string(),
+        packing_method: method,
+        original_size: size,
+        reserved: 0,
+        timestamp: 0,
+        data_size: size,
+    };
+    let mut data: Vec<u8> = Vec::new();
+    header("", 0x5665_7273, 0).write(&mut data).unwrap();
+    data.write_all(b"prefix\0x\\test\0\0").unwrap();
+    header("script.sqf", 0, 5).write(&mut data).unwrap();
+    header("bad\u{1}name", 0, 3).write(&mut data).unwrap();
+    header("", 0x5665_7273, 0).write(&mut data).unwrap();
+    data.write_all(b"prefix\0other\0\0").unwrap();
+    header("after.sqf", 0, 2).write(&mut data).unwrap();
+    header("huge.bin", 0, 0x7FFF_FFFF).write(&mut data).unwrap();
+    header("", 0, 0).write(&mut data).unwrap();
+    data.write_all(b"hint!").unwrap();
+    data.write_all(b"xyz").unwrap();
+    data.write_all(b"ok").unwrap();
+    let checksum = Sha1::digest(&data);
+    data.push(0);
+    data.extend_from_slice(&checksum);
+
+    let pbo = PBO::read(&mut &data[..]).unwrap();
+    let names: Vec<&String> = pbo.files.keys().collect();
+    assert_eq!(vec!["script.sqf", "after.sqf"], names);
+    assert_eq!(b"ok", &pbo.files["after.sqf"].get_ref()[..]);
+    assert_eq!("x\\test", pbo.header_extensions["prefix"]);
+    assert_eq!(Some(ChecksumStatus::Valid), pbo.checksum_status);
+    let reasons: Vec<GarbageReason> = pbo.garbage.iter().map(|g| g.reason).collect();
+    assert_eq!(
+        vec![
+            GarbageReason::MisplacedExtensions,
+            GarbageReason::ControlCharacters,
+            GarbageReason::DataPastEnd
+        ],
+        reasons
+    );
+
+    let pbo = PBO::read_keeping_garbage(&mut &data[..]).unwrap();
+    assert_eq!(b"xyz", &pbo.files["bad\u{1}name"].get_ref()[..]);
+    assert_eq!(21, pbo.files["huge.bin"].get_ref().len());
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("garbage.pbo");
+    std::fs::write(&path, &data).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("inspect")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Garbage entries"), "{}", stdout);
+    assert!(
+        stdout.contains("- \"huge.bin\" (2147483647 bytes): the data would end after the end"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("\"bad\\u{1}name\" (3 bytes)"), "{}", stdout);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Skipping the entry"));
+}
+
+#[test]
+fn inspect_packing_methods() {
+    let header = |name: &str, method: u32, size: u32| PBOHeader {
+        filename: name.to_string(),
+        packing_method: method,
+        original_size: size,
+        reserved: 0,
+        timestamp: 0,
+        data_size: size,
+    };
+    let mut data: Vec<u8> = Vec::new();
+    header("plain.txt", 0, 1).write(&mut data).unwrap();
+    header("packed.sqf", 0x4370_7273, 1)
+        .write(&mut data)
+        .unwrap();
+    header("unknown.bin", 0x0000_0200, 1)
+        .write(&mut data)
+        .unwrap();
+    header("vendor.bin", 0x456e_6372, 1)
+        .write(&mut data)
+        .unwrap();
+    header("", 0, 0).write(&mut data).unwrap();
+    data.write_all(b"abcd").unwrap();
+    let checksum = Sha1::digest(&data);
+    data.push(0);
+    data.extend_from_slice(&checksum);
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("methods.pbo");
+    std::fs::write(&path, &data).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("inspect")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let method = |name: &str| {
+        stdout
+            .lines()
+            .find(|line| line.starts_with(name))
+            .unwrap()
+            .split_whitespace()
+            .nth(1)
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!("0", method("plain.txt"));
+    assert_eq!("Cprs", method("packed.sqf"));
+    assert_eq!("0x00000200", method("unknown.bin"));
+    assert_eq!("Encr", method("vendor.bin"));
+}
@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+
+use armake2::lint::*;
+use armake2::Config;
+
+#[test]
+fn lint_cfgpatches_findings() {
+    let input = String::from(
+        "\
+class CfgPatches {
+    class my_addon {
+        requiredVersion = 156;
+        requiredAddons[] = {\"A3_Data_F\", \"\", \"my bad\", \"a3_data_f\", \"my_other\", \"cba_main\"};
+    };
+    class my_other {
+        requiredVersion = 1.56;
+        requiredAddons[] = {\"my_addon\"};
+    };
+};\n",
+    );
+    let config = Config::from_string(input, None, &Vec::new(), |path| {
+        std::fs::read_to_string(path).unwrap()
+    })
+    .unwrap();
+
+    let known: HashSet<String> = vec![String::from("A3_Data_F")].into_iter().collect();
+    let lints = lint_cfgpatches(&config, Some(&known));
+    let names: Vec<&str> = lints.iter().map(|l| l.name).collect();
+    assert_eq!(
+        vec![
+            "required-version-implausible",
+            "required-addons-empty",
+            "required-addons-invalid",
+            "required-addons-unknown",
+            "required-addons-duplicate",
+            "required-addons-unknown",
+        ],
+        names
+    );
+    assert_eq!("CfgPatches >> my_addon >> requiredVersion", lints[0].path);
+
+    assert_eq!(vec!["my_addon", "my_other"], defined_addons(&config));
+    assert_eq!(4, lint_cfgpatches(&config, None).len());
+}
+
+#[test]
+fn lint_missing_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::File::create(dir.path().join("wheel.paa")).unwrap();
+
+    let input = String::from(
+        "\
+class CfgVehicles {
+    class my_car {
+        model = \"\\x\\my_mod\\addons\\main\\car\";
+        icon = \"\\x\\my_mod\\addons\\main\\data\\icon_ca.paa\";
+        hiddenSelectionsTextures[] = {
+            \"x\\my_mod\\addons\\main\\data\\body_CO.paa\",
+            \"\\a3\\data_f\\wheel.paa\",
+            \"\\a3\\data_f\\tyre.paa\",
+            \"\\other\\thing.paa\",
+            \"#(argb,8,8,3)color(1,0,0,1)\"
+        };
+        displayName = \"My Car\";
+    };
+};\n",
+    );
+    let config = Config::from_string(input, None, &Vec::new(), |path| {
+        std::fs::read_to_string(path).unwrap()
+    })
+    .unwrap();
+
+    let entries: HashSet<String> = vec!["car.p3d", "data\\body_co.paa"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let known = vec![(String::from("a3\\data_f"), dir.path().to_path_buf())];
+    let lints = lint_paths(&config, "x\\my_mod\\addons\\main", &entries, &known);
+
+    let messages: Vec<&str> = lints.iter().map(|l| l.message.as_str()).collect();
+    assert_eq!(
+        vec![
+            "\"\\x\\my_mod\\addons\\main\\data\\icon_ca.paa\" does not exist",
+            "\"\\a3\\data_f\\tyre.paa\" does not exist",
+        ],
+        messages
+    );
+    assert_eq!("CfgVehicles >> my_car >> icon", lints[0].path);
+    assert_eq!("missing-path", lints[0].name);
+}
+
+#[test]
+fn lint_locations() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("config.cpp"),
+        "class CfgPatches {\n    #include \"addon.hpp\"\n};\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("addon.hpp"),
+        "class my_addon {\n    units[] = {};\n    requiredVersion = 156;\n};\n",
+    )
+    .unwrap();
+
+    let (config, map) = read_config_file(&dir.path().join("config.cpp"), &[]).unwrap();
+    let mut lints = lint_cfgpatches(&config, None);
+    locate(&mut lints, &map.unwrap());
+    assert_eq!(1, lints.len());
+    assert_eq!(Some(3), lints[0].line);
+    assert!(lints[0].file.as_ref().unwrap().ends_with("addon.hpp"));
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .current_dir(dir.path())
+        .args(["lint", "config.cpp"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("addon.hpp:3] "), "{}", stderr);
+    assert!(
+        stderr.contains("CfgPatches >> my_addon >> requiredVersion: requiredVersion 156"),
+        "{}",
+        stderr
+    );
+}
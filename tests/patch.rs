@@ -0,0 +1,218 @@
+use std::fs::{create_dir_all, remove_file, File};
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::process::Command;
+
+use sha1::{Digest, Sha1};
+use tempfile::tempdir;
+
+use armake2::pbo::patch::{PBOPatch, PatchEntry};
+use armake2::{PBOHeader, PBO};
+
+fn write_files(folder: &Path, files: &[(&str, &[u8])]) {
+    for (name, content) in files {
+        let path = folder.join(name);
+        create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(path).unwrap().write_all(content).unwrap();
+    }
+}
+
+fn pack(folder: &Path) -> Vec<u8> {
+    PBO::from_directory(folder.to_path_buf(), false, &[], &[])
+        .unwrap()
+        .to_cursor()
+        .unwrap()
+        .into_inner()
+}
+
+/// Writes a PBO with the entries in the given order, which `PBO::write` wouldn't keep.
+fn pack_unsorted(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut pbo = Vec::new();
+    let header = PBOHeader {
+        filename: String::new(),
+        packing_method: 0x5665_7273,
+        original_size: 0,
+        reserved: 0,
+        timestamp: 0,
+        data_size: 0,
+    };
+    header.write(&mut pbo).unwrap();
+    pbo.write_all(b"prefix\0x\\unsorted\0\0").unwrap();
+    for (name, content) in files {
+        PBOHeader {
+            filename: name.to_string(),
+            packing_method: 0,
+            original_size: content.len() as u32,
+            timestamp: 1_500_000_000,
+            data_size: content.len() as u32,
+            ..header.clone()
+        }
+        .write(&mut pbo)
+        .unwrap();
+    }
+    PBOHeader {
+        packing_method: 0,
+        ..header
+    }
+    .write(&mut pbo)
+    .unwrap();
+    for (_, content) in files {
+        pbo.write_all(content).unwrap();
+    }
+    let checksum = Sha1::digest(&pbo);
+    pbo.push(0);
+    pbo.extend_from_slice(&checksum);
+    pbo
+}
+
+fn round_trip(old: &[u8], new: &[u8]) -> PBOPatch {
+    let patch = PBOPatch::create(old, new).unwrap();
+    let mut written = Vec::new();
+    patch.write(&mut written).unwrap();
+    let patch = PBOPatch::read(&mut Cursor::new(written)).unwrap();
+    assert_eq!(new, &patch.apply(old).unwrap()[..]);
+    patch
+}
+
+#[test]
+fn patch_round_trips() {
+    let dir = tempdir().unwrap();
+    let folder = dir.path().join("addon");
+    let large = vec![7; 100_000];
+    write_files(
+        &folder,
+        &[
+            ("$PBOPREFIX$", b"x\\test\\addon"),
+            ("data/large.paa", &large),
+            ("script.sqf", b"hint 'hello';"),
+            ("other.sqf", b"hint 'other';"),
+        ],
+    );
+    let original = pack(&folder);
+
+    // identical PBOs only copy
+    let patch = round_trip(&original, &original);
+    assert!(patch
+        .entries
+        .iter()
+        .all(|e| matches!(e, PatchEntry::Copy(_))));
+
+    // changed entry, only its data is stored
+    write_files(&folder, &[("script.sqf", b"hint 'goodbye';")]);
+    let changed = pack(&folder);
+    let patch = round_trip(&original, &changed);
+    assert_eq!(
+        vec![PatchEntry::Data(b"hint 'goodbye';".to_vec())],
+        patch
+            .entries
+            .iter()
+            .filter(|e| matches!(e, PatchEntry::Data(_)))
+            .cloned()
+            .collect::<Vec<_>>()
+    );
+    assert!(patch.removed.is_empty());
+
+    // added and removed entries
+    remove_file(folder.join("other.sqf")).unwrap();
+    write_files(&folder, &[("added.sqf", b"hint 'added';")]);
+    let added = pack(&folder);
+    let patch = round_trip(&changed, &added);
+    assert_eq!(vec![String::from("other.sqf")], patch.removed);
+
+    // renamed entry with the same data
+    remove_file(folder.join("data").join("large.paa")).unwrap();
+    write_files(&folder, &[("data/renamed.paa", &large)]);
+    let renamed = pack(&folder);
+    let patch = round_trip(&added, &renamed);
+    let mut written = Vec::new();
+    patch.write(&mut written).unwrap();
+    assert!(written.len() < 1000);
+
+    // changed header extensions
+    write_files(&folder, &[("$PBOPREFIX$", b"x\\test\\other")]);
+    let prefixed = pack(&folder);
+    round_trip(&renamed, &prefixed);
+
+    // entries in an order PBO::write wouldn't produce
+    let unsorted = pack_unsorted(&[("z.sqf", b"z"), ("a.sqf", b"hint 'added';")]);
+    round_trip(&prefixed, &unsorted);
+    round_trip(&unsorted, &original);
+
+    // entries with an empty name and data, which PBO::read keeps as entries
+    let unnamed = pack_unsorted(&[("", b"data"), ("a.sqf", b"hint 'added';")]);
+    let patch = round_trip(&unsorted, &unnamed);
+    assert_eq!(2, patch.entries.len());
+    round_trip(&unnamed, &unsorted);
+}
+
+#[test]
+fn patch_wrong_base() {
+    let old = pack_unsorted(&[("a.sqf", b"a")]);
+    let new = pack_unsorted(&[("a.sqf", b"b")]);
+    let other = pack_unsorted(&[("a.sqf", b"c")]);
+    let patch = PBOPatch::create(&old, &new).unwrap();
+
+    assert!(patch
+        .apply(&other)
+        .unwrap_err()
+        .to_string()
+        .contains("does not apply"));
+
+    // content that doesn't match the checksum is refused
+    let mut corrupt = old.clone();
+    let position = corrupt.len() - 22;
+    corrupt[position] = b'x';
+    assert!(patch.apply(&corrupt).is_err());
+
+    let mut corrupt_patch = patch.clone();
+    corrupt_patch.entries = vec![PatchEntry::Data(b"x".to_vec())];
+    assert!(corrupt_patch.apply(&old).is_err());
+
+    assert!(PBOPatch::read(&mut Cursor::new(b"not a patch".to_vec())).is_err());
+}
+
+#[test]
+fn patch_command() {
+    let dir = tempdir().unwrap();
+    let old_path = dir.path().join("old.pbo");
+    let new_path = dir.path().join("new.pbo");
+    let patch_path = dir.path().join("addon.patch");
+    let out_path = dir.path().join("out.pbo");
+    std::fs::write(
+        &old_path,
+        pack_unsorted(&[("a.sqf", b"a"), ("b.sqf", b"b")]),
+    )
+    .unwrap();
+    std::fs::write(
+        &new_path,
+        pack_unsorted(&[("a.sqf", b"a"), ("c.sqf", b"c")]),
+    )
+    .unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["pbo-patch", "create"])
+        .args([&old_path, &new_path, &patch_path])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["pbo-patch", "apply"])
+        .args([&old_path, &patch_path, &out_path])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(
+        std::fs::read(&new_path).unwrap(),
+        std::fs::read(&out_path).unwrap()
+    );
+
+    // the existing output is allowed, the patch doesn't fit the PBO
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["pbo-patch", "apply", "-f"])
+        .args([&new_path, &patch_path, &out_path])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("already exists"));
+}
@@ -0,0 +1,855 @@
+use std::fs::{create_dir, File};
+use std::io::{Cursor, Write};
+
+use tempfile::tempdir;
+
+use armake2::pbo::{
+    lzss, matches_glob, parse_ignore, parse_prefix, ChecksumStatus, FileAction, FileRule,
+    FileRules, HashMismatch, HASH_ENTRY, HASH_EXTENSION,
+};
+use armake2::preprocess::PreprocessOptions;
+use armake2::{BinarizeOptions, PBO};
+
+#[test]
+fn pbo_rename() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    create_dir(source.join("data")).unwrap();
+    for name in ["tex_CO.paa", "other.paa"].iter() {
+        File::create(source.join("data").join(name))
+            .unwrap()
+            .write_all(name.as_bytes())
+            .unwrap();
+    }
+
+    let mut pbo = PBO::read(
+        &mut PBO::from_directory(source, false, &[], &[])
+            .unwrap()
+            .to_cursor()
+            .unwrap(),
+    )
+    .unwrap();
+    let checksum = pbo.checksum.clone();
+
+    assert!(pbo.rename("data\\missing.paa", "data\\x.paa").is_err());
+    assert!(pbo.rename("data\\tex_CO.paa", "data\\OTHER.paa").is_err());
+    assert!(pbo.rename("data\\tex_CO.paa", "data\\..\\x.paa").is_err());
+    assert!(pbo.rename("data\\tex_CO.paa", "\\data\\x.paa").is_err());
+
+    // case-only rename, looked up case-insensitively
+    pbo.rename("DATA\\TEX_co.PAA", "data\\tex_co.paa").unwrap();
+
+    let pbo = PBO::read(&mut pbo.to_cursor().unwrap()).unwrap();
+    let names: Vec<&String> = pbo.files.keys().collect();
+    assert_eq!(vec!["data\\other.paa", "data\\tex_co.paa"], names);
+    assert_eq!(b"tex_CO.paa", &pbo.files["data\\tex_co.paa"].get_ref()[..]);
+    assert_eq!(
+        Some("addon"),
+        pbo.header_extensions.get("prefix").map(|s| s.as_str())
+    );
+    assert_ne!(checksum, pbo.checksum);
+}
+
+#[test]
+fn pbo_empty_addon() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    for name in ["$PBOPREFIX$", "$REVISION$", "notes.txt"].iter() {
+        File::create(source.join(name))
+            .unwrap()
+            .write_all(b"x\\addon")
+            .unwrap();
+    }
+
+    assert!(!PBO::from_directory(source.clone(), false, &[], &[])
+        .unwrap()
+        .is_empty_addon());
+    assert!(PBO::from_directory(source.clone(), false, &["*.txt"], &[])
+        .unwrap()
+        .is_empty_addon());
+
+    let pack = |strict: bool| {
+        let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"));
+        command
+            .args(["pack", "-f", "-x", "*.txt"])
+            .arg(&source)
+            .arg(dir.path().join("addon.pbo"));
+        if strict {
+            command.arg("--strict");
+        }
+        command.output().unwrap()
+    };
+
+    let output = pack(false);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("contains no addon files: found 3 file(s), 1 excluded"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("1 by \"*.txt\""), "{}", stderr);
+    assert!(
+        stderr.contains("only metadata files left: $REVISION$"),
+        "{}",
+        stderr
+    );
+
+    let output = pack(true);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("empty addon"));
+}
+
+#[test]
+fn pbo_hash_manifest() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    for name in ["script.sqf", "other.sqf"].iter() {
+        File::create(source.join(name))
+            .unwrap()
+            .write_all(name.as_bytes())
+            .unwrap();
+    }
+
+    let mut pbo = PBO::from_directory(source.clone(), false, &[], &[]).unwrap();
+    assert!(pbo.verify_hash_manifest().is_err());
+    pbo.add_hash_manifest();
+    assert_eq!(
+        Some(&String::from(
+            "other.sqf:94ee80da18309b8e4baefb04be92a29681fec291;\
+             script.sqf:655fa931580dfe4a89fa66ae663207273cb59948;"
+        )),
+        pbo.header_extensions.get(HASH_EXTENSION)
+    );
+
+    let mut pbo = PBO::read(&mut pbo.to_cursor().unwrap()).unwrap();
+    assert!(pbo.verify_hash_manifest().unwrap().is_empty());
+
+    *pbo.files.get_mut("script.sqf").unwrap() = Cursor::new(b"changed".to_vec().into_boxed_slice());
+    pbo.files.remove("other.sqf");
+    pbo.files.insert(
+        String::from("new.sqf"),
+        Cursor::new(Vec::new().into_boxed_slice()),
+    );
+    assert_eq!(
+        vec![
+            HashMismatch::Modified(String::from("script.sqf")),
+            HashMismatch::Added(String::from("new.sqf")),
+            HashMismatch::Missing(String::from("other.sqf")),
+        ],
+        pbo.verify_hash_manifest().unwrap()
+    );
+
+    // manifests too long for a header extension are stored in an entry
+    for i in 0..30 {
+        File::create(source.join(format!("file_{}.sqf", i))).unwrap();
+    }
+    let mut pbo = PBO::from_directory(source, false, &[], &[]).unwrap();
+    pbo.add_hash_manifest();
+    assert!(!pbo.header_extensions.contains_key(HASH_EXTENSION));
+    assert!(pbo.files.contains_key(HASH_ENTRY));
+    let pbo = PBO::read(&mut pbo.to_cursor().unwrap()).unwrap();
+    assert!(pbo.verify_hash_manifest().unwrap().is_empty());
+}
+
+#[test]
+fn pbo_pack_files_from() {
+    use std::process::{Command, Stdio};
+
+    let dir = tempdir().unwrap();
+    std::fs::write(dir.path().join("b.sqf"), b"hint 'b';").unwrap();
+    std::fs::write(dir.path().join("a.sqf"), b"hint 'a';").unwrap();
+    std::fs::write(dir.path().join("config.cpp"), b"#define X 1\nx = X;\n").unwrap();
+    let target = dir.path().join("out.pbo");
+
+    let pack = |list: &str, flags: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .current_dir(dir.path())
+            .args(["pack", "-f"])
+            .args(flags)
+            .args(["--files-from", "-"])
+            .arg(&target)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(list.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let output = pack(
+        "scripts\\b.sqf=b.sqf\nscripts\\a.sqf=a.sqf\nconfig.cpp=config.cpp\n",
+        &["--prefix", "x\\y", "-h", "version=1.0"],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let pbo = PBO::read(&mut File::open(&target).unwrap()).unwrap();
+    let names: Vec<&str> = pbo.headers.iter().map(|h| h.filename.as_str()).collect();
+    assert_eq!(
+        vec!["scripts\\b.sqf", "scripts\\a.sqf", "config.cpp"],
+        names
+    );
+    assert_eq!("x\\y", pbo.header_extensions["prefix"]);
+    assert_eq!("1.0", pbo.header_extensions["version"]);
+    assert_eq!(
+        &b"#define X 1\nx = X;\n"[..],
+        &pbo.files["config.cpp"].get_ref()[..]
+    );
+
+    let output = pack(
+        "config.bin=config.cpp\0a.sqf=a.sqf",
+        &["-0", "--rapify-configs"],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let pbo = PBO::read(&mut File::open(&target).unwrap()).unwrap();
+    let names: Vec<&str> = pbo.headers.iter().map(|h| h.filename.as_str()).collect();
+    assert_eq!(vec!["config.bin", "a.sqf"], names);
+    assert!(pbo.files["config.bin"].get_ref().starts_with(b"\0raP"));
+    assert!(!pbo.header_extensions.contains_key("prefix"));
+
+    let output = pack("a.sqf=a.sqf\nno separator\n", &[]);
+    assert!(!output.status.success());
+    // errors are printed to stdout
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Line 2 "), "{}", stdout);
+
+    let output = pack("a.sqf=a.sqf\nA.SQF=b.sqf\n", &[]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn pbo_file_rules() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    create_dir(source.join("data")).unwrap();
+    for (name, content) in [
+        ("config.cpp", "class CfgPatches {};\n"),
+        ("data\\macros.hpp", "x = 1;\n"),
+        ("data\\art.PSD", "layers"),
+        ("data\\fn.sqf", "systemChat \"hi\";"),
+    ]
+    .iter()
+    {
+        File::create(source.join(name.replace('\\', "/")))
+            .unwrap()
+            .write_all(content.as_bytes())
+            .unwrap();
+    }
+
+    let rules = FileRules::new(vec![
+        "*.hpp=rapify".parse().unwrap(),
+        "data\\*.psd=skip".parse().unwrap(),
+        FileRule::new("*.sqf", FileAction::Copy),
+    ]);
+    let pbo = PBO::from_directory_with_dependencies(
+        source,
+        true,
+        &[],
+        &[],
+        &PreprocessOptions::default(),
+        &BinarizeOptions::default(),
+        &rules,
+        &mut Vec::new(),
+    )
+    .unwrap();
+
+    let mut names: Vec<&str> = pbo.files.keys().map(|n| n.as_str()).collect();
+    names.sort_unstable();
+    assert_eq!(
+        vec!["config.bin", "data\\fn.sqf", "data\\macros.hpp"],
+        names
+    );
+    assert!(pbo.files["data\\macros.hpp"]
+        .get_ref()
+        .starts_with(b"\0raP"));
+    assert_eq!(
+        &b"systemChat \"hi\";"[..],
+        &pbo.entry_data("data\\fn.sqf").unwrap().unwrap()[..]
+    );
+
+    assert_eq!(
+        FileAction::Rapify,
+        rules.with_defaults(true).rule("Config.CPP").unwrap().action
+    );
+    assert_eq!(
+        FileAction::Copy,
+        rules
+            .with_defaults(false)
+            .rule("config.cpp")
+            .unwrap()
+            .action
+    );
+    assert!("*.png=convert-paa".parse::<FileRule>().is_err());
+    assert!("*.png=frobnicate".parse::<FileRule>().is_err());
+    assert!("=copy".parse::<FileRule>().is_err());
+}
+
+#[test]
+fn pbo_prefix_files() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    let write = |name: &str, content: &str| {
+        File::create(source.join(name))
+            .unwrap()
+            .write_all(content.as_bytes())
+            .unwrap();
+    };
+    write("script.sqf", "hint 'hello';");
+    write("PBOPREFIX.TXT", "x\\txt\nversion=1\n");
+
+    let pbo = PBO::from_directory(source.clone(), false, &["*.txt"], &[]).unwrap();
+    let names: Vec<&String> = pbo.files.keys().collect();
+    assert_eq!(vec!["script.sqf"], names);
+    assert_eq!("x\\txt", pbo.header_extensions["prefix"]);
+    assert_eq!(
+        vec!["prefix", "version"],
+        pbo.header_extensions.keys().collect::<Vec<_>>()
+    );
+
+    // the same values in several files are fine
+    write("$prefix$", "version=1\nx\\txt\n");
+    let pbo = PBO::from_directory(source.clone(), false, &[], &[]).unwrap();
+    let names: Vec<&String> = pbo.files.keys().collect();
+    assert_eq!(vec!["script.sqf"], names);
+    assert_eq!("1", pbo.header_extensions["version"]);
+
+    write("$PBOPREFIX$", "x\\other");
+    let error = PBO::from_directory(source, false, &[], &[])
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(error.contains("conflicting"), "{}", error);
+}
+
+#[test]
+fn pbo_prefix_crlf() {
+    let extensions = parse_prefix(
+        "\u{feff}z\\my_mod\\addon \r\nversion = 1.0\r\nauthor= me =you\r\n\r\nignored=1",
+    );
+    assert_eq!(
+        vec![
+            (String::from("prefix"), String::from("z\\my_mod\\addon")),
+            (String::from("version"), String::from("1.0")),
+            (String::from("author"), String::from("me =you")),
+        ],
+        extensions
+    );
+
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    File::create(source.join("$PBOPREFIX$"))
+        .unwrap()
+        .write_all(b"\xef\xbb\xbfz\\my_mod\\addon\r\n")
+        .unwrap();
+    File::create(source.join("config.cpp"))
+        .unwrap()
+        .write_all(b"\xef\xbb\xbfclass CfgPatches {\r\n    class addon {};\r\n};\r\n")
+        .unwrap();
+    let pbo = PBO::from_directory(source, true, &[], &[]).unwrap();
+    assert_eq!("z\\my_mod\\addon", pbo.header_extensions["prefix"]);
+    assert!(pbo.files["config.bin"].get_ref().starts_with(b"\0raP"));
+}
+
+/// "   abcabcabc" compressed: a back reference before the start, three literals and a back
+/// reference overlapping its own output, followed by the checksum
+const COMPRESSED: &[u8] = b"\x0e\x05\x00abc\x03\x03\xd2\x03\x00\x00";
+
+#[test]
+fn pbo_lzss() {
+    assert_eq!(
+        b"   abcabcabc",
+        &lzss::decompress(COMPRESSED, 12).unwrap()[..]
+    );
+    assert!(lzss::decompress(&COMPRESSED[..6], 12).is_err());
+    assert!(lzss::decompress(&COMPRESSED[..8], 12).is_err());
+    let mut corrupt = COMPRESSED.to_vec();
+    corrupt[3] = b'x';
+    assert!(lzss::decompress(&corrupt, 12)
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("Checksum mismatch"));
+
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    for (name, content) in [("good.txt", COMPRESSED), ("bad.txt", &corrupt[..])].iter() {
+        File::create(source.join(name))
+            .unwrap()
+            .write_all(content)
+            .unwrap();
+    }
+    let mut pbo = PBO::read(
+        &mut PBO::from_directory(source, false, &[], &[])
+            .unwrap()
+            .to_cursor()
+            .unwrap(),
+    )
+    .unwrap();
+    for header in pbo.headers.iter_mut() {
+        header.packing_method = 0x4370_7273;
+        header.original_size = 12;
+    }
+    let path = dir.path().join("addon.pbo");
+    pbo.write(&mut File::create(&path).unwrap()).unwrap();
+
+    let pbo = PBO::read(&mut std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(
+        b"   abcabcabc",
+        &pbo.entry_data("good.txt").unwrap().unwrap()[..]
+    );
+    let error = pbo.entry_data("bad.txt").err().unwrap().to_string();
+    assert!(error.contains("bad.txt"), "{}", error);
+
+    let cat = |name: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+            .arg("cat")
+            .arg(&path)
+            .args([name, "-"])
+            .output()
+            .unwrap()
+    };
+    let output = cat("good.txt");
+    assert!(output.status.success());
+    assert_eq!(b"   abcabcabc", &output.stdout[..]);
+    let output = cat("bad.txt");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("bad.txt"));
+}
+
+#[test]
+fn pbo_compress() {
+    let text: Vec<u8> = (0..2000)
+        .flat_map(|i| format!("class Item_{} {{ scope = {}; }};\n", i % 37, i % 3).into_bytes())
+        .collect();
+    // xorshift, barely compressible
+    let mut state: u32 = 0x1234_5678;
+    let noise: Vec<u8> = (0..20_000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect();
+    for data in [&text[..], &noise[..], &[0; 10_000][..], b"ab", b""].iter() {
+        let compressed = lzss::compress(data);
+        assert_eq!(
+            *data,
+            &lzss::decompress(&compressed, data.len()).unwrap()[..]
+        );
+    }
+    assert!(lzss::compress(&text).len() < text.len() / 4);
+
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    for name in ["script.sqf", "texture.paa", "notes.txt", "unique.bin"].iter() {
+        let content: &[u8] = if *name == "unique.bin" { &noise } else { &text };
+        File::create(source.join(name))
+            .unwrap()
+            .write_all(content)
+            .unwrap();
+    }
+    let pbo_path = dir.path().join("addon.pbo");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["pack", "-z", "--no-compress", "*.TXT"])
+        .arg(&source)
+        .arg(&pbo_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let pbo = PBO::read(&mut std::fs::File::open(&pbo_path).unwrap()).unwrap();
+    let methods: Vec<(&str, u32)> = pbo
+        .headers
+        .iter()
+        .map(|h| (h.filename.as_str(), h.packing_method))
+        .collect();
+    assert_eq!(
+        vec![
+            ("notes.txt", 0),
+            ("script.sqf", 0x4370_7273),
+            ("texture.paa", 0),
+            ("unique.bin", 0)
+        ],
+        methods
+    );
+    assert_eq!(text.len() as u32, pbo.headers[1].original_size);
+    assert!(pbo.headers[1].data_size < pbo.headers[1].original_size);
+
+    // unpacking and packing again gives the same files
+    let target = dir.path().join("extracted");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "--no-manifest"])
+        .arg(&pbo_path)
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(text, std::fs::read(target.join("script.sqf")).unwrap());
+    let repacked = dir.path().join("repacked.pbo");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["pack", "-z", "--no-compress", "*.txt"])
+        .arg(&target)
+        .arg(&repacked)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let repacked = PBO::read(&mut std::fs::File::open(&repacked).unwrap()).unwrap();
+    for name in pbo.files.keys() {
+        assert_eq!(
+            pbo.entry_data(name).unwrap(),
+            repacked.entry_data(name).unwrap()
+        );
+    }
+
+    // a source file that can't be read fails with the name of its entry
+    let mut pbo = PBO::from_directory(source.clone(), false, &[], &[]).unwrap();
+    std::fs::remove_file(source.join("script.sqf")).unwrap();
+    let error = pbo.compress(&[]).unwrap_err().to_string();
+    assert!(error.contains("script.sqf"), "{}", error);
+}
+
+#[test]
+fn pbo_timestamps() {
+    std::env::remove_var("SOURCE_DATE_EPOCH");
+    let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    for name in ["config.cpp", "script.sqf"].iter() {
+        let mut file = File::create(source.join(name)).unwrap();
+        file.write_all(b"class CfgPatches {};\n").unwrap();
+        file.set_modified(modified).unwrap();
+    }
+
+    let pbo = PBO::from_directory(source.clone(), true, &[], &[]).unwrap();
+    let pbo = PBO::read(&mut pbo.to_cursor().unwrap()).unwrap();
+    let timestamps: Vec<(&str, u32)> = pbo
+        .headers
+        .iter()
+        .map(|h| (h.filename.as_str(), h.timestamp))
+        .collect();
+    assert_eq!(
+        vec![("config.bin", 1_600_000_000), ("script.sqf", 1_600_000_000)],
+        timestamps
+    );
+
+    // --timestamp overrides the modification times and SOURCE_DATE_EPOCH
+    let pbo_path = dir.path().join("addon.pbo");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["pack", "--timestamp", "1234567890"])
+        .env("SOURCE_DATE_EPOCH", "1000000000")
+        .arg(&source)
+        .arg(&pbo_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let pbo = PBO::read(&mut File::open(&pbo_path).unwrap()).unwrap();
+    assert!(pbo.headers.iter().all(|h| h.timestamp == 1_234_567_890));
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("inspect")
+        .arg(&pbo_path)
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("2009-02-13 23:31:30"));
+}
+
+#[test]
+fn pbo_index() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    create_dir(source.join("data")).unwrap();
+    File::create(source.join("$PBOPREFIX$"))
+        .unwrap()
+        .write_all(b"x\\index")
+        .unwrap();
+    let text: Vec<u8> = (0..500)
+        .flat_map(|i| format!("private _item{} = {};\n", i % 11, i).into_bytes())
+        .collect();
+    File::create(source.join("script.sqf"))
+        .unwrap()
+        .write_all(&text)
+        .unwrap();
+    File::create(source.join("data").join("notes.txt"))
+        .unwrap()
+        .write_all(b"notes")
+        .unwrap();
+
+    let mut pbo = PBO::from_directory(source, false, &[], &[]).unwrap();
+    pbo.compress(&[]).unwrap();
+    let mut data: Vec<u8> = Vec::new();
+    pbo.write(&mut data).unwrap();
+    let path = dir.path().join("addon.pbo");
+    std::fs::write(&path, &data).unwrap();
+
+    let pbo = PBO::read(&mut &data[..]).unwrap();
+    let mut file = File::open(&path).unwrap();
+    let index = PBO::read_headers(&mut file).unwrap();
+    assert_eq!(pbo.header_extensions, index.header_extensions);
+    assert_eq!(pbo.headers, index.headers().cloned().collect::<Vec<_>>());
+    assert!(pbo.header("script.sqf").unwrap().data_size < text.len() as u32);
+    for header in pbo.headers.iter() {
+        assert_eq!(
+            pbo.entry_data(&header.filename).unwrap().unwrap(),
+            &index
+                .read_file(&mut file, &header.filename)
+                .unwrap()
+                .unwrap()[..]
+        );
+        assert_eq!(
+            pbo.files[&header.filename].get_ref(),
+            &index
+                .read_raw(&mut file, &header.filename)
+                .unwrap()
+                .unwrap()
+                .into_boxed_slice()
+        );
+    }
+    assert_eq!(Some("data\\notes.txt"), index.retrieve("DATA/notes.txt"));
+    assert_eq!(None, index.read_file(&mut file, "missing.sqf").unwrap());
+    assert_eq!(
+        ChecksumStatus::Valid,
+        index.checksum_status(&mut file).unwrap()
+    );
+
+    let last = data.len() - 1;
+    data[last] ^= 0xFF;
+    let mut cursor = Cursor::new(&data[..]);
+    let index = PBO::read_headers(&mut cursor).unwrap();
+    assert_eq!(
+        ChecksumStatus::Mismatch,
+        index.checksum_status(&mut cursor).unwrap()
+    );
+    let mut cursor = Cursor::new(&data[..data.len() - 10]);
+    let index = PBO::read_headers(&mut cursor).unwrap();
+    assert_eq!(
+        ChecksumStatus::Missing,
+        index.checksum_status(&mut cursor).unwrap()
+    );
+}
+
+#[test]
+fn pbo_glob() {
+    let cases: &[(&str, &str, bool)] = &[
+        ("script.sqf", "*.sqf", true),
+        ("data\\script.sqf", "*.sqf", true),
+        ("script.sqf", "script.sqf*", true),
+        ("script.sqf", "*script.sqf", true),
+        ("script.sqf", "*.sq", false),
+        ("", "*", true),
+        ("", "?", false),
+        ("a.bak", "?.bak", true),
+        ("ab.bak", "?.bak", false),
+        ("Data\\Tex_CO.paa", "data\\*_co.paa", true),
+        ("data\\tex_co.paa", "DATA/*_CO.PAA", true),
+        (".git\\config", "**/.git/*", true),
+        ("sub\\deep\\.git\\objects\\ab", "**/.git/*", true),
+        ("sub\\my.git\\config", "**/.git/*", false),
+        ("a\\b\\c.hpp", "a\\**\\c.hpp", true),
+        ("a\\c.hpp", "a\\**\\c.hpp", true),
+        ("file1.txt", "file[0-9].txt", true),
+        ("filex.txt", "file[0-9].txt", false),
+        ("filex.txt", "file[!0-9].txt", true),
+        ("file].txt", "file[]].txt", true),
+        ("file[.txt", "file[.txt", true),
+        ("über.sqf", "*.sqf", true),
+        ("über.sqf", "?ber.sqf", true),
+        ("daten\\größe.paa", "*\\gr??e.paa", true),
+        ("ÜBER.sqf", "über*", true),
+        ("file.sqf", "*é", false),
+    ];
+    for (name, pattern, expected) in cases.iter() {
+        assert_eq!(
+            *expected,
+            matches_glob(name, pattern),
+            "{} with {}",
+            name,
+            pattern
+        );
+    }
+}
+
+#[test]
+fn pbo_streamed_entries() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    File::create(source.join("config.cpp"))
+        .unwrap()
+        .write_all(b"class CfgPatches { class addon {}; };")
+        .unwrap();
+    // larger than the copy buffer
+    let terrain: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    File::create(source.join("world.wrp"))
+        .unwrap()
+        .write_all(&terrain)
+        .unwrap();
+
+    let pbo = PBO::from_directory(source.clone(), true, &[], &[]).unwrap();
+    assert!(pbo.sources.contains_key("world.wrp"));
+    assert!(!pbo.sources.contains_key("config.bin"));
+    assert!(pbo.files["world.wrp"].get_ref().is_empty());
+    assert_eq!(
+        terrain.len() as u32,
+        pbo.header("world.wrp").unwrap().data_size
+    );
+    assert_eq!(
+        &terrain[..],
+        &pbo.entry_data("world.wrp").unwrap().unwrap()[..]
+    );
+
+    let read = PBO::read(&mut pbo.to_cursor().unwrap()).unwrap();
+    assert_eq!(Some(ChecksumStatus::Valid), read.checksum_status);
+    assert_eq!(&terrain[..], &read.files["world.wrp"].get_ref()[..]);
+
+    // the file is read again when writing
+    File::create(source.join("world.wrp"))
+        .unwrap()
+        .write_all(b"smaller")
+        .unwrap();
+    let read = PBO::read(&mut pbo.to_cursor().unwrap()).unwrap();
+    assert_eq!(Some(ChecksumStatus::Valid), read.checksum_status);
+    assert_eq!(b"smaller", &read.files["world.wrp"].get_ref()[..]);
+}
+
+#[test]
+fn pbo_header_extension_order() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    File::create(source.join("script.sqf"))
+        .unwrap()
+        .write_all(b"hint 'hi';")
+        .unwrap();
+    File::create(source.join("$PBOPREFIX$"))
+        .unwrap()
+        .write_all(b"zulu=1\nVersion=2\nPrefix=z\\mod\\addon\nalpha=3\nmikero=4\n")
+        .unwrap();
+
+    let pbo = PBO::from_directory(source.clone(), false, &[], &[]).unwrap();
+    let keys: Vec<&String> = pbo.header_extensions.keys().collect();
+    assert_eq!(vec!["prefix", "zulu", "Version", "alpha", "mikero"], keys);
+    assert_eq!(Some("2"), pbo.header_extension("version"));
+
+    // repeated builds have the same header section
+    let first = pbo.to_cursor().unwrap().into_inner();
+    for _ in 0..5 {
+        let again = PBO::from_directory(source.clone(), false, &[], &[])
+            .unwrap()
+            .to_cursor()
+            .unwrap()
+            .into_inner();
+        assert_eq!(first, again);
+    }
+
+    // a stored key in another case is found, and set in place
+    let mut pbo = PBO::read(&mut Cursor::new(first)).unwrap();
+    pbo.header_extensions.remove("prefix");
+    pbo.header_extensions
+        .insert(String::from("PREFIX"), String::from("z\\mod\\other"));
+    assert_eq!(Some("z\\mod\\other"), pbo.header_extension("prefix"));
+    assert_eq!(
+        Some(String::from("2")),
+        pbo.set_header_extension("VERSION", "3")
+    );
+    assert_eq!(None, pbo.set_header_extension("build", "7"));
+    let pbo = PBO::read(&mut pbo.to_cursor().unwrap()).unwrap();
+    let values: Vec<(&str, &str)> = pbo
+        .header_extensions
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            ("zulu", "1"),
+            ("Version", "3"),
+            ("alpha", "3"),
+            ("mikero", "4"),
+            ("PREFIX", "z\\mod\\other"),
+            ("build", "7"),
+        ],
+        values
+    );
+}
+
+#[test]
+fn pbo_ignore_file() {
+    let patterns = parse_ignore(
+        "\u{feff}# textures\r\n*.psd\r\n\r\n/tools/\nThumbs.db\ndocs/*.md\n",
+        ".pboignore",
+    )
+    .unwrap();
+    assert_eq!(
+        vec![
+            "**/*.psd",
+            "**/*.psd/*",
+            "tools/*",
+            "**/Thumbs.db",
+            "**/Thumbs.db/*",
+            "docs/*.md",
+            "docs/*.md/*",
+        ],
+        patterns
+    );
+    let error = parse_ignore("*.psd\n!keep.psd\n", ".pboignore").unwrap_err();
+    assert!(error.to_string().contains(".pboignore:2"));
+
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir(&source).unwrap();
+    create_dir(source.join("tools")).unwrap();
+    create_dir(source.join("data")).unwrap();
+    create_dir(source.join("data").join("tools")).unwrap();
+    for name in [
+        "script.sqf",
+        "logo.psd",
+        "tools/make.py",
+        "data/tools/keep.paa",
+        "data/raw.xcf",
+        "data/notes.txt",
+    ] {
+        File::create(source.join(name))
+            .unwrap()
+            .write_all(b"x")
+            .unwrap();
+    }
+    File::create(source.join(".pboignore"))
+        .unwrap()
+        .write_all(b"*.psd\n/tools/\n")
+        .unwrap();
+
+    let mut dependencies = Vec::new();
+    let pbo = PBO::from_directory_with_dependencies(
+        source.clone(),
+        false,
+        &["*.xcf"],
+        &[],
+        &PreprocessOptions::default(),
+        &BinarizeOptions::default(),
+        &FileRules::default(),
+        &mut dependencies,
+    )
+    .unwrap();
+    let mut names: Vec<&String> = pbo.files.keys().collect();
+    names.sort();
+    assert_eq!(
+        vec!["data\\notes.txt", "data\\tools\\keep.paa", "script.sqf"],
+        names
+    );
+    assert!(dependencies.contains(&source.join(".pboignore")));
+}
@@ -121,9 +121,7 @@ bar_foo\n",
         .write_all(prefix.as_bytes())
         .unwrap();
 
-    let includepath = PathBuf::from(addondir.join("include.h"))
-        .canonicalize()
-        .unwrap();
+    let includepath = addondir.join("include.h").canonicalize().unwrap();
 
     let includefolders = vec![PathBuf::from(includedir.path())];
     let (output, info) = preprocess(
@@ -146,6 +144,51 @@ bar_foo\n",
     assert_eq!((2, Some(PathBuf::from("myfile"))), info.line_origins[2]);
 }
 
+#[test]
+fn test_preprocess_include_nearest() {
+    let dir = tempdir().unwrap();
+    let top = dir.path().join("addon");
+    let sub = top.join("sub");
+    let inc = dir.path().join("include");
+    for folder in [&top, &sub, &inc].iter() {
+        create_dir(folder).unwrap();
+    }
+    for (path, content) in [
+        (top.join("script_component.hpp"), "#define LEVEL 1\n"),
+        (sub.join("script_component.hpp"), "#define SUB_LEVEL 2\n"),
+        (top.join("shared.hpp"), "#define SHARED 3\n"),
+        (inc.join("common.hpp"), "#define COMMON 4\n"),
+        (
+            sub.join("component.hpp"),
+            "#include \"script_component.hpp\"\n#include \"shared.hpp\"\n#include \"common.hpp\"\n\
+             sub = SUB_LEVEL;\nshared = SHARED;\ncommon = COMMON;\n",
+        ),
+    ]
+    .iter()
+    {
+        File::create(path)
+            .unwrap()
+            .write_all(content.as_bytes())
+            .unwrap();
+    }
+
+    let input = String::from(
+        "#include \"script_component.hpp\"\n#include \"sub\\component.hpp\"\ntop = LEVEL;\n",
+    );
+    std::fs::write(top.join("config.cpp"), &input).unwrap();
+    let (output, _) = preprocess(input, Some(top.join("config.cpp")), &[inc], |path| {
+        std::fs::read_to_string(path).unwrap()
+    })
+    .unwrap();
+
+    // sub\script_component.hpp is closer to component.hpp than the one of the addon
+    assert!(output.contains("sub = 2;"), "{}", output);
+    // not next to component.hpp, found next to config.cpp and in the include folder
+    assert!(output.contains("shared = 3;"), "{}", output);
+    assert!(output.contains("common = 4;"), "{}", output);
+    assert!(output.contains("top = 1;"), "{}", output);
+}
+
 #[test]
 fn test_proprocess_bom() {
     let input = String::from_utf8(vec![0xef, 0xbb, 0xbf]).unwrap() + "blub";
@@ -162,6 +205,32 @@ fn test_proprocess_bom() {
     assert_eq!("blub", output.trim());
 }
 
+#[test]
+fn test_preprocess_crlf_bom_include() {
+    let dir = tempdir().unwrap();
+    File::create(dir.path().join("include.hpp"))
+        .unwrap()
+        .write_all(b"\xef\xbb\xbf#define VALUE 3\r\n#define TEXT \"a\"\r\n")
+        .unwrap();
+    let input = "\u{feff}#include \"include.hpp\"\r\nvalue = VALUE;\r\ntext = TEXT;\r\n";
+    std::fs::write(dir.path().join("config.cpp"), input).unwrap();
+
+    let (output, _) = preprocess(
+        input.to_string(),
+        Some(dir.path().join("config.cpp")),
+        &Vec::new(),
+        |path| std::fs::read_to_string(path).unwrap(),
+    )
+    .unwrap();
+
+    assert!(
+        !output.contains('\u{feff}') && !output.contains('\r'),
+        "{:?}",
+        output
+    );
+    assert_eq!("value = 3;\ntext = \"a\";", output.trim());
+}
+
 #[test]
 fn test_preprocess_lineorigins() {
     let input = String::from(
@@ -190,3 +259,673 @@ class test\\
     assert_eq!(5, info.line_origins.len());
     assert_eq!(8, info.line_origins[2].0);
 }
+
+#[test]
+fn test_preprocess_macro_table() {
+    let input = String::from(
+        "\
+#define FOO 1
+#define BAR(x, y) x##y
+#define FOO 2
+BAR(a, FOO)
+#undef BAR
+#define BAZ(x) BAR(x, x)
+BAZ(b)\n",
+    );
+
+    let options = PreprocessOptions {
+        trace_macros: vec![String::from("FOO")],
+        ..Default::default()
+    };
+    let mut info = PreprocessInfo::default();
+    preprocess_with_options(
+        input,
+        Some(PathBuf::from("myfile")),
+        &Vec::new(),
+        |path| {
+            let mut content = String::new();
+            File::open(path)
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            content
+        },
+        &options,
+        &mut info,
+    )
+    .unwrap();
+
+    let macros: Vec<String> = info.macros().iter().map(|d| d.to_string()).collect();
+    assert_eq!(
+        vec!["BAZ(x) BAR(x, x)", "FOO 2", "__ARMA3__ 1", "__ARMA__ 1"],
+        macros
+    );
+    assert_eq!(
+        Some((Some(&PathBuf::from("myfile")), 3)),
+        info.macros()[1].location()
+    );
+    assert_eq!(vec!["FOO -> 2"], info.macro_traces);
+}
+
+#[test]
+fn test_preprocess_keep_comments() {
+    let input = String::from(
+        "\
+#define FOO 1 // gone
+// the answer
+foo = FOO; /* inline */
+/* multi
+line */ bar = 2;
+baz = 3;\n",
+    );
+
+    let options = PreprocessOptions {
+        keep_comments: true,
+        ..Default::default()
+    };
+    let mut info = PreprocessInfo::default();
+    let output = preprocess_with_options(
+        input,
+        None,
+        &Vec::new(),
+        |path| {
+            let mut content = String::new();
+            File::open(path)
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            content
+        },
+        &options,
+        &mut info,
+    )
+    .unwrap();
+
+    assert_eq!(
+        "// the answer\nfoo = 1 ; /* inline */\n/* multi\nline */ bar = 2;\nbaz = 3;",
+        output.trim()
+    );
+    let lines: Vec<u32> = info.line_origins.iter().map(|(l, _)| *l).collect();
+    assert_eq!(vec![2, 3, 4, 5, 6, 7], lines);
+}
+
+#[test]
+fn test_preprocess_argument_count() {
+    let preprocess_str = |input: &str| {
+        preprocess(
+            String::from(input),
+            Some(PathBuf::from("myfile")),
+            &Vec::new(),
+            |_| unreachable!(),
+        )
+        .map(|(output, _)| output.trim().to_string())
+        .map_err(|e| e.to_string())
+    };
+
+    let error = preprocess_str("#define DOUBLES(x,y) x##_##y\n\nfoo = DOUBLES(a);\n").unwrap_err();
+    assert_eq!(
+        "Preprocessor: [myfile:3] Macro DOUBLES expects 2 arguments but got 1, defined at myfile:1",
+        error
+    );
+
+    // nested invocations are checked as well
+    assert!(preprocess_str("#define ID(x) x\n#define D(x,y) x\nfoo = ID(D(a));\n").is_err());
+
+    assert_eq!(
+        "foo = a_b;",
+        preprocess_str("#define DOUBLES(x,y) x##_##y\nfoo = DOUBLES(a,b,c);\n").unwrap()
+    );
+    assert_eq!(
+        "foo = x;",
+        preprocess_str("#define EMPTY() x\nfoo = EMPTY();\n").unwrap()
+    );
+
+    // only invocations with parentheses are checked, so macros can be passed to other macros
+    assert_eq!(
+        "foo = \"bar\";\nbaz = QUOTE;",
+        preprocess_str(
+            "#define QUOTE(x) #x\n#define APPLY(f,x) f(x)\nfoo = APPLY(QUOTE, bar);\nbaz = QUOTE;\n"
+        )
+        .unwrap()
+    );
+
+    // inactive lines are not expanded
+    assert_eq!(
+        "",
+        preprocess_str("#define ID(x) x\n#ifdef NOPE\nfoo = ID();\nfoo = ID(a,b);\n#endif\n")
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_preprocess_if() {
+    let input = "\
+#define USE_COMPAT 1
+#define VERSION 3
+#define NEWER(x) (VERSION > x)
+#if USE_COMPATS
+a = 1;
+#elif NEWER(2) && defined(USE_COMPAT)
+a = 2;
+#else
+a = 3;
+#endif
+#if 0
+    #if NOPE
+    #endif
+b = 1;
+#elif !defined NOPE && VERSION * 2 == 0x6
+b = 2;
+#elif UNDEFINED
+b = 3;
+#endif
+";
+
+    let run = |strict_conditions: bool| {
+        let options = PreprocessOptions {
+            strict_conditions,
+            ..Default::default()
+        };
+        preprocess_with_options(
+            String::from(input),
+            Some(PathBuf::from("myfile")),
+            &Vec::new(),
+            |_| unreachable!(),
+            &options,
+            &mut PreprocessInfo::default(),
+        )
+        .map_err(|e| e.to_string())
+    };
+
+    let output: Vec<String> = run(false)
+        .unwrap()
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.to_string())
+        .collect();
+    assert_eq!(vec!["a = 2;", "b = 2;"], output);
+
+    assert_eq!(
+        "Preprocessor: [myfile:4] USE_COMPATS is not defined in condition \"USE_COMPATS\"",
+        run(true).unwrap_err()
+    );
+}
+
+const HEADER: &str = "\
+// Common macros, trimmed from a real addon header
+#define PREFIX ace
+#define COMPONENT frag
+#define DOUBLES(var1,var2) var1##_##var2
+#define TRIPLES(var1,var2,var3) var1##_##var2##_##var3
+#define QUOTE(var1) #var1
+#define ADDON DOUBLES(PREFIX,COMPONENT)
+#define GVAR(var1) DOUBLES(ADDON,var1)
+#define QGVAR(var1) QUOTE(GVAR(var1))
+#define PATHTOF(var1) \\x\\PREFIX\\addons\\COMPONENT\\var1
+#define ARR_2(a,b) a, b
+
+#ifdef DEBUG_MODE_FULL
+    #define LOG(msg) diag_log text msg
+#else
+    #define LOG(msg) /* disabled */
+#endif
+
+#if 1 + 1 == 2
+    #define TWO 2
+#endif
+
+#define MULTI(a) class a { \\
+    value = QUOTE(a); \\
+    list[] = {ARR_2(1,2)}; \\
+}
+
+class CfgPatches {
+    class ADDON {
+        name = \"Fragmentation \"\"quoted\"\"\";
+        units[] = {};
+        requiredAddons[] = {\"ace_common\"}; // trailing comment
+        path = QUOTE(PATHTOF(data\\frag.paa));
+        /* multi
+           line */
+        author = QGVAR(author);
+    };
+};
+MULTI(Test)
+";
+
+#[test]
+fn test_preprocess_truncated() {
+    let crlf = HEADER.replace('\n', "\r\n");
+    for header in &[HEADER, crlf.as_str()] {
+        for length in (0..=header.len()).filter(|i| header.is_char_boundary(*i)) {
+            let input = header[..length].to_string();
+            let result = std::panic::catch_unwind(|| {
+                let _ = preprocess(input.clone(), None, &Vec::new(), |_| String::new());
+                let _ = armake2::Config::from_string(input, None, &Vec::new(), |_| String::new());
+            });
+            assert!(result.is_ok(), "panicked at length {}", length);
+        }
+    }
+}
+
+#[test]
+fn test_preprocess_eof() {
+    let preprocess_str = |input: &str| {
+        preprocess(
+            String::from(input),
+            Some(PathBuf::from("myfile")),
+            &Vec::new(),
+            |_| unreachable!(),
+        )
+        .map(|(output, _)| output)
+        .map_err(|e| e.to_string())
+    };
+
+    assert_eq!(
+        "a = 1;\nb = 2;\n",
+        preprocess_str("a = 1;\nb = 2;").unwrap()
+    );
+    assert_eq!(
+        "a = 1;\nb = 2; \n",
+        preprocess_str("a = 1;\nb = 2; \\").unwrap()
+    );
+    assert_eq!("a = 1;\n", preprocess_str("a = 1;\\\r\n").unwrap());
+    assert_eq!("a = \"\\\\\";\n", preprocess_str("a = \"\\\\\";").unwrap());
+
+    for input in &["#define FOO 1 \\\n    2 \\", "#define FOO 1 \\\n"] {
+        assert_eq!(
+            "Preprocessor: [myfile:1] Definition of FOO continues past the end of the file",
+            preprocess_str(input).unwrap_err()
+        );
+    }
+}
+
+#[test]
+fn test_preprocess_predefined() {
+    let dir = tempdir().unwrap();
+    File::create(dir.path().join("platform.hpp"))
+        .unwrap()
+        .write_all(
+            b"\
+#ifdef __ARMA3__
+    #define PLATFORM arma3
+#else
+    #define PLATFORM other
+#endif
+#ifdef __A3_DEBUG__
+    #define BUILD debug
+#else
+    #define BUILD release
+#endif
+",
+        )
+        .unwrap();
+
+    File::create(dir.path().join("config.cpp")).unwrap();
+
+    let run = |input: &str, options: &PreprocessOptions| {
+        let mut info = PreprocessInfo::default();
+        let output = preprocess_with_options(
+            format!("#include \"platform.hpp\"\n{}\n", input),
+            Some(dir.path().join("config.cpp")),
+            &Vec::new(),
+            |path| {
+                let mut content = String::new();
+                File::open(path)
+                    .unwrap()
+                    .read_to_string(&mut content)
+                    .unwrap();
+                content
+            },
+            options,
+            &mut info,
+        )
+        .unwrap();
+        output.trim().to_string()
+    };
+
+    let options = PreprocessOptions::default();
+    assert_eq!("arma3 release", run("PLATFORM BUILD", &options));
+    assert_eq!("1 1", run("__ARMA__ __ARMA3__", &options));
+
+    let options = PreprocessOptions {
+        debug_defines: true,
+        ..Default::default()
+    };
+    assert_eq!("arma3 debug", run("PLATFORM BUILD", &options));
+
+    let options = PreprocessOptions {
+        undefines: vec![String::from("__ARMA3__")],
+        ..Default::default()
+    };
+    assert_eq!("other release 1", run("PLATFORM BUILD __ARMA__", &options));
+
+    let options = PreprocessOptions {
+        defines: vec![
+            (String::from("__ARMA3__"), String::from("2")),
+            (String::from("EXTRA"), String::from("yes")),
+        ],
+        ..Default::default()
+    };
+    assert_eq!("arma3 2 yes", run("PLATFORM __ARMA3__ EXTRA", &options));
+
+    let options = PreprocessOptions::default();
+    assert_eq!(
+        "3 __ARMA__",
+        run(
+            "#define __ARMA3__ 3\n#undef __ARMA__\n__ARMA3__ __ARMA__",
+            &options
+        )
+    );
+}
+
+#[test]
+fn test_preprocess_normalize() {
+    assert_eq!(
+        "a = 1;\nb = {1, 2};\n class C {};\n",
+        normalize("a = 1;  \r\n\r\nb\t=  {1,\t2};\r \t\n\tclass C {};")
+    );
+
+    let left = "foo = 1;\nbar = 2;\nbaz = 3;\nqux = 4;\n";
+    let right = "foo  = 1;\r\n\r\nbar = 2;\r\nBAZ = 3;\r\nqux = 4;\r\nend = 5;\r\n";
+    assert_eq!(
+        vec![
+            Difference {
+                left_line: 3,
+                right_line: 4,
+                before: vec!["foo = 1;".to_string(), "bar = 2;".to_string()],
+                left: vec!["baz = 3;".to_string()],
+                right: vec!["BAZ = 3;".to_string()],
+                after: vec!["qux = 4;".to_string()],
+            },
+            Difference {
+                left_line: 4,
+                right_line: 6,
+                before: vec!["baz = 3;".to_string(), "qux = 4;".to_string()],
+                left: Vec::new(),
+                right: vec!["end = 5;".to_string()],
+                after: Vec::new(),
+            },
+        ],
+        compare_normalized(left, right, 10)
+    );
+    assert_eq!(1, compare_normalized(left, right, 1).len());
+    assert!(compare_normalized(left, &normalize(left), 10).is_empty());
+}
+
+#[test]
+fn test_preprocess_builtins() {
+    let dir = tempdir().unwrap();
+    let include = dir.path().join("log.hpp");
+    std::fs::write(
+        &include,
+        "#define LOG(msg) diag_log [__FILE__, __LINE__, msg]\nincluded = __LINE__;\n",
+    )
+    .unwrap();
+    let config = dir.path().join("config.cpp");
+    let input = String::from(
+        "#include \"log.hpp\"\n\nLOG(\"hello\");\n#if __LINE__ == 4\nfour = 1;\n#endif\n\
+         #define __LINE__ 0\nline = __LINE__;\n",
+    );
+    std::fs::write(&config, &input).unwrap();
+
+    let (output, _) = preprocess(input, Some(config.clone()), &[], |path| {
+        std::fs::read_to_string(path).unwrap()
+    })
+    .unwrap();
+
+    // __LINE__ outside of a macro is the line in the file it is in
+    assert!(output.contains("included = 2;"), "{}", output);
+    // in a macro from the include it is the location of the use
+    assert!(
+        output.contains(&format!(
+            "diag_log [\"{}\", 3, \"hello\"];",
+            config.display()
+        )),
+        "{}",
+        output
+    );
+    assert!(output.contains("four = 1;"), "{}", output);
+    // definitions take precedence
+    assert!(output.contains("line = 0;"), "{}", output);
+}
+
+#[test]
+fn test_preprocess_cba_macros() {
+    // trimmed from CBA's script_macros_common.hpp
+    let macros = "\
+#define MAINPREFIX z
+#define PREFIX ace
+#define COMPONENT common
+#define SUBPREFIX addons
+#define DOUBLES(var1,var2) var1##_##var2
+#define TRIPLES(var1,var2,var3) var1##_##var2##_##var3
+#define QUOTE(var1) #var1
+#define ARR_2(ARG1,ARG2) ARG1, ARG2
+#define ADDON DOUBLES(PREFIX,COMPONENT)
+#define GVAR(var1) DOUBLES(ADDON,var1)
+#define EGVAR(var1,var2) TRIPLES(PREFIX,var1,var2)
+#define QGVAR(var1) QUOTE(GVAR(var1))
+#define QEGVAR(var1,var2) QUOTE(EGVAR(var1,var2))
+#define QQGVAR(var1) QUOTE(QGVAR(var1))
+#define FUNC(var1) TRIPLES(ADDON,fnc,var1)
+#define QFUNC(var1) QUOTE(FUNC(var1))
+#define PATHTOF_SYS(var1,var2,var3) \\MAINPREFIX\\var1\\SUBPREFIX\\var2\\var3
+#define PATHTOF(var1) PATHTOF_SYS(PREFIX,COMPONENT,var1)
+#define QPATHTOF(var1) QUOTE(PATHTOF(var1))
+#define CSTRING(var1) QUOTE(DOUBLES($STR,GVAR(var1)))
+#define ECSTRING(var1,var2) QUOTE(TRIPLES($STR,DOUBLES(PREFIX,var1),var2))
+";
+    let input = format!(
+        "{}\
+class ADDON {{ name = QUOTE(ADDON); }};
+a = QGVAR(foo);
+b = QEGVAR(medical,bar);
+c = QUOTE(_x = QQGVAR(baz));
+d = QFUNC(init);
+e = QPATHTOF(data\\icon.paa);
+f = CSTRING(displayName);
+g = ECSTRING(medical,name);
+h = QUOTE([ARR_2(1,2)] call FUNC(y));
+i = GVAR(DOUBLES(a,b));
+",
+        macros
+    );
+
+    let (output, _) = preprocess(input, None, &[], |_| String::new()).unwrap();
+    let lines: Vec<&str> = output.lines().filter(|l| !l.is_empty()).collect();
+    assert_eq!(
+        vec![
+            "class ace_common { name = \"ace_common\"; };",
+            "a = \"ace_common_foo\";",
+            "b = \"ace_medical_bar\";",
+            // quotes inside are kept as they are, that's what QQGVAR is for
+            "c = \"_x = \"\"ace_common_baz\"\"\";",
+            "d = \"ace_common_fnc_init\";",
+            "e = \"\\z\\ace\\addons\\common\\data\\icon.paa\";",
+            "f = \"$STR_ace_common_displayName\";",
+            "g = \"$STR_ace_medical_name\";",
+            "h = \"[1, 2] call ace_common_fnc_y\";",
+            "i = ace_common_a_b;",
+        ],
+        lines
+    );
+
+    // the joined tokens are rescanned, spaces around ## are dropped
+    let (output, _) = preprocess(
+        String::from(
+            "#define CAT(a,b) a ## b\n#define foobar 42\n#define QUOTE(x) #x\n\
+             #define Q2(x) QUOTE(x)\nx = CAT(foo,bar);\ny = Q2(CAT(foo,bar));\n",
+        ),
+        None,
+        &[],
+        |_| String::new(),
+    )
+    .unwrap();
+    assert_eq!("x = 42;\ny = \"42\";", output.trim());
+}
+
+#[test]
+fn test_preprocess_nested_conditionals() {
+    let input = "\
+#ifndef GUARD
+#define GUARD
+#define A
+#ifdef A
+    #ifdef B
+        #define C
+        #ifdef C
+            skipped = 1;
+        #endif
+    #else
+        #undef A
+        #ifndef A
+            #ifdef GUARD
+                nested = 3;
+            #else
+                skipped = 2;
+            #endif
+        #endif
+    #endif
+#endif
+#endif
+a = A; c = C;
+";
+    let run = |input: &str| {
+        preprocess(
+            String::from(input),
+            Some(PathBuf::from("guard.hpp")),
+            &[],
+            |_| unreachable!(),
+        )
+        .map(|(output, _)| output)
+        .map_err(|e| e.to_string())
+    };
+
+    // the second time, the guard skips everything and nothing is redefined
+    let output = run(&format!("{}{}", input, input)).unwrap();
+    let lines: Vec<&str> = output
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .collect();
+    assert_eq!(vec!["nested = 3;", "a = A; c = C;", "a = A; c = C;"], lines);
+
+    let error = run("a = 1;\n#else\n").unwrap_err();
+    assert!(
+        error.contains("[guard.hpp:2] #else without #if, #ifdef or #ifndef"),
+        "{}",
+        error
+    );
+    let error = run("#ifdef A\n#if 1\n#endif\n").unwrap_err();
+    assert!(
+        error.contains("[guard.hpp:1] #ifdef without #endif"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_preprocess_circular_include() {
+    let dir = tempdir().unwrap();
+    let write = |name: &str, content: &str| std::fs::write(dir.path().join(name), content).unwrap();
+    write("a.hpp", "#include \"b.hpp\"\n");
+    write("b.hpp", "\n#include \"a.hpp\"\n");
+    write("c.hpp", "#include \"missing.hpp\"\n");
+    write("d.hpp", "#include \"c.hpp\"\n");
+    for i in 0..5 {
+        write(
+            &format!("deep{}.hpp", i),
+            &format!("#include \"deep{}.hpp\"\n", i + 1),
+        );
+    }
+    write("deep5.hpp", "deepest = 1;\n");
+
+    let run = |name: &str, max_include_depth: usize| {
+        let path = dir.path().join(name);
+        preprocess_with_options(
+            std::fs::read_to_string(&path).unwrap(),
+            Some(path),
+            &[],
+            |path| std::fs::read_to_string(path).unwrap(),
+            &PreprocessOptions {
+                max_include_depth,
+                ..Default::default()
+            },
+            &mut PreprocessInfo::default(),
+        )
+        .map_err(|e| e.to_string())
+    };
+
+    let error = run("a.hpp", DEFAULT_MAX_INCLUDE_DEPTH).unwrap_err();
+    assert!(
+        error.contains("b.hpp:2] Circular include: a.hpp -> b.hpp -> a.hpp"),
+        "{}",
+        error
+    );
+
+    let error = run("d.hpp", DEFAULT_MAX_INCLUDE_DEPTH).unwrap_err();
+    assert!(error.contains("c.hpp:1] "), "{}", error);
+    assert!(
+        error.contains("include chain: d.hpp -> c.hpp -> missing.hpp"),
+        "{}",
+        error
+    );
+
+    assert!(run("deep0.hpp", 6).unwrap().contains("deepest = 1;"));
+    let error = run("deep0.hpp", 5).unwrap_err();
+    assert!(
+        error.contains("Includes are nested deeper than 5 files: deep0.hpp -> deep1.hpp"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_preprocess_warnings() {
+    let dir = tempdir().unwrap();
+    create_dir(dir.path().join("Include")).unwrap();
+    File::create(dir.path().join("Include").join("Macros.hpp"))
+        .unwrap()
+        .write_all(b"#define JOIN(a) ## a\n")
+        .unwrap();
+    let path = dir.path().join("config.cpp");
+    let input = String::from(
+        "\
+#include \"include\\macros.hpp\"
+#define VALUE 1
+#define VALUE 2
+value = VALUE;
+",
+    );
+    std::fs::write(&path, &input).unwrap();
+
+    let (result, warnings) = armake2::warning::collect(|| {
+        preprocess(input, Some(path.clone()), &[], |path| {
+            std::fs::read_to_string(path).unwrap()
+        })
+    });
+    assert!(result.unwrap().0.contains("value = 2;"));
+
+    let found: Vec<(&str, u32, bool)> = warnings
+        .iter()
+        .map(|w| {
+            let file = PathBuf::from(w.file.as_ref().unwrap());
+            (w.name, w.line.unwrap(), file.ends_with("Macros.hpp"))
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            ("case-insensitive-include", 1, false),
+            ("excessive-concatenation", 1, true),
+            ("redefinition-wo-undef", 3, false),
+        ],
+        found
+    );
+    assert!(warnings[2]
+        .to_string()
+        .contains(":3] Macro VALUE is redefined without #undef, previously defined at "));
+    assert!(warnings[2]
+        .to_string()
+        .ends_with("config.cpp:2 [redefinition-wo-undef]"));
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "signing")]
+
+use std::fs::{create_dir_all, File};
+use std::io::Write;
+use std::process::Command;
+
+use tempfile::tempdir;
+
+use armake2::PBO;
+
+#[test]
+fn report_modfolder() {
+    let dir = tempdir().unwrap();
+    let modfolder = dir.path().join("@mod");
+    let addons = modfolder.join("addons");
+    create_dir_all(&addons).unwrap();
+    create_dir_all(modfolder.join("keys")).unwrap();
+
+    for name in ["a", "b"].iter() {
+        let source = dir.path().join(name);
+        create_dir_all(&source).unwrap();
+        File::create(source.join("script.sqf"))
+            .unwrap()
+            .write_all(b"hint 'hello';")
+            .unwrap();
+        File::create(source.join("$PBOPREFIX$"))
+            .unwrap()
+            .write_all(format!("prefix=x\\{}\nversion=1.2.3\n", name).as_bytes())
+            .unwrap();
+        let mut pbo = PBO::from_directory(source, false, &[], &[])
+            .unwrap()
+            .to_cursor()
+            .unwrap()
+            .into_inner();
+        if *name == "b" {
+            let last = pbo.len() - 1;
+            pbo[last] ^= 0xff;
+        }
+        std::fs::write(addons.join(format!("{}.pbo", name)), pbo).unwrap();
+    }
+    std::fs::write(addons.join("c.pbo"), b"not a pbo").unwrap();
+    std::fs::write(addons.join("a.pbo.mod_key.bisign"), b"mod_key\0signature").unwrap();
+    std::fs::write(addons.join("b.pbo.other.bisign"), b"other\0signature").unwrap();
+    std::fs::write(
+        modfolder.join("keys").join("mod_key.bikey"),
+        b"mod_key\0key",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("report")
+        .arg("--json")
+        .arg(&modfolder)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let pbos = report["pbos"].as_array().unwrap();
+    assert_eq!(3, pbos.len());
+    assert_eq!("addons/a.pbo", pbos[0]["path"]);
+    assert_eq!("x\\a", pbos[0]["prefix"]);
+    assert_eq!("1.2.3", pbos[0]["version"]);
+    assert_eq!(1, pbos[0]["files"]);
+    assert_eq!(13, pbos[0]["size"]);
+    assert_eq!(true, pbos[0]["checksum_valid"]);
+    assert_eq!(serde_json::json!(["mod_key"]), pbos[0]["signatures"]);
+    assert_eq!(false, pbos[1]["checksum_valid"]);
+    assert!(pbos[2]["error"].is_string());
+    assert_eq!(serde_json::json!(["mod_key"]), report["keys"]);
+    assert_eq!(
+        serde_json::json!({
+            "pbos": 3,
+            "files": 2,
+            "size": 26,
+            "invalid_checksums": 1,
+            "unsigned": 0,
+            "unreadable": 1,
+        }),
+        report["totals"]
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("report")
+        .arg(&modfolder)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.contains("checksum:   INVALID"), "{}", text);
+    assert!(text.contains("other (no key in keys/)"), "{}", text);
+    assert!(text.contains("Total: 3 PBOs, 2 files, 26 B"), "{}", text);
+}
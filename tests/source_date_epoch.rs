@@ -0,0 +1,76 @@
+use std::fs::File;
+use std::io::{Cursor, Write};
+use std::thread::sleep;
+use std::time::Duration;
+
+use tempfile::tempdir;
+
+use armake2::PBO;
+
+#[test]
+fn source_date_epoch_reproducible() {
+    std::env::set_var("SOURCE_DATE_EPOCH", "1500000000");
+
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    std::fs::create_dir(&source).unwrap();
+    let touch = || {
+        File::create(source.join("file.txt"))
+            .unwrap()
+            .write_all(b"hello world")
+            .unwrap();
+    };
+
+    touch();
+    let first = PBO::from_directory(source.clone(), false, &[], &[])
+        .unwrap()
+        .to_cursor()
+        .unwrap();
+
+    sleep(Duration::from_millis(1100));
+    touch();
+    let second = PBO::from_directory(source.clone(), false, &[], &[])
+        .unwrap()
+        .to_cursor()
+        .unwrap();
+
+    assert_eq!(first.get_ref(), second.get_ref());
+
+    let pbo = PBO::read(&mut Cursor::new(first.into_inner())).unwrap();
+    assert_eq!(1_500_000_000, pbo.headers[0].timestamp);
+}
+
+#[test]
+fn source_date_epoch_keeps_recorded_timestamps() {
+    std::env::set_var("SOURCE_DATE_EPOCH", "1500000000");
+
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    std::fs::create_dir(&source).unwrap();
+    File::create(source.join("file.txt"))
+        .unwrap()
+        .write_all(b"hello world")
+        .unwrap();
+
+    let mut pbo = PBO::from_directory(source, false, &[], &[]).unwrap();
+    pbo.set_timestamps(0);
+    let written = pbo.to_cursor().unwrap();
+
+    let mut pbo = PBO::read(&mut Cursor::new(written.into_inner())).unwrap();
+    pbo.files.insert(
+        String::from("added.txt"),
+        Cursor::new(b"added".to_vec().into_boxed_slice()),
+    );
+    let rewritten = pbo.to_cursor().unwrap();
+
+    let pbo = PBO::read(&mut Cursor::new(rewritten.into_inner())).unwrap();
+    let timestamp = |name: &str| {
+        pbo.headers
+            .iter()
+            .find(|h| h.filename == name)
+            .unwrap()
+            .timestamp
+    };
+    assert_eq!(0, timestamp("file.txt"));
+    assert_eq!(1_500_000_000, timestamp("added.txt"));
+}
@@ -0,0 +1,71 @@
+use armake2::stringtable::*;
+use armake2::Config;
+
+#[test]
+fn stringtable_check() {
+    let input = "\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<Project name=\"my_mod\">
+    <Package name=\"main\">
+        <Container name=\"foo\">
+            <Key ID=\"STR_my_mod_foo\">
+                <Original>Foo</Original>
+            </Key>
+            <Key ID=\"STR_my_mod_foo\">
+                <Original>Foo again</Original>
+            </Key>
+            <Key ID=\"STR_my_mod_bar\">
+                <Original></Original>
+                <German>Bar</German>
+            </Key>
+        </Container>
+        <Key ID=\"STR_my_mod_baz\">
+            <English>Baz</English>
+        </Key>
+        <Entry/>
+    </Package>
+</Project>
+";
+
+    let stringtable = Stringtable::read(input).unwrap();
+    assert_eq!(
+        vec!["STR_my_mod_foo", "STR_my_mod_bar", "STR_my_mod_baz"],
+        stringtable.keys
+    );
+
+    let lints: Vec<(&str, &str)> = stringtable
+        .lints
+        .iter()
+        .map(|l| (l.name, l.path.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            ("stringtable-duplicate-key", "line 8"),
+            ("stringtable-empty-original", "line 12"),
+            ("stringtable-empty-original", "line 16"),
+            ("stringtable-structure", "line 19"),
+        ],
+        lints
+    );
+
+    let config = Config::from_string(
+        String::from(
+            "class CfgVehicles { class my_car { displayName = \"$STR_MY_MOD_FOO\"; \
+             texts[] = {\"$STR_my_mod_missing\", \"STR_not_a_reference\"}; }; };",
+        ),
+        None,
+        &Vec::new(),
+        |path| std::fs::read_to_string(path).unwrap(),
+    )
+    .unwrap();
+    let missing = lint_references(&config, &stringtable);
+    assert_eq!(1, missing.len());
+    assert_eq!("stringtable-missing-key", missing[0].name);
+    assert_eq!("CfgVehicles >> my_car >> texts", missing[0].path);
+}
+
+#[test]
+fn stringtable_malformed() {
+    let error = Stringtable::read("<Project>\n<Package>\n</Project>").unwrap_err();
+    assert!(error.to_string().contains("3:"), "{}", error);
+}
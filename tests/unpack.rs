@@ -0,0 +1,710 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use sha1::{Digest, Sha1};
+use tempfile::tempdir;
+
+use armake2::pbo::Manifest;
+use armake2::{PBOHeader, PBO};
+
+/// Writes a PBO with a prefix and a single file `data\\file.txt` containing "hello world".
+fn write_pbo(pbo_path: &Path) {
+    let mut pbo = File::create(pbo_path).unwrap();
+
+    let header = PBOHeader {
+        filename: String::new(),
+        packing_method: 0x5665_7273,
+        original_size: 0,
+        reserved: 0,
+        timestamp: 0,
+        data_size: 0,
+    };
+    header.write(&mut pbo).unwrap();
+    pbo.write_all(b"prefix\0x\\test\\addon\0\0").unwrap();
+    PBOHeader {
+        filename: String::from("data\\file.txt"),
+        packing_method: 0,
+        original_size: 11,
+        timestamp: 1_500_000_000,
+        data_size: 11,
+        ..header.clone()
+    }
+    .write(&mut pbo)
+    .unwrap();
+    PBOHeader {
+        packing_method: 0,
+        ..header
+    }
+    .write(&mut pbo)
+    .unwrap();
+    pbo.write_all(b"hello world\0").unwrap();
+    pbo.write_all(&[0; 20]).unwrap();
+    drop(pbo);
+}
+
+#[test]
+fn unpack_zip() {
+    let dir = tempdir().unwrap();
+    let pbo_path = dir.path().join("addon.pbo");
+    write_pbo(&pbo_path);
+
+    let zip_path = dir.path().join("addon.zip");
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("unpack")
+        .arg("--zip")
+        .arg(&pbo_path)
+        .arg(&zip_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let mut zip = zip::ZipArchive::new(File::open(&zip_path).unwrap()).unwrap();
+    let names: Vec<PathBuf> = (0..zip.len())
+        .map(|i| PathBuf::from(zip.by_index(i).unwrap().name()))
+        .collect();
+    assert_eq!(
+        vec![PathBuf::from("$PBOPREFIX$"), PathBuf::from("data/file.txt")],
+        names
+    );
+
+    let mut content = String::new();
+    zip.by_name("$PBOPREFIX$")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!("prefix=x\\test\\addon\n", content);
+
+    let mut file = zip.by_name("data/file.txt").unwrap();
+    assert_eq!(zip::CompressionMethod::Deflated, file.compression());
+    assert_eq!(2017, file.last_modified().year());
+    content.clear();
+    file.read_to_string(&mut content).unwrap();
+    assert_eq!("hello world", content);
+}
+
+#[test]
+fn unpack_manifest() {
+    let dir = tempdir().unwrap();
+    let pbo_path = dir.path().join("addon.pbo");
+    write_pbo(&pbo_path);
+
+    let target = dir.path().join("addon");
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("unpack")
+        .arg(&pbo_path)
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let content = std::fs::read_to_string(target.join(".armake2-manifest.json")).unwrap();
+    let manifest: Manifest = serde_json::from_str(&content).unwrap();
+    assert_eq!(env!("CARGO_PKG_VERSION"), manifest.version);
+    assert_eq!(Some("x\\test\\addon"), manifest.prefix.as_deref());
+    assert!(manifest.extensions.is_empty());
+    assert_eq!(1, manifest.entries.len());
+    let entry = &manifest.entries[0];
+    assert_eq!("data\\file.txt", entry.name);
+    assert_eq!("data/file.txt", entry.path);
+    assert_eq!(11, entry.size);
+    assert_eq!("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed", entry.sha1);
+    assert_eq!(1_500_000_000, entry.timestamp);
+
+    // the manifest is not packed again
+    let repacked = PBO::from_directory(target.clone(), false, &[], &[]).unwrap();
+    assert_eq!(
+        vec!["data\\file.txt"],
+        repacked.files.keys().collect::<Vec<_>>()
+    );
+
+    // explicit path, the output is the same for the same PBO
+    let manifest_path = dir.path().join("manifest.json");
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "-f", "--manifest"])
+        .arg(&manifest_path)
+        .arg(&pbo_path)
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(content, std::fs::read_to_string(&manifest_path).unwrap());
+}
+
+#[test]
+fn unpack_batch() {
+    let dir = tempdir().unwrap();
+    let addons = dir.path().join("addons");
+    std::fs::create_dir(&addons).unwrap();
+    for (name, prefix) in [("a", Some("x\\a")), ("b", Some("x\\a")), ("c", None)].iter() {
+        let source = dir.path().join(name);
+        std::fs::create_dir(&source).unwrap();
+        std::fs::write(source.join("script.sqf"), name).unwrap();
+        if let Some(prefix) = prefix {
+            std::fs::write(source.join("$PBOPREFIX$"), prefix).unwrap();
+        }
+        PBO::from_directory(source, false, &[], &[])
+            .unwrap()
+            .write(&mut File::create(addons.join(format!("{}.pbo", name))).unwrap())
+            .unwrap();
+    }
+    std::fs::write(addons.join("d.pbo"), b"not a pbo").unwrap();
+
+    let target = dir.path().join("extracted");
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("unpack")
+        .arg(addons.join("*.PBO"))
+        .arg(&target)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Unpacked 3 of 4 PBOs"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("d.pbo"), "{}", stderr);
+    assert!(stderr.contains("same prefix"), "{}", stderr);
+
+    for (folder, content) in [("x/a", "a"), ("x/a_b", "b"), ("c", "c")].iter() {
+        let folder = target.join(folder);
+        assert_eq!(
+            *content,
+            std::fs::read_to_string(folder.join("script.sqf")).unwrap()
+        );
+        assert!(folder.join(".armake2-manifest.json").is_file());
+    }
+
+    // explicit sources work the same
+    let target = dir.path().join("explicit");
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("unpack")
+        .arg("--no-manifest")
+        .arg(addons.join("a.pbo"))
+        .arg(addons.join("c.pbo"))
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(target.join("x").join("a").join("script.sqf").is_file());
+    assert!(target.join("c").join("script.sqf").is_file());
+    assert!(!target.join("c").join(".armake2-manifest.json").exists());
+}
+
+#[test]
+fn unpack_build_order_round_trip() {
+    let dir = tempdir().unwrap();
+    let pbo_path = dir.path().join("addon.pbo");
+
+    // entries out of alphabetical order, the prefix after another extension and headers that
+    // armake2 wouldn't write itself
+    let mut data: Vec<u8> = Vec::new();
+    let header = PBOHeader {
+        filename: String::new(),
+        packing_method: 0x5665_7273,
+        original_size: 0,
+        reserved: 0,
+        timestamp: 0,
+        data_size: 0,
+    };
+    header.write(&mut data).unwrap();
+    data.write_all(b"version\x001.2\0prefix\0x\\test\\addon\0\0")
+        .unwrap();
+    let entries: [(&str, u32, u32, u32, &[u8]); 3] = [
+        ("zeta.txt", 0, 0, 1_500_000_000, b"last in the alphabet"),
+        ("data\\config.bin", 0, 7, 1_400_000_000, b"\0raP not really"),
+        ("alpha.sqf", 0x0430_7273, 0, 0, b"packed bytes"),
+    ];
+    for (name, method, reserved, timestamp, content) in entries.iter() {
+        PBOHeader {
+            filename: name.to_string(),
+            packing_method: *method,
+            original_size: if *method == 0 {
+                content.len() as u32
+            } else {
+                100
+            },
+            reserved: *reserved,
+            timestamp: *timestamp,
+            data_size: content.len() as u32,
+        }
+        .write(&mut data)
+        .unwrap();
+    }
+    PBOHeader {
+        packing_method: 0,
+        ..header
+    }
+    .write(&mut data)
+    .unwrap();
+    for (_, _, _, _, content) in entries.iter() {
+        data.write_all(content).unwrap();
+    }
+    let checksum = Sha1::digest(&data);
+    data.push(0);
+    data.extend_from_slice(&checksum);
+    std::fs::write(&pbo_path, &data).unwrap();
+
+    let folder = dir.path().join("addon");
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "--order"])
+        .arg(&pbo_path)
+        .arg(&folder)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(folder.join(".pbo-order").is_file());
+
+    let rebuilt = dir.path().join("rebuilt.pbo");
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .env_remove("SOURCE_DATE_EPOCH")
+        .arg("build")
+        .arg("--order-file")
+        .arg(folder.join(".pbo-order"))
+        .arg(&folder)
+        .arg(&rebuilt)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    assert!(std::fs::read(&rebuilt).unwrap() == data);
+}
+
+#[test]
+fn unpack_prefix_style() {
+    let dir = tempdir().unwrap();
+    let pbo_path = dir.path().join("test.pbo");
+    write_pbo(&pbo_path);
+
+    let target = dir.path().join("extracted");
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "--no-manifest", "--prefix-style", "pboprefix-txt"])
+        .arg(&pbo_path)
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(!target.join("$PBOPREFIX$").exists());
+    assert_eq!(
+        "prefix=x\\test\\addon\n",
+        std::fs::read_to_string(target.join("$PBOPREFIX$.txt")).unwrap()
+    );
+
+    // the prefix file is read back and not packed
+    let pbo = PBO::from_directory(target, false, &[], &[]).unwrap();
+    let names: Vec<&String> = pbo.files.keys().collect();
+    assert_eq!(vec!["data\\file.txt"], names);
+    assert_eq!("x\\test\\addon", pbo.header_extensions["prefix"]);
+}
+
+#[test]
+fn unpack_nested_and_unusual_names() {
+    let dir = tempdir().unwrap();
+    let mut pbo = PBO::read(
+        &mut PBO::from_directory(dir.path().to_path_buf(), false, &[], &[])
+            .unwrap()
+            .to_cursor()
+            .unwrap(),
+    )
+    .unwrap();
+    for (name, content) in [
+        ("a\\b\\c\\d.sqf", &b"hint 'deep';"[..]),
+        ("empty\\", b""),
+        ("..\\outside.txt", b"nope"),
+    ]
+    .iter()
+    {
+        pbo.files.insert(
+            name.to_string(),
+            std::io::Cursor::new(content.to_vec().into_boxed_slice()),
+        );
+    }
+    let pbo_path = dir.path().join("test.pbo");
+    pbo.write(&mut File::create(&pbo_path).unwrap()).unwrap();
+
+    let target = dir.path().join("out").join("extracted");
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "--no-manifest"])
+        .arg(&pbo_path)
+        .arg(&target)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("..\\outside.txt"));
+    assert_eq!(
+        "hint 'deep';",
+        std::fs::read_to_string(target.join("a").join("b").join("c").join("d.sqf")).unwrap()
+    );
+    assert!(target.join("empty").is_dir());
+    assert!(!dir.path().join("out").join("outside.txt").exists());
+}
+
+#[test]
+fn unpack_patterns() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    for name in ["config.cpp", "data/a.paa", "data/B.PAA", "data/notes.txt"].iter() {
+        let path = source.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, name.as_bytes()).unwrap();
+    }
+    let pbo_path = dir.path().join("test.pbo");
+    PBO::from_directory(source, false, &[], &[])
+        .unwrap()
+        .write(&mut File::create(&pbo_path).unwrap())
+        .unwrap();
+
+    let target = dir.path().join("extracted");
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args([
+            "unpack",
+            "-i",
+            "data\\*.paa",
+            "-i",
+            "config.cpp",
+            "-x",
+            "data\\b*",
+        ])
+        .arg(&pbo_path)
+        .arg(&target)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("extracting 2 of 4 entries, 2 skipped by the patterns"));
+    assert!(target.join("config.cpp").is_file());
+    assert!(target.join("data").join("a.paa").is_file());
+    assert!(!target.join("data").join("B.PAA").exists());
+    assert!(!target.join("data").join("notes.txt").exists());
+
+    // the manifest only lists the extracted files
+    let content = std::fs::read_to_string(target.join(".armake2-manifest.json")).unwrap();
+    let manifest: Manifest = serde_json::from_str(&content).unwrap();
+    assert_eq!(2, manifest.entries.len());
+}
+
+#[test]
+fn unpack_timestamps() {
+    let dir = tempdir().unwrap();
+    let pbo_path = dir.path().join("test.pbo");
+    write_pbo(&pbo_path);
+
+    let target = dir.path().join("extracted");
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "--no-manifest"])
+        .arg(&pbo_path)
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let modified = std::fs::metadata(target.join("data").join("file.txt"))
+        .unwrap()
+        .modified()
+        .unwrap();
+    assert_eq!(
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_500_000_000),
+        modified
+    );
+
+    // without a timestamp in the header the file keeps the time it was written
+    let mut pbo = PBO::read(&mut File::open(&pbo_path).unwrap()).unwrap();
+    pbo.headers[0].timestamp = 0;
+    pbo.write(&mut File::create(&pbo_path).unwrap()).unwrap();
+    let target = dir.path().join("unknown");
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "--no-manifest"])
+        .arg(&pbo_path)
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let modified = std::fs::metadata(target.join("data").join("file.txt"))
+        .unwrap()
+        .modified()
+        .unwrap();
+    assert!(modified.elapsed().unwrap() < std::time::Duration::from_secs(3600));
+}
+
+#[test]
+fn unpack_obfuscated_names() {
+    let header = |name: &str, size: u32| PBOHeader {
+        filename: name.to_string(),
+        packing_method: 0,
+        original_size: size,
+        reserved: 0,
+        timestamp: 0,
+        data_size: size,
+    };
+    let mut data: Vec<u8> = Vec::new();
+    for (name, size) in [
+        ("script.sqf", 3),
+        ("SCRIPT.sqf", 3),
+        ("script.sqf", 4),
+        ("", 5),
+        ("\\\\", 6),
+        ("", 0),
+    ]
+    .iter()
+    {
+        header(name, *size).write(&mut data).unwrap();
+    }
+    data.write_all(b"onetwothreefour5six666").unwrap();
+    let dir = tempdir().unwrap();
+    let pbo_path = dir.path().join("obfuscated.pbo");
+    std::fs::write(&pbo_path, &data).unwrap();
+
+    // data stays with its header, duplicates get a suffix
+    let pbo = PBO::read(&mut &data[..]).unwrap();
+    let entries: Vec<(&str, &[u8])> = pbo
+        .files
+        .iter()
+        .map(|(name, cursor)| (name.as_str(), &cursor.get_ref()[..]))
+        .collect();
+    assert_eq!(
+        vec![
+            ("script.sqf", &b"one"[..]),
+            ("SCRIPT.sqf.1", b"two"),
+            ("script.sqf.2", b"thre"),
+            ("", b"efour"),
+            ("\\\\", b"5six66"),
+        ],
+        entries
+    );
+    assert_eq!(pbo.files.len(), pbo.headers.len());
+
+    let target = dir.path().join("extracted");
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "--no-manifest"])
+        .arg(&pbo_path)
+        .arg(&target)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let mut extracted: Vec<(String, Vec<u8>)> = std::fs::read_dir(&target)
+        .unwrap()
+        .map(|e| {
+            let path = e.unwrap().path();
+            (
+                path.file_name().unwrap().to_string_lossy().to_string(),
+                std::fs::read(&path).unwrap(),
+            )
+        })
+        .collect();
+    extracted.sort();
+    assert_eq!(
+        vec![
+            ("SCRIPT.sqf.1".to_string(), b"two".to_vec()),
+            ("script.sqf".to_string(), b"one".to_vec()),
+            ("script.sqf.2".to_string(), b"thre".to_vec()),
+            ("unnamed".to_string(), b"efour".to_vec()),
+            ("unnamed.1".to_string(), b"5six66".to_vec()),
+        ],
+        extracted
+    );
+}
+
+/// Writes a PBO with the given entries to `pbo_path`, the names are not checked.
+fn write_crafted_pbo(pbo_path: &Path, entries: &[(&str, &[u8])]) {
+    let dir = tempdir().unwrap();
+    let mut pbo = PBO::read(
+        &mut PBO::from_directory(dir.path().to_path_buf(), false, &[], &[])
+            .unwrap()
+            .to_cursor()
+            .unwrap(),
+    )
+    .unwrap();
+    for (name, content) in entries {
+        pbo.files.insert(
+            name.to_string(),
+            std::io::Cursor::new(content.to_vec().into_boxed_slice()),
+        );
+    }
+    pbo.write(&mut File::create(pbo_path).unwrap()).unwrap();
+}
+
+#[test]
+fn unpack_path_traversal() {
+    let dir = tempdir().unwrap();
+    let pbo_path = dir.path().join("evil.pbo");
+    write_crafted_pbo(
+        &pbo_path,
+        &[
+            ("ok.txt", b"fine"),
+            ("..\\..\\escaped.txt", b"nope"),
+            ("a\\..\\..\\escaped2.txt", b"nope"),
+            ("C:\\Windows\\evil.txt", b"nope"),
+            ("\\rooted.txt", b"nope"),
+        ],
+    );
+
+    let target = dir.path().join("out").join("extracted");
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "--no-manifest"])
+        .arg(&pbo_path)
+        .arg(&target)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for name in [
+        "..\\..\\escaped.txt",
+        "a\\..\\..\\escaped2.txt",
+        "C:\\Windows\\evil.txt",
+        "\\rooted.txt",
+    ] {
+        assert!(stderr.contains(&format!("Skipping {}", name)), "{}", stderr);
+    }
+    assert_eq!(
+        "fine",
+        std::fs::read_to_string(target.join("ok.txt")).unwrap()
+    );
+    assert!(!dir.path().join("escaped.txt").exists());
+    assert!(!dir.path().join("out").join("escaped2.txt").exists());
+    assert!(!target.join("C:").exists() && !target.join("Windows").exists());
+}
+
+#[test]
+fn unpack_path_traversal_insecure() {
+    let dir = tempdir().unwrap();
+    let pbo_path = dir.path().join("trusted.pbo");
+    write_crafted_pbo(&pbo_path, &[("..\\..\\outside.txt", b"trusted")]);
+
+    let target = dir.path().join("out").join("extracted");
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "--no-manifest", "--insecure"])
+        .arg(&pbo_path)
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(
+        "trusted",
+        std::fs::read_to_string(dir.path().join("outside.txt")).unwrap()
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn unpack_through_symlink() {
+    let dir = tempdir().unwrap();
+    let pbo_path = dir.path().join("evil.pbo");
+    write_crafted_pbo(
+        &pbo_path,
+        &[("link\\escaped.txt", b"nope"), ("ok.txt", b"fine")],
+    );
+
+    let elsewhere = dir.path().join("elsewhere");
+    std::fs::create_dir(&elsewhere).unwrap();
+    let target = dir.path().join("extracted");
+    std::fs::create_dir(&target).unwrap();
+    std::os::unix::fs::symlink(&elsewhere, target.join("link")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "--no-manifest", "--force"])
+        .arg(&pbo_path)
+        .arg(&target)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Skipping link\\escaped.txt, it would be extracted outside of the target"));
+    assert!(!elsewhere.join("escaped.txt").exists());
+    assert_eq!(
+        "fine",
+        std::fs::read_to_string(target.join("ok.txt")).unwrap()
+    );
+}
+
+#[test]
+fn unpack_derapify() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("addon");
+    std::fs::create_dir_all(source.join("data")).unwrap();
+    std::fs::write(
+        source.join("config.cpp"),
+        "class CfgPatches {\n    class addon {\n        units[] = {};\n        requiredVersion = 2.0;\n    };\n};\n",
+    )
+    .unwrap();
+    std::fs::write(
+        source.join("data").join("surface.rvmat"),
+        "ambient[] = {1, 1, 1, 1};\n",
+    )
+    .unwrap();
+    // a rapified config cut off in the middle
+    std::fs::write(
+        dir.path().join("broken.cpp"),
+        "class A { value = \"abc\"; };\n",
+    )
+    .unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("rapify")
+        .arg(dir.path().join("broken.cpp"))
+        .arg(dir.path().join("broken.bin"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let mut broken = std::fs::read(dir.path().join("broken.bin")).unwrap();
+    broken.truncate(20);
+    std::fs::write(source.join("broken.bin"), &broken).unwrap();
+    std::fs::write(source.join("$PBOPREFIX$"), "x\\test\\addon\n").unwrap();
+    let pbo_path = dir.path().join("addon.pbo");
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("build")
+        .arg(&source)
+        .arg(&pbo_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let target = dir.path().join("unpacked");
+    let output = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["unpack", "--derapify"])
+        .arg(&pbo_path)
+        .arg(&target)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Failed to derapify broken.bin, extracting it as it is"));
+
+    assert!(!target.join("config.bin").exists());
+    let config = std::fs::read_to_string(target.join("config.cpp")).unwrap();
+    assert!(config.contains("class CfgPatches"));
+    assert!(config.contains("requiredVersion"));
+    let rvmat = std::fs::read_to_string(target.join("data").join("surface.rvmat")).unwrap();
+    assert!(rvmat.contains("ambient[]"));
+    assert_eq!(broken, std::fs::read(target.join("broken.bin")).unwrap());
+    let mut manifest = String::new();
+    File::open(target.join(".armake2-manifest.json"))
+        .unwrap()
+        .read_to_string(&mut manifest)
+        .unwrap();
+    let manifest: Manifest = serde_json::from_str(&manifest).unwrap();
+    let entry = manifest
+        .entries
+        .iter()
+        .find(|e| e.name == "config.bin")
+        .unwrap();
+    assert_eq!("config.cpp", entry.path);
+    assert_eq!(config.len() as u64, entry.size);
+
+    // building the unpacked folder again gives the same configs
+    std::fs::remove_file(target.join(".armake2-manifest.json")).unwrap();
+    let rebuilt_path = dir.path().join("rebuilt.pbo");
+    let status = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .arg("build")
+        .arg(&target)
+        .arg(&rebuilt_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let original = PBO::read(&mut File::open(&pbo_path).unwrap()).unwrap();
+    let rebuilt = PBO::read(&mut File::open(&rebuilt_path).unwrap()).unwrap();
+    assert_eq!(original.header_extensions, rebuilt.header_extensions);
+    for name in ["config.bin", "data\\surface.rvmat", "broken.bin"] {
+        assert_eq!(
+            original.files[name].get_ref(),
+            rebuilt.files[name].get_ref(),
+            "{}",
+            name
+        );
+    }
+}